@@ -0,0 +1,104 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Derive macros for the `atom` crate, enabled through its `atom-derive`
+//! feature.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Error, Fields, Member};
+
+/// Implement `GetNextMut` and `GetNext` for `Box<Self>`.
+///
+/// The field holding the next pointer is marked with `#[next]`. If no field
+/// is marked, a field called `next` is used. The associated `NextPtr` is
+/// the type of that field, either an `Option<Box<Self>>` or an
+/// `AtomLink<Box<Self>>`.
+///
+/// ```ignore
+/// #[derive(GetNextMut)]
+/// struct Node {
+///     #[next]
+///     link: Option<Box<Node>>,
+///     value: u32,
+/// }
+/// ```
+#[proc_macro_derive(GetNextMut, attributes(next))]
+pub fn derive_get_next_mut(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "GetNextMut can only be derived for structs",
+            ))
+        }
+    };
+
+    let marked: Vec<_> = fields
+        .iter()
+        .enumerate()
+        .filter(|&(_, f)| f.attrs.iter().any(|a| a.path().is_ident("next")))
+        .collect();
+    let (index, field) = match marked.len() {
+        0 => fields
+            .iter()
+            .enumerate()
+            .find(|&(_, f)| f.ident.as_ref().is_some_and(|i| i == "next"))
+            .ok_or_else(|| Error::new_spanned(input, "mark the next pointer field with #[next]"))?,
+        1 => marked[0],
+        _ => {
+            return Err(Error::new_spanned(
+                marked[1].1,
+                "only one field can be marked with #[next]",
+            ))
+        }
+    };
+
+    let member = match *fields {
+        Fields::Named(_) => Member::Named(field.ident.clone().unwrap()),
+        _ => Member::Unnamed(index.into()),
+    };
+    let ty = &field.ty;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::atom::GetNextMut for ::std::boxed::Box<#name #ty_generics> #where_clause {
+            type NextPtr = #ty;
+            fn get_next(&mut self) -> &mut #ty {
+                &mut self.#member
+            }
+        }
+
+        impl #impl_generics ::atom::GetNext for ::std::boxed::Box<#name #ty_generics> #where_clause {
+            fn get_next_ref(&self) -> &#ty {
+                &self.#member
+            }
+        }
+    })
+}
@@ -14,56 +14,23 @@
 
 extern crate atom;
 
-use atom::*;
-use std::mem;
-use std::sync::{atomic::Ordering, Arc, Barrier};
+use atom::list::AppendList;
+use std::sync::{Arc, Barrier};
 use std::thread;
 
 const THREADS: usize = 100;
 
-#[derive(Debug)]
-struct Link {
-    next: AtomSetOnce<Box<Link>>,
-}
-
-impl Drop for Link {
-    fn drop(&mut self) {
-        // This is done to avoid a recusive drop of the List
-        while let Some(mut h) = self.next.atom().take(Ordering::Acquire) {
-            self.next = mem::replace(&mut h.next, AtomSetOnce::empty());
-        }
-    }
-}
-
 fn main() {
     let b = Arc::new(Barrier::new(THREADS + 1));
 
-    let head = Arc::new(Link {
-        next: AtomSetOnce::empty(),
-    });
+    let list = Arc::new(AppendList::new());
 
-    for _ in 0..THREADS {
+    for t in 0..THREADS {
         let b = b.clone();
-        let head = head.clone();
+        let list = list.clone();
         thread::spawn(move || {
-            let mut hptr = &*head;
-
-            for _ in 0..10_000 {
-                let mut my_awesome_node = Box::new(Link {
-                    next: AtomSetOnce::empty(),
-                });
-
-                loop {
-                    while let Some(h) = hptr.next.get(Ordering::Acquire) {
-                        hptr = h;
-                    }
-
-                    my_awesome_node =
-                        match hptr.next.set_if_none(my_awesome_node, Ordering::Release) {
-                            Some(v) => v,
-                            None => break,
-                        };
-                }
+            for i in 0..10_000 {
+                list.append((t, i));
             }
             b.wait();
         });
@@ -71,12 +38,7 @@ fn main() {
 
     b.wait();
 
-    let mut hptr = &*head;
-    let mut count = 0;
-    while let Some(h) = hptr.next.get(Ordering::Acquire) {
-        hptr = h;
-        count += 1;
-    }
+    let count = list.iter().count();
     println!(
         "Using {} threads we wrote {} links at the same time!",
         THREADS, count
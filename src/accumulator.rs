@@ -0,0 +1,127 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A place for worker threads to leave partial results.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::Ordering;
+
+use {Atom, GetNextMut};
+
+struct Partial<T> {
+    value: T,
+    next: Option<Box<Partial<T>>>,
+}
+
+impl<T> GetNextMut for Box<Partial<T>> {
+    type NextPtr = Option<Box<Partial<T>>>;
+    fn get_next(&mut self) -> &mut Option<Box<Partial<T>>> {
+        &mut self.next
+    }
+}
+
+/// The target of a parallel reduction.
+///
+/// Workers `contribute` their partial results, which costs one allocation
+/// and one CAS each, and whoever joins them folds everything contributed so
+/// far with `finish`. Partial results come out newest first, so the fold
+/// has to give the same answer in any order.
+///
+/// ```
+/// use std::thread;
+/// use atom::accumulator::AtomAccumulator;
+///
+/// let sums = AtomAccumulator::new();
+/// thread::scope(|s| {
+///     for chunk in [1, 2, 3, 4, 5, 6].chunks(2) {
+///         let sums = &sums;
+///         s.spawn(move || sums.contribute(chunk.iter().sum::<u32>()));
+///     }
+/// });
+/// assert_eq!(sums.finish(|a, b| a + b), Some(21));
+/// assert_eq!(sums.finish(|a, b| a + b), None);
+/// ```
+pub struct AtomAccumulator<T> {
+    head: Atom<Box<Partial<T>>>,
+}
+
+impl<T> AtomAccumulator<T> {
+    /// Create an accumulator with nothing contributed
+    pub const fn new() -> AtomAccumulator<T> {
+        AtomAccumulator {
+            head: Atom::empty(),
+        }
+    }
+
+    /// Add a partial result
+    pub fn contribute(&self, value: T) {
+        let partial = Box::new(Partial { value, next: None });
+        self.head
+            .replace_and_set_next(partial, Ordering::Relaxed, Ordering::AcqRel);
+    }
+
+    /// Take every partial result contributed so far and fold them
+    /// starting from `init`
+    pub fn fold<R, F>(&self, init: R, mut f: F) -> R
+    where
+        F: FnMut(R, T) -> R,
+    {
+        let mut acc = init;
+        let mut next = self.head.take(Ordering::Acquire);
+        while let Some(mut partial) = next {
+            next = partial.next.take();
+            acc = f(acc, partial.value);
+        }
+        acc
+    }
+
+    /// Take every partial result contributed so far and combine them with
+    /// `f`, or return `None` if there were none
+    pub fn finish<F>(&self, mut f: F) -> Option<T>
+    where
+        F: FnMut(T, T) -> T,
+    {
+        self.fold(None, |acc, value| match acc {
+            Some(acc) => Some(f(acc, value)),
+            None => Some(value),
+        })
+    }
+
+    /// Check to see if nothing was contributed since the last fold
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for AtomAccumulator<T> {
+    fn drop(&mut self) {
+        // unlink one by one, dropping the chain in one go could recurse
+        // as deep as it is long
+        self.fold((), |(), value| drop(value));
+    }
+}
+
+impl<T> Default for AtomAccumulator<T> {
+    fn default() -> AtomAccumulator<T> {
+        AtomAccumulator::new()
+    }
+}
+
+impl<T> Debug for AtomAccumulator<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "AtomAccumulator({:?})", self.head)
+    }
+}
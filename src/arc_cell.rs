@@ -0,0 +1,149 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An `Arc` slot that can be read without taking the value out.
+//!
+//! Reading an `Atom<Arc<T>>` without `take` is racy: between loading the
+//! pointer and bumping its reference count a writer may swap the value out
+//! and drop the last reference. `ArcCell` closes that window by having
+//! readers announce themselves in one of two counters before they load the
+//! pointer. A writer swaps the pointer, flips readers over to the other
+//! counter and then waits for the old counter to drain before it releases
+//! the previous value, so it never has to wait for readers that arrive
+//! after the swap.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use {Atom, Token};
+
+/// A shared `Arc<T>` that can be loaded and replaced concurrently.
+///
+/// `load` never blocks and never fails, it costs two counter updates and a
+/// reference count increment, plus a retry if a writer flips the counters
+/// while it announces itself. Writers are serialized among themselves and
+/// wait for loads that were already in flight when they swapped.
+///
+/// ```
+/// use std::sync::Arc;
+/// use atom::arc_cell::ArcCell;
+///
+/// let config = ArcCell::new(Arc::new("v1"));
+/// let before = config.load();
+/// config.store(Arc::new("v2"));
+/// assert_eq!(*before, "v1");
+/// assert_eq!(*config.load(), "v2");
+/// ```
+pub struct ArcCell<T> {
+    value: Atom<Arc<T>>,
+    phase: AtomicUsize,
+    readers: [AtomicUsize; 2],
+    writer: Mutex<()>,
+}
+
+impl<T> ArcCell<T> {
+    /// Create a new cell holding `value`
+    pub fn new(value: Arc<T>) -> ArcCell<T> {
+        ArcCell {
+            value: Atom::new(value),
+            phase: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Get a snapshot of the current value
+    pub fn load(&self) -> Arc<T> {
+        let readers = loop {
+            let phase = self.phase.load(Ordering::SeqCst);
+            let readers = &self.readers[phase & 1];
+            readers.fetch_add(1, Ordering::SeqCst);
+            // A writer that flipped the phase in between only waits for the
+            // other counter, and the one after it would not wait for us at
+            // all. Once the phase is seen unchanged, the next writer to flip
+            // it waits for this counter before any later writer can swap.
+            if self.phase.load(Ordering::SeqCst) == phase {
+                break readers;
+            }
+            readers.fetch_sub(1, Ordering::Release);
+        };
+        let ptr = self.value.inner.load(Ordering::SeqCst) as *const T;
+        // The writer that swaps this pointer out waits for `readers` to
+        // drain before it lets go of its reference, so it is still alive.
+        let out = unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        };
+        readers.fetch_sub(1, Ordering::Release);
+        out
+    }
+
+    /// Get a `Token` identifying the value currently stored
+    pub fn token(&self) -> Token {
+        self.value.token(Ordering::Acquire)
+    }
+
+    /// Replace the value, returning the previous one
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let old = self
+            .value
+            .swap(value, Ordering::SeqCst)
+            .expect("ArcCell is never empty");
+        let phase = self.phase.fetch_add(1, Ordering::SeqCst);
+        let readers = &self.readers[phase & 1];
+        while readers.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        old
+    }
+
+    /// Replace the value, dropping the previous one
+    pub fn store(&self, value: Arc<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Consume the cell, returning the value
+    pub fn into_inner(self) -> Arc<T> {
+        self.value
+            .take(Ordering::Relaxed)
+            .expect("ArcCell is never empty")
+    }
+}
+
+impl<T> From<Arc<T>> for ArcCell<T> {
+    fn from(value: Arc<T>) -> ArcCell<T> {
+        ArcCell::new(value)
+    }
+}
+
+impl<T> Default for ArcCell<T>
+where
+    T: Default,
+{
+    fn default() -> ArcCell<T> {
+        ArcCell::new(Arc::new(T::default()))
+    }
+}
+
+impl<T> Debug for ArcCell<T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "ArcCell({:?})", self.load())
+    }
+}
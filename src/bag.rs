@@ -0,0 +1,149 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An unordered bag sharded across threads.
+//!
+//! Each thread inserts into its own shard, so producers on different
+//! threads rarely touch the same cache line. An insert is a single swap of
+//! the shard's head followed by a plain store of the new node's next
+//! pointer, so it is wait-free. That leaves the chain briefly unlinked, but
+//! only `drain` walks it and `drain` needs the bag exclusively, which means
+//! every insert has finished by then.
+
+use std::fmt::{self, Debug, Formatter};
+use std::slice;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use chain::{self, Drain as ChainDrain};
+use {thread_shard, Atom, CachePadded, FromRawPtr, GetNextMut};
+
+struct Node<T> {
+    next: Option<Box<Node<T>>>,
+    value: T,
+}
+
+impl<T> GetNextMut for Box<Node<T>> {
+    type NextPtr = Option<Box<Node<T>>>;
+    fn get_next(&mut self) -> &mut Option<Box<Node<T>>> {
+        &mut self.next
+    }
+}
+
+type Shard<T> = CachePadded<Atom<Box<Node<T>>>>;
+
+/// A collection that many threads can insert into and one can drain.
+///
+/// ```
+/// use atom::bag::Bag;
+///
+/// let mut bag = Bag::new();
+/// bag.insert(1);
+/// bag.insert(2);
+/// let mut all: Vec<_> = bag.drain().collect();
+/// all.sort();
+/// assert_eq!(all, [1, 2]);
+/// ```
+pub struct Bag<T> {
+    shards: Box<[Shard<T>]>,
+}
+
+impl<T> Bag<T> {
+    /// Create an empty bag with a shard for each available CPU
+    pub fn new() -> Bag<T> {
+        Bag::with_shards(thread::available_parallelism().map_or(4, |n| n.get()))
+    }
+
+    /// Create an empty bag with `shards` shards
+    pub fn with_shards(shards: usize) -> Bag<T> {
+        assert!(shards > 0, "a bag needs at least one shard");
+        Bag {
+            shards: (0..shards).map(|_| CachePadded(Atom::empty())).collect(),
+        }
+    }
+
+    /// Add `value` to the bag
+    pub fn insert(&self, value: T) {
+        let shard = &self.shards[thread_shard() % self.shards.len()];
+        let node = Atom::raw(Box::new(Node { next: None, value }));
+        let prev = shard.inner.swap(node, Ordering::AcqRel);
+        // Nobody reads `next` before `drain`, which cannot run until this
+        // insert has returned.
+        unsafe {
+            (*(node as *mut Node<T>)).next = (!prev.is_null()).then(|| FromRawPtr::from_raw(prev));
+        }
+    }
+
+    /// Remove everything from the bag, in no particular order
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain {
+            shards: self.shards.iter(),
+            current: ChainDrain::new(None),
+        }
+    }
+}
+
+impl<T> Default for Bag<T> {
+    fn default() -> Bag<T> {
+        Bag::new()
+    }
+}
+
+impl<T> Drop for Bag<T> {
+    fn drop(&mut self) {
+        for shard in self.shards.iter() {
+            chain::drop_chain(shard.take(Ordering::Acquire));
+        }
+    }
+}
+
+impl<T> Debug for Bag<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Bag({} shards)", self.shards.len())
+    }
+}
+
+/// An iterator removing every value from a `Bag`, see `Bag::drain`.
+///
+/// Values that are not consumed are dropped with the iterator.
+pub struct Drain<'a, T: 'a> {
+    shards: slice::Iter<'a, Shard<T>>,
+    current: ChainDrain<Box<Node<T>>>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(node) = self.current.next() {
+                return Some(node.value);
+            }
+            let shard = self.shards.next()?;
+            self.current = shard.drain(Ordering::Acquire);
+        }
+    }
+}
+
+impl<'a, T> Drop for Drain<'a, T> {
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+impl<'a, T> Debug for Drain<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Drain({} shards left)", self.shards.len())
+    }
+}
@@ -0,0 +1,308 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Buffers that a writer fills in private and publishes all at once.
+//!
+//! In a `DoubleBuffer`, readers announce themselves in one of two counters, picked by the
+//! current phase, before they load the front buffer. A flip swaps the
+//! buffers, moves the phase on and waits for the old phase's counter to
+//! drain before the writer may touch the old front. A reader that picked a
+//! phase which has moved on by the time it announced itself backs out and
+//! tries again, so it is always counted where the next flip looks.
+//!
+//! A `TripleBuffer` needs no waiting at all. The producer and the consumer
+//! each own one of three buffers, and the third is passed between them by
+//! swapping its index, with a bit that says whether it holds a value the
+//! consumer has not seen yet.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use Atom;
+
+struct Shared<T> {
+    front: Atom<Box<T>>,
+    phase: AtomicUsize,
+    readers: [AtomicUsize; 2],
+}
+
+/// The writing end of a pair of buffers.
+///
+/// The writer prepares the next frame in `back_mut` while readers look at
+/// the front buffer, and `flip` publishes it. A flip waits for readers that
+/// may still be looking at the old front before handing it back to the
+/// writer, readers never wait.
+///
+/// ```
+/// use atom::buffer::DoubleBuffer;
+///
+/// let mut frames = DoubleBuffer::new(vec![0; 4], vec![0; 4]);
+/// let reader = frames.reader();
+/// frames.back_mut()[0] = 1;
+/// assert_eq!(reader.read()[0], 0);
+/// frames.flip();
+/// assert_eq!(reader.read()[0], 1);
+/// ```
+pub struct DoubleBuffer<T> {
+    shared: Arc<Shared<T>>,
+    // only `None` while a flip is underway
+    back: Option<Box<T>>,
+}
+
+impl<T> DoubleBuffer<T> {
+    /// Create a pair of buffers, showing `front` to readers
+    pub fn new(front: T, back: T) -> DoubleBuffer<T> {
+        DoubleBuffer {
+            shared: Arc::new(Shared {
+                front: Atom::new(Box::new(front)),
+                phase: AtomicUsize::new(0),
+                readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            }),
+            back: Some(Box::new(back)),
+        }
+    }
+
+    /// Get a handle for reading the front buffer
+    pub fn reader(&self) -> BufferReader<T> {
+        BufferReader {
+            shared: self.shared.clone(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Get the buffer readers are looking at
+    pub fn front(&self) -> &T {
+        // only `flip` replaces it, and that needs `&mut self`
+        unsafe { &*(self.shared.front.inner.load(Ordering::Acquire) as *const T) }
+    }
+
+    /// Get the buffer being prepared
+    pub fn back(&self) -> &T {
+        self.back.as_ref().expect("back buffer")
+    }
+
+    /// Get the buffer being prepared for writing
+    pub fn back_mut(&mut self) -> &mut T {
+        self.back.as_mut().expect("back buffer")
+    }
+
+    /// Publish the back buffer and take the old front as the new back
+    pub fn flip(&mut self) {
+        let shared = &*self.shared;
+        let back = self.back.take().expect("back buffer");
+        let old = shared
+            .front
+            .swap(back, Ordering::SeqCst)
+            .expect("DoubleBuffer is never empty");
+        let phase = shared.phase.fetch_add(1, Ordering::SeqCst);
+        let readers = &shared.readers[phase & 1];
+        while readers.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        self.back = Some(old);
+    }
+}
+
+impl<T: Debug> Debug for DoubleBuffer<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "DoubleBuffer({:?}, {:?})", self.front(), self.back())
+    }
+}
+
+/// A handle for reading the front buffer of a `DoubleBuffer`
+pub struct BufferReader<T> {
+    shared: Arc<Shared<T>>,
+    // hands out `&T` on whichever thread it is moved to
+    _marker: PhantomData<Arc<T>>,
+}
+
+impl<T> BufferReader<T> {
+    /// Get the front buffer. A flip that happens while the guard is held
+    /// waits for it to be dropped before the writer reuses the buffer.
+    pub fn read(&self) -> Front<'_, T> {
+        let shared = &*self.shared;
+        loop {
+            let phase = shared.phase.load(Ordering::SeqCst);
+            let readers = &shared.readers[phase & 1];
+            readers.fetch_add(1, Ordering::SeqCst);
+            if shared.phase.load(Ordering::SeqCst) != phase {
+                readers.fetch_sub(1, Ordering::Release);
+                continue;
+            }
+            let ptr = shared.front.inner.load(Ordering::SeqCst) as *const T;
+            return Front {
+                // the writer waits for `readers` before it reuses this
+                value: unsafe { &*ptr },
+                readers,
+            };
+        }
+    }
+}
+
+impl<T> Clone for BufferReader<T> {
+    fn clone(&self) -> BufferReader<T> {
+        BufferReader {
+            shared: self.shared.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Debug> Debug for BufferReader<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "BufferReader({:?})", &*self.read())
+    }
+}
+
+/// The front buffer, returned by `BufferReader::read`
+pub struct Front<'a, T> {
+    value: &'a T,
+    readers: &'a AtomicUsize,
+}
+
+impl<'a, T> Deref for Front<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> Drop for Front<'a, T> {
+    fn drop(&mut self) {
+        self.readers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<'a, T: Debug> Debug for Front<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Front({:?})", self.value)
+    }
+}
+
+/// Set in `middle` when it holds a buffer the consumer has not seen
+const FRESH: usize = 0b100;
+const INDEX: usize = 0b011;
+
+struct Triple<T> {
+    buffers: [UnsafeCell<T>; 3],
+    middle: AtomicUsize,
+}
+
+// each buffer is owned by whoever holds its index
+unsafe impl<T: Send> Send for Triple<T> {}
+unsafe impl<T: Send> Sync for Triple<T> {}
+
+/// The producing end of a triple buffer.
+///
+/// The producer writes into its own buffer and `publish` swaps it for the
+/// spare one, the consumer swaps the spare for its own buffer whenever a
+/// fresher value has been published. Neither side ever waits for the
+/// other or allocates, values the consumer never got to are overwritten.
+///
+/// ```
+/// use atom::buffer::TripleBuffer;
+///
+/// let (mut sensor, mut latest) = TripleBuffer::new(0.0f32);
+/// sensor.write(1.5);
+/// sensor.write(2.5);
+/// assert!(latest.has_update());
+/// assert_eq!(*latest.read(), 2.5);
+/// assert!(!latest.has_update());
+/// ```
+pub struct TripleBuffer<T> {
+    shared: Arc<Triple<T>>,
+    back: usize,
+}
+
+impl<T: Clone> TripleBuffer<T> {
+    /// Create a triple buffer whose buffers all start as `value`, returning
+    /// the producing and the consuming end
+    pub fn new(value: T) -> (TripleBuffer<T>, TripleReader<T>) {
+        let shared = Arc::new(Triple {
+            buffers: [
+                UnsafeCell::new(value.clone()),
+                UnsafeCell::new(value.clone()),
+                UnsafeCell::new(value),
+            ],
+            middle: AtomicUsize::new(1),
+        });
+        (
+            TripleBuffer {
+                shared: shared.clone(),
+                back: 0,
+            },
+            TripleReader { shared, front: 2 },
+        )
+    }
+}
+
+impl<T> TripleBuffer<T> {
+    /// Get the buffer being prepared for writing. It holds whatever value
+    /// it was last given, which is not necessarily the last one published.
+    pub fn back_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.buffers[self.back].get() }
+    }
+
+    /// Hand the back buffer to the consumer
+    pub fn publish(&mut self) {
+        let old = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = old & INDEX;
+    }
+
+    /// Publish `value`
+    pub fn write(&mut self, value: T) {
+        *self.back_mut() = value;
+        self.publish();
+    }
+}
+
+impl<T> Debug for TripleBuffer<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "TripleBuffer(back={})", self.back)
+    }
+}
+
+/// The consuming end of a triple buffer, see `TripleBuffer`
+pub struct TripleReader<T> {
+    shared: Arc<Triple<T>>,
+    front: usize,
+}
+
+impl<T> TripleReader<T> {
+    /// Check to see if a value was published since the last `read`
+    pub fn has_update(&self) -> bool {
+        self.shared.middle.load(Ordering::Relaxed) & FRESH != 0
+    }
+
+    /// Get the latest published value
+    pub fn read(&mut self) -> &T {
+        if self.has_update() {
+            let old = self.shared.middle.swap(self.front, Ordering::AcqRel);
+            self.front = old & INDEX;
+        }
+        unsafe { &*self.shared.buffers[self.front].get() }
+    }
+}
+
+impl<T> Debug for TripleReader<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "TripleReader(front={})", self.front)
+    }
+}
@@ -0,0 +1,131 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Reader-side caching of a shared `Arc`.
+//!
+//! Checking whether a shared value changed only needs a `Token`, which is a
+//! single load. A `Cache` keeps its own clone of the last value it fetched
+//! and only goes back to the source when the token moves. Because the cache
+//! holds that clone, the allocation cannot be reused while it is cached, so
+//! an unchanged token really means an unchanged value.
+//!
+//! A plain `Atom<Arc<T>>` is not a source: the only way to copy its value
+//! without a reclamation scheme is `Atom::load_cloned`, which briefly
+//! empties the Atom for everyone else. Share the value through an `ArcCell`
+//! instead.
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use arc_cell::ArcCell;
+use Token;
+
+/// A shared slot that a `Cache` can read from.
+pub trait CacheSource {
+    /// The type behind the `Arc`
+    type Target;
+
+    /// Identify the value currently stored
+    fn token(&self) -> Token;
+
+    /// Get a copy of the value currently stored
+    fn fetch(&self) -> Option<Arc<Self::Target>>;
+}
+
+impl<T> CacheSource for ArcCell<T> {
+    type Target = T;
+
+    fn token(&self) -> Token {
+        ArcCell::token(self)
+    }
+
+    fn fetch(&self) -> Option<Arc<T>> {
+        Some(self.load())
+    }
+}
+
+fn token_of<T>(value: &Option<Arc<T>>) -> Token {
+    match *value {
+        Some(ref v) => Token(Arc::as_ptr(v) as usize),
+        None => Token(0),
+    }
+}
+
+/// A per-reader cache of the value in an `ArcCell`.
+///
+/// `S` is whatever gives access to the source, typically a reference or an
+/// `Arc`. A cache is meant to be owned by one reader thread.
+///
+/// ```
+/// use std::sync::Arc;
+/// use atom::arc_cell::ArcCell;
+/// use atom::cache::Cache;
+///
+/// let config = ArcCell::new(Arc::new(1));
+/// let mut cache = Cache::new(&config);
+/// assert_eq!(cache.load().map(|v| **v), Some(1));
+/// config.store(Arc::new(2));
+/// assert_eq!(cache.load().map(|v| **v), Some(2));
+/// ```
+pub struct Cache<S>
+where
+    S: Deref,
+    S::Target: CacheSource,
+{
+    source: S,
+    token: Token,
+    value: Option<Arc<<S::Target as CacheSource>::Target>>,
+}
+
+impl<S> Cache<S>
+where
+    S: Deref,
+    S::Target: CacheSource,
+{
+    /// Create a cache reading from `source`
+    pub fn new(source: S) -> Cache<S> {
+        let value = source.fetch();
+        Cache {
+            token: token_of(&value),
+            source,
+            value,
+        }
+    }
+
+    /// Get the current value, only going back to the source if it changed
+    /// since the last call.
+    pub fn load(&mut self) -> Option<&Arc<<S::Target as CacheSource>::Target>> {
+        if self.source.token() != self.token {
+            self.value = self.source.fetch();
+            self.token = token_of(&self.value);
+        }
+        self.value.as_ref()
+    }
+
+    /// Access the source this cache reads from
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+}
+
+impl<S> Debug for Cache<S>
+where
+    S: Deref,
+    S::Target: CacheSource,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Cache({:?})", self.token)
+    }
+}
@@ -0,0 +1,194 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A closure that can be replaced while other threads are calling it.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+
+use arc_cell::ArcCell;
+use strongest_failure_ordering;
+
+/// The closures an `AtomCallback` holds
+pub type Callback<Args, R> = dyn Fn(Args) -> R + Send + Sync;
+
+/// A hot-swappable `Fn(Args) -> R`, for handlers such as loggers and hooks.
+///
+/// Every call holds its own reference to the closure it runs, so `replace`
+/// never frees a closure that is still running, and a closure may replace
+/// itself. Calls never block. Replacing waits only for calls that were
+/// fetching the closure at that very moment, not for ones already running.
+///
+/// ```
+/// use atom::callback::AtomCallback;
+///
+/// let log = AtomCallback::new(|line: &str| line.len());
+/// assert_eq!(log.call("hello"), 5);
+/// log.replace(|_| 0);
+/// assert_eq!(log.call("hello"), 0);
+/// ```
+pub struct AtomCallback<Args, R> {
+    cell: ArcCell<Box<Callback<Args, R>>>,
+}
+
+impl<Args, R> AtomCallback<Args, R> {
+    /// Create a callback that runs `f`
+    pub fn new<F>(f: F) -> AtomCallback<Args, R>
+    where
+        F: Fn(Args) -> R + Send + Sync + 'static,
+    {
+        AtomCallback {
+            cell: ArcCell::new(Arc::new(Box::new(f))),
+        }
+    }
+
+    /// Run the current closure
+    pub fn call(&self, args: Args) -> R {
+        (self.cell.load())(args)
+    }
+
+    /// Get the current closure, to call it several times without looking
+    /// it up again
+    pub fn get(&self) -> Arc<Box<Callback<Args, R>>> {
+        self.cell.load()
+    }
+
+    /// Make later calls run `f`, returning the closure they ran before
+    pub fn replace<F>(&self, f: F) -> Arc<Box<Callback<Args, R>>>
+    where
+        F: Fn(Args) -> R + Send + Sync + 'static,
+    {
+        self.cell.swap(Arc::new(Box::new(f)))
+    }
+
+    /// Consume the callback, returning the current closure
+    pub fn into_inner(self) -> Arc<Box<Callback<Args, R>>> {
+        self.cell.into_inner()
+    }
+}
+
+impl<Args, R> Debug for AtomCallback<Args, R> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "AtomCallback({:?})", Arc::as_ptr(&self.cell.load()))
+    }
+}
+
+/// A hot-swappable `fn(Args) -> R`, kept directly in the atomic word.
+///
+/// Function pointers are never null and never need freeing, so unlike
+/// `AtomCallback` there is no allocation and no reference counting: a call
+/// is one load and an indirect jump. The price is that only plain functions
+/// and closures that capture nothing can be stored.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::callback::AtomFn;
+///
+/// fn slow(x: u32) -> u32 { (0..x).map(|_| 1).sum() }
+/// fn fast(x: u32) -> u32 { x }
+///
+/// static DISPATCH: AtomFn<u32, u32> = AtomFn::empty();
+/// assert_eq!(DISPATCH.call(3), None);
+/// assert_eq!(DISPATCH.set_if_none(slow, Ordering::AcqRel), None);
+/// assert_eq!(DISPATCH.call(3), Some(3));
+/// DISPATCH.swap(fast, Ordering::AcqRel);
+/// assert_eq!(DISPATCH.call(4), Some(4));
+/// ```
+pub struct AtomFn<Args, R> {
+    inner: AtomicPtr<()>,
+    data: PhantomData<fn(Args) -> R>,
+}
+
+impl<Args, R> AtomFn<Args, R> {
+    fn unpack(ptr: *mut ()) -> Option<fn(Args) -> R> {
+        if ptr.is_null() {
+            None
+        } else {
+            // every pointer stored came from a `fn(Args) -> R`
+            Some(unsafe { mem::transmute::<*mut (), fn(Args) -> R>(ptr) })
+        }
+    }
+
+    /// Create an empty AtomFn
+    pub const fn empty() -> AtomFn<Args, R> {
+        AtomFn {
+            inner: AtomicPtr::new(ptr::null_mut()),
+            data: PhantomData,
+        }
+    }
+
+    /// Create an AtomFn holding `f`
+    pub fn new(f: fn(Args) -> R) -> AtomFn<Args, R> {
+        AtomFn {
+            inner: AtomicPtr::new(f as *mut ()),
+            data: PhantomData,
+        }
+    }
+
+    /// Run the current function, or return `None` if there is none
+    pub fn call(&self, args: Args) -> Option<R> {
+        self.load(Ordering::Acquire).map(|f| f(args))
+    }
+
+    /// Read the current function
+    pub fn load(&self, order: Ordering) -> Option<fn(Args) -> R> {
+        Self::unpack(self.inner.load(order))
+    }
+
+    /// Make later calls run `f`, returning the function they ran before
+    pub fn swap(&self, f: fn(Args) -> R, order: Ordering) -> Option<fn(Args) -> R> {
+        Self::unpack(self.inner.swap(f as *mut (), order))
+    }
+
+    /// Remove the function, leaving the AtomFn empty
+    pub fn take(&self, order: Ordering) -> Option<fn(Args) -> R> {
+        Self::unpack(self.inner.swap(ptr::null_mut(), order))
+    }
+
+    /// Store `f` if the AtomFn is empty. If it was not, `f` is returned.
+    pub fn set_if_none(&self, f: fn(Args) -> R, order: Ordering) -> Option<fn(Args) -> R> {
+        self.inner
+            .compare_exchange(
+                ptr::null_mut(),
+                f as *mut (),
+                order,
+                strongest_failure_ordering(order),
+            )
+            .err()
+            .map(|_| f)
+    }
+
+    /// Check to see if the AtomFn is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.inner.load(order).is_null()
+    }
+}
+
+impl<Args, R> Default for AtomFn<Args, R> {
+    fn default() -> AtomFn<Args, R> {
+        AtomFn::empty()
+    }
+}
+
+impl<Args, R> Debug for AtomFn<Args, R> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "AtomFn({:?})", self.inner.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,291 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Cooperative cancellation that works on threads and in any executor.
+//!
+//! Each token is an `AtomSetOnce` flag plus a link to its parent. Deriving
+//! a child allocates one node and never touches the parent, instead a token
+//! counts as cancelled when it or any of its ancestors is. Waiters register
+//! with every flag on the way up, so cancelling an ancestor wakes them too.
+
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Waker;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use wait::{self, Registration};
+use AtomSetOnce;
+
+/// What a flag is set to, only whether it is set matters
+static SET: () = ();
+
+struct Node {
+    flag: AtomSetOnce<&'static ()>,
+    parent: Option<Arc<Node>>,
+}
+
+impl Node {
+    fn ancestors(&self) -> Ancestors<'_> {
+        Ancestors { next: Some(self) }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.ancestors()
+            .any(|node| !node.flag.is_none(Ordering::Acquire))
+    }
+
+    fn register(&self, waker: &Waker) -> Vec<Registration<'static>> {
+        self.ancestors()
+            .map(|node| node.flag.waiters().register(waker.clone()))
+            .collect()
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        // unlink a long line of ancestors one at a time instead of recursively
+        let mut parent = self.parent.take();
+        while let Some(node) = parent {
+            parent = match Arc::try_unwrap(node) {
+                Ok(mut node) => node.parent.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/// Walks from a node up to the root
+struct Ancestors<'a> {
+    next: Option<&'a Node>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<&'a Node> {
+        let node = self.next?;
+        self.next = node.parent.as_deref();
+        Some(node)
+    }
+}
+
+/// A shared flag that asks work to stop.
+///
+/// Clones share the same flag. `child` derives a token that is cancelled
+/// along with this one but can also be cancelled on its own, without
+/// affecting its parent.
+///
+/// Checking a token walks up to the root, so it costs one load per
+/// generation of children. Each blocking or async wait queues a waker on
+/// every flag along that path, and withdraws them all once it is done
+/// waiting, so waits that time out or are dropped do not pile up on a
+/// long-lived root.
+///
+/// ```
+/// use std::thread;
+/// use atom::cancel::CancellationToken;
+///
+/// let shutdown = CancellationToken::new();
+/// let request = shutdown.child();
+/// let worker = {
+///     let request = request.clone();
+///     thread::spawn(move || request.wait())
+/// };
+/// shutdown.cancel();
+/// worker.join().unwrap();
+/// assert!(request.is_cancelled());
+/// ```
+#[derive(Clone)]
+pub struct CancellationToken {
+    node: Arc<Node>,
+}
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            node: Arc::new(Node {
+                flag: AtomSetOnce::empty(),
+                parent: None,
+            }),
+        }
+    }
+
+    /// Derive a token that is cancelled whenever this one is. The child
+    /// is also cancelled when it is dropped, unless it is `detach`ed.
+    pub fn child(&self) -> Child {
+        Child {
+            token: CancellationToken {
+                node: Arc::new(Node {
+                    flag: AtomSetOnce::empty(),
+                    parent: Some(self.node.clone()),
+                }),
+            },
+        }
+    }
+
+    /// Cancel this token and everything derived from it, waking everyone
+    /// waiting on them. Returns false if this token was already cancelled
+    /// directly, cancelling it through a parent does not count.
+    pub fn cancel(&self) -> bool {
+        self.node
+            .flag
+            .set_if_none(&SET, Ordering::Release)
+            .is_none()
+    }
+
+    /// Check to see if this token or one of its parents has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.node.is_cancelled()
+    }
+
+    /// Block the current thread until the token is cancelled
+    pub fn wait(&self) {
+        self.wait_until(None);
+    }
+
+    /// Block the current thread until the token is cancelled or `timeout`
+    /// has passed. Returns whether the token is cancelled.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.wait_until(Some(Instant::now() + timeout))
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> bool {
+        wait::park_with(
+            |waker| self.node.register(waker),
+            || self.is_cancelled(),
+            deadline,
+        )
+    }
+
+    /// Wait for the token to be cancelled without blocking the thread.
+    ///
+    /// The returned future is cancel safe, dropping it early leaves the
+    /// token untouched and takes back the wakers it queued.
+    #[cfg(feature = "async")]
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled {
+            token: self,
+            registered: Vec::new(),
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> CancellationToken {
+        CancellationToken::new()
+    }
+}
+
+impl Debug for CancellationToken {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let state = if self.is_cancelled() {
+            "cancelled"
+        } else {
+            "live"
+        };
+        write!(f, "CancellationToken({})", state)
+    }
+}
+
+/// A token derived with `CancellationToken::child`.
+///
+/// It dereferences to the token itself, and cancels it when it goes out of
+/// scope, so work started on behalf of a scope stops when the scope ends.
+/// Clones of the token taken before then are cancelled with it.
+pub struct Child {
+    token: CancellationToken,
+}
+
+impl Child {
+    /// Get the child token without cancelling it on drop
+    pub fn detach(self) -> CancellationToken {
+        let this = ManuallyDrop::new(self);
+        // `this` is never dropped, so the token is moved out exactly once
+        unsafe { ptr::read(&this.token) }
+    }
+}
+
+impl Deref for Child {
+    type Target = CancellationToken;
+
+    fn deref(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+impl Debug for Child {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Child({:?})", self.token)
+    }
+}
+
+/// The future returned by `CancellationToken::cancelled`.
+///
+/// Dropping it before it completes is fine, the wakers it queued on every
+/// ancestor's flag are withdrawn and their nodes are cleared out like those
+/// of a thread that timed out.
+#[cfg(feature = "async")]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+    registered: Vec<Registration<'static>>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> Future for Cancelled<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        if !this.token.is_cancelled() {
+            // keep the queued nodes while they all wake this task, a notify
+            // that took any of them off its list means queueing again
+            let current = !this.registered.is_empty()
+                && this
+                    .registered
+                    .iter()
+                    .all(|registered| registered.is_queued() && registered.will_wake(cx.waker()));
+            if !current {
+                this.registered = this.token.node.register(cx.waker());
+            }
+            if !this.token.is_cancelled() {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> Debug for Cancelled<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Cancelled({:?})", self.token)
+    }
+}
@@ -0,0 +1,432 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Helpers for intrusive chains built with `replace_and_set_next`.
+
+use std::fmt::{self, Debug, Formatter};
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+use {Atom, AtomSetOnce, AtomStorable, GetNext, GetNextMut, NextSlot, RawDeref, Token};
+
+/// An owning iterator over the nodes of a chain.
+///
+/// Each node is yielded with its next pointer cleared, so dropping a node
+/// never drops the rest of the chain. Nodes that are not consumed are
+/// dropped one at a time when the `Drain` is dropped.
+pub struct Drain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    next: Option<P>,
+}
+
+impl<P> Drain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    /// Iterate over a chain that is already owned
+    pub fn new(head: Option<P>) -> Drain<P> {
+        Drain { next: head }
+    }
+}
+
+impl<P> Iterator for Drain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    type Item = P;
+
+    fn next(&mut self) -> Option<P> {
+        self.next.take().map(|mut node| {
+            self.next = node.get_next().slot().take();
+            node
+        })
+    }
+}
+
+impl<P> Drop for Drain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+impl<P> Debug for Drain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Drain {{ empty: {} }}", self.next.is_none())
+    }
+}
+
+impl<P> Atom<P>
+where
+    P: AtomStorable + GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    /// Take the whole chain out of the Atom with a single swap, and
+    /// iterate over its nodes from the most recently pushed one.
+    pub fn drain(&self, order: Ordering) -> Drain<P> {
+        Drain::new(self.take(order))
+    }
+}
+
+/// A chain of nodes that is owned by the caller, typically the result of
+/// taking a LIFO out of an `Atom`.
+///
+/// Dropping a `Chain` drops its nodes one at a time rather than recursing
+/// through the next pointers.
+pub struct Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    head: Option<P>,
+}
+
+impl<P> Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    /// Wrap an owned chain starting at `head`
+    pub fn new(head: Option<P>) -> Chain<P> {
+        Chain { head }
+    }
+
+    /// Check to see if the chain has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// Count the nodes in the chain
+    pub fn len(&self) -> usize
+    where
+        P: GetNext,
+    {
+        let mut count = 0;
+        let mut next = self.head.as_ref();
+        while let Some(node) = next {
+            count += 1;
+            next = node.get_next_ref().peek();
+        }
+        count
+    }
+
+    /// Iterate over the values in the chain, starting at the head.
+    pub fn iter(&self) -> Iter<'_, P>
+    where
+        P: GetNext,
+    {
+        Iter {
+            next: self.head.as_ref(),
+        }
+    }
+
+    /// Reverse the chain in place. A chain taken from a LIFO is ordered
+    /// from the newest node, reversing it gives the order they were pushed.
+    pub fn reverse(&mut self) {
+        let mut prev = None;
+        let mut current = self.head.take();
+        while let Some(mut node) = current {
+            current = mem::replace(node.get_next().slot(), prev);
+            prev = Some(node);
+        }
+        self.head = prev;
+    }
+
+    /// Get the head of the chain back
+    pub fn into_inner(mut self) -> Option<P> {
+        self.head.take()
+    }
+}
+
+impl<P> Drop for Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn drop(&mut self) {
+        drop(Drain::new(self.head.take()));
+    }
+}
+
+impl<P> IntoIterator for Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    type Item = P;
+    type IntoIter = Drain<P>;
+
+    fn into_iter(self) -> Drain<P> {
+        Drain::new(self.into_inner())
+    }
+}
+
+impl<P> Debug for Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Chain {{ empty: {} }}", self.head.is_none())
+    }
+}
+
+/// A borrowing iterator over a `Chain`
+pub struct Iter<'a, P>
+where
+    P: 'a,
+{
+    next: Option<&'a P>,
+}
+
+impl<'a, T, P> Iterator for Iter<'a, P>
+where
+    P: GetNext + Deref<Target = T> + 'a,
+    P::NextPtr: NextSlot<P>,
+    T: 'a,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.next.map(|node| {
+            self.next = node.get_next_ref().peek();
+            &**node
+        })
+    }
+}
+
+impl<'a, P> Debug for Iter<'a, P>
+where
+    P: 'a,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Iter {{ done: {} }}", self.next.is_none())
+    }
+}
+
+impl<P> Atom<P>
+where
+    P: AtomStorable + GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    /// Take the whole chain out of the Atom with a single swap
+    pub fn take_chain(&self, order: Ordering) -> Chain<P> {
+        Chain::new(self.take(order))
+    }
+
+    /// Push every node of `chain` onto this LIFO with a single successful
+    /// CAS. The tail of `chain` is linked to the previous contents of the
+    /// Atom, so its nodes keep their order above them.
+    ///
+    /// Returns a `Token` for the head that was replaced, like
+    /// `replace_and_set_next`. Pushing an empty chain does nothing and
+    /// returns `None`.
+    pub fn replace_and_set_next_chain(
+        &self,
+        chain: Chain<P>,
+        load_order: Ordering,
+        cas_order: Ordering,
+    ) -> Option<Token> {
+        let mut head = chain.into_inner()?;
+        let mut tail = head.get_next().slot() as *mut Option<P>;
+        // Each node is owned by the one before it, so the next pointers
+        // stay in place once the head is turned into a raw pointer.
+        while let Some(node) = unsafe { (*tail).as_mut() } {
+            tail = node.get_next().slot() as *mut Option<P>;
+        }
+        let raw = Self::raw(head);
+        unsafe {
+            ptr::drop_in_place(tail);
+            Some(self.splice(raw, tail, load_order, cas_order))
+        }
+    }
+}
+
+/// An iterator following a chain of `AtomSetOnce` links.
+///
+/// Set-once links can never be removed while they are shared, so walking
+/// them only needs a shared reference.
+pub struct SetOnceIter<'a, T, P>
+where
+    T: 'a,
+    P: AtomStorable + RawDeref<Target = T> + 'a,
+{
+    next: &'a AtomSetOnce<P>,
+    link: fn(&T) -> &AtomSetOnce<P>,
+    order: Ordering,
+}
+
+impl<'a, T, P> Iterator for SetOnceIter<'a, T, P>
+where
+    T: 'a,
+    P: AtomStorable + RawDeref<Target = T> + 'a,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let value = self.next.get(self.order)?;
+        self.next = (self.link)(value);
+        Some(value)
+    }
+}
+
+impl<'a, T, P> Debug for SetOnceIter<'a, T, P>
+where
+    T: 'a,
+    P: AtomStorable + RawDeref<Target = T> + 'a,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "SetOnceIter({:?})", self.next.inner)
+    }
+}
+
+impl<T, P> AtomSetOnce<P>
+where
+    P: AtomStorable + RawDeref<Target = T>,
+{
+    /// Iterate over a chain of set-once links starting at this one. `link`
+    /// selects the field of each value that holds the next link.
+    pub fn iter(&self, order: Ordering, link: fn(&T) -> &AtomSetOnce<P>) -> SetOnceIter<'_, T, P> {
+        SetOnceIter {
+            next: self,
+            link,
+            order,
+        }
+    }
+
+    /// Count the links set so far, starting at this one
+    pub fn chain_len(&self, order: Ordering, link: fn(&T) -> &AtomSetOnce<P>) -> usize {
+        self.iter(order, link).count()
+    }
+}
+
+impl<P> Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    /// Push a node onto the head of the chain
+    pub fn push(&mut self, mut node: P) {
+        *node.get_next().slot() = self.head.take();
+        self.head = Some(node);
+    }
+}
+
+impl<P> Default for Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn default() -> Chain<P> {
+        Chain::new(None)
+    }
+}
+
+impl<P> Extend<P> for Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn extend<I: IntoIterator<Item = P>>(&mut self, iter: I) {
+        for node in iter {
+            self.push(node);
+        }
+    }
+}
+
+/// Collecting pushes each node in turn, so the last node yielded by the
+/// iterator ends up at the head, just like a LIFO.
+impl<P> FromIterator<P> for Chain<P>
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Chain<P> {
+        let mut chain = Chain::default();
+        chain.extend(iter);
+        chain
+    }
+}
+
+/// Extending an Atom links the new nodes together locally and then publishes
+/// them with a single `replace_and_set_next_chain`.
+impl<P> Extend<P> for Atom<P>
+where
+    P: AtomStorable + GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn extend<I: IntoIterator<Item = P>>(&mut self, iter: I) {
+        let chain = iter.into_iter().collect();
+        self.replace_and_set_next_chain(chain, Ordering::Relaxed, Ordering::AcqRel);
+    }
+}
+
+impl<P> FromIterator<P> for Atom<P>
+where
+    P: AtomStorable + GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Atom<P> {
+        let chain: Chain<P> = iter.into_iter().collect();
+        match chain.into_inner() {
+            Some(head) => Atom::new(head),
+            None => Atom::empty(),
+        }
+    }
+}
+
+/// Drop every node of an owned chain one at a time.
+///
+/// Letting a long chain drop on its own recurses once per node and can
+/// overflow the stack. Call this from the `Drop` of whatever owns the head.
+pub fn drop_chain<P>(head: Option<P>)
+where
+    P: GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    drop(Drain::new(head));
+}
+
+impl<T, P> AtomSetOnce<P>
+where
+    P: AtomStorable + DerefMut<Target = T>,
+{
+    /// Drop a chain of set-once links one node at a time, leaving this link
+    /// empty. `link` selects the field of each value that holds the next
+    /// link.
+    ///
+    /// This is meant to be called from the `Drop` of the type that holds the
+    /// first link, to avoid a recursive drop of the whole chain.
+    pub fn drop_chain(&mut self, link: fn(&mut T) -> &mut AtomSetOnce<P>) {
+        let mut current = self.inner.take(Ordering::Acquire);
+        while let Some(mut node) = current {
+            current = link(&mut node).inner.take(Ordering::Acquire);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A flat-combining front end for a contended LIFO head.
+//!
+//! Instead of every producer fighting over the head with its own CAS, a
+//! producer announces its node in one of a fixed number of slots. Whichever
+//! producer manages to become the combiner gathers every announced node into
+//! a local chain and splices it onto the head with a single CAS.
+
+use std::fmt::{self, Debug, Formatter};
+use std::hint;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use chain::Chain;
+use {Atom, AtomStorable, GetNextMut, NextSlot};
+
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SLOT: usize = NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A LIFO head whose pushes are batched through announcement slots.
+///
+/// `push` only returns once the node is reachable from `head()`, so the
+/// consumer side is the plain `Atom` API: `take`, `take_chain`, `drain` and
+/// so on.
+pub struct CombiningLifo<P>
+where
+    P: AtomStorable + GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    head: Atom<P>,
+    slots: Box<[Atom<P>]>,
+    combining: AtomicBool,
+}
+
+impl<P> CombiningLifo<P>
+where
+    P: AtomStorable + GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    /// Create an empty LIFO with `slots` announcement slots. Using about as
+    /// many slots as there are producer threads works well.
+    pub fn new(slots: usize) -> CombiningLifo<P> {
+        assert!(slots > 0, "at least one announcement slot is required");
+        CombiningLifo {
+            head: Atom::empty(),
+            slots: (0..slots).map(|_| Atom::empty()).collect(),
+            combining: AtomicBool::new(false),
+        }
+    }
+
+    /// The shared head that combined nodes are published to
+    pub fn head(&self) -> &Atom<P> {
+        &self.head
+    }
+
+    /// Push `node` onto the LIFO.
+    ///
+    /// If this thread's announcement slot is already in use the node is
+    /// pushed directly onto the head instead.
+    pub fn push(&self, node: P) {
+        let slot = &self.slots[SLOT.with(|slot| *slot) % self.slots.len()];
+        let node = match slot.set_if_none(node, Ordering::Release) {
+            None => slot.token(Ordering::Relaxed),
+            Some(node) => {
+                self.head
+                    .replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+                return;
+            }
+        };
+
+        // Wait for some combiner to pick the node up, or become one
+        while slot.token(Ordering::Acquire) == node {
+            if self
+                .combining
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.combine();
+                self.combining.store(false, Ordering::Release);
+                return;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    fn combine(&self) {
+        let mut batch = Chain::default();
+        for slot in self.slots.iter() {
+            if let Some(node) = slot.take(Ordering::Acquire) {
+                batch.push(node);
+            }
+        }
+        self.head
+            .replace_and_set_next_chain(batch, Ordering::Relaxed, Ordering::AcqRel);
+    }
+}
+
+impl<P> Debug for CombiningLifo<P>
+where
+    P: AtomStorable + GetNextMut,
+    P::NextPtr: NextSlot<P>,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("CombiningLifo")
+            .field("head", &self.head)
+            .field("slots", &self.slots.len())
+            .finish()
+    }
+}
@@ -0,0 +1,246 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An atom that holds either a `&'static` value or an owned one.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use {strongest_failure_ordering, Token};
+
+/// Set in the pointer of an owned value
+const OWNED: usize = 1;
+
+/// What an `AtomCow` holds
+#[derive(Debug, PartialEq, Eq)]
+pub enum Payload<T: 'static> {
+    /// A value that lives forever and is never dropped
+    Static(&'static T),
+    /// A value that is dropped when it leaves the `AtomCow`
+    Owned(Box<T>),
+}
+
+impl<T> Payload<T> {
+    /// Check to see if the value is owned
+    pub fn is_owned(&self) -> bool {
+        match *self {
+            Payload::Static(_) => false,
+            Payload::Owned(_) => true,
+        }
+    }
+
+    /// Get the value as a `Box`, cloning it if it was borrowed
+    pub fn into_owned(self) -> Box<T>
+    where
+        T: Clone,
+    {
+        match self {
+            Payload::Static(value) => Box::new(value.clone()),
+            Payload::Owned(value) => value,
+        }
+    }
+}
+
+impl<T> Deref for Payload<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match *self {
+            Payload::Static(value) => value,
+            Payload::Owned(ref value) => value,
+        }
+    }
+}
+
+impl<T> From<&'static T> for Payload<T> {
+    fn from(value: &'static T) -> Payload<T> {
+        Payload::Static(value)
+    }
+}
+
+impl<T> From<Box<T>> for Payload<T> {
+    fn from(value: Box<T>) -> Payload<T> {
+        Payload::Owned(value)
+    }
+}
+
+/// An `Atom` for a value that starts out as a `'static` default and may be
+/// replaced by one loaded at run time.
+///
+/// The low bit of the pointer tells the two apart, so only owned values are
+/// ever dropped and no second allocation is needed to hold the choice. That
+/// bit has to be free, which makes an `AtomCow` of a type aligned to a
+/// single byte a compile time error.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::cow::{AtomCow, Payload};
+///
+/// static DEFAULT: u32 = 8;
+/// static WORKERS: AtomCow<u32> = AtomCow::from_static(&DEFAULT);
+///
+/// let old = WORKERS.swap(Box::new(32).into(), Ordering::AcqRel);
+/// assert_eq!(old, Some(Payload::Static(&8)));
+/// let loaded = WORKERS.take(Ordering::Acquire).unwrap();
+/// assert!(loaded.is_owned());
+/// assert_eq!(*loaded, 32);
+/// ```
+pub struct AtomCow<T: 'static> {
+    inner: AtomicPtr<T>,
+    data: PhantomData<Payload<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for AtomCow<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomCow<T> {}
+
+impl<T> AtomCow<T> {
+    const ALIGNED: () = assert!(
+        mem::align_of::<T>() > OWNED,
+        "the payload is not aligned enough to hold the owned bit"
+    );
+
+    /// Create an empty AtomCow
+    pub const fn empty() -> AtomCow<T> {
+        AtomCow {
+            inner: AtomicPtr::new(ptr::null_mut()),
+            data: PhantomData,
+        }
+    }
+
+    /// Create an AtomCow holding a `'static` value
+    pub const fn from_static(value: &'static T) -> AtomCow<T> {
+        AtomCow {
+            inner: AtomicPtr::new(value as *const T as *mut T),
+            data: PhantomData,
+        }
+    }
+
+    /// Create an AtomCow holding `value`
+    pub fn new(value: Payload<T>) -> AtomCow<T> {
+        AtomCow {
+            inner: AtomicPtr::new(Self::pack(Some(value))),
+            data: PhantomData,
+        }
+    }
+
+    fn pack(value: Option<Payload<T>>) -> *mut T {
+        let () = Self::ALIGNED;
+        match value {
+            None => ptr::null_mut(),
+            Some(Payload::Static(value)) => value as *const T as *mut T,
+            Some(Payload::Owned(value)) => {
+                Box::into_raw(value).cast::<u8>().wrapping_add(OWNED).cast()
+            }
+        }
+    }
+
+    fn unpack(ptr: *mut T) -> Option<Payload<T>> {
+        if ptr.is_null() {
+            None
+        } else if ptr as usize & OWNED == 0 {
+            Some(Payload::Static(unsafe { &*ptr }))
+        } else {
+            // the pointer came out of `pack` and is owned by the caller
+            let ptr = ptr.cast::<u8>().wrapping_sub(OWNED).cast();
+            Some(Payload::Owned(unsafe { Box::from_raw(ptr) }))
+        }
+    }
+
+    /// Swap a new value into the AtomCow, returning the old value
+    pub fn swap(&self, v: Payload<T>, order: Ordering) -> Option<Payload<T>> {
+        Self::unpack(self.inner.swap(Self::pack(Some(v)), order))
+    }
+
+    /// Take the value out of the AtomCow, leaving it empty
+    pub fn take(&self, order: Ordering) -> Option<Payload<T>> {
+        Self::unpack(self.inner.swap(ptr::null_mut(), order))
+    }
+
+    /// Store `v` if the AtomCow is empty. If it was not, `v` is returned.
+    pub fn set_if_none(&self, v: Payload<T>, order: Ordering) -> Option<Payload<T>> {
+        let new = Self::pack(Some(v));
+        match self.inner.compare_exchange(
+            ptr::null_mut(),
+            new,
+            order,
+            strongest_failure_ordering(order),
+        ) {
+            Ok(_) => None,
+            Err(_) => Self::unpack(new),
+        }
+    }
+
+    /// Check to see if the AtomCow holds an owned value
+    ///
+    /// This only means that the contents was owned when it was measured
+    pub fn is_owned(&self, order: Ordering) -> bool {
+        self.inner.load(order) as usize & OWNED != 0
+    }
+
+    /// Get a `Token` identifying the value currently stored
+    pub fn token(&self, order: Ordering) -> Token {
+        Token(self.inner.load(order) as usize)
+    }
+
+    /// Check to see if the AtomCow is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.inner.load(order).is_null()
+    }
+
+    /// Consume the AtomCow, returning its value
+    pub fn into_inner(mut self) -> Option<Payload<T>> {
+        Self::unpack(mem::replace(self.inner.get_mut(), ptr::null_mut()))
+    }
+}
+
+impl<T> Drop for AtomCow<T> {
+    fn drop(&mut self) {
+        drop(Self::unpack(*self.inner.get_mut()));
+    }
+}
+
+impl<T> Default for AtomCow<T> {
+    fn default() -> AtomCow<T> {
+        AtomCow::empty()
+    }
+}
+
+impl<T> From<&'static T> for AtomCow<T> {
+    fn from(value: &'static T) -> AtomCow<T> {
+        AtomCow::from_static(value)
+    }
+}
+
+impl<T> Debug for AtomCow<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let ptr = self.inner.load(Ordering::Relaxed);
+        let kind = if ptr as usize & OWNED == 0 {
+            "Static"
+        } else {
+            "Owned"
+        };
+        write!(
+            f,
+            "AtomCow({}({:?}))",
+            kind,
+            ptr.cast::<u8>().wrapping_sub(ptr as usize & OWNED)
+        )
+    }
+}
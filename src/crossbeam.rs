@@ -0,0 +1,92 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Interoperability with `crossbeam-epoch`.
+//!
+//! An `Atom<Box<T>>` stores the same pointer a crossbeam `Atomic<T>` would,
+//! so it can be read under a crossbeam `Guard` as long as every value taken
+//! out of it is retired through that guard. `Guard` implements `DropPolicy`
+//! for exactly that purpose.
+//!
+//! ```
+//! extern crate atom;
+//! extern crate crossbeam_epoch as epoch;
+//!
+//! use std::sync::atomic::Ordering;
+//! use atom::Atom;
+//!
+//! fn main() {
+//!     let atom = Atom::new(Box::new(1));
+//!     let guard = epoch::pin();
+//!     let current = atom.load(Ordering::Acquire, &guard);
+//!     atom.store_with(Box::new(2), Ordering::AcqRel, &guard);
+//!     assert_eq!(unsafe { current.deref() }, &1);
+//! }
+//! ```
+
+use std::sync::atomic::Ordering;
+
+use crossbeam_epoch::{Atomic, Guard, Owned, Shared};
+
+use policy::DropPolicy;
+use Atom;
+
+impl<T> Atom<Box<T>> {
+    /// Load the current pointer as a crossbeam `Shared`.
+    ///
+    /// Dereferencing the result is only sound if every value removed from
+    /// this Atom is retired through a crossbeam guard, for example with
+    /// `store_with(v, order, &guard)`.
+    pub fn load<'g>(&self, order: Ordering, _guard: &'g Guard) -> Shared<'g, T> {
+        Shared::from(self.inner.load(order) as *const T)
+    }
+
+    /// Take over the value held by a crossbeam `Atomic`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer in `atomic` must be null or valid, and nobody else may
+    /// hold a reference to the value, as with `Atomic::into_owned`.
+    pub unsafe fn from_atomic(atomic: Atomic<T>) -> Atom<Box<T>> {
+        match atomic.try_into_owned() {
+            Some(owned) => Atom::new(owned.into_box()),
+            None => Atom::empty(),
+        }
+    }
+}
+
+impl<T> From<Owned<T>> for Atom<Box<T>> {
+    fn from(owned: Owned<T>) -> Atom<Box<T>> {
+        Atom::new(owned.into_box())
+    }
+}
+
+impl<T> From<Atom<Box<T>>> for Atomic<T> {
+    fn from(atom: Atom<Box<T>>) -> Atomic<T> {
+        match atom.take(Ordering::Acquire) {
+            Some(value) => Atomic::from(Owned::from(value)),
+            None => Atomic::null(),
+        }
+    }
+}
+
+/// Retire displaced values through crossbeam's epoch collector.
+impl<P> DropPolicy<P> for Guard
+where
+    P: Send + 'static,
+{
+    fn release(&self, value: P) {
+        self.defer(move || drop(value));
+    }
+}
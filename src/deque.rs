@@ -0,0 +1,320 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A Chase-Lev work-stealing deque.
+//!
+//! The owning `Worker` pushes and pops at the bottom, any number of
+//! `Stealer`s take from the top. Only the last element is contended: the
+//! owner and the thieves race for it with a CAS on `top`, everything else
+//! is plain loads and stores ordered by fences. The orderings follow Lê,
+//! Pop, Cohen and Zappa Nardelli, "Correct and Efficient Work-Stealing for
+//! Weak Memory Models".
+//!
+//! When the buffer is full the owner copies the elements into one twice the
+//! size. A thief may still be reading from the old buffer, so steals run
+//! pinned and the old buffer is freed through `epoch`.
+//!
+//! Building with `--cfg loom` swaps the index atomics for loom's, see
+//! `tests/loom.rs`.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+#[cfg(loom)]
+use loom::sync::atomic::{fence, AtomicIsize};
+#[cfg(not(loom))]
+use std::sync::atomic::{fence, AtomicIsize};
+
+use epoch;
+use {Atom, FromRawPtr};
+
+const MIN_CAPACITY: usize = 32;
+
+struct Buffer<T> {
+    // a power of two, so the index can be masked
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> Buffer<T> {
+        debug_assert!(capacity.is_power_of_two());
+        Buffer {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+        self.slots[index as usize & (self.slots.len() - 1)].get()
+    }
+
+    unsafe fn write(&self, index: isize, value: T) {
+        ptr::write(self.slot(index), MaybeUninit::new(value));
+    }
+
+    /// A bitwise copy of the element at `index`. It only becomes the
+    /// caller's to drop once it has won the element.
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        ptr::read(self.slot(index))
+    }
+}
+
+unsafe fn free_buffer<T>(addr: usize) {
+    drop(<Box<Buffer<T>> as FromRawPtr>::from_raw(addr as *mut ()));
+}
+
+struct Inner<T> {
+    // the next element to steal
+    top: AtomicIsize,
+    // one past the last element pushed
+    bottom: AtomicIsize,
+    buffer: Atom<Box<Buffer<T>>>,
+}
+
+impl<T> Inner<T> {
+    fn buffer(&self, order: Ordering) -> &Buffer<T> {
+        // Never empty. The owner is the only one to replace it, and anyone
+        // else reading it is pinned.
+        unsafe { &*(self.buffer.inner.load(order) as *const Buffer<T>) }
+    }
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = self.top.load(Ordering::Relaxed);
+        let bottom = self.bottom.load(Ordering::Relaxed);
+        let buffer = self.buffer(Ordering::Relaxed);
+        for index in top..bottom {
+            unsafe { buffer.read(index).assume_init_drop() };
+        }
+    }
+}
+
+/// The result of `Stealer::steal`
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    /// There was nothing to steal
+    Empty,
+    /// An element was stolen
+    Success(T),
+    /// Lost a race for the element, try again
+    Retry,
+}
+
+impl<T> Steal<T> {
+    /// The stolen element, if there was one
+    pub fn success(self) -> Option<T> {
+        match self {
+            Steal::Success(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// The owning end of a work-stealing deque.
+///
+/// ```
+/// use atom::deque::{Steal, Worker};
+///
+/// let mut worker = Worker::new();
+/// let stealer = worker.stealer();
+/// worker.push(1);
+/// worker.push(2);
+/// assert_eq!(stealer.steal(), Steal::Success(1));
+/// assert_eq!(worker.pop(), Some(2));
+/// assert_eq!(stealer.steal(), Steal::Empty);
+/// ```
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    // only one thread may push and pop
+    _marker: PhantomData<*mut ()>,
+}
+
+unsafe impl<T: Send> Send for Worker<T> {}
+
+impl<T> Worker<T> {
+    /// Create an empty deque
+    pub fn new() -> Worker<T> {
+        Worker::with_capacity(MIN_CAPACITY)
+    }
+
+    /// Create an empty deque with room for at least `capacity` elements
+    /// before it has to grow
+    pub fn with_capacity(capacity: usize) -> Worker<T> {
+        let capacity = capacity.max(1).next_power_of_two();
+        Worker {
+            inner: Arc::new(Inner {
+                top: AtomicIsize::new(0),
+                bottom: AtomicIsize::new(0),
+                buffer: Atom::new(Box::new(Buffer::new(capacity))),
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Create a handle other threads can steal from this deque with
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Push `value` onto the bottom of the deque
+    pub fn push(&mut self, value: T) {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Ordering::Relaxed);
+        let top = inner.top.load(Ordering::Acquire);
+        let mut buffer = inner.buffer(Ordering::Relaxed);
+        if bottom - top >= buffer.capacity() as isize {
+            self.grow(top, bottom);
+            buffer = inner.buffer(Ordering::Relaxed);
+        }
+        unsafe { buffer.write(bottom, value) };
+        fence(Ordering::Release);
+        inner.bottom.store(bottom + 1, Ordering::Relaxed);
+    }
+
+    fn grow(&self, top: isize, bottom: isize) {
+        let inner = &*self.inner;
+        let old = inner.buffer(Ordering::Relaxed);
+        let new = Buffer::new(old.capacity() * 2);
+        for index in top..bottom {
+            unsafe { ptr::copy_nonoverlapping(old.slot(index), new.slot(index), 1) };
+        }
+        let pin = epoch::pin();
+        let old = inner
+            .buffer
+            .inner
+            .swap(Atom::raw(Box::new(new)), Ordering::Release);
+        // Thieves that loaded the old buffer may still read from it, the
+        // elements themselves now belong to the new one.
+        let addr = old as usize;
+        let free: unsafe fn(usize) = free_buffer::<T>;
+        pin.defer(move || unsafe { free(addr) });
+    }
+
+    /// Pop the most recently pushed element off the bottom of the deque
+    pub fn pop(&mut self) -> Option<T> {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Ordering::Relaxed) - 1;
+        let buffer = inner.buffer(Ordering::Relaxed);
+        inner.bottom.store(bottom, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        let top = inner.top.load(Ordering::Relaxed);
+        if top > bottom {
+            inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+        let value = unsafe { buffer.read(bottom) };
+        if top == bottom {
+            // the last element, race the thieves for it
+            let won = inner
+                .top
+                .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        }
+        Some(unsafe { value.assume_init() })
+    }
+
+    /// The number of elements in the deque
+    pub fn len(&self) -> usize {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Relaxed);
+        (bottom - top).max(0) as usize
+    }
+
+    /// Check to see if the deque is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for Worker<T> {
+    fn default() -> Worker<T> {
+        Worker::new()
+    }
+}
+
+impl<T> Debug for Worker<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Worker({})", self.len())
+    }
+}
+
+/// A handle for stealing from a `Worker`'s deque
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Stealer<T> {
+    /// Steal the oldest element off the top of the deque
+    pub fn steal(&self) -> Steal<T> {
+        let inner = &*self.inner;
+        let _pin = epoch::pin();
+        let top = inner.top.load(Ordering::Acquire);
+        fence(Ordering::SeqCst);
+        let bottom = inner.bottom.load(Ordering::Acquire);
+        if top >= bottom {
+            return Steal::Empty;
+        }
+        let value = unsafe { inner.buffer(Ordering::Acquire).read(top) };
+        match inner
+            .top
+            .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+        {
+            Ok(_) => Steal::Success(unsafe { value.assume_init() }),
+            Err(_) => Steal::Retry,
+        }
+    }
+
+    /// Check to see if the deque is empty
+    ///
+    /// This only means that the deque was empty when it was measured
+    pub fn is_empty(&self) -> bool {
+        let top = self.inner.top.load(Ordering::Acquire);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+        top >= bottom
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Debug for Stealer<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Stealer({:p})", &*self.inner)
+    }
+}
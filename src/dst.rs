@@ -0,0 +1,227 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Atoms for pointers to dynamically sized values.
+//!
+//! A `Box<[T]>`, `Box<str>` or `Arc<dyn Trait>` is a pointer plus a length
+//! or vtable, two words that do not fit in an `AtomicPtr`. A `DstAtom`
+//! keeps both words in a pair that is replaced as one, the same way a
+//! `WideAtom` does, so no header allocation is needed.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Arc;
+
+use dword::AtomicPair;
+
+/// Convert a pointer to an unsized value into its two raw words
+///
+/// The words must never both be zero, since `DstAtom` uses that to mark
+/// itself as empty.
+pub trait IntoWidePtr {
+    fn into_wide(self) -> [usize; 2];
+}
+
+/// Convert the two raw words back into a pointer
+pub trait FromWidePtr {
+    /// # Safety
+    ///
+    /// `words` must have been produced by `IntoWidePtr::into_wide` on the
+    /// same type, and ownership of them must not have been reclaimed already.
+    unsafe fn from_wide(words: [usize; 2]) -> Self;
+}
+
+// Pointers to slices, `str` and trait objects are two words on every
+// platform Rust supports, and the check turns a pointer to a sized type into
+// a compile error. The order of the words does not matter, only that the
+// data half is never null.
+fn split<D: ?Sized>(ptr: *const D) -> [usize; 2] {
+    const {
+        assert!(
+            mem::size_of::<*const D>() == mem::size_of::<[usize; 2]>(),
+            "DstAtom needs a pointer to an unsized type, use an Atom instead"
+        )
+    };
+    unsafe { mem::transmute_copy(&ptr) }
+}
+
+fn join<D: ?Sized>(words: [usize; 2]) -> *const D {
+    const { assert!(mem::size_of::<*const D>() == mem::size_of::<[usize; 2]>()) };
+    unsafe { mem::transmute_copy(&words) }
+}
+
+impl<D: ?Sized> IntoWidePtr for Box<D> {
+    #[inline]
+    fn into_wide(self) -> [usize; 2] {
+        split(Box::into_raw(self))
+    }
+}
+
+impl<D: ?Sized> FromWidePtr for Box<D> {
+    #[inline]
+    unsafe fn from_wide(words: [usize; 2]) -> Box<D> {
+        Box::from_raw(join::<D>(words) as *mut D)
+    }
+}
+
+impl<D: ?Sized> IntoWidePtr for Arc<D> {
+    #[inline]
+    fn into_wide(self) -> [usize; 2] {
+        split(Arc::into_raw(self))
+    }
+}
+
+impl<D: ?Sized> FromWidePtr for Arc<D> {
+    #[inline]
+    unsafe fn from_wide(words: [usize; 2]) -> Arc<D> {
+        Arc::from_raw(join::<D>(words))
+    }
+}
+
+/// An `Atom` for `Box` or `Arc` pointers to slices, `str` and trait
+/// objects.
+///
+/// It has the `swap`, `take` and `set_if_none` surface of an `Atom`. Like
+/// a `WideAtom` the pair is updated with a double-width compare and swap
+/// where the processor has one, and under a striped spinlock elsewhere, so
+/// every operation is sequentially consistent and takes no ordering.
+///
+/// ```
+/// use std::sync::Arc;
+/// use atom::dst::DstAtom;
+///
+/// let config: DstAtom<Arc<str>> = DstAtom::new(Arc::from("v1"));
+/// assert_eq!(config.swap(Arc::from("v2")).as_deref(), Some("v1"));
+///
+/// let samples: DstAtom<Box<[u32]>> = DstAtom::empty();
+/// assert_eq!(samples.set_if_none(vec![1, 2].into_boxed_slice()), None);
+/// assert_eq!(samples.take().as_deref(), Some(&[1, 2][..]));
+/// ```
+pub struct DstAtom<P>
+where
+    P: IntoWidePtr + FromWidePtr,
+{
+    pair: AtomicPair,
+    data: PhantomData<UnsafeCell<P>>,
+}
+
+unsafe impl<P> Send for DstAtom<P> where P: IntoWidePtr + FromWidePtr + Send {}
+unsafe impl<P> Sync for DstAtom<P> where P: IntoWidePtr + FromWidePtr + Send {}
+
+impl<P> DstAtom<P>
+where
+    P: IntoWidePtr + FromWidePtr,
+{
+    /// Create an empty DstAtom
+    pub const fn empty() -> DstAtom<P> {
+        DstAtom {
+            pair: AtomicPair::new([0, 0]),
+            data: PhantomData,
+        }
+    }
+
+    /// Create a DstAtom holding `value`
+    pub fn new(value: P) -> DstAtom<P> {
+        DstAtom {
+            pair: AtomicPair::new(Self::raw(value)),
+            data: PhantomData,
+        }
+    }
+
+    fn raw(value: P) -> [usize; 2] {
+        let words = value.into_wide();
+        debug_assert!(words != [0, 0], "IntoWidePtr returned a null pointer");
+        words
+    }
+
+    /// # Safety
+    ///
+    /// `words` must have come from `raw` and be owned by the caller
+    unsafe fn from_raw(words: [usize; 2]) -> Option<P> {
+        if words == [0, 0] {
+            None
+        } else {
+            Some(FromWidePtr::from_wide(words))
+        }
+    }
+
+    /// Swap a new value into the DstAtom, returning the old one
+    pub fn swap(&self, value: P) -> Option<P> {
+        let old = self.pair.swap(Self::raw(value));
+        unsafe { Self::from_raw(old) }
+    }
+
+    /// Take the value out, leaving the DstAtom empty
+    pub fn take(&self) -> Option<P> {
+        let old = self.pair.swap([0, 0]);
+        unsafe { Self::from_raw(old) }
+    }
+
+    /// Store `value` if the DstAtom is empty. If it was not, `value` is
+    /// returned.
+    pub fn set_if_none(&self, value: P) -> Option<P> {
+        let new = Self::raw(value);
+        match self.pair.compare_exchange([0, 0], new) {
+            Ok(_) => None,
+            Err(_) => Some(unsafe { FromWidePtr::from_wide(new) }),
+        }
+    }
+
+    /// Check to see if the DstAtom is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self) -> bool {
+        self.pair.load() == [0, 0]
+    }
+
+    /// Consume the DstAtom, returning its value
+    pub fn into_inner(mut self) -> Option<P> {
+        let words = mem::take(self.pair.get_mut());
+        unsafe { Self::from_raw(words) }
+    }
+}
+
+impl<P> Drop for DstAtom<P>
+where
+    P: IntoWidePtr + FromWidePtr,
+{
+    fn drop(&mut self) {
+        drop(unsafe { Self::from_raw(*self.pair.get_mut()) });
+    }
+}
+
+impl<P> Default for DstAtom<P>
+where
+    P: IntoWidePtr + FromWidePtr,
+{
+    fn default() -> DstAtom<P> {
+        DstAtom::empty()
+    }
+}
+
+impl<P> Debug for DstAtom<P>
+where
+    P: IntoWidePtr + FromWidePtr,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let words = self.pair.load();
+        write!(
+            f,
+            "DstAtom({:?}, {:?})",
+            words[0] as *const (), words[1] as *const ()
+        )
+    }
+}
@@ -0,0 +1,168 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A pair of words that is read and written as one.
+//!
+//! On x86_64 processors that have it this is `lock cmpxchg16b`, which every
+//! operation goes through, loads included. Everywhere else, and on the rare
+//! x86_64 without it, each pair is guarded by one of a fixed set of
+//! spinlocks picked by its address.
+
+use std::cell::UnsafeCell;
+use std::hint;
+#[cfg(target_arch = "x86_64")]
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use CachePadded;
+
+/// Two words that are only ever accessed together
+#[repr(C, align(16))]
+pub struct AtomicPair {
+    words: UnsafeCell<[usize; 2]>,
+}
+
+// every access goes through `cas` or `with_lock`
+unsafe impl Send for AtomicPair {}
+unsafe impl Sync for AtomicPair {}
+
+impl AtomicPair {
+    pub const fn new(words: [usize; 2]) -> AtomicPair {
+        AtomicPair {
+            words: UnsafeCell::new(words),
+        }
+    }
+
+    /// Read both words
+    pub fn load(&self) -> [usize; 2] {
+        // a failed exchange of zeroes still reports the current value
+        match self.compare_exchange([0, 0], [0, 0]) {
+            Ok(words) | Err(words) => words,
+        }
+    }
+
+    /// Replace both words if they equal `current`. Returns what they held,
+    /// as `Ok` if they were replaced.
+    pub fn compare_exchange(
+        &self,
+        current: [usize; 2],
+        new: [usize; 2],
+    ) -> Result<[usize; 2], [usize; 2]> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if has_cmpxchg16b() {
+                return unsafe { cmpxchg16b(self.words.get(), current, new) };
+            }
+        }
+        self.with_lock(|words| {
+            if *words == current {
+                *words = new;
+                Ok(current)
+            } else {
+                Err(*words)
+            }
+        })
+    }
+
+    /// Replace both words, returning what they held
+    pub fn swap(&self, new: [usize; 2]) -> [usize; 2] {
+        let mut current = self.load();
+        loop {
+            match self.compare_exchange(current, new) {
+                Ok(old) => return old,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Get the words through exclusive access
+    pub fn get_mut(&mut self) -> &mut [usize; 2] {
+        unsafe { &mut *self.words.get() }
+    }
+
+    fn with_lock<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut [usize; 2]) -> R,
+    {
+        let lock = &LOCKS[(self as *const AtomicPair as usize >> 4) % LOCKS.len()];
+        let mut spins = 0;
+        while lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spins += 1;
+            if spins < 64 {
+                hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+        let out = f(unsafe { &mut *self.words.get() });
+        lock.store(false, Ordering::Release);
+        out
+    }
+}
+
+static LOCKS: [CachePadded<AtomicBool>; 64] = [const { CachePadded(AtomicBool::new(false)) }; 64];
+
+/// Unknown until the first check, then 1 without and 2 with the instruction
+#[cfg(target_arch = "x86_64")]
+static CMPXCHG16B: AtomicU8 = AtomicU8::new(0);
+
+#[cfg(target_arch = "x86_64")]
+fn has_cmpxchg16b() -> bool {
+    if cfg!(target_feature = "cmpxchg16b") {
+        return true;
+    }
+    match CMPXCHG16B.load(Ordering::Relaxed) {
+        0 => {
+            let found = is_x86_feature_detected!("cmpxchg16b");
+            CMPXCHG16B.store(if found { 2 } else { 1 }, Ordering::Relaxed);
+            found
+        }
+        known => known == 2,
+    }
+}
+
+/// Compare and exchange 16 aligned bytes, sequentially consistent
+#[cfg(target_arch = "x86_64")]
+unsafe fn cmpxchg16b(
+    dst: *mut [usize; 2],
+    current: [usize; 2],
+    new: [usize; 2],
+) -> Result<[usize; 2], [usize; 2]> {
+    let (lo, hi): (usize, usize);
+    let swapped: u8;
+    // rbx is reserved by LLVM, so the low half of `new` is swapped into it
+    // for the duration of the instruction
+    std::arch::asm!(
+        "xchg {new_lo}, rbx",
+        "lock cmpxchg16b xmmword ptr [{dst}]",
+        "sete {swapped}",
+        "mov rbx, {new_lo}",
+        dst = in(reg) dst,
+        new_lo = inout(reg) new[0] => _,
+        swapped = out(reg_byte) swapped,
+        inout("rax") current[0] => lo,
+        inout("rdx") current[1] => hi,
+        in("rcx") new[1],
+        options(nostack),
+    );
+    if swapped != 0 {
+        Ok([lo, hi])
+    } else {
+        Err([lo, hi])
+    }
+}
@@ -0,0 +1,249 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An atom that holds a box of one of two types.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use {strongest_failure_ordering, Token};
+
+/// Set in the pointer of a right value
+const RIGHT: usize = 1;
+
+/// What an `AtomEither` holds
+#[derive(Debug, PartialEq, Eq)]
+pub enum Either<A, B> {
+    /// A value of the first type
+    Left(Box<A>),
+    /// A value of the second type
+    Right(Box<B>),
+}
+
+impl<A, B> Either<A, B> {
+    /// The value, if it is a left one
+    pub fn left(self) -> Option<Box<A>> {
+        match self {
+            Either::Left(value) => Some(value),
+            Either::Right(_) => None,
+        }
+    }
+
+    /// The value, if it is a right one
+    pub fn right(self) -> Option<Box<B>> {
+        match self {
+            Either::Left(_) => None,
+            Either::Right(value) => Some(value),
+        }
+    }
+}
+
+/// An `Atom` for a slot that holds either a `Box<A>` or a `Box<B>`.
+///
+/// Which one it is is kept in the low bit of the pointer, so moving between
+/// the two is a single swap and neither side pays for an enum around it.
+/// That bit has to be free, which makes either type being aligned to a
+/// single byte a compile time error.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::either::{AtomEither, Either};
+///
+/// struct Request(u32);
+/// struct Response(u64);
+///
+/// let slot = AtomEither::<Request, Response>::new(Either::Left(Box::new(Request(7))));
+/// let request = slot.swap_right(Box::new(Response(49)), Ordering::AcqRel);
+/// assert_eq!(request.and_then(Either::left).unwrap().0, 7);
+/// assert!(slot.take_left(Ordering::Acquire).is_none());
+/// assert_eq!(slot.take_right(Ordering::Acquire).unwrap().0, 49);
+/// ```
+pub struct AtomEither<A, B> {
+    inner: AtomicPtr<()>,
+    data: PhantomData<Either<A, B>>,
+}
+
+unsafe impl<A: Send, B: Send> Send for AtomEither<A, B> {}
+unsafe impl<A: Send, B: Send> Sync for AtomEither<A, B> {}
+
+impl<A, B> AtomEither<A, B> {
+    const ALIGNED: () = assert!(
+        mem::align_of::<A>() > RIGHT && mem::align_of::<B>() > RIGHT,
+        "the payloads are not aligned enough to hold the tag bit"
+    );
+
+    /// Create an empty AtomEither
+    pub const fn empty() -> AtomEither<A, B> {
+        AtomEither {
+            inner: AtomicPtr::new(ptr::null_mut()),
+            data: PhantomData,
+        }
+    }
+
+    /// Create an AtomEither holding `value`
+    pub fn new(value: Either<A, B>) -> AtomEither<A, B> {
+        AtomEither {
+            inner: AtomicPtr::new(Self::pack(Some(value))),
+            data: PhantomData,
+        }
+    }
+
+    fn pack(value: Option<Either<A, B>>) -> *mut () {
+        let () = Self::ALIGNED;
+        match value {
+            None => ptr::null_mut(),
+            Some(Either::Left(value)) => Box::into_raw(value).cast(),
+            Some(Either::Right(value)) => {
+                Box::into_raw(value).cast::<u8>().wrapping_add(RIGHT).cast()
+            }
+        }
+    }
+
+    fn unpack(ptr: *mut ()) -> Option<Either<A, B>> {
+        // the pointer came out of `pack` and is owned by the caller
+        if ptr.is_null() {
+            None
+        } else if ptr as usize & RIGHT == 0 {
+            Some(Either::Left(unsafe { Box::from_raw(ptr.cast()) }))
+        } else {
+            let ptr = ptr.cast::<u8>().wrapping_sub(RIGHT).cast();
+            Some(Either::Right(unsafe { Box::from_raw(ptr) }))
+        }
+    }
+
+    /// Swap a new value into the AtomEither, returning the old value
+    pub fn swap(&self, v: Either<A, B>, order: Ordering) -> Option<Either<A, B>> {
+        Self::unpack(self.inner.swap(Self::pack(Some(v)), order))
+    }
+
+    /// Swap a left value in, returning the old value
+    pub fn swap_left(&self, v: Box<A>, order: Ordering) -> Option<Either<A, B>> {
+        self.swap(Either::Left(v), order)
+    }
+
+    /// Swap a right value in, returning the old value
+    pub fn swap_right(&self, v: Box<B>, order: Ordering) -> Option<Either<A, B>> {
+        self.swap(Either::Right(v), order)
+    }
+
+    /// Take the value out of the AtomEither, whichever it is
+    pub fn take_either(&self, order: Ordering) -> Option<Either<A, B>> {
+        Self::unpack(self.inner.swap(ptr::null_mut(), order))
+    }
+
+    /// Take the value out only if it is a left one
+    pub fn take_left(&self, order: Ordering) -> Option<Box<A>> {
+        self.take_if(0, order).and_then(Either::left)
+    }
+
+    /// Take the value out only if it is a right one
+    pub fn take_right(&self, order: Ordering) -> Option<Box<B>> {
+        self.take_if(RIGHT, order).and_then(Either::right)
+    }
+
+    fn take_if(&self, tag: usize, order: Ordering) -> Option<Either<A, B>> {
+        let failure = strongest_failure_ordering(order);
+        let mut current = self.inner.load(failure);
+        loop {
+            if current.is_null() || current as usize & RIGHT != tag {
+                return None;
+            }
+            match self
+                .inner
+                .compare_exchange_weak(current, ptr::null_mut(), order, failure)
+            {
+                Ok(_) => return Self::unpack(current),
+                Err(found) => current = found,
+            }
+        }
+    }
+
+    /// Store `v` if the AtomEither is empty. If it was not, `v` is returned.
+    pub fn set_if_none(&self, v: Either<A, B>, order: Ordering) -> Option<Either<A, B>> {
+        let new = Self::pack(Some(v));
+        match self.inner.compare_exchange(
+            ptr::null_mut(),
+            new,
+            order,
+            strongest_failure_ordering(order),
+        ) {
+            Ok(_) => None,
+            Err(_) => Self::unpack(new),
+        }
+    }
+
+    /// Check to see if the AtomEither holds a left value
+    ///
+    /// This only means that the contents was left when it was measured
+    pub fn is_left(&self, order: Ordering) -> bool {
+        let ptr = self.inner.load(order);
+        !ptr.is_null() && ptr as usize & RIGHT == 0
+    }
+
+    /// Check to see if the AtomEither holds a right value
+    ///
+    /// This only means that the contents was right when it was measured
+    pub fn is_right(&self, order: Ordering) -> bool {
+        self.inner.load(order) as usize & RIGHT != 0
+    }
+
+    /// Get a `Token` identifying the value currently stored
+    pub fn token(&self, order: Ordering) -> Token {
+        Token(self.inner.load(order) as usize)
+    }
+
+    /// Check to see if the AtomEither is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.inner.load(order).is_null()
+    }
+
+    /// Consume the AtomEither, returning its value
+    pub fn into_inner(mut self) -> Option<Either<A, B>> {
+        Self::unpack(mem::replace(self.inner.get_mut(), ptr::null_mut()))
+    }
+}
+
+impl<A, B> Drop for AtomEither<A, B> {
+    fn drop(&mut self) {
+        drop(Self::unpack(*self.inner.get_mut()));
+    }
+}
+
+impl<A, B> Default for AtomEither<A, B> {
+    fn default() -> AtomEither<A, B> {
+        AtomEither::empty()
+    }
+}
+
+impl<A, B> Debug for AtomEither<A, B> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let ptr = self.inner.load(Ordering::Relaxed);
+        let side = if ptr as usize & RIGHT == 0 {
+            "Left"
+        } else {
+            "Right"
+        };
+        write!(
+            f,
+            "AtomEither({}({:?}))",
+            side,
+            ptr.cast::<u8>().wrapping_sub(ptr as usize & RIGHT)
+        )
+    }
+}
@@ -0,0 +1,236 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Epoch-based reclamation.
+//!
+//! Threads `pin` themselves before reading pointers that a writer might
+//! unlink. A writer that unlinks a value hands its destructor to `defer`
+//! instead of running it, and the destructor only runs once every thread
+//! that was pinned at the time has unpinned.
+//!
+//! This works with a global epoch counter. Pinning records the current
+//! epoch, and the epoch can only advance once every pinned thread has seen
+//! it. Garbage retired in epoch `e` is therefore unreachable by the time
+//! the epoch reaches `e + 2`.
+//!
+//! ```
+//! use std::sync::atomic::Ordering;
+//! use atom::epoch;
+//! use atom::Atom;
+//!
+//! let atom = Atom::new(Box::new(1));
+//! let guard = epoch::pin();
+//! if let Some(old) = atom.swap(Box::new(2), Ordering::AcqRel) {
+//!     guard.defer(move || drop(old));
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+
+use chain::Chain;
+use {Atom, AtomSetOnce, GetNextMut};
+
+/// How many values are retired between attempts to collect garbage.
+const COLLECT_EVERY: usize = 64;
+
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+static PARTICIPANTS: AtomSetOnce<&'static Participant> = AtomSetOnce::empty();
+static GARBAGE: Atom<Box<Garbage>> = Atom::empty();
+static RETIRED: AtomicUsize = AtomicUsize::new(0);
+
+/// Per-thread pin state. Participants are leaked and reused by later
+/// threads once their owner exits, so walking the list never races with a
+/// free.
+struct Participant {
+    // `(epoch << 1) | 1` while pinned, 0 otherwise
+    epoch: AtomicUsize,
+    pins: AtomicUsize,
+    claimed: AtomicBool,
+    next: AtomSetOnce<&'static Participant>,
+}
+
+struct Garbage {
+    next: Option<Box<Garbage>>,
+    epoch: usize,
+    free: Box<dyn FnOnce() + Send>,
+}
+
+impl GetNextMut for Box<Garbage> {
+    type NextPtr = Option<Box<Garbage>>;
+    fn get_next(&mut self) -> &mut Option<Box<Garbage>> {
+        &mut self.next
+    }
+}
+
+fn participants() -> ::chain::SetOnceIter<'static, Participant, &'static Participant> {
+    PARTICIPANTS.iter(Ordering::Acquire, |p| &p.next)
+}
+
+fn register() -> &'static Participant {
+    for p in participants() {
+        if !p.claimed.load(Ordering::Relaxed)
+            && p.claimed
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            return p;
+        }
+    }
+
+    let new: &'static Participant = Box::leak(Box::new(Participant {
+        epoch: AtomicUsize::new(0),
+        pins: AtomicUsize::new(0),
+        claimed: AtomicBool::new(true),
+        next: AtomSetOnce::empty(),
+    }));
+    let mut tail = &PARTICIPANTS;
+    loop {
+        match tail.try_insert(new) {
+            Ok(_) => return new,
+            Err((last, _)) => tail = &last.next,
+        }
+    }
+}
+
+struct Local(&'static Participant);
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        // A guard that outlives the thread-local keeps the participant, it
+        // is simply never handed out again.
+        if self.0.pins.load(Ordering::Relaxed) == 0 {
+            self.0.claimed.store(false, Ordering::Release);
+        }
+    }
+}
+
+thread_local! {
+    static LOCAL: Local = Local(register());
+}
+
+/// Pin the current thread, keeping every value retired from now on alive
+/// until the returned `Guard` is dropped.
+///
+/// Pins nest, only dropping the outermost `Guard` unpins the thread.
+pub fn pin() -> Guard {
+    let (participant, release) = match LOCAL.try_with(|l| l.0) {
+        Ok(p) => (p, false),
+        // the thread-local is gone during thread teardown
+        Err(_) => (register(), true),
+    };
+    if participant.pins.fetch_add(1, Ordering::Relaxed) == 0 {
+        let epoch = EPOCH.load(Ordering::Relaxed);
+        participant.epoch.store((epoch << 1) | 1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+    }
+    Guard {
+        participant,
+        release,
+        not_send: PhantomData,
+    }
+}
+
+/// Advance the epoch if every pinned thread has caught up with it.
+fn try_advance() -> usize {
+    let epoch = EPOCH.load(Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+    for p in participants() {
+        let local = p.epoch.load(Ordering::Relaxed);
+        if local & 1 == 1 && local >> 1 != epoch {
+            return epoch;
+        }
+    }
+    fence(Ordering::Acquire);
+    match EPOCH.compare_exchange(epoch, epoch + 1, Ordering::Release, Ordering::Relaxed) {
+        Ok(_) => epoch + 1,
+        Err(current) => current,
+    }
+}
+
+/// Run the destructors of every retired value that is no longer
+/// reachable, returning how many ran.
+///
+/// This happens on its own as values are retired, calling it is only
+/// needed to flush garbage eagerly.
+pub fn collect() -> usize {
+    let epoch = try_advance();
+    let mut keep = Chain::default();
+    let mut freed = 0;
+    for node in GARBAGE.drain(Ordering::Acquire) {
+        if node.epoch + 2 <= epoch {
+            let node = *node;
+            (node.free)();
+            freed += 1;
+        } else {
+            keep.push(node);
+        }
+    }
+    GARBAGE.replace_and_set_next_chain(keep, Ordering::Relaxed, Ordering::AcqRel);
+    freed
+}
+
+/// Proof that the current thread is pinned.
+///
+/// Pointers loaded while a `Guard` is alive stay valid until it is dropped,
+/// as long as writers retire them through `defer`.
+pub struct Guard {
+    participant: &'static Participant,
+    release: bool,
+    // the pin belongs to this thread
+    not_send: PhantomData<*const ()>,
+}
+
+impl Guard {
+    /// Run `f` once no thread can still hold a reference to anything that
+    /// was unlinked before this call.
+    pub fn defer<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        fence(Ordering::SeqCst);
+        let node = Box::new(Garbage {
+            next: None,
+            epoch: EPOCH.load(Ordering::Relaxed),
+            free: Box::new(f),
+        });
+        GARBAGE.replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+        if RETIRED.fetch_add(1, Ordering::Relaxed) % COLLECT_EVERY == COLLECT_EVERY - 1 {
+            collect();
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let p = self.participant;
+        if p.pins.fetch_sub(1, Ordering::Relaxed) == 1 {
+            p.epoch.store(0, Ordering::Release);
+            if self.release {
+                p.claimed.store(false, Ordering::Release);
+            }
+        }
+    }
+}
+
+impl Debug for Guard {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Guard(epoch={})",
+            self.participant.epoch.load(Ordering::Relaxed) >> 1
+        )
+    }
+}
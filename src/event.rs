@@ -0,0 +1,92 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A one-time broadcast signal.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use AtomSetOnce;
+
+/// What the flag is set to, only whether it is set matters
+static SET: () = ();
+
+/// A flag that starts out clear, is set once and then stays set.
+///
+/// Setting it wakes every thread blocked in `wait`, and every later `wait`
+/// returns straight away. It is an `AtomSetOnce` underneath, whose waiters
+/// are an intrusive chain of parked threads, so there is no mutex or
+/// condition variable to keep in step with the flag.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use atom::event::Event;
+///
+/// let shutdown = Arc::new(Event::new());
+/// let worker = {
+///     let shutdown = shutdown.clone();
+///     thread::spawn(move || shutdown.wait())
+/// };
+/// assert!(shutdown.set());
+/// worker.join().unwrap();
+/// assert!(!shutdown.set());
+/// ```
+pub struct Event {
+    flag: AtomSetOnce<&'static ()>,
+}
+
+impl Event {
+    /// Create a clear event
+    pub const fn new() -> Event {
+        Event {
+            flag: AtomSetOnce::empty(),
+        }
+    }
+
+    /// Set the event, waking everyone waiting on it. Returns false if it
+    /// was already set.
+    pub fn set(&self) -> bool {
+        self.flag.set_if_none(&SET, Ordering::Release).is_none()
+    }
+
+    /// Check to see if the event has been set
+    pub fn is_set(&self) -> bool {
+        !self.flag.is_none(Ordering::Acquire)
+    }
+
+    /// Block the current thread until the event is set
+    pub fn wait(&self) {
+        self.flag.wait();
+    }
+
+    /// Block the current thread until the event is set or `timeout` has
+    /// passed. Returns whether the event is set.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.flag.wait_timeout(timeout).is_some()
+    }
+}
+
+impl Default for Event {
+    fn default() -> Event {
+        Event::new()
+    }
+}
+
+impl Debug for Event {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Event({})", if self.is_set() { "set" } else { "clear" })
+    }
+}
@@ -0,0 +1,164 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+use std::fmt::{self, Debug, Formatter};
+use std::hint;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::thread;
+
+/// A generation counter shared by several atoms that are updated together.
+///
+/// Writers make their changes inside `write`, readers read inside `read`.
+/// A read that overlapped a write is thrown away and run again, so the
+/// values a read returns all belong to the same generation. The members
+/// are ordinary atoms owned by the caller, which has to read them with
+/// operations that are safe on their own, such as `ArcCell::load`, and
+/// only change them inside `write`.
+///
+/// Writes are serialized and never wait for readers. Reading from within
+/// a `write` on the same group never finishes.
+///
+/// ```
+/// use std::sync::Arc;
+/// use atom::arc_cell::ArcCell;
+/// use atom::AtomGroup;
+///
+/// let group = AtomGroup::new();
+/// let topology = ArcCell::new(Arc::new(vec!["a", "b"]));
+/// let routes = ArcCell::new(Arc::new(vec![("a", "b")]));
+///
+/// group.write(|| {
+///     topology.store(Arc::new(vec!["a", "b", "c"]));
+///     routes.store(Arc::new(vec![("a", "b"), ("b", "c")]));
+/// });
+///
+/// let (topology, routes) = group.read(|| (topology.load(), routes.load()));
+/// assert_eq!(topology.len(), routes.len() + 1);
+/// ```
+pub struct AtomGroup {
+    generation: AtomicUsize,
+}
+
+impl AtomGroup {
+    /// Create a group at generation 0
+    pub const fn new() -> AtomGroup {
+        AtomGroup {
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Run `f` as one step for readers, returning its result
+    ///
+    /// The generation moves on even if `f` panics, with whatever `f`
+    /// managed to change before that.
+    pub fn write<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let _writing = Writing::lock(&self.generation);
+        f()
+    }
+
+    /// Run `f` until it does not overlap a `write`, returning the result of
+    /// the run that did not
+    pub fn read<F, R>(&self, mut f: F) -> R
+    where
+        F: FnMut() -> R,
+    {
+        let mut spins = 0;
+        loop {
+            if let Some(value) = self.try_read(&mut f) {
+                return value;
+            }
+            backoff(&mut spins);
+        }
+    }
+
+    /// Run `f` once, returning `None` if it overlapped a `write`
+    pub fn try_read<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce() -> R,
+    {
+        let before = self.generation.load(Ordering::Acquire);
+        if before & 1 != 0 {
+            return None;
+        }
+        let value = f();
+        // keep the reads in `f` from being reordered after the second load
+        fence(Ordering::Acquire);
+        if self.generation.load(Ordering::Relaxed) == before {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Get the number of writes that have finished
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire) >> 1
+    }
+}
+
+/// Holds the generation odd while a write runs
+struct Writing<'a> {
+    generation: &'a AtomicUsize,
+    before: usize,
+}
+
+impl<'a> Writing<'a> {
+    fn lock(generation: &'a AtomicUsize) -> Writing<'a> {
+        let mut spins = 0;
+        loop {
+            let before = generation.load(Ordering::Relaxed);
+            if before & 1 == 0
+                && generation
+                    .compare_exchange_weak(before, before | 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                // readers that see a change must see the odd generation
+                fence(Ordering::Release);
+                return Writing { generation, before };
+            }
+            backoff(&mut spins);
+        }
+    }
+}
+
+impl<'a> Drop for Writing<'a> {
+    fn drop(&mut self) {
+        self.generation
+            .store(self.before.wrapping_add(2), Ordering::Release);
+    }
+}
+
+fn backoff(spins: &mut u32) {
+    if *spins < 64 {
+        *spins += 1;
+        hint::spin_loop();
+    } else {
+        thread::yield_now();
+    }
+}
+
+impl Default for AtomGroup {
+    fn default() -> AtomGroup {
+        AtomGroup::new()
+    }
+}
+
+impl Debug for AtomGroup {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "AtomGroup(generation={})", self.generation())
+    }
+}
@@ -0,0 +1,236 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use {Atom, AtomStorable, Token};
+
+/// A read-only view of a shared `Atom`.
+///
+/// A reader can observe whether the Atom is set and how its contents
+/// change, but it can never store or remove a value. That rules out
+/// `Atom::load_cloned` too, which briefly takes the value out of the Atom.
+pub struct AtomReader<P>
+where
+    P: AtomStorable,
+{
+    atom: Arc<Atom<P>>,
+}
+
+impl<P> AtomReader<P>
+where
+    P: AtomStorable,
+{
+    /// Create a reader for a shared Atom
+    pub fn new(atom: &Arc<Atom<P>>) -> AtomReader<P> {
+        AtomReader { atom: atom.clone() }
+    }
+
+    /// Check to see if the atom is None
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.atom.is_none(order)
+    }
+
+    /// Get a `Token` identifying the value currently stored in the Atom
+    pub fn token(&self, order: Ordering) -> Token {
+        self.atom.token(order)
+    }
+}
+
+impl<P> Clone for AtomReader<P>
+where
+    P: AtomStorable,
+{
+    fn clone(&self) -> AtomReader<P> {
+        AtomReader {
+            atom: self.atom.clone(),
+        }
+    }
+}
+
+impl<P> Debug for AtomReader<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "reader({:?})", self.atom)
+    }
+}
+
+/// A write-only view of a shared `Atom`.
+///
+/// A writer can publish values into the Atom. Any value it displaces is
+/// handed back to the writer, but it can never look at or remove the
+/// current contents on its own.
+pub struct AtomWriter<P>
+where
+    P: AtomStorable,
+{
+    atom: Arc<Atom<P>>,
+}
+
+impl<P> AtomWriter<P>
+where
+    P: AtomStorable,
+{
+    /// Create a writer for a shared Atom
+    pub fn new(atom: &Arc<Atom<P>>) -> AtomWriter<P> {
+        AtomWriter { atom: atom.clone() }
+    }
+
+    /// Swap a new value into the Atom, returning the old value.
+    pub fn swap(&self, v: P, order: Ordering) -> Option<P> {
+        self.atom.swap(v, order)
+    }
+
+    /// Set the value only if the Atom is empty, otherwise `v` is returned.
+    pub fn set_if_none(&self, v: P, order: Ordering) -> Option<P> {
+        self.atom.set_if_none(v, order)
+    }
+}
+
+impl<P> Clone for AtomWriter<P>
+where
+    P: AtomStorable,
+{
+    fn clone(&self) -> AtomWriter<P> {
+        AtomWriter {
+            atom: self.atom.clone(),
+        }
+    }
+}
+
+impl<P> Debug for AtomWriter<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "writer({:?})", self.atom)
+    }
+}
+
+impl<P> Atom<P>
+where
+    P: AtomStorable,
+{
+    /// Split a shared Atom into the two ends of a single-producer,
+    /// single-consumer hand-off slot.
+    ///
+    /// Neither end can be cloned and both need `&mut self`, so exactly one
+    /// thread can set and exactly one can take. The roles are only exclusive
+    /// if `self` was the last `Arc` pointing at the Atom.
+    ///
+    /// ```
+    /// use std::sync::atomic::Ordering;
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use atom::Atom;
+    ///
+    /// let (mut setter, mut taker) = Arc::new(Atom::<Box<u32>>::empty()).split_slot();
+    /// let consumer = thread::spawn(move || *taker.take_blocking());
+    /// setter.swap(Box::new(5), Ordering::Release);
+    /// assert_eq!(consumer.join().unwrap(), 5);
+    /// ```
+    pub fn split_slot(self: Arc<Self>) -> (Setter<P>, Taker<P>) {
+        (Setter { atom: self.clone() }, Taker { atom: self })
+    }
+}
+
+/// The producing end of `Atom::split_slot`.
+///
+/// Every value it stores wakes the `Taker` if it is blocked.
+pub struct Setter<P>
+where
+    P: AtomStorable,
+{
+    atom: Arc<Atom<P>>,
+}
+
+impl<P> Setter<P>
+where
+    P: AtomStorable,
+{
+    /// Swap a new value into the slot, returning the one the taker had not
+    /// taken yet.
+    pub fn swap(&mut self, v: P, order: Ordering) -> Option<P> {
+        self.atom.set_and_notify(v, order)
+    }
+
+    /// Store a new value into the slot, dropping the one the taker had not
+    /// taken yet.
+    pub fn set(&mut self, v: P) {
+        drop(self.swap(v, Ordering::AcqRel));
+    }
+
+    /// Check to see if the taker has taken the last value
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.atom.is_none(order)
+    }
+}
+
+impl<P> Debug for Setter<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "setter({:?})", self.atom)
+    }
+}
+
+/// The consuming end of `Atom::split_slot`.
+pub struct Taker<P>
+where
+    P: AtomStorable,
+{
+    atom: Arc<Atom<P>>,
+}
+
+impl<P> Taker<P>
+where
+    P: AtomStorable,
+{
+    /// Take the value out of the slot if there is one
+    pub fn take(&mut self, order: Ordering) -> Option<P> {
+        self.atom.take(order)
+    }
+
+    /// Block the current thread until the setter stores a value and take it
+    pub fn take_blocking(&mut self) -> P {
+        self.atom.take_blocking()
+    }
+
+    /// Like `take_blocking` but gives up once `timeout` has passed
+    pub fn take_blocking_timeout(&mut self, timeout: Duration) -> Option<P> {
+        self.atom.take_blocking_timeout(timeout)
+    }
+
+    /// Check to see if the slot is empty
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.atom.is_none(order)
+    }
+}
+
+impl<P> Debug for Taker<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "taker({:?})", self.atom)
+    }
+}
@@ -0,0 +1,257 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Hazard pointers.
+//!
+//! A reader publishes the pointer it is about to dereference in a hazard
+//! slot, then checks that the Atom still holds it. A writer that removes a
+//! value hands it to `Domain::retire`, which only frees values that no slot
+//! points at. Unlike `epoch`, a stalled reader only keeps the one value it
+//! protects alive.
+//!
+//! ```
+//! use std::sync::atomic::Ordering;
+//! use atom::hazard::Domain;
+//! use atom::Atom;
+//!
+//! let domain = Domain::new();
+//! let atom = Atom::new(Box::new(1));
+//!
+//! let mut guard = domain.guard();
+//! let value = unsafe { atom.load_protected(&mut guard) };
+//! if let Some(old) = atom.swap(Box::new(2), Ordering::AcqRel) {
+//!     domain.retire(old);
+//! }
+//! assert_eq!(value, Some(&1));
+//! ```
+
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Formatter};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use chain::Chain;
+use {Atom, AtomSetOnce, AtomStorable, FromRawPtr, GetNextMut, RawDeref};
+
+/// How many values may be waiting for reclamation before `retire` scans
+/// the hazard slots.
+const RECLAIM_THRESHOLD: usize = 64;
+
+struct Slot {
+    hazard: AtomicPtr<()>,
+    claimed: AtomicBool,
+    next: AtomSetOnce<Box<Slot>>,
+}
+
+struct Retired {
+    next: Option<Box<Retired>>,
+    // raw pointers are not Send, the address is
+    addr: usize,
+    free: unsafe fn(*mut ()),
+}
+
+impl GetNextMut for Box<Retired> {
+    type NextPtr = Option<Box<Retired>>;
+    fn get_next(&mut self) -> &mut Option<Box<Retired>> {
+        &mut self.next
+    }
+}
+
+unsafe fn free<P: FromRawPtr>(ptr: *mut ()) {
+    drop(P::from_raw(ptr));
+}
+
+/// A set of hazard slots and the values retired against them.
+///
+/// Readers and writers of an Atom must agree on the `Domain` they use.
+pub struct Domain {
+    slots: AtomSetOnce<Box<Slot>>,
+    retired: Atom<Box<Retired>>,
+    pending: AtomicUsize,
+}
+
+impl Domain {
+    /// Create an empty domain. This is a `const fn`, so a domain can be
+    /// placed in a `static`.
+    pub const fn new() -> Domain {
+        Domain {
+            slots: AtomSetOnce::empty(),
+            retired: Atom::empty(),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    /// Claim a hazard slot. The slot is returned to the domain when the
+    /// guard is dropped.
+    pub fn guard(&self) -> HazardGuard<'_> {
+        for slot in self.slots.iter(Ordering::Acquire, |s| &s.next) {
+            if !slot.claimed.load(Ordering::Relaxed)
+                && slot
+                    .claimed
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return HazardGuard { slot };
+            }
+        }
+
+        let mut new = Box::new(Slot {
+            hazard: AtomicPtr::new(ptr::null_mut()),
+            claimed: AtomicBool::new(true),
+            next: AtomSetOnce::empty(),
+        });
+        let mut tail = &self.slots;
+        loop {
+            match tail.try_insert(new) {
+                Ok(slot) => return HazardGuard { slot },
+                Err((last, rejected)) => {
+                    tail = &last.next;
+                    new = rejected;
+                }
+            }
+        }
+    }
+
+    /// Drop `value` once no hazard slot points at it. `value` must already
+    /// be unreachable for new readers.
+    pub fn retire<P>(&self, value: P)
+    where
+        P: AtomStorable + Send + 'static,
+    {
+        let node = Box::new(Retired {
+            next: None,
+            addr: value.into_raw() as usize,
+            free: free::<P>,
+        });
+        self.retired
+            .replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+        if self.pending.fetch_add(1, Ordering::Relaxed) + 1 >= RECLAIM_THRESHOLD {
+            self.reclaim();
+        }
+    }
+
+    /// Free every retired value that is not protected by a hazard slot,
+    /// returning how many were freed.
+    pub fn reclaim(&self) -> usize {
+        self.pending.store(0, Ordering::Relaxed);
+        let retired = self.retired.take_chain(Ordering::Acquire);
+        fence(Ordering::SeqCst);
+        let hazards: HashSet<usize> = self
+            .slots
+            .iter(Ordering::Acquire, |s| &s.next)
+            .map(|s| s.hazard.load(Ordering::Relaxed) as usize)
+            .filter(|&addr| addr != 0)
+            .collect();
+
+        let mut keep = Chain::default();
+        let mut kept = 0;
+        let mut freed = 0;
+        for node in retired {
+            if hazards.contains(&node.addr) {
+                keep.push(node);
+                kept += 1;
+            } else {
+                unsafe { (node.free)(node.addr as *mut ()) };
+                freed += 1;
+            }
+        }
+        self.pending.fetch_add(kept, Ordering::Relaxed);
+        self.retired
+            .replace_and_set_next_chain(keep, Ordering::Relaxed, Ordering::AcqRel);
+        freed
+    }
+}
+
+impl Default for Domain {
+    fn default() -> Domain {
+        Domain::new()
+    }
+}
+
+impl Drop for Domain {
+    fn drop(&mut self) {
+        // guards borrow the domain, so none are left
+        for node in self.retired.drain(Ordering::Acquire) {
+            unsafe { (node.free)(node.addr as *mut ()) };
+        }
+        self.slots.drop_chain(|s| &mut s.next);
+    }
+}
+
+impl Debug for Domain {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Domain(slots={}, pending={})",
+            self.slots.chain_len(Ordering::Acquire, |s| &s.next),
+            self.pending.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// A claimed hazard slot, protecting at most one value at a time.
+pub struct HazardGuard<'a> {
+    slot: &'a Slot,
+}
+
+impl<'a> HazardGuard<'a> {
+    /// Stop protecting the current value
+    pub fn reset(&mut self) {
+        self.slot.hazard.store(ptr::null_mut(), Ordering::Release);
+    }
+}
+
+impl<'a> Drop for HazardGuard<'a> {
+    fn drop(&mut self) {
+        self.reset();
+        self.slot.claimed.store(false, Ordering::Release);
+    }
+}
+
+impl<'a> Debug for HazardGuard<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "HazardGuard({:p})",
+            self.slot.hazard.load(Ordering::Relaxed)
+        )
+    }
+}
+
+impl<T, P> Atom<P>
+where
+    P: AtomStorable + RawDeref<Target = T>,
+{
+    /// Borrow the current value, protecting it with `guard` so that it is
+    /// not freed while the borrow lasts. Protecting a new value releases
+    /// the one the guard held before, which the `&mut` borrow enforces.
+    ///
+    /// # Safety
+    ///
+    /// Every value removed from this Atom while guards may still hold it,
+    /// by `swap`, `take` or otherwise, must be dropped through `retire` on
+    /// the guard's `Domain` rather than directly.
+    pub unsafe fn load_protected<'a>(&'a self, guard: &'a mut HazardGuard) -> Option<&'a T> {
+        let mut ptr = self.inner.load(Ordering::Acquire);
+        loop {
+            guard.slot.hazard.store(ptr, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            let current = self.inner.load(Ordering::Acquire);
+            if current == ptr {
+                return (ptr as *const T).as_ref();
+            }
+            ptr = current;
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Atoms that hold an index into storage instead of a pointer.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use strongest_failure_ordering;
+
+/// An integer type that an `IndexAtom` can hold.
+///
+/// The largest value of the type is what an empty `IndexAtom` holds, so it
+/// can never be stored as an index.
+pub trait Index: Copy + Eq + Debug {
+    /// The atomic integer the index is kept in
+    type Atomic;
+
+    /// The value that marks an empty `IndexAtom`
+    const NONE: Self;
+
+    /// Create the atomic integer
+    fn atomic(value: Self) -> Self::Atomic;
+
+    /// Swap `value` into `atomic`
+    fn swap(atomic: &Self::Atomic, value: Self, order: Ordering) -> Self;
+
+    /// Read `atomic`
+    fn load(atomic: &Self::Atomic, order: Ordering) -> Self;
+
+    /// Compare and exchange `atomic`
+    fn compare_exchange(
+        atomic: &Self::Atomic,
+        current: Self,
+        new: Self,
+        order: Ordering,
+    ) -> Result<Self, Self>;
+
+    /// Read `atomic` through exclusive access
+    fn get_mut(atomic: &mut Self::Atomic) -> &mut Self;
+
+    /// Convert to an index into a slice
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! index {
+    ($int:ty, $atomic:ty) => {
+        impl Index for $int {
+            type Atomic = $atomic;
+
+            const NONE: $int = <$int>::MAX;
+
+            fn atomic(value: $int) -> $atomic {
+                <$atomic>::new(value)
+            }
+
+            fn swap(atomic: &$atomic, value: $int, order: Ordering) -> $int {
+                atomic.swap(value, order)
+            }
+
+            fn load(atomic: &$atomic, order: Ordering) -> $int {
+                atomic.load(order)
+            }
+
+            fn compare_exchange(
+                atomic: &$atomic,
+                current: $int,
+                new: $int,
+                order: Ordering,
+            ) -> Result<$int, $int> {
+                atomic.compare_exchange(current, new, order, strongest_failure_ordering(order))
+            }
+
+            fn get_mut(atomic: &mut $atomic) -> &mut $int {
+                atomic.get_mut()
+            }
+
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+        }
+    };
+}
+
+index!(u32, AtomicU32);
+index!(usize, AtomicUsize);
+
+/// An `Atom` for an index into an arena, slab or component array.
+///
+/// Since nothing is owned, values can be loaded as well as swapped, and
+/// the atom can be moved or copied along with the storage it points into.
+/// An `IndexAtom<u32>` is half the size of a pointer on 64-bit targets.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::index::IndexAtom;
+///
+/// let entities = ["player", "enemy", "door"];
+/// let target = IndexAtom::<u32>::empty();
+/// assert_eq!(target.resolve(&entities, Ordering::Acquire), None);
+/// target.swap(1, Ordering::AcqRel);
+/// assert_eq!(target.resolve(&entities, Ordering::Acquire), Some(&"enemy"));
+/// ```
+pub struct IndexAtom<I: Index = u32> {
+    inner: I::Atomic,
+}
+
+impl<I: Index> IndexAtom<I> {
+    fn pack(index: Option<I>) -> I {
+        match index {
+            Some(index) => {
+                assert!(index != I::NONE, "{:?} marks an empty IndexAtom", I::NONE);
+                index
+            }
+            None => I::NONE,
+        }
+    }
+
+    fn unpack(index: I) -> Option<I> {
+        if index == I::NONE {
+            None
+        } else {
+            Some(index)
+        }
+    }
+
+    /// Create an empty IndexAtom
+    pub fn empty() -> IndexAtom<I> {
+        IndexAtom {
+            inner: I::atomic(I::NONE),
+        }
+    }
+
+    /// Create an IndexAtom holding `index`
+    ///
+    /// # Panics
+    ///
+    /// If `index` is `I::NONE`, the same goes for every method that stores
+    /// an index.
+    pub fn new(index: I) -> IndexAtom<I> {
+        IndexAtom {
+            inner: I::atomic(Self::pack(Some(index))),
+        }
+    }
+
+    /// Swap a new index into the IndexAtom, returning the old one
+    pub fn swap(&self, index: I, order: Ordering) -> Option<I> {
+        Self::unpack(I::swap(&self.inner, Self::pack(Some(index)), order))
+    }
+
+    /// Take the index out of the IndexAtom, leaving it empty
+    pub fn take(&self, order: Ordering) -> Option<I> {
+        Self::unpack(I::swap(&self.inner, I::NONE, order))
+    }
+
+    /// Store `index` if the IndexAtom is empty. If it was not, `index` is
+    /// returned.
+    pub fn set_if_none(&self, index: I, order: Ordering) -> Option<I> {
+        I::compare_exchange(&self.inner, I::NONE, Self::pack(Some(index)), order)
+            .err()
+            .map(|_| index)
+    }
+
+    /// Store `new` if the IndexAtom holds `current`. Returns the index that
+    /// was there before either way.
+    pub fn compare_exchange(
+        &self,
+        current: Option<I>,
+        new: Option<I>,
+        order: Ordering,
+    ) -> Result<Option<I>, Option<I>> {
+        I::compare_exchange(&self.inner, Self::pack(current), Self::pack(new), order)
+            .map(Self::unpack)
+            .map_err(Self::unpack)
+    }
+
+    /// Read the index
+    pub fn load(&self, order: Ordering) -> Option<I> {
+        Self::unpack(I::load(&self.inner, order))
+    }
+
+    /// Look the index up in `arena`, returning `None` if the IndexAtom is
+    /// empty or the index is out of bounds
+    pub fn resolve<'a, T>(&self, arena: &'a [T], order: Ordering) -> Option<&'a T> {
+        self.load(order)
+            .and_then(|index| arena.get(index.to_usize()))
+    }
+
+    /// Look the index up in `arena` for writing, see `resolve`
+    pub fn resolve_mut<'a, T>(&self, arena: &'a mut [T], order: Ordering) -> Option<&'a mut T> {
+        self.load(order)
+            .and_then(move |index| arena.get_mut(index.to_usize()))
+    }
+
+    /// Check to see if the IndexAtom is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.load(order).is_none()
+    }
+
+    /// Get the index through exclusive access
+    pub fn get_mut(&mut self) -> Option<I> {
+        Self::unpack(*I::get_mut(&mut self.inner))
+    }
+
+    /// Consume the IndexAtom, returning its index
+    pub fn into_inner(mut self) -> Option<I> {
+        self.get_mut()
+    }
+}
+
+impl<I: Index> Default for IndexAtom<I> {
+    fn default() -> IndexAtom<I> {
+        IndexAtom::empty()
+    }
+}
+
+impl<I: Index> From<Option<I>> for IndexAtom<I> {
+    fn from(index: Option<I>) -> IndexAtom<I> {
+        IndexAtom {
+            inner: I::atomic(Self::pack(index)),
+        }
+    }
+}
+
+impl<I: Index> Debug for IndexAtom<I> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "IndexAtom({:?})", self.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,204 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A concurrent string interner.
+//!
+//! Interned strings are the keys of an `AtomMap`, whose entries never move
+//! once inserted, so a `Symbol` can simply point at the key. Two symbols
+//! from the same interner are equal exactly when they point at the same
+//! string, which makes comparing and hashing them as cheap as for an
+//! integer id. The thread that wins the insert of a new string also
+//! appends it to an `AppendList`, which remembers the order strings were
+//! first interned in.
+
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::ptr;
+
+use list::{self, AppendList};
+use map::AtomMap;
+
+/// A string owned by the map, as remembered by the list
+struct Interned(*const str);
+
+// Only ever dereferenced while the interner, which owns the string, is
+// borrowed.
+unsafe impl Send for Interned {}
+unsafe impl Sync for Interned {}
+
+/// A handle to a string stored in an `Interner`
+#[derive(Clone, Copy)]
+pub struct Symbol<'a> {
+    value: &'a str,
+}
+
+impl<'a> Symbol<'a> {
+    /// The interned string
+    pub fn as_str(&self) -> &'a str {
+        self.value
+    }
+}
+
+impl<'a> PartialEq for Symbol<'a> {
+    fn eq(&self, other: &Symbol<'a>) -> bool {
+        ptr::eq(self.value, other.value)
+    }
+}
+
+impl<'a> Eq for Symbol<'a> {}
+
+impl<'a> Hash for Symbol<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.as_ptr().hash(state)
+    }
+}
+
+impl<'a> Deref for Symbol<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.value
+    }
+}
+
+impl<'a> Display for Symbol<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        Display::fmt(self.value, f)
+    }
+}
+
+impl<'a> Debug for Symbol<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Symbol({:?})", self.value)
+    }
+}
+
+/// A set of strings that hands out a `Symbol` for each distinct string.
+///
+/// Symbols borrow the interner, put it in a `static` (see `atom_static!`)
+/// to get symbols that live for `'static`.
+///
+/// ```
+/// use atom::intern::Interner;
+///
+/// let names = Interner::new();
+/// let a = names.intern("position");
+/// let b = names.intern(&String::from("position"));
+/// assert_eq!(a, b);
+/// assert_eq!(a.as_str(), "position");
+/// assert_ne!(a, names.intern("velocity"));
+/// ```
+pub struct Interner {
+    strings: AtomMap<Box<str>, ()>,
+    order: AppendList<Interned>,
+}
+
+impl Interner {
+    /// Create an empty interner
+    pub fn new() -> Interner {
+        Interner::with_buckets(1024)
+    }
+
+    /// Create an empty interner whose map has `buckets` buckets, around the
+    /// number of distinct strings expected is a good size
+    pub fn with_buckets(buckets: usize) -> Interner {
+        Interner {
+            strings: AtomMap::with_buckets(buckets),
+            order: AppendList::new(),
+        }
+    }
+
+    /// Get the symbol for `value`, interning it if it is new
+    pub fn intern(&self, value: &str) -> Symbol<'_> {
+        if let Some(symbol) = self.get(value) {
+            return symbol;
+        }
+        if self.strings.try_insert(value.into(), ()).is_ok() {
+            let symbol = self.get(value).expect("interned strings are never removed");
+            self.order.append(Interned(symbol.value));
+            return symbol;
+        }
+        // another thread interned it first
+        self.get(value).expect("interned strings are never removed")
+    }
+
+    /// Get the symbol for `value` if it has been interned
+    pub fn get(&self, value: &str) -> Option<Symbol<'_>> {
+        self.strings
+            .get_key_value(value)
+            .map(|(key, _)| Symbol { value: key })
+    }
+
+    /// The number of distinct strings interned
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Check to see if nothing has been interned
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Iterate over the symbols interned so far, in the order they were
+    /// first interned
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            order: self.order.iter(),
+        }
+    }
+}
+
+impl Default for Interner {
+    fn default() -> Interner {
+        Interner::new()
+    }
+}
+
+impl Debug for Interner {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a Interner {
+    type Item = Symbol<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// An iterator over the symbols of an `Interner`, see `Interner::iter`
+pub struct Iter<'a> {
+    order: list::Iter<'a, Interned>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Symbol<'a>;
+
+    fn next(&mut self) -> Option<Symbol<'a>> {
+        // the string lives in the map, which lives as long as the interner
+        self.order.next().map(|interned| Symbol {
+            value: unsafe { &*interned.0 },
+        })
+    }
+}
+
+impl<'a> Debug for Iter<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Iter({:?})", self.order)
+    }
+}
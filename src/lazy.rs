@@ -0,0 +1,138 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::Ordering;
+
+use AtomSetOnce;
+
+/// A value that is computed on first access.
+///
+/// The first thread to dereference an `AtomLazy` runs the initializer and
+/// stores the result in an `AtomSetOnce`, every later access is a single
+/// load. Nothing ever blocks: threads that race on the first access each
+/// run the initializer and all but the first result are dropped, which is
+/// why the initializer is an `Fn` rather than an `FnOnce`.
+///
+/// ```
+/// use atom::AtomLazy;
+///
+/// let lazy = AtomLazy::new(|| vec![1, 2, 3]);
+/// assert_eq!(AtomLazy::get(&lazy), None);
+/// assert_eq!(lazy.len(), 3);
+/// assert_eq!(AtomLazy::get(&lazy), Some(&vec![1, 2, 3]));
+/// ```
+pub struct AtomLazy<T, F = fn() -> T> {
+    cell: AtomSetOnce<Box<T>>,
+    init: F,
+    // hands out `&T` to every thread, so `T` must be `Sync` as well as `Send`
+    _marker: PhantomData<T>,
+}
+
+impl<T, F> AtomLazy<T, F>
+where
+    F: Fn() -> T,
+{
+    /// Create a lazy value that will be computed by `init`
+    pub const fn new(init: F) -> AtomLazy<T, F> {
+        AtomLazy {
+            cell: AtomSetOnce::empty(),
+            init,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Get the value, computing it if this is the first access
+    pub fn force(this: &AtomLazy<T, F>) -> &T {
+        this.cell.get_or_init(|| Box::new((this.init)()))
+    }
+
+    /// Get the value if it has already been computed
+    pub fn get(this: &AtomLazy<T, F>) -> Option<&T> {
+        this.cell.get(Ordering::Acquire)
+    }
+
+    /// Consume the lazy value, returning it if it was computed, or the
+    /// initializer if it was not
+    pub fn into_value(this: AtomLazy<T, F>) -> Result<T, F> {
+        match this.cell.into_inner() {
+            Some(v) => Ok(*v),
+            None => Err(this.init),
+        }
+    }
+}
+
+impl<T, F> Deref for AtomLazy<T, F>
+where
+    F: Fn() -> T,
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        AtomLazy::force(self)
+    }
+}
+
+impl<T> Default for AtomLazy<T>
+where
+    T: Default,
+{
+    fn default() -> AtomLazy<T> {
+        AtomLazy::new(T::default)
+    }
+}
+
+impl<T, F> Debug for AtomLazy<T, F>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        match self.cell.get(Ordering::Acquire) {
+            Some(v) => write!(f, "AtomLazy({:?})", v),
+            None => write!(f, "AtomLazy(<uninit>)"),
+        }
+    }
+}
+
+/// Declare global statics that are computed on first access.
+///
+/// Each static is an `AtomLazy` behind the scenes, so reads after the first
+/// are a single load and no locks are involved.
+///
+/// ```
+/// #[macro_use]
+/// extern crate atom;
+///
+/// atom_static! {
+///     static GREETING: String = format!("hello {}", "world");
+///     /// Attributes and visibility are kept.
+///     pub static ANSWER: u32 = 6 * 7;
+/// }
+///
+/// fn main() {
+///     assert_eq!(&*GREETING, "hello world");
+///     assert_eq!(*ANSWER, 42);
+/// }
+/// ```
+#[macro_export]
+macro_rules! atom_static {
+    ($(#[$attr:meta])* $vis:vis static $name:ident : $t:ty = $init:expr; $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::AtomLazy<$t> = $crate::AtomLazy::new(|| $init);
+        $crate::atom_static!($($rest)*);
+    };
+    () => {};
+}
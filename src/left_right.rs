@@ -0,0 +1,168 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Two copies of a value, one for readers and one for the writer.
+//!
+//! This is the left-right technique of Ramalhete and Correia. Readers
+//! announce themselves on the counter picked by `version` and read the
+//! copy picked by `side`, which never needs a retry. The writer changes
+//! the copy readers are not using, points `side` at it and then waits for
+//! readers of the old copy to leave in two steps: first for the counter
+//! that `version` is about to move to, then, after moving it, for the
+//! counter it moved away from. After that nobody can be reading the old
+//! copy, and the writer applies the same change to it.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use CachePadded;
+
+/// A value that is read wait-free and written by applying a change twice.
+///
+/// Reads never allocate, never retry and never wait. Writes are serialized
+/// and run the change once on each copy, waiting in between for readers of
+/// the copy they are about to touch, so the change has to be
+/// deterministic for the copies to stay the same.
+///
+/// ```
+/// use atom::left_right::LeftRight;
+///
+/// let routes = LeftRight::new(vec![("a", 1)]);
+/// routes.write(|routes| routes.push(("b", 2)));
+/// assert_eq!(routes.read().len(), 2);
+/// ```
+pub struct LeftRight<T> {
+    copies: [UnsafeCell<T>; 2],
+    side: AtomicUsize,
+    version: AtomicUsize,
+    readers: [CachePadded<AtomicUsize>; 2],
+    writer: Mutex<()>,
+}
+
+unsafe impl<T: Send + Sync> Send for LeftRight<T> {}
+unsafe impl<T: Send + Sync> Sync for LeftRight<T> {}
+
+impl<T: Clone> LeftRight<T> {
+    /// Create a cell holding `value`
+    pub fn new(value: T) -> LeftRight<T> {
+        LeftRight {
+            copies: [UnsafeCell::new(value.clone()), UnsafeCell::new(value)],
+            side: AtomicUsize::new(0),
+            version: AtomicUsize::new(0),
+            readers: [
+                CachePadded::new(AtomicUsize::new(0)),
+                CachePadded::new(AtomicUsize::new(0)),
+            ],
+            writer: Mutex::new(()),
+        }
+    }
+}
+
+impl<T> LeftRight<T> {
+    /// Get the current value
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        let readers = &*self.readers[self.version.load(Ordering::SeqCst) & 1];
+        readers.fetch_add(1, Ordering::SeqCst);
+        let side = self.side.load(Ordering::SeqCst);
+        ReadGuard {
+            // the writer waits for `readers` before touching this copy
+            value: unsafe { &*self.copies[side].get() },
+            readers,
+        }
+    }
+
+    /// Apply `f` to the value
+    ///
+    /// # Panics
+    ///
+    /// If an earlier `f` panicked, since the copies may differ after that.
+    pub fn write<F>(&self, mut f: F)
+    where
+        F: FnMut(&mut T),
+    {
+        let _writer = self
+            .writer
+            .lock()
+            .expect("a LeftRight write panicked, its copies may differ");
+        let side = self.side.load(Ordering::Relaxed);
+        // readers are not using the other copy, and only we write
+        f(unsafe { &mut *self.copies[side ^ 1].get() });
+        self.side.store(side ^ 1, Ordering::SeqCst);
+
+        let version = self.version.load(Ordering::Relaxed) & 1;
+        self.drain(version ^ 1);
+        self.version.store(version ^ 1, Ordering::SeqCst);
+        self.drain(version);
+        f(unsafe { &mut *self.copies[side].get() });
+    }
+
+    fn drain(&self, version: usize) {
+        while self.readers[version].load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+    }
+
+    /// Consume the cell, returning the copy readers were using
+    pub fn into_inner(self) -> T {
+        let side = self.side.load(Ordering::Relaxed);
+        let [left, right] = self.copies;
+        if side == 0 {
+            left.into_inner()
+        } else {
+            right.into_inner()
+        }
+    }
+}
+
+impl<T: Clone + Default> Default for LeftRight<T> {
+    fn default() -> LeftRight<T> {
+        LeftRight::new(T::default())
+    }
+}
+
+impl<T: Debug> Debug for LeftRight<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "LeftRight({:?})", &*self.read())
+    }
+}
+
+/// The value of a `LeftRight`, returned by `read`
+pub struct ReadGuard<'a, T> {
+    value: &'a T,
+    readers: &'a AtomicUsize,
+}
+
+impl<'a, T> Deref for ReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> Drop for ReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.readers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<'a, T: Debug> Debug for ReadGuard<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "ReadGuard({:?})", self.value)
+    }
+}
@@ -12,21 +12,121 @@
 //   See the License for the specific language governing permissions and
 //   limitations under the License.
 
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+#[cfg(feature = "atom-derive")]
+extern crate atom_derive;
+#[cfg(feature = "crossbeam")]
+extern crate crossbeam_epoch;
+#[cfg(loom)]
+extern crate loom;
+#[cfg(feature = "parking_lot")]
+extern crate parking_lot_core;
+#[cfg(feature = "zeroize")]
+extern crate zeroize;
+
+#[cfg(feature = "allocator_api")]
+use std::alloc::Allocator;
 use std::cell::UnsafeCell;
+use std::ffi::{c_char, CString};
 use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hint::{self, unreachable_unchecked};
 use std::marker::PhantomData;
-use std::mem;
-use std::ops::Deref;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
 use std::ptr;
-use std::sync::atomic::AtomicPtr;
+use std::rc::Rc;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicPtr, AtomicUsize};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant};
+
+pub mod accumulator;
+pub mod arc_cell;
+pub mod bag;
+pub mod buffer;
+pub mod cache;
+pub mod callback;
+pub mod cancel;
+pub mod chain;
+pub mod combining;
+pub mod cow;
+#[cfg(feature = "crossbeam")]
+pub mod crossbeam;
+pub mod deque;
+pub mod dst;
+mod dword;
+pub mod either;
+pub mod epoch;
+pub mod event;
+mod group;
+mod handle;
+pub mod hazard;
+pub mod index;
+pub mod intern;
+mod lazy;
+pub mod left_right;
+pub mod list;
+pub mod local;
+pub mod mailbox;
+pub mod map;
+pub mod mcas;
+#[cfg(feature = "observe")]
+pub mod observe;
+pub mod oneshot;
+pub mod persistent;
+pub mod policy;
+pub mod pool;
+pub mod qsbr;
+#[cfg(feature = "paranoid")]
+mod quarantine;
+pub mod queue;
+pub mod raw;
+pub mod rcu;
+pub mod registry;
+pub mod scoped;
+#[cfg(feature = "zeroize")]
+pub mod secret;
+pub mod seqlock;
+pub mod sharded;
+pub mod slab;
+pub mod small;
+pub mod spsc;
+pub mod stack;
+pub mod stamped;
+pub mod tagged;
+#[cfg(feature = "async")]
+pub mod task;
+pub mod thin;
+mod wait;
+pub mod waitfree;
+pub mod watch;
+pub mod weak;
+pub mod wide;
+
+#[cfg(feature = "atom-derive")]
+pub use atom_derive::GetNextMut;
+pub use group::AtomGroup;
+pub use handle::{AtomReader, AtomWriter, Setter, Taker};
+pub use lazy::AtomLazy;
+pub use wait::{Registration, WaitList};
 
 /// An Atom wraps an AtomicPtr, it allows for safe mutation of an atomic
 /// into common Rust Types.
+///
+/// A null pointer is used to represent an empty Atom, so every payload must
+/// convert into a non-null pointer. This includes zero-sized payloads:
+/// `Box<T>` of a zero-sized `T` is a dangling but non-null, well aligned
+/// pointer and `Arc<T>` always points at its reference counts, so both
+/// round-trip through `swap`, `take`, `set_if_none` and `is_none` like any
+/// other value. Be aware that every `Box` of the same zero-sized type has
+/// the same address, so the `compare_*` family cannot tell two of them apart.
 pub struct Atom<P>
 where
-    P: IntoRawPtr + FromRawPtr,
+    P: AtomStorable,
 {
     inner: AtomicPtr<()>,
     data: PhantomData<UnsafeCell<P>>,
@@ -34,7 +134,7 @@ pub struct Atom<P>
 
 impl<P> Debug for Atom<P>
 where
-    P: IntoRawPtr + FromRawPtr,
+    P: AtomStorable,
 {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         write!(f, "atom({:?})", self.inner.load(Ordering::Relaxed))
@@ -43,10 +143,12 @@ fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
 
 impl<P> Atom<P>
 where
-    P: IntoRawPtr + FromRawPtr,
+    P: AtomStorable,
 {
     /// Create a empty Atom
-    pub fn empty() -> Atom<P> {
+    ///
+    /// This is a `const fn`, so an empty Atom can be placed in a `static`.
+    pub const fn empty() -> Atom<P> {
         Atom {
             inner: AtomicPtr::new(ptr::null_mut()),
             data: PhantomData,
@@ -56,7 +158,7 @@ pub fn empty() -> Atom<P> {
     /// Create a new Atomic from Pointer P
     pub fn new(value: P) -> Atom<P> {
         Atom {
-            inner: AtomicPtr::new(value.into_raw()),
+            inner: AtomicPtr::new(Self::raw(value)),
             data: PhantomData,
         }
     }
@@ -64,7 +166,7 @@ pub fn new(value: P) -> Atom<P> {
     /// Swap a new value into the Atom, This will try multiple
     /// times until it succeeds. The old value will be returned.
     pub fn swap(&self, v: P, order: Ordering) -> Option<P> {
-        let new = v.into_raw();
+        let new = Self::raw(v);
         let old = self.inner.swap(new, order);
         unsafe { Self::inner_from_raw(old) }
     }
@@ -82,40 +184,114 @@ pub fn take(&self, order: Ordering) -> Option<P> {
     /// otherwise a `Some(v)` will be returned, where the value was
     /// the same value that you passed into this function
     pub fn set_if_none(&self, v: P, order: Ordering) -> Option<P> {
-        let new = v.into_raw();
-        let old = self.inner.compare_and_swap(ptr::null_mut(), new, order);
-        if !old.is_null() {
-            Some(unsafe { FromRawPtr::from_raw(new) })
-        } else {
-            None
+        let new = Self::raw(v);
+        match self.inner.compare_exchange(
+            ptr::null_mut(),
+            new,
+            order,
+            strongest_failure_ordering(order),
+        ) {
+            Ok(_) => None,
+            Err(_) => Some(unsafe { FromRawPtr::from_raw(new) }),
         }
     }
 
     /// Take the current content, write it into P then do a CAS to extent this
     /// Atom with the previous contents. This can be used to create a LIFO
     ///
-    /// Returns true if this set this migrated the Atom from null.
+    /// Returns a `Token` for the head that was replaced. If the token
+    /// `is_none` this push migrated the Atom from null, meaning it was the
+    /// first push since the chain was last taken.
     pub fn replace_and_set_next(
         &self,
         mut value: P,
         load_order: Ordering,
         cas_order: Ordering,
-    ) -> bool
+    ) -> Token
     where
-        P: GetNextMut<NextPtr = Option<P>>,
+        P: GetNextMut,
+        P::NextPtr: NextSlot<P>,
     {
-        let next = value.get_next() as *mut Option<P>;
-        let raw = value.into_raw();
+        let next = value.get_next().slot() as *mut Option<P>;
+        let raw = Self::raw(value);
         // If next was set to Some(P) we want to
         // assert that it was droppeds
-        unsafe { ptr::drop_in_place(next) };
+        unsafe {
+            ptr::drop_in_place(next);
+            self.splice(raw, next, load_order, cas_order)
+        }
+    }
+
+    /// Publish the node at `raw`, whose next pointer lives at `next`, as the
+    /// new head after writing the current head into `next`.
+    ///
+    /// `next` must be uninitialized and owned by the chain starting at `raw`.
+    unsafe fn splice(
+        &self,
+        raw: *mut (),
+        next: *mut Option<P>,
+        load_order: Ordering,
+        cas_order: Ordering,
+    ) -> Token
+    where
+        P: GetNextMut,
+        P::NextPtr: NextSlot<P>,
+    {
         loop {
             let pcurrent = self.inner.load(load_order);
-            let current = unsafe { Self::inner_from_raw(pcurrent) };
-            unsafe { ptr::write(next, current) };
-            let last = self.inner.compare_and_swap(pcurrent, raw, cas_order);
-            if last == pcurrent {
-                return last.is_null();
+            let current = Self::inner_peek(pcurrent);
+            ptr::write(next, current);
+            if let Ok(last) = self.inner.compare_exchange(
+                pcurrent,
+                raw,
+                cas_order,
+                strongest_failure_ordering(cas_order),
+            ) {
+                return Token(last as usize);
+            }
+        }
+    }
+
+    /// Remove the node at the head of a LIFO built by `replace_and_set_next`,
+    /// leaving the rest of the chain in the Atom. The returned node's next
+    /// pointer is cleared.
+    ///
+    /// Any number of threads may push while one thread pops.
+    ///
+    /// # Safety
+    ///
+    /// This reads the next pointer of the current head before unlinking it,
+    /// so no other thread may remove nodes from this Atom while `pop_next`
+    /// runs. That means no concurrent `pop_next`, `take`, `swap` or
+    /// `compare_*` calls. A second remover could free the head out from
+    /// under this one, or re-push it and cause an ABA race.
+    pub unsafe fn pop_next(&self, load_order: Ordering, cas_order: Ordering) -> Option<P>
+    where
+        P: GetNextMut,
+        P::NextPtr: NextSlot<P>,
+    {
+        loop {
+            let phead = self.inner.load(load_order);
+            let mut head = Self::inner_peek(phead)?;
+            // Copy the next pointer out, the chain stays owned by the Atom
+            let pnext = match *head.get_next().slot() {
+                Some(ref next) => Self::inner_as_raw(next),
+                None => ptr::null_mut(),
+            };
+            mem::forget(head);
+            if self
+                .inner
+                .compare_exchange(
+                    phead,
+                    pnext,
+                    cas_order,
+                    strongest_failure_ordering(cas_order),
+                )
+                .is_ok()
+            {
+                let mut head: P = FromRawPtr::from_raw(phead);
+                ptr::write(head.get_next().slot(), None);
+                return Some(head);
             }
         }
     }
@@ -127,14 +303,107 @@ pub fn is_none(&self, order: Ordering) -> bool {
         self.inner.load(order).is_null()
     }
 
+    /// Get a `Token` identifying the value currently stored in the Atom.
+    ///
+    /// Two tokens compare equal if the Atom held the same raw pointer when
+    /// they were taken. Allocations can be reused, so equal tokens do not
+    /// prove that the Atom was never changed in between.
+    pub fn token(&self, order: Ordering) -> Token {
+        Token(self.inner.load(order) as usize)
+    }
+
+    /// Swap a new value into the Atom like `swap`, then wake every thread
+    /// blocked on this Atom in `take_blocking`.
+    ///
+    /// Only this method wakes blocked threads, values written with `swap`
+    /// or `set_if_none` sit in the Atom until a blocked thread happens to be
+    /// woken for some other reason.
+    pub fn set_and_notify(&self, v: P, order: Ordering) -> Option<P> {
+        let old = self.swap(v, order);
+        wait::parking(&self.inner).notify_all();
+        old
+    }
+
+    /// Take the value out of the Atom, blocking the current thread until
+    /// there is one. A thread that finds the Atom empty spins for a moment,
+    /// then parks until a `set_and_notify` on this Atom wakes it.
+    pub fn take_blocking(&self) -> P {
+        self.take_until(None)
+            .expect("waiting without a deadline returned empty")
+    }
+
+    /// Like `take_blocking` but gives up once `timeout` has passed,
+    /// returning `None` if the Atom stayed empty.
+    pub fn take_blocking_timeout(&self, timeout: Duration) -> Option<P> {
+        self.take_until(Some(Instant::now() + timeout))
+    }
+
+    fn take_until(&self, deadline: Option<Instant>) -> Option<P> {
+        for _ in 0..SPIN_LIMIT {
+            if let Some(v) = self.take(Ordering::Acquire) {
+                return Some(v);
+            }
+            hint::spin_loop();
+        }
+        let parking = wait::parking(&self.inner);
+        loop {
+            let full = parking.wait_until(|| !self.is_none(Ordering::SeqCst), deadline);
+            match self.take(Ordering::Acquire) {
+                Some(v) => return Some(v),
+                // someone else took it first, wait for the next one
+                None if full => continue,
+                None => return None,
+            }
+        }
+    }
+
+    /// Block the current thread until the Atom holds something other than
+    /// what `token` identifies, or until `timeout` has passed. Returns a
+    /// `Token` for what the Atom holds now, which equals `token` if it timed
+    /// out.
+    ///
+    /// Like `take_blocking` this is only woken by `set_and_notify`. The
+    /// comparison is by address, so a new value that reuses the old
+    /// allocation does not count as a change.
+    pub fn wait_for_change(&self, token: Token, timeout: Duration) -> Token {
+        let deadline = Instant::now() + timeout;
+        wait::parking(&self.inner)
+            .wait_until(|| self.token(Ordering::SeqCst) != token, Some(deadline));
+        self.token(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn raw(val: P) -> *mut () {
+        let ptr = val.into_raw();
+        debug_assert!(!ptr.is_null(), "IntoRawPtr returned a null pointer");
+        ptr
+    }
+
     #[inline]
     fn inner_into_raw(val: Option<P>) -> *mut () {
         match val {
-            Some(val) => val.into_raw(),
+            Some(val) => Self::raw(val),
             None => ptr::null_mut(),
         }
     }
 
+    /// Reconstitute the value at `ptr` without taking ownership of it away
+    /// from the Atom. The result must be forgotten, never dropped.
+    #[inline]
+    unsafe fn inner_peek(ptr: *mut ()) -> Option<P> {
+        #[cfg(feature = "paranoid")]
+        let _quiet = quarantine::Quiet::new();
+        Self::inner_from_raw(ptr)
+    }
+
+    /// Find the raw pointer of a value that is owned elsewhere.
+    #[inline]
+    unsafe fn inner_as_raw(val: &P) -> *mut () {
+        #[cfg(feature = "paranoid")]
+        let _quiet = quarantine::Quiet::new();
+        Self::raw(ptr::read(val))
+    }
+
     #[inline]
     unsafe fn inner_from_raw(ptr: *mut ()) -> Option<P> {
         if !ptr.is_null() {
@@ -147,7 +416,7 @@ unsafe fn inner_from_raw(ptr: *mut ()) -> Option<P> {
 
 impl<P, T> Atom<P>
 where
-    P: IntoRawPtr + FromRawPtr + Deref<Target = T>,
+    P: AtomStorable + Deref<Target = T>,
 {
     /// Stores `new` in the Atom if `current` has the same raw pointer
     /// representation as the currently stored value.
@@ -167,11 +436,12 @@ pub fn compare_and_swap(
     ) -> Result<Option<P>, (Option<P>, *mut P)> {
         let pcurrent = Self::inner_as_ptr(current);
         let pnew = Self::inner_into_raw(new);
-        let pprev = self.inner.compare_and_swap(pcurrent, pnew, order);
-        if pprev == pcurrent {
-            Ok(unsafe { Self::inner_from_raw(pprev) })
-        } else {
-            Err((unsafe { Self::inner_from_raw(pnew) }, pprev as *mut P))
+        match self
+            .inner
+            .compare_exchange(pcurrent, pnew, order, strongest_failure_ordering(order))
+        {
+            Ok(pprev) => Ok(unsafe { Self::inner_from_raw(pprev) }),
+            Err(pprev) => Err((unsafe { Self::inner_from_raw(pnew) }, pprev as *mut P)),
         }
     }
 
@@ -239,50 +509,205 @@ fn inner_as_ptr(val: Option<&P>) -> *mut () {
     }
 }
 
-impl<P> Drop for Atom<P>
+impl<P> Atom<P>
 where
-    P: IntoRawPtr + FromRawPtr,
+    P: AtomStorable + Clone,
 {
-    fn drop(&mut self) {
-        self.take(Ordering::Relaxed);
+    /// Get a copy of the current value by taking it out, cloning it and
+    /// putting it back.
+    ///
+    /// This is safe without any reclamation scheme, but it is not a pure
+    /// read: while the value is out other threads see the Atom as empty,
+    /// including a concurrent `load_cloned` which then returns `None`. If a
+    /// writer stores a value in the meantime the writer wins, the taken
+    /// value is dropped instead of put back, and its clone is still
+    /// returned since it was current when it was taken.
+    pub fn load_cloned(&self) -> Option<P> {
+        let value = self.take(Ordering::Acquire)?;
+        let copy = value.clone();
+        drop(self.set_if_none(value, Ordering::Release));
+        Some(copy)
     }
 }
 
-unsafe impl<P> Send for Atom<P>
-where
-    P: IntoRawPtr + FromRawPtr + Send,
-{
+impl<T> Atom<Box<MaybeUninit<T>>> {
+    /// Reserve the Atom for a value that is written after it is published,
+    /// such as a buffer that a device or the kernel fills in.
+    ///
+    /// If the Atom is empty an uninitialized allocation is stored and a
+    /// pointer to it is returned to write the value through. If it was not
+    /// empty nothing is allocated and `None` is returned.
+    pub fn set_uninit(&self, order: Ordering) -> Option<*mut T> {
+        if !self.is_none(strongest_failure_ordering(order)) {
+            return None;
+        }
+        let new = Self::raw(Box::new(MaybeUninit::uninit()));
+        match self.inner.compare_exchange(
+            ptr::null_mut(),
+            new,
+            order,
+            strongest_failure_ordering(order),
+        ) {
+            Ok(_) => Some(new as *mut T),
+            Err(_) => {
+                drop(unsafe { Self::inner_from_raw(new) });
+                None
+            }
+        }
+    }
+
+    /// Take the value out as a `Box<T>`.
+    ///
+    /// # Safety
+    ///
+    /// The value must be fully initialized, and whoever wrote it, through the
+    /// pointer from `set_uninit` or otherwise, must be done with it. Use
+    /// `take` to get at a value that might not be.
+    pub unsafe fn assume_init_take(&self, order: Ordering) -> Option<Box<T>> {
+        self.take(order).map(|slot| slot.assume_init())
+    }
+}
+
+/// How many times a blocking take polls before parking the thread
+const SPIN_LIMIT: usize = 64;
+
+/// An opaque snapshot of the pointer stored in an `Atom`, used to detect
+/// that the contents changed without giving access to them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Token(usize);
+
+impl Token {
+    /// Check to see if the token was taken while the Atom was empty
+    pub fn is_none(&self) -> bool {
+        self.0 == 0
+    }
 }
-unsafe impl<P> Sync for Atom<P>
+
+impl<P> Drop for Atom<P>
 where
-    P: IntoRawPtr + FromRawPtr + Send,
+    P: AtomStorable,
 {
+    fn drop(&mut self) {
+        self.take(Ordering::Relaxed);
+    }
 }
 
+unsafe impl<P> Send for Atom<P> where P: AtomStorable + Send {}
+unsafe impl<P> Sync for Atom<P> where P: AtomStorable + Send {}
+
 /// Convert from into a raw pointer
+///
+/// The returned pointer must never be null, since `Atom` uses null to mark
+/// itself as empty.
 pub trait IntoRawPtr {
     fn into_raw(self) -> *mut ();
 }
 
 /// Convert from a raw ptr into a pointer
 pub trait FromRawPtr {
+    /// # Safety
+    ///
+    /// `ptr` must have been produced by `IntoRawPtr::into_raw` on the same
+    /// type, and ownership of it must not have been reclaimed already.
     unsafe fn from_raw(ptr: *mut ()) -> Self;
 }
 
+/// A pointer that can be stored in an `Atom`.
+///
+/// `IntoRawPtr` and `FromRawPtr` are safe to implement, this is where a
+/// payload type promises that its pair of conversions can be trusted.
+///
+/// # Safety
+///
+/// - `into_raw` never returns null.
+/// - `into_raw` hands over ownership: the raw pointer is the only thing
+///   left of the value, and the value is not dropped until `from_raw`
+///   turns that pointer back into it.
+/// - `from_raw(into_raw(p))` gives back `p` unchanged, and the raw pointer
+///   stays the same for as long as it is held, independent of where the
+///   original value lived. It may be handed to `from_raw` on any thread the
+///   type can be sent to.
+pub unsafe trait AtomStorable: IntoRawPtr + FromRawPtr {}
+
+/// A pointer whose raw form points straight at its target.
+///
+/// `AtomSetOnce` uses this to hand out `&T` from the stored raw pointer
+/// without rebuilding the owning pointer just to borrow through it. Pointers
+/// that cannot implement it are still readable with `AtomSetOnce::get_with`.
+///
+/// # Safety
+///
+/// `into_raw` must return the address of the value that `deref` would have
+/// returned, and that value must stay put for as long as the raw pointer is
+/// owned by someone.
+pub unsafe trait RawDeref: IntoRawPtr + Deref {}
+
+#[cfg(not(feature = "allocator_api"))]
 impl<T> IntoRawPtr for Box<T> {
     #[inline]
     fn into_raw(self) -> *mut () {
-        Box::into_raw(self) as *mut ()
+        let ptr = Box::into_raw(self) as *mut ();
+        #[cfg(feature = "paranoid")]
+        quarantine::acquire::<T>(ptr);
+        ptr
     }
 }
 
+#[cfg(not(feature = "allocator_api"))]
 impl<T> FromRawPtr for Box<T> {
     #[inline]
     unsafe fn from_raw(ptr: *mut ()) -> Box<T> {
+        #[cfg(feature = "paranoid")]
+        quarantine::release::<T>(ptr);
         Box::from_raw(ptr as *mut T)
     }
 }
 
+#[cfg(not(feature = "allocator_api"))]
+unsafe impl<T> AtomStorable for Box<T> {}
+
+// A zero-sized allocator such as `Global` carries no state, so the raw
+// pointer is the value's own just like above. An allocator with state, a
+// reference to an arena for example, has nowhere to go in a single word, so
+// the box travels in a header box on the global heap that holds both.
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> IntoRawPtr for Box<T, A> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        let ptr = if mem::size_of::<A>() != 0 {
+            Box::into_raw(Box::new(self)) as *mut ()
+        } else {
+            let (ptr, alloc) = Box::into_raw_with_allocator(self);
+            mem::forget(alloc);
+            ptr as *mut ()
+        };
+        #[cfg(feature = "paranoid")]
+        quarantine::acquire::<T>(ptr);
+        ptr
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> FromRawPtr for Box<T, A> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Box<T, A> {
+        #[cfg(feature = "paranoid")]
+        quarantine::release::<T>(ptr);
+        if mem::size_of::<A>() != 0 {
+            *Box::from_raw(ptr as *mut Box<T, A>)
+        } else {
+            // stands in for the allocator that `into_raw` forgot
+            let alloc = ptr::read(ptr::NonNull::<A>::dangling().as_ptr());
+            Box::from_raw_in(ptr as *mut T, alloc)
+        }
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<T, A: Allocator> AtomStorable for Box<T, A> {}
+
+unsafe impl<T> RawDeref for Box<T> {}
+
 impl<T> IntoRawPtr for Arc<T> {
     #[inline]
     fn into_raw(self) -> *mut () {
@@ -297,32 +722,278 @@ unsafe fn from_raw(ptr: *mut ()) -> Arc<T> {
     }
 }
 
-// This impl can be useful for stack-allocated and 'static values.
-impl<'a, T> IntoRawPtr for &'a T {
+unsafe impl<T> AtomStorable for Arc<T> {}
+
+unsafe impl<T> RawDeref for Arc<T> {}
+
+// `AtomWeak` is the one to use for upgrading a shared slot. Every `Weak`,
+// including an empty `Weak::new()`, has a non-null raw pointer.
+impl<T> IntoRawPtr for Weak<T> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        Weak::into_raw(self) as *mut T as *mut ()
+    }
+}
+
+impl<T> FromRawPtr for Weak<T> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Weak<T> {
+        Weak::from_raw(ptr as *const () as *const T)
+    }
+}
+
+unsafe impl<T> AtomStorable for Weak<T> {}
+
+impl<T> IntoRawPtr for Rc<T> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        Rc::into_raw(self) as *mut T as *mut ()
+    }
+}
+
+impl<T> FromRawPtr for Rc<T> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Rc<T> {
+        Rc::from_raw(ptr as *const () as *const T)
+    }
+}
+
+unsafe impl<T> AtomStorable for Rc<T> {}
+
+unsafe impl<T> RawDeref for Rc<T> {}
+
+// The value never moves while it is stored, the raw pointer is all anyone
+// gets to see until `from_raw` pins it again.
+impl<T> IntoRawPtr for Pin<Box<T>> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        unsafe { Pin::into_inner_unchecked(self) }.into_raw()
+    }
+}
+
+impl<T> FromRawPtr for Pin<Box<T>> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Pin<Box<T>> {
+        Box::into_pin(<Box<T> as FromRawPtr>::from_raw(ptr))
+    }
+}
+
+unsafe impl<T> AtomStorable for Pin<Box<T>> {}
+
+unsafe impl<T> RawDeref for Pin<Box<T>> {}
+
+// A `Vec` is three words, so it travels in a header allocation holding just
+// those. The buffer itself is neither copied nor shrunk, and keeps its
+// capacity when it comes back out. The raw pointer is the header's, which
+// is why there is no `RawDeref` impl.
+#[cfg(not(feature = "allocator_api"))]
+impl<T> IntoRawPtr for Vec<T> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        Box::new(self).into_raw()
+    }
+}
+
+#[cfg(not(feature = "allocator_api"))]
+impl<T> FromRawPtr for Vec<T> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Vec<T> {
+        *<Box<Vec<T>> as FromRawPtr>::from_raw(ptr)
+    }
+}
+
+#[cfg(not(feature = "allocator_api"))]
+unsafe impl<T> AtomStorable for Vec<T> {}
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> IntoRawPtr for Vec<T, A> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        Box::new(self).into_raw()
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> FromRawPtr for Vec<T, A> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Vec<T, A> {
+        *<Box<Vec<T, A>> as FromRawPtr>::from_raw(ptr)
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+unsafe impl<T, A: Allocator> AtomStorable for Vec<T, A> {}
+
+// Same as `Vec<u8>`.
+impl IntoRawPtr for String {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        Box::new(self).into_raw()
+    }
+}
+
+impl FromRawPtr for String {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> String {
+        *<Box<String> as FromRawPtr>::from_raw(ptr)
+    }
+}
+
+unsafe impl AtomStorable for String {}
+
+// Stored as the `char *` that `CString::into_raw` would hand to C, without
+// a header allocation in front of it.
+impl IntoRawPtr for CString {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        CString::into_raw(self) as *mut ()
+    }
+}
+
+impl FromRawPtr for CString {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> CString {
+        CString::from_raw(ptr as *mut c_char)
+    }
+}
+
+unsafe impl AtomStorable for CString {}
+
+// Only `'static` references, nothing ties a raw pointer to the lifetime of
+// a shorter borrow. `scoped::ScopedAtom` holds those, and with the
+// `unsafe_borrowed` feature so does `raw::Borrowed`.
+impl<T> IntoRawPtr for &'static T {
     #[inline]
     fn into_raw(self) -> *mut () {
         self as *const _ as *mut ()
     }
 }
 
-impl<'a, T> FromRawPtr for &'a T {
+impl<T> FromRawPtr for &'static T {
     #[inline]
-    unsafe fn from_raw(ptr: *mut ()) -> &'a T {
+    unsafe fn from_raw(ptr: *mut ()) -> &'static T {
         &*(ptr as *mut T)
     }
 }
 
-/// Transforms lifetime of the second pointer to match the first.
-#[inline]
-unsafe fn copy_lifetime<'a, S: ?Sized, T: ?Sized + 'a>(_ptr: &'a S, ptr: &T) -> &'a T {
-    &*(ptr as *const T)
+unsafe impl<T> AtomStorable for &'static T {}
+
+unsafe impl<T> RawDeref for &'static T {}
+
+// Only `'static` borrows, so an item handed out of a `static` pool can move
+// between threads through an Atom and whoever takes it out has the only
+// mutable access to it. Dropping one just ends the borrow, the item stays
+// in the pool.
+impl<T> IntoRawPtr for &'static mut T {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        self as *mut T as *mut ()
+    }
+}
+
+impl<T> FromRawPtr for &'static mut T {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> &'static mut T {
+        &mut *(ptr as *mut T)
+    }
 }
 
-/// Transforms lifetime of the second pointer to match the first.
+unsafe impl<T> AtomStorable for &'static mut T {}
+
+unsafe impl<T> RawDeref for &'static mut T {}
+
+/// Maps a success ordering onto the strongest ordering that is valid for the
+/// failure case of a `compare_exchange`.
 #[inline]
-#[allow(unknown_lints, mut_from_ref)]
-unsafe fn copy_mut_lifetime<'a, S: ?Sized, T: ?Sized + 'a>(_ptr: &'a S, ptr: &mut T) -> &'a mut T {
-    &mut *(ptr as *mut T)
+fn strongest_failure_ordering(order: Ordering) -> Ordering {
+    match order {
+        Ordering::Release => Ordering::Relaxed,
+        Ordering::AcqRel => Ordering::Acquire,
+        order => order,
+    }
+}
+
+/// Keeps a value on a cache line of its own, so that threads hammering it
+/// do not slow down access to whatever would otherwise sit next to it.
+///
+/// Values are aligned to 128 bytes on x86_64 and aarch64, whose prefetchers
+/// pull in cache lines in pairs, and to 64 bytes elsewhere.
+///
+/// ```
+/// use std::mem;
+/// use std::sync::atomic::Ordering;
+/// use atom::{Atom, PaddedAtom};
+///
+/// // neighbouring slots no longer share a cache line
+/// let slots: Vec<PaddedAtom<Box<u32>>> = (0..4).map(|_| PaddedAtom::new(Atom::empty())).collect();
+/// assert!(mem::size_of_val(&slots[0]) >= 64);
+/// slots[1].swap(Box::new(1), Ordering::AcqRel);
+/// assert!(slots[0].is_none(Ordering::Acquire));
+/// ```
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(
+    not(any(target_arch = "x86_64", target_arch = "aarch64")),
+    repr(align(64))
+)]
+pub struct CachePadded<T>(T);
+
+impl<T> CachePadded<T> {
+    /// Pad `value` out to a cache line
+    pub const fn new(value: T) -> CachePadded<T> {
+        CachePadded(value)
+    }
+
+    /// Remove the padding, returning the value
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> From<T> for CachePadded<T> {
+    fn from(value: T) -> CachePadded<T> {
+        CachePadded(value)
+    }
+}
+
+impl<T: Default> Default for CachePadded<T> {
+    fn default() -> CachePadded<T> {
+        CachePadded(T::default())
+    }
+}
+
+impl<T: Debug> Debug for CachePadded<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "CachePadded({:?})", self.0)
+    }
+}
+
+/// An `Atom` on a cache line of its own, for arrays of Atoms that
+/// different threads write to
+pub type PaddedAtom<P> = CachePadded<Atom<P>>;
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A number that stays the same for the life of the calling thread and is
+/// handed out in sequence, for spreading threads over shards
+fn thread_shard() -> usize {
+    SHARD.with(|shard| *shard)
 }
 
 /// This is a restricted version of the Atom. It allows for only
@@ -333,17 +1004,17 @@ unsafe fn copy_mut_lifetime<'a, S: ?Sized, T: ?Sized + 'a>(_ptr: &'a S, ptr: &mu
 #[derive(Debug)]
 pub struct AtomSetOnce<P>
 where
-    P: IntoRawPtr + FromRawPtr,
+    P: AtomStorable,
 {
     inner: Atom<P>,
 }
 
 impl<P> AtomSetOnce<P>
 where
-    P: IntoRawPtr + FromRawPtr,
+    P: AtomStorable,
 {
     /// Create an empty `AtomSetOnce`
-    pub fn empty() -> AtomSetOnce<P> {
+    pub const fn empty() -> AtomSetOnce<P> {
         AtomSetOnce {
             inner: Atom::empty(),
         }
@@ -360,8 +1031,21 @@ pub fn new(value: P) -> AtomSetOnce<P> {
     /// this will return `OK(())` if the value was written,
     /// otherwise a `Err(P)` will be returned, where the value was
     /// the same value that you passed into this function
+    ///
+    /// Threads blocked in `wait` are woken once the value is written.
     pub fn set_if_none(&self, v: P, order: Ordering) -> Option<P> {
-        self.inner.set_if_none(v, order)
+        let rejected = self.inner.set_if_none(v, order);
+        if rejected.is_none() {
+            self.waiters().notify_all();
+        }
+        rejected
+    }
+
+    /// The list threads waiting for this cell park in. It is one of the
+    /// lists shared through `wait::parking`, so an `AtomSetOnce` that nobody
+    /// waits on stays the size of a pointer.
+    fn waiters(&self) -> &'static WaitList {
+        wait::parking(&self.inner)
     }
 
     /// Convert an `AtomSetOnce` into an `Atom`
@@ -369,6 +1053,26 @@ pub fn into_atom(self) -> Atom<P> {
         self.inner
     }
 
+    /// Extract the value, if it was set. Owning the cell means nobody else
+    /// can observe it, so this is a plain read rather than an atomic swap.
+    pub fn into_inner(mut self) -> Option<P> {
+        self.take()
+    }
+
+    /// Remove the value, leaving the cell empty so it can be set again.
+    ///
+    /// This needs exclusive access, shared users of the cell still only
+    /// ever see it go from empty to set.
+    pub fn take(&mut self) -> Option<P> {
+        let ptr = mem::replace(self.inner.inner.get_mut(), ptr::null_mut());
+        unsafe { Atom::inner_from_raw(ptr) }
+    }
+
+    /// Drop the value, if any, and leave the cell empty.
+    pub fn reset(&mut self) {
+        drop(self.take());
+    }
+
     /// Allow access to the atom if exclusive access is granted
     pub fn atom(&mut self) -> &mut Atom<P> {
         &mut self.inner
@@ -380,49 +1084,217 @@ pub fn atom(&mut self) -> &mut Atom<P> {
     pub fn is_none(&self, order: Ordering) -> bool {
         self.inner.is_none(order)
     }
+
+    /// If the Atom is set, call `f` with the value and return its result.
+    ///
+    /// Unlike `get` this works for any `Deref` pointer, the borrow is taken
+    /// through the stored pointer and only lasts for the call to `f`.
+    pub fn get_with<R, F>(&self, order: Ordering, f: F) -> Option<R>
+    where
+        P: Deref,
+        F: FnOnce(&P::Target) -> R,
+    {
+        let ptr = self.inner.inner.load(order);
+        // The cell keeps owning the value, so it must not be dropped here.
+        let value = mem::ManuallyDrop::new(unsafe { Atom::<P>::inner_peek(ptr)? });
+        Some(f(&value))
+    }
 }
 
 impl<T, P> AtomSetOnce<P>
 where
-    P: IntoRawPtr + FromRawPtr + Deref<Target = T>,
+    P: AtomStorable + RawDeref<Target = T>,
 {
     /// If the Atom is set, get the value
+    ///
+    /// This needs `RawDeref`, earlier versions took any `Deref` pointer. A
+    /// pointer type whose raw form is the address of its target only needs
+    /// an `unsafe impl RawDeref`, any other pointer can use `get_with`.
     pub fn get(&self, order: Ordering) -> Option<&T> {
-        let ptr = self.inner.inner.load(order);
-        let val = unsafe { Atom::inner_from_raw(ptr) };
-        val.map(|v: P| {
-            // This is safe since ptr cannot be changed once it is set
-            // which means that this is now a Arc or a Box.
-            let out = unsafe { copy_lifetime(self, &*v) };
-            mem::forget(v);
-            out
-        })
+        self.deref_raw(self.inner.inner.load(order))
+    }
+
+    /// Get the value without checking that the Atom is set.
+    ///
+    /// # Safety
+    ///
+    /// The Atom must have been set, and that store must happen-before this
+    /// call, e.g. an earlier `get(Ordering::Acquire)` returned `Some`.
+    pub unsafe fn get_unchecked(&self) -> &T {
+        let ptr = self.inner.inner.load(Ordering::Relaxed);
+        debug_assert!(!ptr.is_null(), "get_unchecked on an empty AtomSetOnce");
+        self.deref_raw(ptr)
+            .unwrap_or_else(|| unreachable_unchecked())
+    }
+
+    fn deref_raw(&self, ptr: *mut ()) -> Option<&T> {
+        // The pointer cannot change while the cell is shared, so the value
+        // it points at lives as long as `self` does.
+        unsafe { (ptr as *const T).as_ref() }
+    }
+
+    /// Store `v` if the Atom is empty and return a reference to it.
+    ///
+    /// If the Atom was already set the existing value is returned alongside
+    /// the rejected `v`, both come from the same `CAS` so the caller knows
+    /// exactly which value won.
+    pub fn try_insert(&self, v: P) -> Result<&T, (&T, P)> {
+        let new = Atom::raw(v);
+        match self.inner.inner.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                self.waiters().notify_all();
+                Ok(self.deref_raw(new).expect("stored a null pointer"))
+            }
+            Err(current) => {
+                let current = self
+                    .deref_raw(current)
+                    .expect("AtomSetOnce was emptied while shared");
+                Err((current, unsafe { FromRawPtr::from_raw(new) }))
+            }
+        }
+    }
+
+    /// Get the value, initializing it with `f` if the Atom is empty.
+    ///
+    /// Several threads may race to initialize the Atom, in which case each
+    /// of them runs its own `f`. Only the first value to be stored is kept,
+    /// the others are dropped, and every caller gets a reference to the value
+    /// that won.
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> P,
+    {
+        if let Some(v) = self.get(Ordering::Acquire) {
+            return v;
+        }
+        drop(self.set_if_none(f(), Ordering::AcqRel));
+        self.get(Ordering::Acquire)
+            .expect("AtomSetOnce was emptied while shared")
+    }
+
+    /// Get the value, initializing it with `f` if the Atom is empty.
+    ///
+    /// If `f` fails the error is returned and the Atom is left empty, so a
+    /// later call can try again. Racing initializers behave like they do in
+    /// `get_or_init`: the first successful value is kept.
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<P, E>,
+    {
+        if let Some(v) = self.get(Ordering::Acquire) {
+            return Ok(v);
+        }
+        drop(self.set_if_none(f()?, Ordering::AcqRel));
+        Ok(self
+            .get(Ordering::Acquire)
+            .expect("AtomSetOnce was emptied while shared"))
+    }
+
+    /// Block the current thread until the Atom is set, then get the value.
+    pub fn wait(&self) -> &T {
+        self.waiters()
+            .wait_until(|| !self.inner.is_none(Ordering::SeqCst), None);
+        self.get(Ordering::Acquire)
+            .expect("AtomSetOnce was emptied while shared")
+    }
+
+    /// Block the current thread until the Atom is set or `timeout` has
+    /// passed. Returns `None` if the Atom is still empty.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<&T> {
+        let deadline = Instant::now() + timeout;
+        self.waiters()
+            .wait_until(|| !self.inner.is_none(Ordering::SeqCst), Some(deadline));
+        self.get(Ordering::Acquire)
+    }
+
+    /// Wait for the Atom to be set without blocking the thread, resolving to
+    /// the value once it is.
+    ///
+    /// The returned future is cancel safe, dropping it early leaves the Atom
+    /// untouched.
+    #[cfg(feature = "async")]
+    pub fn wait_async<'a>(&'a self) -> impl Future<Output = &'a T>
+    where
+        T: 'a,
+    {
+        wait::WaitAsync::new(self)
     }
 }
 
 impl<T> AtomSetOnce<Box<T>> {
+    /// Store `value` if the Atom is empty, handing it back otherwise.
+    ///
+    /// This mirrors `OnceLock::set`, together with `get_or_init`,
+    /// `into_inner`, `take` and the `OnceLock` conversions it lets code move
+    /// between the two types with few edits.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        match self.set_if_none(Box::new(value), Ordering::AcqRel) {
+            None => Ok(()),
+            Some(rejected) => Err(*rejected),
+        }
+    }
+
     /// If the Atom is set, get the value
     pub fn get_mut(&mut self, order: Ordering) -> Option<&mut T> {
-        let ptr = self.inner.inner.load(order);
-        let val = unsafe { Atom::inner_from_raw(ptr) };
-        val.map(move |mut v: Box<T>| {
-            // This is safe since ptr cannot be changed once it is set
-            // which means that this is now a Arc or a Box.
-            let out = unsafe { copy_mut_lifetime(self, &mut *v) };
-            mem::forget(v);
-            out
-        })
+        // The Box is owned by the cell, which we have exclusive access to.
+        unsafe { (self.inner.inner.load(order) as *mut T).as_mut() }
+    }
+}
+
+impl<T> AtomSetOnce<Pin<Box<T>>> {
+    /// If the Atom is set, get the pinned value
+    pub fn get_pin_mut(&mut self, order: Ordering) -> Option<Pin<&mut T>> {
+        // Owned by the cell like in `AtomSetOnce<Box<T>>::get_mut`, and it
+        // was pinned when it was stored.
+        let value = unsafe { (self.inner.inner.load(order) as *mut T).as_mut()? };
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+}
+
+impl<T> AtomSetOnce<Arc<T>> {
+    /// If the Atom is set and this is the only `Arc` pointing at the value,
+    /// get a mutable reference to it
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        let ptr = *self.inner.inner.get_mut();
+        let mut arc = mem::ManuallyDrop::new(unsafe { Atom::<Arc<T>>::inner_peek(ptr)? });
+        // `Arc::get_mut` checks that no other `Arc` or `Weak` exists, after
+        // that the cell's exclusive borrow keeps it that way.
+        let unique = Arc::get_mut(&mut arc)? as *mut T;
+        unsafe { unique.as_mut() }
+    }
+}
+
+impl<T> From<OnceLock<T>> for AtomSetOnce<Box<T>> {
+    fn from(cell: OnceLock<T>) -> AtomSetOnce<Box<T>> {
+        match cell.into_inner() {
+            Some(v) => AtomSetOnce::new(Box::new(v)),
+            None => AtomSetOnce::empty(),
+        }
+    }
+}
+
+impl<T> From<AtomSetOnce<Box<T>>> for OnceLock<T> {
+    fn from(cell: AtomSetOnce<Box<T>>) -> OnceLock<T> {
+        match cell.into_inner() {
+            Some(v) => OnceLock::from(*v),
+            None => OnceLock::new(),
+        }
     }
 }
 
 impl<T> AtomSetOnce<T>
 where
-    T: Clone + IntoRawPtr + FromRawPtr,
+    T: Clone + AtomStorable,
 {
     /// Duplicate the inner pointer if it is set
     pub fn dup(&self, order: Ordering) -> Option<T> {
         let ptr = self.inner.inner.load(order);
-        let val = unsafe { Atom::inner_from_raw(ptr) };
+        let val = unsafe { Atom::inner_peek(ptr) };
         val.map(|v: T| {
             let out = v.clone();
             mem::forget(v);
@@ -431,9 +1303,127 @@ pub fn dup(&self, order: Ordering) -> Option<T> {
     }
 }
 
+impl<T> Clone for AtomSetOnce<T>
+where
+    T: Clone + AtomStorable,
+{
+    /// Snapshot the cell. The clone holds its own copy of the pointer if
+    /// it was set and is empty otherwise, the two cells are independent
+    /// from then on.
+    fn clone(&self) -> AtomSetOnce<T> {
+        match self.dup(Ordering::Acquire) {
+            Some(v) => AtomSetOnce::new(v),
+            None => AtomSetOnce::empty(),
+        }
+    }
+}
+
 /// This is a utility Trait that fetches the next ptr from
 /// an object.
+///
+/// With the `atom-derive` feature this can be derived for `Box<T>` by adding
+/// `#[derive(GetNextMut)]` to `T`.
 pub trait GetNextMut {
     type NextPtr;
     fn get_next(&mut self) -> &mut Self::NextPtr;
 }
+
+/// Shared access to the next ptr of an object, used to walk a chain
+/// without borrowing it mutably.
+///
+/// `#[derive(GetNextMut)]` implements this too.
+pub trait GetNext: GetNextMut {
+    fn get_next_ref(&self) -> &Self::NextPtr;
+}
+
+/// The slot holding the next pointer of a node in a chain.
+///
+/// This is implemented for a plain `Option<P>` and for `AtomLink<P>`, it
+/// cannot be implemented outside of this crate.
+pub trait NextSlot<P>: sealed::Sealed {
+    #[doc(hidden)]
+    fn slot(&mut self) -> &mut Option<P>;
+    #[doc(hidden)]
+    fn peek(&self) -> Option<&P>;
+}
+
+impl<P> NextSlot<P> for Option<P> {
+    #[inline]
+    fn slot(&mut self) -> &mut Option<P> {
+        self
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<&P> {
+        self.as_ref()
+    }
+}
+
+/// A next pointer to embed in an intrusive node.
+///
+/// `AtomLink` starts out unlinked and its contents can only be changed by
+/// the chain operations of this crate, so a node cannot be pushed while it
+/// still drags a stale chain along behind it. Nodes embed an
+/// `AtomLink<Box<Self>>` and point `GetNextMut` at it.
+///
+/// ```
+/// use atom::{AtomLink, GetNextMut};
+///
+/// struct Node {
+///     link: AtomLink<Box<Node>>,
+///     value: u32,
+/// }
+///
+/// impl GetNextMut for Box<Node> {
+///     type NextPtr = AtomLink<Box<Node>>;
+///     fn get_next(&mut self) -> &mut AtomLink<Box<Node>> {
+///         &mut self.link
+///     }
+/// }
+/// ```
+pub struct AtomLink<P> {
+    next: Option<P>,
+}
+
+impl<P> AtomLink<P> {
+    /// Create an unlinked `AtomLink`
+    pub fn new() -> AtomLink<P> {
+        AtomLink { next: None }
+    }
+
+    /// Check to see if this link points at another node
+    pub fn is_linked(&self) -> bool {
+        self.next.is_some()
+    }
+}
+
+impl<P> Default for AtomLink<P> {
+    fn default() -> AtomLink<P> {
+        AtomLink::new()
+    }
+}
+
+impl<P> Debug for AtomLink<P> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "AtomLink {{ linked: {} }}", self.is_linked())
+    }
+}
+
+impl<P> NextSlot<P> for AtomLink<P> {
+    #[inline]
+    fn slot(&mut self) -> &mut Option<P> {
+        &mut self.next
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<&P> {
+        self.next.as_ref()
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl<P> Sealed for Option<P> {}
+    impl<P> Sealed for super::AtomLink<P> {}
+}
@@ -0,0 +1,387 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Concurrent linked lists.
+//!
+//! In an `AppendList` every node hangs off an `AtomSetOnce` link, so once a
+//! value is in the list it stays put until the list is dropped. Appending
+//! walks to the last link and `try_insert`s there, and iterating only needs
+//! a shared reference and never waits for an append in progress.
+//!
+//! An `OrderedList` keeps its entries sorted by key and can also remove
+//! them, after Harris. Removing first marks the low bit of the node's next
+//! pointer, which stops anyone from linking behind it, and then unlinks
+//! it. Any operation that walks past a marked node helps unlink it. Since
+//! a walk may be standing on a node while it is unlinked, every operation
+//! runs pinned and unlinked nodes are freed through `epoch`.
+
+use std::fmt::{self, Debug, Formatter};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use chain::SetOnceIter;
+use epoch;
+use AtomSetOnce;
+
+struct Link<T> {
+    value: T,
+    next: AtomSetOnce<Box<Link<T>>>,
+}
+
+/// A list that can be appended to by any number of threads at once.
+///
+/// ```
+/// use atom::list::AppendList;
+///
+/// let list = AppendList::new();
+/// let first = list.append(1);
+/// list.append(2);
+/// assert_eq!(*first, 1);
+/// assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2]);
+/// ```
+pub struct AppendList<T> {
+    head: AtomSetOnce<Box<Link<T>>>,
+    // A node at or near the end of the list, null while it is empty. It is
+    // only a hint for where `append` starts walking, so it may lag behind.
+    tail: AtomicPtr<Link<T>>,
+    // values are shared between threads through `iter`
+    _marker: PhantomData<*const T>,
+}
+
+unsafe impl<T: Send> Send for AppendList<T> {}
+unsafe impl<T: Send + Sync> Sync for AppendList<T> {}
+
+impl<T> AppendList<T> {
+    /// Create an empty list
+    pub const fn new() -> AppendList<T> {
+        AppendList {
+            head: AtomSetOnce::empty(),
+            tail: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add `value` to the end of the list, returning a reference to it
+    /// where it now lives.
+    pub fn append(&self, value: T) -> &T {
+        let mut node = Box::new(Link {
+            value,
+            next: AtomSetOnce::empty(),
+        });
+        // Nodes are never removed while the list is borrowed, so the hint
+        // always points at a live node.
+        let mut link = match unsafe { self.tail.load(Ordering::Acquire).as_ref() } {
+            Some(tail) => &tail.next,
+            None => &self.head,
+        };
+        loop {
+            match link.try_insert(node) {
+                Ok(inserted) => {
+                    self.tail.store(
+                        inserted as *const Link<T> as *mut Link<T>,
+                        Ordering::Release,
+                    );
+                    return &inserted.value;
+                }
+                Err((current, back)) => {
+                    node = back;
+                    link = &current.next;
+                }
+            }
+        }
+    }
+
+    /// Iterate over every value appended so far, oldest first
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            links: self.head.iter(Ordering::Acquire, |link| &link.next),
+        }
+    }
+
+    /// Check to see if anything has been appended
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none(Ordering::Acquire)
+    }
+}
+
+impl<T> Default for AppendList<T> {
+    fn default() -> AppendList<T> {
+        AppendList::new()
+    }
+}
+
+impl<T> Drop for AppendList<T> {
+    fn drop(&mut self) {
+        self.head.drop_chain(|link| &mut link.next);
+    }
+}
+
+impl<T: Debug> Debug for AppendList<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Extend<T> for AppendList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.append(value);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for AppendList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> AppendList<T> {
+        let mut list = AppendList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<'a, T> IntoIterator for &'a AppendList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the values of an `AppendList`, see `AppendList::iter`.
+///
+/// Values appended after the iterator reached the end of the list are not
+/// seen, values appended before that are.
+pub struct Iter<'a, T: 'a> {
+    links: SetOnceIter<'a, Link<T>, Box<Link<T>>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.links.next().map(|link| &link.value)
+    }
+}
+
+impl<'a, T> Debug for Iter<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Iter({:?})", self.links)
+    }
+}
+
+/// Set in a node's next pointer once the node has been removed
+const REMOVED: usize = 1;
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    // address of the next entry, tagged with REMOVED
+    next: AtomicUsize,
+}
+
+unsafe fn free_entry<K, V>(addr: usize) {
+    drop(Box::from_raw(addr as *mut Entry<K, V>));
+}
+
+/// A map kept sorted by key that any number of threads can insert into and
+/// remove from at once.
+///
+/// ```
+/// use atom::list::OrderedList;
+///
+/// let list = OrderedList::new();
+/// assert!(list.insert(2, "two").is_ok());
+/// assert!(list.insert(1, "one").is_ok());
+/// assert_eq!(list.insert(1, "uno"), Err((1, "uno")));
+/// assert!(list.remove(&1));
+/// assert!(!list.contains(&1));
+/// assert_eq!(list.get(&2), Some("two"));
+/// ```
+pub struct OrderedList<K, V> {
+    // address of the first entry, never tagged
+    head: AtomicUsize,
+    // entries are dropped by whichever thread unlinks them
+    _marker: PhantomData<*const Entry<K, V>>,
+}
+
+unsafe impl<K: Send, V: Send> Send for OrderedList<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for OrderedList<K, V> {}
+
+impl<K, V> OrderedList<K, V>
+where
+    K: Ord + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    /// Create an empty list
+    pub const fn new() -> OrderedList<K, V> {
+        OrderedList {
+            head: AtomicUsize::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Find the first entry with a key not below `key`, unlinking removed
+    /// entries on the way. Returns the link pointing at it and its address,
+    /// which is 0 at the end of the list.
+    fn find<'g>(&'g self, key: &K, pin: &'g epoch::Guard) -> (&'g AtomicUsize, usize) {
+        'retry: loop {
+            let mut prev = &self.head;
+            let mut current = prev.load(Ordering::Acquire);
+            while current != 0 {
+                // `pin` keeps everything reachable from the list alive
+                let entry = unsafe { &*(current as *const Entry<K, V>) };
+                let next = entry.next.load(Ordering::Acquire);
+                if next & REMOVED != 0 {
+                    let next = next & !REMOVED;
+                    if prev
+                        .compare_exchange(current, next, Ordering::AcqRel, Ordering::Relaxed)
+                        .is_err()
+                    {
+                        // `prev` was removed or changed under us
+                        continue 'retry;
+                    }
+                    let free: unsafe fn(usize) = free_entry::<K, V>;
+                    pin.defer(move || unsafe { free(current) });
+                    current = next;
+                    continue;
+                }
+                if entry.key >= *key {
+                    break;
+                }
+                prev = &entry.next;
+                current = next;
+            }
+            return (prev, current);
+        }
+    }
+
+    fn lookup<'g>(&'g self, key: &K, pin: &'g epoch::Guard) -> Option<&'g Entry<K, V>> {
+        let (_, current) = self.find(key, pin);
+        let entry = unsafe { (current as *const Entry<K, V>).as_ref()? };
+        if entry.key == *key {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Insert `value` under `key`, or hand both back if `key` is already
+    /// present
+    pub fn insert(&self, key: K, value: V) -> Result<(), (K, V)> {
+        let pin = epoch::pin();
+        let raw = Box::into_raw(Box::new(Entry {
+            key,
+            value,
+            next: AtomicUsize::new(0),
+        }));
+        // not shared until the CAS below succeeds
+        let entry = unsafe { &*raw };
+        loop {
+            let (prev, current) = self.find(&entry.key, &pin);
+            if let Some(found) = unsafe { (current as *const Entry<K, V>).as_ref() } {
+                if found.key == entry.key {
+                    let entry = unsafe { *Box::from_raw(raw) };
+                    return Err((entry.key, entry.value));
+                }
+            }
+            entry.next.store(current, Ordering::Relaxed);
+            if prev
+                .compare_exchange(current, raw as usize, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Remove the entry for `key`, returning whether there was one
+    pub fn remove(&self, key: &K) -> bool {
+        let pin = epoch::pin();
+        loop {
+            let entry = match self.lookup(key, &pin) {
+                Some(entry) => entry,
+                None => return false,
+            };
+            let next = entry.next.fetch_or(REMOVED, Ordering::AcqRel);
+            if next & REMOVED == 0 {
+                // This thread removed it, walking past it unlinks it.
+                self.find(key, &pin);
+                return true;
+            }
+            // Someone else got there first, so look again: a new entry for
+            // `key` may have been inserted behind it meanwhile.
+        }
+    }
+
+    /// Check to see if there is an entry for `key`
+    pub fn contains(&self, key: &K) -> bool {
+        self.lookup(key, &epoch::pin()).is_some()
+    }
+
+    /// Get a copy of the value stored under `key`
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let pin = epoch::pin();
+        self.lookup(key, &pin).map(|entry| entry.value.clone())
+    }
+
+    /// Check to see if the list is empty
+    ///
+    /// This only means that the list was empty when it was measured
+    pub fn is_empty(&self) -> bool {
+        let _pin = epoch::pin();
+        let mut current = self.head.load(Ordering::Acquire);
+        while current != 0 {
+            let entry = unsafe { &*(current as *const Entry<K, V>) };
+            let next = entry.next.load(Ordering::Acquire);
+            if next & REMOVED == 0 {
+                return false;
+            }
+            current = next & !REMOVED;
+        }
+        true
+    }
+}
+
+impl<K, V> Default for OrderedList<K, V>
+where
+    K: Ord + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    fn default() -> OrderedList<K, V> {
+        OrderedList::new()
+    }
+}
+
+impl<K, V> Drop for OrderedList<K, V> {
+    fn drop(&mut self) {
+        // Entries still linked, removed or not, belong to the list. Those
+        // already unlinked were handed to `epoch`.
+        let mut current = *self.head.get_mut();
+        while current != 0 {
+            let mut entry = unsafe { Box::from_raw(current as *mut Entry<K, V>) };
+            current = *entry.next.get_mut() & !REMOVED;
+        }
+    }
+}
+
+impl<K, V> Debug for OrderedList<K, V> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "OrderedList({:#x})", self.head.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,195 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An atom for a single thread, for payloads such as `Rc`.
+
+use std::cell::Cell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr;
+
+use {AtomStorable, FromRawPtr, IntoRawPtr, Token};
+
+/// An `Atom` that never leaves its thread while shared.
+///
+/// It keeps the raw pointer in a `Cell`, so it is never `Sync` and holds
+/// any `AtomStorable` payload, including `Rc<T>`. It is `Send`
+/// when `P` is. What it guards against is reentrancy: every method is a
+/// single step that never calls back into user code while the value is
+/// half moved, so a callback or a task on a local executor that runs in
+/// the middle of another use of the same `LocalAtom` sees a consistent
+/// state. Since there is no other thread to order against, none of the
+/// methods take an `Ordering`.
+///
+/// ```
+/// use std::rc::Rc;
+/// use atom::local::LocalAtom;
+///
+/// let slot = LocalAtom::new(Rc::new("first"));
+/// let old = slot.swap(Rc::new("second"));
+/// assert_eq!(old.as_deref(), Some(&"first"));
+/// assert_eq!(slot.load_cloned().as_deref(), Some(&"second"));
+/// ```
+pub struct LocalAtom<P>
+where
+    P: AtomStorable,
+{
+    inner: Cell<*mut ()>,
+    data: PhantomData<P>,
+}
+
+unsafe impl<P> Send for LocalAtom<P> where P: AtomStorable + Send {}
+
+impl<P> LocalAtom<P>
+where
+    P: AtomStorable,
+{
+    /// Create an empty LocalAtom
+    pub const fn empty() -> LocalAtom<P> {
+        LocalAtom {
+            inner: Cell::new(ptr::null_mut()),
+            data: PhantomData,
+        }
+    }
+
+    /// Create a new LocalAtom from a pointer
+    pub fn new(value: P) -> LocalAtom<P> {
+        LocalAtom {
+            inner: Cell::new(value.into_raw()),
+            data: PhantomData,
+        }
+    }
+
+    fn unpack(ptr: *mut ()) -> Option<P> {
+        if ptr.is_null() {
+            None
+        } else {
+            // the pointer came from `into_raw` and is owned by the caller
+            Some(unsafe { FromRawPtr::from_raw(ptr) })
+        }
+    }
+
+    /// Swap a new value into the LocalAtom, returning the old value
+    pub fn swap(&self, v: P) -> Option<P> {
+        Self::unpack(self.inner.replace(v.into_raw()))
+    }
+
+    /// Take the value out of the LocalAtom, leaving it empty
+    pub fn take(&self) -> Option<P> {
+        Self::unpack(self.inner.replace(ptr::null_mut()))
+    }
+
+    /// Store `v` if the LocalAtom is empty. If it was not, `v` is returned.
+    pub fn set_if_none(&self, v: P) -> Option<P> {
+        if self.inner.get().is_null() {
+            self.inner.set(v.into_raw());
+            None
+        } else {
+            Some(v)
+        }
+    }
+
+    /// Get a `Token` identifying the value currently stored
+    pub fn token(&self) -> Token {
+        Token(self.inner.get() as usize)
+    }
+
+    /// Check to see if the LocalAtom is empty
+    pub fn is_none(&self) -> bool {
+        self.inner.get().is_null()
+    }
+
+    /// Consume the LocalAtom, returning its value
+    pub fn into_inner(self) -> Option<P> {
+        self.take()
+    }
+}
+
+impl<P, T> LocalAtom<P>
+where
+    P: AtomStorable + Deref<Target = T>,
+{
+    /// Store `new` if `current` points at the value currently stored.
+    ///
+    /// On success the previous value is returned, on failure `new` is.
+    pub fn compare_exchange(
+        &self,
+        current: Option<&P>,
+        new: Option<P>,
+    ) -> Result<Option<P>, Option<P>> {
+        let current = match current {
+            Some(current) => &**current as *const T as *mut (),
+            None => ptr::null_mut(),
+        };
+        if self.inner.get() != current {
+            return Err(new);
+        }
+        let new = new.map_or(ptr::null_mut(), IntoRawPtr::into_raw);
+        Ok(Self::unpack(self.inner.replace(new)))
+    }
+}
+
+impl<P> LocalAtom<P>
+where
+    P: AtomStorable + Clone,
+{
+    /// Get a copy of the current value.
+    ///
+    /// The value is taken out while it is cloned, so a `clone` that looks
+    /// at this LocalAtom finds it empty, and if it stores a value, that
+    /// value wins and the taken one is dropped.
+    pub fn load_cloned(&self) -> Option<P> {
+        let value = self.take()?;
+        let copy = value.clone();
+        drop(self.set_if_none(value));
+        Some(copy)
+    }
+}
+
+impl<P> Drop for LocalAtom<P>
+where
+    P: AtomStorable,
+{
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
+impl<P> Default for LocalAtom<P>
+where
+    P: AtomStorable,
+{
+    fn default() -> LocalAtom<P> {
+        LocalAtom::empty()
+    }
+}
+
+impl<P> From<Option<P>> for LocalAtom<P>
+where
+    P: AtomStorable,
+{
+    fn from(value: Option<P>) -> LocalAtom<P> {
+        value.map_or_else(LocalAtom::empty, LocalAtom::new)
+    }
+}
+
+impl<P> Debug for LocalAtom<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "LocalAtom({:?})", self.inner.get())
+    }
+}
@@ -0,0 +1,121 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A single slot that keeps only the newest value.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use {Atom, WaitList};
+
+/// A "latest value wins" slot whose consumer can block until it is filled.
+///
+/// `put` swaps the new value in, handing back the one it displaced, and
+/// then wakes the consumer. `take_blocking` registers itself before it looks at the slot
+/// again, so a `put` that lands between the two is never missed.
+///
+/// It is meant for one consumer, but several can share it: each `put` is
+/// taken by exactly one of them.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use atom::mailbox::SyncMailbox;
+///
+/// let commands = Arc::new(SyncMailbox::new());
+/// let worker = {
+///     let commands = commands.clone();
+///     thread::spawn(move || commands.take_blocking())
+/// };
+/// commands.put("stop");
+/// assert_eq!(worker.join().unwrap(), "stop");
+/// ```
+pub struct SyncMailbox<T> {
+    slot: Atom<Box<T>>,
+    waiters: WaitList,
+}
+
+impl<T> SyncMailbox<T> {
+    /// Create an empty mailbox
+    pub const fn new() -> SyncMailbox<T> {
+        SyncMailbox {
+            slot: Atom::empty(),
+            waiters: WaitList::new(),
+        }
+    }
+
+    /// Store `value`, returning the value it replaced if the consumer had
+    /// not taken it yet
+    pub fn put(&self, value: T) -> Option<T> {
+        let old = self.slot.swap(Box::new(value), Ordering::AcqRel);
+        self.waiters.notify_all();
+        old.map(|old| *old)
+    }
+
+    /// Take the value if there is one
+    pub fn take(&self) -> Option<T> {
+        self.slot.take(Ordering::Acquire).map(|v| *v)
+    }
+
+    /// Block the current thread until a value is available and take it
+    pub fn take_blocking(&self) -> T {
+        self.take_until(None)
+            .expect("waiting without a deadline returned empty")
+    }
+
+    /// Block the current thread until a value is available or `timeout`
+    /// has passed, taking the value if there is one
+    pub fn take_timeout(&self, timeout: Duration) -> Option<T> {
+        self.take_until(Some(Instant::now() + timeout))
+    }
+
+    fn take_until(&self, deadline: Option<Instant>) -> Option<T> {
+        loop {
+            if let Some(v) = self.take() {
+                return Some(v);
+            }
+            let full = self
+                .waiters
+                .wait_until(|| !self.slot.is_none(Ordering::SeqCst), deadline);
+            // another consumer may beat us to it, then go back to waiting
+            if !full {
+                return self.take();
+            }
+        }
+    }
+
+    /// Check to see if there is a value waiting
+    pub fn is_empty(&self) -> bool {
+        self.slot.is_none(Ordering::Acquire)
+    }
+
+    /// Consume the mailbox, returning the value in it
+    pub fn into_inner(self) -> Option<T> {
+        self.take()
+    }
+}
+
+impl<T> Default for SyncMailbox<T> {
+    fn default() -> SyncMailbox<T> {
+        SyncMailbox::new()
+    }
+}
+
+impl<T> Debug for SyncMailbox<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let state = if self.is_empty() { "empty" } else { "full" };
+        write!(f, "SyncMailbox({})", state)
+    }
+}
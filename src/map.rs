@@ -0,0 +1,310 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An insert-once concurrent hash map.
+//!
+//! Each bucket is a chain of `AtomSetOnce` links, so an entry never moves or
+//! changes once it is in the map. Lookups follow the chain with plain
+//! loads, inserts `try_insert` at the end of it and, if another thread got
+//! that link first, keep walking from the entry it put there.
+//!
+//! The number of buckets is fixed when the map is created. Chains simply
+//! get longer as the map fills up, so size it for the number of keys you
+//! expect.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug, Formatter};
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use AtomSetOnce;
+
+const DEFAULT_BUCKETS: usize = 64;
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    next: Link<K, V>,
+}
+
+type Link<K, V> = AtomSetOnce<Box<Entry<K, V>>>;
+
+/// The new entry, or the one that was already there and the rejected one
+type Inserted<'a, K, V> = Result<&'a Entry<K, V>, (&'a Entry<K, V>, Box<Entry<K, V>>)>;
+
+/// A hash map whose entries, once inserted, are never replaced or removed.
+///
+/// ```
+/// use atom::map::AtomMap;
+///
+/// let map = AtomMap::new();
+/// let first = map.get_or_insert_with("answer", || 42);
+/// let second = map.get_or_insert_with("answer", || unreachable!());
+/// assert!(std::ptr::eq(first, second));
+/// assert_eq!(map.get("answer"), Some(&42));
+/// ```
+pub struct AtomMap<K, V, S = RandomState> {
+    buckets: Box<[Link<K, V>]>,
+    len: AtomicUsize,
+    hasher: S,
+    // keys and values are shared between threads by reference
+    _marker: PhantomData<*const Entry<K, V>>,
+}
+
+unsafe impl<K: Send, V: Send, S: Send> Send for AtomMap<K, V, S> {}
+unsafe impl<K: Send + Sync, V: Send + Sync, S: Sync> Sync for AtomMap<K, V, S> {}
+
+impl<K, V> AtomMap<K, V, RandomState>
+where
+    K: Hash + Eq,
+{
+    /// Create an empty map
+    pub fn new() -> AtomMap<K, V, RandomState> {
+        AtomMap::with_buckets(DEFAULT_BUCKETS)
+    }
+
+    /// Create an empty map with `buckets` buckets
+    pub fn with_buckets(buckets: usize) -> AtomMap<K, V, RandomState> {
+        AtomMap::with_buckets_and_hasher(buckets, RandomState::new())
+    }
+}
+
+impl<K, V, S> AtomMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Create an empty map with `buckets` buckets that hashes keys with
+    /// `hasher`
+    pub fn with_buckets_and_hasher(buckets: usize, hasher: S) -> AtomMap<K, V, S> {
+        assert!(buckets > 0, "a map needs at least one bucket");
+        AtomMap {
+            buckets: (0..buckets).map(|_| AtomSetOnce::empty()).collect(),
+            len: AtomicUsize::new(0),
+            hasher,
+            _marker: PhantomData,
+        }
+    }
+
+    fn bucket<Q>(&self, key: &Q) -> &Link<K, V>
+    where
+        Q: Hash + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        &self.buckets[(hash % self.buckets.len() as u64) as usize]
+    }
+
+    /// Walk the chain from `link` looking for `key`. Returns the entry if
+    /// it is there, or the empty link at the end of the chain.
+    fn find<'a, Q>(
+        &'a self,
+        mut link: &'a Link<K, V>,
+        key: &Q,
+    ) -> Result<&'a Entry<K, V>, &'a Link<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        while let Some(entry) = link.get(Ordering::Acquire) {
+            if entry.key.borrow() == key {
+                return Ok(entry);
+            }
+            link = &entry.next;
+        }
+        Err(link)
+    }
+
+    /// Link `entry` in unless its key turns up first
+    fn insert_entry(&self, mut entry: Box<Entry<K, V>>) -> Inserted<'_, K, V> {
+        let mut link = self.bucket(&entry.key);
+        loop {
+            link = match self.find(link, &entry.key) {
+                Ok(existing) => return Err((existing, entry)),
+                Err(link) => link,
+            };
+            match link.try_insert(entry) {
+                Ok(inserted) => {
+                    self.len.fetch_add(1, Ordering::Relaxed);
+                    return Ok(inserted);
+                }
+                // Someone else extended the chain, their entry may be for
+                // the same key.
+                Err((_, back)) => entry = back,
+            }
+        }
+    }
+
+    /// Get the value for `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(self.bucket(key), key)
+            .ok()
+            .map(|entry| &entry.value)
+    }
+
+    /// Get the key and value stored for `key`
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(self.bucket(key), key)
+            .ok()
+            .map(|entry| (&entry.key, &entry.value))
+    }
+
+    /// Check to see if there is a value for `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Insert `value` for `key` unless there already is one. On failure the
+    /// value already in the map is returned together with `key` and
+    /// `value`.
+    pub fn try_insert(&self, key: K, value: V) -> Result<&V, (&V, K, V)> {
+        let entry = Box::new(Entry {
+            key,
+            value,
+            next: AtomSetOnce::empty(),
+        });
+        match self.insert_entry(entry) {
+            Ok(entry) => Ok(&entry.value),
+            Err((existing, entry)) => Err((&existing.value, entry.key, entry.value)),
+        }
+    }
+
+    /// Get the value for `key`, inserting the result of `f` if there is
+    /// none.
+    ///
+    /// `f` is only called if `key` is missing, but threads that race to
+    /// insert the same key may each call it. Only one result is kept, the
+    /// others are dropped.
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> &V
+    where
+        F: FnOnce() -> V,
+    {
+        if let Ok(entry) = self.find(self.bucket(&key), &key) {
+            return &entry.value;
+        }
+        let entry = Box::new(Entry {
+            key,
+            value: f(),
+            next: AtomSetOnce::empty(),
+        });
+        match self.insert_entry(entry) {
+            Ok(entry) | Err((entry, _)) => &entry.value,
+        }
+    }
+
+    /// The number of entries in the map
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Check to see if the map is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over every entry inserted so far, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets.iter(),
+            link: None,
+        }
+    }
+}
+
+impl<K, V> Default for AtomMap<K, V, RandomState>
+where
+    K: Hash + Eq,
+{
+    fn default() -> AtomMap<K, V, RandomState> {
+        AtomMap::new()
+    }
+}
+
+impl<K, V, S> Drop for AtomMap<K, V, S> {
+    fn drop(&mut self) {
+        for bucket in self.buckets.iter_mut() {
+            bucket.drop_chain(|entry| &mut entry.next);
+        }
+    }
+}
+
+impl<K, V, S> Debug for AtomMap<K, V, S>
+where
+    K: Hash + Eq + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a AtomMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of an `AtomMap`, see `AtomMap::iter`
+pub struct Iter<'a, K: 'a, V: 'a> {
+    buckets: slice::Iter<'a, Link<K, V>>,
+    link: Option<&'a Link<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            let link = match self.link {
+                Some(link) => link,
+                None => self.buckets.next()?,
+            };
+            match link.get(Ordering::Acquire) {
+                Some(entry) => {
+                    self.link = Some(&entry.next);
+                    return Some((&entry.key, &entry.value));
+                }
+                None => self.link = None,
+            }
+        }
+    }
+}
+
+impl<'a, K, V> Debug for Iter<'a, K, V> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Iter({} buckets left)", self.buckets.len())
+    }
+}
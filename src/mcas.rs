@@ -0,0 +1,443 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Compare and swap several Atoms as one operation.
+//!
+//! This is the descriptor based k-CAS of Harris, Fraser and Pratt. A
+//! transaction writes a descriptor into each of its atoms in address order,
+//! using a restricted double compare single swap (RDCSS) so that nothing is
+//! installed once the outcome is decided. Whoever runs into a descriptor
+//! finishes that transaction before going on, so a stalled thread never
+//! blocks the others. The two low bits of the word tell a value from either
+//! kind of descriptor, which is why the payload has to be aligned to at
+//! least 4 bytes. Descriptors are freed through `epoch`.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::hint;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use epoch;
+use {FromRawPtr, RawDeref, Token};
+
+/// The word holds an `Rdcss` descriptor
+const RDCSS: usize = 0b01;
+/// The word holds a `Descriptor`
+const MCAS: usize = 0b10;
+const TAGS: usize = RDCSS | MCAS;
+
+const UNDECIDED: usize = 0;
+const SUCCEEDED: usize = 1;
+const FAILED: usize = 2;
+
+/// Set in `Descriptor::helpers` once the transaction has returned
+const FINISHED: usize = 1 << (usize::BITS - 1);
+
+/// An Atom that can take part in a `transaction`.
+///
+/// On its own it behaves like an `Atom`, except that every operation is
+/// `SeqCst` and may have to finish a transaction that is in its way.
+///
+/// ```
+/// use atom::mcas::{transaction, McasAtom};
+///
+/// let from = McasAtom::new(Box::new(10u32));
+/// let to = McasAtom::new(Box::new(0u32));
+///
+/// let old = transaction(vec![
+///     (&from, from.token(), Some(Box::new(7))),
+///     (&to, to.token(), Some(Box::new(3))),
+/// ]);
+/// assert_eq!(old.unwrap(), vec![Some(Box::new(10)), Some(Box::new(0))]);
+/// assert_eq!(from.take(), Some(Box::new(7)));
+/// ```
+pub struct McasAtom<P>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    inner: AtomicUsize,
+    data: PhantomData<UnsafeCell<P>>,
+}
+
+unsafe impl<P> Send for McasAtom<P>
+where
+    P: RawDeref + FromRawPtr + Send,
+    P::Target: Sized,
+{
+}
+
+unsafe impl<P> Sync for McasAtom<P>
+where
+    P: RawDeref + FromRawPtr + Send,
+    P::Target: Sized,
+{
+}
+
+impl<P> McasAtom<P>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    const ALIGNED: () = assert!(
+        mem::align_of::<P::Target>() > TAGS,
+        "the payload is not aligned enough to tell it from a descriptor"
+    );
+
+    /// Create an empty McasAtom
+    pub const fn empty() -> McasAtom<P> {
+        McasAtom {
+            inner: AtomicUsize::new(0),
+            data: PhantomData,
+        }
+    }
+
+    /// Create a McasAtom holding `value`
+    pub fn new(value: P) -> McasAtom<P> {
+        McasAtom {
+            inner: AtomicUsize::new(Self::pack(Some(value))),
+            data: PhantomData,
+        }
+    }
+
+    fn pack(value: Option<P>) -> usize {
+        let () = Self::ALIGNED;
+        match value {
+            Some(value) => value.into_raw() as usize,
+            None => 0,
+        }
+    }
+
+    fn unpack(word: usize) -> Option<P> {
+        debug_assert!(word & TAGS == 0, "unpacking a descriptor");
+        if word == 0 {
+            None
+        } else {
+            // the pointer was produced by `pack` and is owned by the caller
+            Some(unsafe { FromRawPtr::from_raw(word as *mut ()) })
+        }
+    }
+
+    /// Swap a new value into the McasAtom, returning the old value
+    pub fn swap(&self, v: P) -> Option<P> {
+        let new = Self::pack(Some(v));
+        let _pin = epoch::pin();
+        loop {
+            let current = read(&self.inner);
+            if self
+                .inner
+                .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return Self::unpack(current);
+            }
+        }
+    }
+
+    /// Take the value out of the McasAtom, leaving it empty
+    pub fn take(&self) -> Option<P> {
+        let _pin = epoch::pin();
+        loop {
+            let current = read(&self.inner);
+            if current == 0
+                || self
+                    .inner
+                    .compare_exchange(current, 0, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                return Self::unpack(current);
+            }
+        }
+    }
+
+    /// Store `new` if the McasAtom still holds the value `current` was
+    /// taken from, returning the old value. On failure `new` is handed back.
+    pub fn compare_exchange(&self, current: Token, new: Option<P>) -> Result<Option<P>, Option<P>> {
+        let new = Self::pack(new);
+        let _pin = epoch::pin();
+        loop {
+            if read(&self.inner) != current.0 {
+                return Err(Self::unpack(new));
+            }
+            if self
+                .inner
+                .compare_exchange(current.0, new, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return Ok(Self::unpack(current.0));
+            }
+        }
+    }
+
+    /// Get a `Token` identifying the value currently stored
+    pub fn token(&self) -> Token {
+        let _pin = epoch::pin();
+        Token(read(&self.inner))
+    }
+
+    /// Check to see if the McasAtom is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self) -> bool {
+        self.token().is_none()
+    }
+
+    /// Consume the McasAtom, returning its value
+    pub fn into_inner(mut self) -> Option<P> {
+        // every transaction that saw this atom has returned, so no
+        // descriptor is left in it
+        Self::unpack(mem::replace(self.inner.get_mut(), 0))
+    }
+}
+
+impl<P> McasAtom<P>
+where
+    P: RawDeref + FromRawPtr + Copy,
+    P::Target: Sized,
+{
+    /// Read the value. Only pointers that do not own what they point at,
+    /// such as `&T`, can be copied out while the McasAtom keeps them.
+    pub fn load(&self) -> Option<P> {
+        let _pin = epoch::pin();
+        Self::unpack(read(&self.inner))
+    }
+}
+
+/// Store every new value if every atom still holds the value its token was
+/// taken from, as one atomic step.
+///
+/// On success the old values are returned, otherwise nothing is changed and
+/// the new values are handed back, both in the order they were passed in.
+/// Before returning, the caller waits for threads that are midway through
+/// helping this transaction along, since they may still touch the atoms.
+///
+/// # Panics
+///
+/// If the same atom appears more than once.
+pub fn transaction<'a, P, I>(entries: I) -> Result<Vec<Option<P>>, Vec<Option<P>>>
+where
+    P: RawDeref + FromRawPtr + 'a,
+    P::Target: Sized,
+    I: IntoIterator<Item = (&'a McasAtom<P>, Token, Option<P>)>,
+{
+    let mut entries: Vec<_> = entries.into_iter().enumerate().collect();
+    entries.sort_by_key(|&(_, (atom, _, _))| &atom.inner as *const AtomicUsize as usize);
+    for pair in entries.windows(2) {
+        assert!(
+            !std::ptr::eq((pair[0].1).0, (pair[1].1).0),
+            "an atom appears twice in one transaction"
+        );
+    }
+
+    let mut order = Vec::with_capacity(entries.len());
+    let mut words = Vec::with_capacity(entries.len());
+    for (index, (atom, expected, new)) in entries {
+        order.push(index);
+        words.push(Entry {
+            addr: &atom.inner as *const AtomicUsize as usize,
+            expected: expected.0,
+            new: McasAtom::pack(new),
+        });
+    }
+    // a token with tag bits can never match, and would confuse the helpers
+    let possible = words.iter().all(|entry| entry.expected & TAGS == 0);
+
+    let desc = Box::into_raw(Box::new(Descriptor {
+        status: AtomicUsize::new(if possible { UNDECIDED } else { FAILED }),
+        helpers: AtomicUsize::new(0),
+        entries: words,
+    }));
+    let pin = epoch::pin();
+    let cd = unsafe { &*desc };
+    let succeeded = run(cd, &pin);
+    if cd.helpers.fetch_or(FINISHED, Ordering::SeqCst) != 0 {
+        while cd.helpers.load(Ordering::SeqCst) != FINISHED {
+            hint::spin_loop();
+        }
+    }
+
+    let mut values: Vec<Option<P>> = (0..order.len()).map(|_| None).collect();
+    for (&index, entry) in order.iter().zip(&cd.entries) {
+        let word = if succeeded { entry.expected } else { entry.new };
+        values[index] = McasAtom::unpack(word);
+    }
+    retire(&pin, desc);
+    if succeeded {
+        Ok(values)
+    } else {
+        Err(values)
+    }
+}
+
+struct Entry {
+    addr: usize,
+    expected: usize,
+    new: usize,
+}
+
+struct Descriptor {
+    status: AtomicUsize,
+    /// Threads currently helping, plus `FINISHED`
+    helpers: AtomicUsize,
+    /// Sorted by address
+    entries: Vec<Entry>,
+}
+
+/// Swap `new` into `addr` if it holds `expected` and `status` is undecided
+struct Rdcss {
+    status: usize,
+    addr: usize,
+    expected: usize,
+    new: usize,
+}
+
+/// The atom at `addr`, which is kept alive by whoever started the
+/// transaction until every helper is done with it
+fn word<'a>(addr: usize) -> &'a AtomicUsize {
+    unsafe { &*(addr as *const AtomicUsize) }
+}
+
+/// Read the value of an atom, finishing whatever is in the way
+fn read(atom: &AtomicUsize) -> usize {
+    loop {
+        let found = atom.load(Ordering::SeqCst);
+        match found & TAGS {
+            RDCSS => complete(found),
+            MCAS => help(found),
+            _ => return found,
+        }
+    }
+}
+
+/// Drive the transaction at `tagged` to its end, unless it already returned
+fn help(tagged: usize) {
+    let cd = unsafe { &*((tagged & !TAGS) as *const Descriptor) };
+    if cd.helpers.fetch_add(1, Ordering::SeqCst) & FINISHED == 0 {
+        run(cd, &epoch::pin());
+    }
+    cd.helpers.fetch_sub(1, Ordering::SeqCst);
+}
+
+fn run(cd: &Descriptor, pin: &epoch::Guard) -> bool {
+    let tagged = cd as *const Descriptor as usize | MCAS;
+    if cd.status.load(Ordering::SeqCst) == UNDECIDED {
+        let mut outcome = SUCCEEDED;
+        'entries: for entry in &cd.entries {
+            loop {
+                let found = rdcss(cd, tagged, entry, pin);
+                if found == tagged || found == entry.expected {
+                    break;
+                }
+                if found & TAGS == MCAS {
+                    help(found);
+                } else {
+                    outcome = FAILED;
+                    break 'entries;
+                }
+            }
+        }
+        let _ = cd
+            .status
+            .compare_exchange(UNDECIDED, outcome, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    let succeeded = cd.status.load(Ordering::SeqCst) == SUCCEEDED;
+    for entry in &cd.entries {
+        let value = if succeeded { entry.new } else { entry.expected };
+        let _ =
+            word(entry.addr).compare_exchange(tagged, value, Ordering::SeqCst, Ordering::SeqCst);
+    }
+    succeeded
+}
+
+/// Install `tagged` in the entry's atom, returning what was there before
+fn rdcss(cd: &Descriptor, tagged: usize, entry: &Entry, pin: &epoch::Guard) -> usize {
+    let desc = Box::into_raw(Box::new(Rdcss {
+        status: &cd.status as *const AtomicUsize as usize,
+        addr: entry.addr,
+        expected: entry.expected,
+        new: tagged,
+    }));
+    let mine = desc as usize | RDCSS;
+    let atom = word(entry.addr);
+    loop {
+        match atom.compare_exchange(entry.expected, mine, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(found) => {
+                complete(mine);
+                retire(pin, desc);
+                return found;
+            }
+            Err(found) if found & TAGS == RDCSS => complete(found),
+            Err(found) => {
+                // never published
+                drop(unsafe { Box::from_raw(desc) });
+                return found;
+            }
+        }
+    }
+}
+
+/// Replace an installed `Rdcss` with its new or its expected value
+fn complete(tagged: usize) {
+    let desc = unsafe { &*((tagged & !TAGS) as *const Rdcss) };
+    let status = word(desc.status).load(Ordering::SeqCst);
+    let value = if status == UNDECIDED {
+        desc.new
+    } else {
+        desc.expected
+    };
+    let _ = word(desc.addr).compare_exchange(tagged, value, Ordering::SeqCst, Ordering::SeqCst);
+}
+
+/// Free a descriptor once no thread can still be reading it.
+fn retire<T>(pin: &epoch::Guard, ptr: *mut T)
+where
+    T: Send + 'static,
+{
+    // raw pointers are not Send, the address is
+    let addr = ptr as usize;
+    pin.defer(move || drop(unsafe { Box::from_raw(addr as *mut T) }));
+}
+
+impl<P> Drop for McasAtom<P>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    fn drop(&mut self) {
+        drop(Self::unpack(*self.inner.get_mut()));
+    }
+}
+
+impl<P> Default for McasAtom<P>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    fn default() -> McasAtom<P> {
+        McasAtom::empty()
+    }
+}
+
+impl<P> Debug for McasAtom<P>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "McasAtom({:?})", self.token())
+    }
+}
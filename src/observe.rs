@@ -0,0 +1,163 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Atoms that call back whenever their contents are replaced.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::Ordering;
+
+use {Atom, AtomSetOnce, AtomStorable, Token};
+
+type Callback<P> = dyn Fn(Option<&P>) + Send + Sync;
+
+/// The callback installed with `ObservedAtom::watch`
+struct Hook<P> {
+    f: Box<Callback<P>>,
+}
+
+/// An `Atom` with an optional callback that runs after every successful
+/// `swap` or `set_if_none`.
+///
+/// The callback is handed the value that the store displaced, `None` if
+/// the Atom was empty. It cannot be handed the new value, which another
+/// thread may already have swapped out and dropped by the time the callback
+/// runs. The callback is installed at most once and never removed, so when
+/// there is none a store costs a single extra load.
+///
+/// Stores from several threads run the callback concurrently, hence `Fn`
+/// and not `FnMut`, use atomics or a lock for whatever state it updates.
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use atom::observe::ObservedAtom;
+///
+/// let swaps = Arc::new(AtomicUsize::new(0));
+/// let atom = ObservedAtom::empty();
+/// let counter = swaps.clone();
+/// assert!(atom.watch(move |_: Option<&Box<u32>>| {
+///     counter.fetch_add(1, Ordering::Relaxed);
+/// }));
+/// atom.swap(Box::new(1), Ordering::AcqRel);
+/// atom.swap(Box::new(2), Ordering::AcqRel);
+/// assert_eq!(swaps.load(Ordering::Relaxed), 2);
+/// ```
+pub struct ObservedAtom<P>
+where
+    P: AtomStorable,
+{
+    atom: Atom<P>,
+    hook: AtomSetOnce<Box<Hook<P>>>,
+}
+
+impl<P> ObservedAtom<P>
+where
+    P: AtomStorable,
+{
+    /// Create an empty Atom without a callback
+    pub const fn empty() -> ObservedAtom<P> {
+        ObservedAtom {
+            atom: Atom::empty(),
+            hook: AtomSetOnce::empty(),
+        }
+    }
+
+    /// Create an Atom holding `value`, without a callback
+    pub fn new(value: P) -> ObservedAtom<P> {
+        ObservedAtom {
+            atom: Atom::new(value),
+            hook: AtomSetOnce::empty(),
+        }
+    }
+
+    /// Install `f` to run after every successful store. Returns false if a
+    /// callback was already installed, `f` is dropped in that case.
+    pub fn watch<F>(&self, f: F) -> bool
+    where
+        F: Fn(Option<&P>) + Send + Sync + 'static,
+    {
+        let hook = Box::new(Hook { f: Box::new(f) });
+        self.hook.set_if_none(hook, Ordering::AcqRel).is_none()
+    }
+
+    fn notify(&self, old: Option<&P>) {
+        if let Some(hook) = self.hook.get(Ordering::Acquire) {
+            (hook.f)(old);
+        }
+    }
+
+    /// Swap a new value into the Atom and run the callback with the old one,
+    /// which is then returned
+    pub fn swap(&self, v: P, order: Ordering) -> Option<P> {
+        let old = self.atom.swap(v, order);
+        self.notify(old.as_ref());
+        old
+    }
+
+    /// Set the value if the Atom is empty, running the callback if it was.
+    /// Otherwise `v` is handed back and the callback does not run.
+    pub fn set_if_none(&self, v: P, order: Ordering) -> Option<P> {
+        let rejected = self.atom.set_if_none(v, order);
+        if rejected.is_none() {
+            self.notify(None);
+        }
+        rejected
+    }
+
+    /// Take the value out of the Atom. This is not a store, so the
+    /// callback does not run.
+    pub fn take(&self, order: Ordering) -> Option<P> {
+        self.atom.take(order)
+    }
+
+    /// Check to see if the Atom is None
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.atom.is_none(order)
+    }
+
+    /// Get a `Token` identifying the value currently stored in the Atom
+    pub fn token(&self, order: Ordering) -> Token {
+        self.atom.token(order)
+    }
+
+    /// Consume the Atom, returning its contents and dropping the callback
+    pub fn into_inner(self) -> Option<P> {
+        self.atom.take(Ordering::Relaxed)
+    }
+}
+
+impl<P> Default for ObservedAtom<P>
+where
+    P: AtomStorable,
+{
+    fn default() -> ObservedAtom<P> {
+        ObservedAtom::empty()
+    }
+}
+
+impl<P> Debug for ObservedAtom<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let hook = if self.hook.is_none(Ordering::Acquire) {
+            "unwatched"
+        } else {
+            "watched"
+        };
+        write!(f, "ObservedAtom({:?}, {})", self.atom, hook)
+    }
+}
@@ -0,0 +1,217 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A channel for sending a single value.
+//!
+//! The value travels through one `Atom<Box<T>>`. A word of flags records
+//! what each side has done, so a `Sender` dropped without sending wakes a
+//! blocked `recv` with an error instead of leaving it parked forever, and a
+//! `send` to a `Receiver` that is already gone hands the value back.
+//!
+//! With the `async` feature the `Receiver` is also a `Future`. Dropping it
+//! before it completes is the same as dropping an unused `Receiver`, and a
+//! value sent meanwhile is dropped with the channel.
+//!
+//! The error types are the ones from `std::sync::mpsc`, a `RecvError` means
+//! the `Sender` was dropped without sending.
+
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async")]
+use task::AtomicWaker;
+use wait::WaitList;
+use Atom;
+
+/// The value has been stored
+const SENT: usize = 0b001;
+/// The sender is gone, with or without sending
+const SENDER_GONE: usize = 0b010;
+/// The receiver is gone
+const RECEIVER_GONE: usize = 0b100;
+
+struct Shared<T> {
+    value: Atom<Box<T>>,
+    state: AtomicUsize,
+    waiters: WaitList,
+    #[cfg(feature = "async")]
+    task: AtomicWaker,
+}
+
+/// Create a connected `Sender` and `Receiver`
+///
+/// ```
+/// use std::thread;
+/// use atom::oneshot;
+///
+/// let (tx, rx) = oneshot::channel();
+/// thread::spawn(move || tx.send("done").unwrap());
+/// assert_eq!(rx.recv(), Ok("done"));
+/// ```
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        value: Atom::empty(),
+        state: AtomicUsize::new(0),
+        waiters: WaitList::new(),
+        #[cfg(feature = "async")]
+        task: AtomicWaker::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// The sending half of a oneshot channel
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Send `value`, or hand it back if the `Receiver` is gone
+    pub fn send(self, value: T) -> Result<(), SendError<T>> {
+        let shared = &*self.shared;
+        if shared.state.load(Ordering::Acquire) & RECEIVER_GONE != 0 {
+            return Err(SendError(value));
+        }
+        let prev = shared.value.swap(Box::new(value), Ordering::Release);
+        debug_assert!(prev.is_none());
+        // The drop below adds SENDER_GONE and wakes the receiver.
+        let state = shared.state.fetch_or(SENT, Ordering::AcqRel);
+        if state & RECEIVER_GONE != 0 {
+            // The receiver left before it could have seen SENT.
+            if let Some(value) = shared.value.take(Ordering::Acquire) {
+                return Err(SendError(*value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check to see if the `Receiver` has been dropped
+    pub fn is_closed(&self) -> bool {
+        self.shared.state.load(Ordering::Acquire) & RECEIVER_GONE != 0
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.state.fetch_or(SENDER_GONE, Ordering::AcqRel);
+        self.shared.waiters.notify_all();
+        #[cfg(feature = "async")]
+        self.shared.task.wake();
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Sender({:#b})",
+            self.shared.state.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// The receiving half of a oneshot channel
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    fn done(&self) -> bool {
+        self.shared.state.load(Ordering::Acquire) & SENDER_GONE != 0
+    }
+
+    /// Take the value if it has been sent
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.shared.value.take(Ordering::Acquire) {
+            return Ok(*value);
+        }
+        if !self.done() {
+            return Err(TryRecvError::Empty);
+        }
+        // the value may have landed between the take and the check
+        match self.shared.value.take(Ordering::Acquire) {
+            Some(value) => Ok(*value),
+            None => Err(TryRecvError::Disconnected),
+        }
+    }
+
+    /// Block until the value is sent, or the `Sender` is dropped without
+    /// sending
+    pub fn recv(mut self) -> Result<T, RecvError> {
+        self.shared.waiters.wait_until(|| self.done(), None);
+        self.try_recv().map_err(|_| RecvError)
+    }
+
+    /// Like `recv`, but give up after `timeout`
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        self.shared
+            .waiters
+            .wait_until(|| self.done(), Some(deadline));
+        self.try_recv().map_err(|err| match err {
+            TryRecvError::Empty => RecvTimeoutError::Timeout,
+            TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<T, RecvError>> {
+        let this = self.get_mut();
+        match this.try_recv() {
+            Ok(value) => return Poll::Ready(Ok(value)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => {}
+        }
+        this.shared.task.register(cx.waker());
+        // the sender may have finished before the waker was in place
+        match this.try_recv() {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.fetch_or(RECEIVER_GONE, Ordering::AcqRel);
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Receiver({:#b})",
+            self.shared.state.load(Ordering::Relaxed)
+        )
+    }
+}
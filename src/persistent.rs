@@ -0,0 +1,277 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A persistent LIFO.
+//!
+//! Nodes are immutable and `Arc`-linked, so pushing and popping only ever
+//! move the head and a snapshot is nothing more than a clone of the head.
+//! Snapshots keep every node they can reach alive by reference count,
+//! independently of the stack.
+//!
+//! Taking a reference to the head races with a pop dropping the stack's
+//! own reference to it. Loads of the head therefore run pinned, and the
+//! reference the stack held on a node it no longer points at is dropped
+//! through `epoch`.
+
+use std::fmt::{self, Debug, Formatter};
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use epoch;
+use {Atom, FromRawPtr, IntoRawPtr};
+
+struct Node<T> {
+    value: T,
+    next: Option<Arc<Node<T>>>,
+}
+
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        // Unlink nodes this was the last owner of one at a time, instead of
+        // letting a long chain drop recursively.
+        let mut next = self.next.take();
+        while let Some(node) = next {
+            next = match Arc::try_unwrap(node) {
+                Ok(mut node) => node.next.take(),
+                Err(_) => break,
+            };
+        }
+    }
+}
+
+fn raw<T>(node: &Option<Arc<Node<T>>>) -> *mut () {
+    match *node {
+        Some(ref node) => &**node as *const Node<T> as *mut (),
+        None => ptr::null_mut(),
+    }
+}
+
+/// A lock-free stack that can be snapshotted while it is being modified.
+///
+/// ```
+/// use atom::persistent::PersistentStack;
+///
+/// let log = PersistentStack::new();
+/// log.push("opened");
+/// let before = log.iter_snapshot();
+/// log.push("closed");
+/// assert_eq!(before.iter().collect::<Vec<_>>(), [&"opened"]);
+/// assert_eq!(log.iter_snapshot().iter().collect::<Vec<_>>(), [&"closed", &"opened"]);
+/// ```
+pub struct PersistentStack<T> {
+    head: Atom<Arc<Node<T>>>,
+}
+
+impl<T> PersistentStack<T>
+where
+    T: Send + Sync + 'static,
+{
+    /// Create an empty stack
+    pub const fn new() -> PersistentStack<T> {
+        PersistentStack {
+            head: Atom::empty(),
+        }
+    }
+
+    fn load_head(&self, _pin: &epoch::Guard) -> Option<Arc<Node<T>>> {
+        let ptr = self.head.inner.load(Ordering::Acquire) as *const Node<T>;
+        if ptr.is_null() {
+            return None;
+        }
+        // The stack's reference to a node it stopped pointing at is only
+        // dropped once `_pin` is gone, so the count is still above zero.
+        unsafe {
+            Arc::increment_strong_count(ptr);
+            Some(Arc::from_raw(ptr))
+        }
+    }
+
+    /// Push `value` onto the stack
+    pub fn push(&self, value: T) {
+        let pin = epoch::pin();
+        let mut node = Arc::new(Node {
+            value,
+            next: self.load_head(&pin),
+        });
+        loop {
+            let current = raw(&node.next);
+            let new = IntoRawPtr::into_raw(node);
+            match self.head.inner.compare_exchange(
+                current,
+                new,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return retire::<T>(&pin, current),
+                Err(_) => {
+                    node = unsafe { FromRawPtr::from_raw(new) };
+                    let next = self.load_head(&pin);
+                    Arc::get_mut(&mut node)
+                        .expect("an unpublished node is not shared")
+                        .next = next;
+                }
+            }
+        }
+    }
+
+    /// Pop the top value off the stack.
+    ///
+    /// Snapshots may still share the node, so the value is cloned out of it.
+    pub fn pop(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        let pin = epoch::pin();
+        loop {
+            let head = self.load_head(&pin)?;
+            let current = &*head as *const Node<T> as *mut ();
+            let next = head.next.clone().map(IntoRawPtr::into_raw);
+            let next = next.unwrap_or(ptr::null_mut());
+            match self.head.inner.compare_exchange(
+                current,
+                next,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    retire::<T>(&pin, current);
+                    return Some(head.value.clone());
+                }
+                Err(_) => {
+                    if !next.is_null() {
+                        drop(unsafe { <Arc<Node<T>> as FromRawPtr>::from_raw(next) });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Take a snapshot of everything on the stack right now
+    pub fn iter_snapshot(&self) -> Snapshot<T> {
+        Snapshot {
+            head: self.load_head(&epoch::pin()),
+        }
+    }
+
+    /// Check to see if the stack is empty
+    ///
+    /// This only means that the stack was empty when it was measured
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none(Ordering::Acquire)
+    }
+}
+
+/// Drop the stack's reference to the node at `ptr` once no pinned load can
+/// still be about to take a new one.
+fn retire<T>(pin: &epoch::Guard, ptr: *mut ())
+where
+    T: Send + Sync + 'static,
+{
+    if ptr.is_null() {
+        return;
+    }
+    // raw pointers are not Send, the address is
+    let addr = ptr as usize;
+    pin.defer(move || drop(unsafe { <Arc<Node<T>> as FromRawPtr>::from_raw(addr as *mut ()) }));
+}
+
+impl<T> Default for PersistentStack<T>
+where
+    T: Send + Sync + 'static,
+{
+    fn default() -> PersistentStack<T> {
+        PersistentStack::new()
+    }
+}
+
+impl<T> Debug for PersistentStack<T>
+where
+    T: Debug + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "PersistentStack({:?})", self.iter_snapshot())
+    }
+}
+
+/// The contents of a `PersistentStack` at the time of `iter_snapshot`,
+/// newest first.
+///
+/// A snapshot owns references to its nodes, so it does not pin the thread
+/// and never holds up the stack or reclamation.
+pub struct Snapshot<T> {
+    head: Option<Arc<Node<T>>>,
+}
+
+impl<T> Snapshot<T> {
+    /// Iterate over the values in the snapshot, newest first
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.as_deref(),
+        }
+    }
+
+    /// Check to see if the snapshot is empty
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Snapshot<T> {
+        Snapshot {
+            head: self.head.clone(),
+        }
+    }
+}
+
+impl<T: Debug> Debug for Snapshot<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Snapshot<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the values of a `Snapshot`
+pub struct Iter<'a, T: 'a> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        Some(&node.value)
+    }
+}
+
+impl<'a, T> Debug for Iter<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Iter({:?})",
+            self.next.map(|node| node as *const Node<T>)
+        )
+    }
+}
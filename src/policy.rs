@@ -0,0 +1,274 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Policies deciding what happens to a value displaced from an `Atom`.
+//!
+//! `Atom::store_with` and `Atom::clear_with` hand the old value to a
+//! `DropPolicy` instead of returning it. `DropNow` matches what happens when
+//! the result of `swap` is ignored, `Leak` never frees the value, and
+//! `Deferred` parks it until some other thread calls `collect`. `Collector`
+//! goes one step further and drops values on a background thread of its own.
+
+use std::fmt::{self, Debug, Formatter};
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use wait::WaitList;
+use {Atom, AtomStorable, GetNextMut};
+
+/// Decides the fate of a value that was displaced from an `Atom`.
+///
+/// A policy is passed to each call rather than being part of the `Atom`'s
+/// type, so it only covers values that leave through `store_with`,
+/// `clear_with` or `swap_deferred`. The rest are still dropped or
+/// returned as usual:
+///
+/// - the value still in an `Atom` when it is dropped is dropped inline,
+///   call `clear_with` first to route it through a policy;
+/// - a value rejected by `set_if_none` is handed back to the caller, who
+///   can pass it to `release` themselves;
+/// - results of `swap` and `take` are owned by the caller as before.
+///
+/// Policies that queue values, like `Deferred`, allocate on every release.
+pub trait DropPolicy<P> {
+    /// Dispose of `value`
+    fn release(&self, value: P);
+}
+
+/// Drop the displaced value immediately on the calling thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DropNow;
+
+impl<P> DropPolicy<P> for DropNow {
+    #[inline]
+    fn release(&self, value: P) {
+        drop(value);
+    }
+}
+
+/// Never run the destructor of the displaced value.
+///
+/// This is useful for `'static` registries where readers may still hold on
+/// to a value after it was replaced.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Leak;
+
+impl<P> DropPolicy<P> for Leak {
+    #[inline]
+    fn release(&self, value: P) {
+        mem::forget(value);
+    }
+}
+
+struct Node<P> {
+    next: Option<Box<Node<P>>>,
+    value: P,
+}
+
+impl<P> GetNextMut for Box<Node<P>> {
+    type NextPtr = Option<Box<Node<P>>>;
+    fn get_next(&mut self) -> &mut Option<Box<Node<P>>> {
+        &mut self.next
+    }
+}
+
+/// Queue displaced values so their destructors run later, on whichever
+/// thread calls `collect`.
+///
+/// Releasing a value allocates a small node and does a single CAS loop, it
+/// never runs the destructor of the value itself. This lets a real-time
+/// thread swap values while a housekeeping thread pays for the drops.
+pub struct Deferred<P> {
+    head: Atom<Box<Node<P>>>,
+}
+
+impl<P> Deferred<P> {
+    /// Create an empty queue
+    pub fn new() -> Deferred<P> {
+        Deferred {
+            head: Atom::empty(),
+        }
+    }
+
+    /// Check to see if there are values waiting to be dropped
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none(Ordering::Acquire)
+    }
+
+    /// Drop every value queued so far, returning how many were dropped.
+    pub fn collect(&self) -> usize {
+        let mut count = 0;
+        let mut chain = self.head.take(Ordering::Acquire);
+        while let Some(node) = chain {
+            let Node { next, value } = *node;
+            drop(value);
+            chain = next;
+            count += 1;
+        }
+        count
+    }
+}
+
+impl<P> Default for Deferred<P> {
+    fn default() -> Deferred<P> {
+        Deferred::new()
+    }
+}
+
+impl<P> Debug for Deferred<P> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Deferred({:?})", self.head)
+    }
+}
+
+impl<P> DropPolicy<P> for Deferred<P> {
+    fn release(&self, value: P) {
+        let node = Box::new(Node { next: None, value });
+        self.head
+            .replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+    }
+}
+
+impl<P> Drop for Deferred<P> {
+    fn drop(&mut self) {
+        self.collect();
+    }
+}
+
+struct CollectorShared {
+    queue: Deferred<Box<dyn Send>>,
+    wake: WaitList,
+    shutdown: AtomicBool,
+}
+
+/// Drop displaced values on a dedicated background thread.
+///
+/// Releasing a value costs what it costs for `Deferred`, plus waking the
+/// background thread if it had run out of work. Dropping the `Collector`
+/// drops everything still queued and joins the thread.
+pub struct Collector {
+    shared: Arc<CollectorShared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Collector {
+    /// Start a collector thread
+    pub fn new() -> Collector {
+        let shared = Arc::new(CollectorShared {
+            queue: Deferred::new(),
+            wake: WaitList::new(),
+            shutdown: AtomicBool::new(false),
+        });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("atom-collector".to_string())
+                .spawn(move || loop {
+                    shared.wake.wait_until(
+                        || !shared.queue.is_empty() || shared.shutdown.load(Ordering::Acquire),
+                        None,
+                    );
+                    shared.queue.collect();
+                    if shared.shutdown.load(Ordering::Acquire) && shared.queue.is_empty() {
+                        return;
+                    }
+                })
+                .expect("failed to spawn the collector thread")
+        };
+        Collector {
+            shared,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Default for Collector {
+    fn default() -> Collector {
+        Collector::new()
+    }
+}
+
+impl Debug for Collector {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Collector({:?})", self.shared.queue)
+    }
+}
+
+impl<P> DropPolicy<P> for Collector
+where
+    P: Send + 'static,
+{
+    fn release(&self, value: P) {
+        let node = Box::new(Node {
+            next: None,
+            value: Box::new(value) as Box<dyn Send>,
+        });
+        let previous =
+            self.shared
+                .queue
+                .head
+                .replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+        // only the push that refills an empty queue needs to wake the thread
+        if previous.is_none() {
+            self.shared.wake.notify_all();
+        }
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<P> Atom<P>
+where
+    P: AtomStorable,
+{
+    /// Swap a new value into the Atom, leaving the old value (if any) for
+    /// `collector` to drop on its own thread.
+    pub fn swap_deferred(&self, v: P, order: Ordering, collector: &Collector)
+    where
+        P: Send + 'static,
+    {
+        self.store_with(v, order, collector)
+    }
+
+    /// Swap a new value into the Atom, handing the old value (if any) to
+    /// `policy` instead of returning it.
+    pub fn store_with<D>(&self, v: P, order: Ordering, policy: &D)
+    where
+        D: DropPolicy<P>,
+    {
+        if let Some(old) = self.swap(v, order) {
+            policy.release(old);
+        }
+    }
+
+    /// Empty the Atom, handing the old value (if any) to `policy`.
+    pub fn clear_with<D>(&self, order: Ordering, policy: &D)
+    where
+        D: DropPolicy<P>,
+    {
+        if let Some(old) = self.take(order) {
+            policy.release(old);
+        }
+    }
+}
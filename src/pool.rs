@@ -0,0 +1,190 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A lock-free object pool.
+//!
+//! Objects waiting in the pool are kept as an intrusive LIFO behind an
+//! `Atom`, so putting an object back is a single `replace_and_set_next`
+//! and never allocates. Popping a single node off a shared LIFO is prone
+//! to ABA, so `get` instead takes the whole chain, keeps the first object
+//! and hands the rest back. While it holds the chain other callers find
+//! the pool empty and simply allocate.
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chain::{self, Chain};
+use {Atom, GetNextMut};
+
+struct Node<T> {
+    next: Option<Box<Node<T>>>,
+    value: T,
+}
+
+impl<T> GetNextMut for Box<Node<T>> {
+    type NextPtr = Option<Box<Node<T>>>;
+    fn get_next(&mut self) -> &mut Option<Box<Node<T>>> {
+        &mut self.next
+    }
+}
+
+/// An object handed out by a `Pool`.
+///
+/// It keeps the allocation it will be linked into the pool with, give it
+/// back with `Pool::put` to reuse that allocation.
+pub struct Pooled<T> {
+    node: Box<Node<T>>,
+}
+
+impl<T> Pooled<T> {
+    /// Wrap `value` so it can be put into a pool
+    pub fn new(value: T) -> Pooled<T> {
+        Pooled {
+            node: Box::new(Node { next: None, value }),
+        }
+    }
+
+    /// Unwrap the object, giving up its allocation
+    pub fn into_inner(this: Pooled<T>) -> T {
+        this.node.value
+    }
+}
+
+impl<T> Deref for Pooled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node.value
+    }
+}
+
+impl<T> DerefMut for Pooled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.node.value
+    }
+}
+
+impl<T: Debug> Debug for Pooled<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Pooled({:?})", self.node.value)
+    }
+}
+
+/// A pool of reusable objects.
+///
+/// Objects come back out exactly as they were put in, reset them before or
+/// after use as needed.
+///
+/// ```
+/// use atom::pool::Pool;
+///
+/// let pool = Pool::new(Vec::new);
+/// let mut buffer = pool.get();
+/// buffer.extend_from_slice(b"hello");
+/// buffer.clear();
+/// pool.put(buffer);
+/// assert_eq!(pool.len(), 1);
+/// assert!(pool.get().capacity() >= 5);
+/// ```
+pub struct Pool<T, F = fn() -> T> {
+    free: Atom<Box<Node<T>>>,
+    len: AtomicUsize,
+    capacity: usize,
+    create: F,
+}
+
+impl<T, F> Pool<T, F>
+where
+    F: Fn() -> T,
+{
+    /// Create an empty pool, `create` makes new objects when it runs dry
+    pub fn new(create: F) -> Pool<T, F> {
+        Pool::with_capacity(usize::MAX, create)
+    }
+
+    /// Create an empty pool that keeps at most `capacity` objects. Objects
+    /// put into a full pool are dropped.
+    pub fn with_capacity(capacity: usize, create: F) -> Pool<T, F> {
+        Pool {
+            free: Atom::empty(),
+            len: AtomicUsize::new(0),
+            capacity,
+            create,
+        }
+    }
+
+    /// Take an object out of the pool, or create one if it is empty
+    pub fn get(&self) -> Pooled<T> {
+        let mut node = match self.free.take(Ordering::Acquire) {
+            Some(node) => node,
+            None => return Pooled::new((self.create)()),
+        };
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        if let Some(rest) = node.next.take() {
+            // Usually nothing was put back meanwhile and the rest goes
+            // back as it is, otherwise it is spliced under the new nodes.
+            if let Some(rest) = self.free.set_if_none(rest, Ordering::Release) {
+                self.free.replace_and_set_next_chain(
+                    Chain::new(Some(rest)),
+                    Ordering::Relaxed,
+                    Ordering::AcqRel,
+                );
+            }
+        }
+        Pooled { node }
+    }
+
+    /// Put an object back into the pool
+    pub fn put(&self, object: Pooled<T>) {
+        if self.len.fetch_add(1, Ordering::Relaxed) >= self.capacity {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            return;
+        }
+        self.free
+            .replace_and_set_next(object.node, Ordering::Relaxed, Ordering::Release);
+    }
+
+    /// The number of objects waiting in the pool
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Check to see if the pool is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The most objects the pool keeps
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T, F> Drop for Pool<T, F> {
+    fn drop(&mut self) {
+        chain::drop_chain(self.free.take(Ordering::Acquire));
+    }
+}
+
+impl<T, F> Debug for Pool<T, F> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Pool({}/{})",
+            self.len.load(Ordering::Relaxed),
+            self.capacity
+        )
+    }
+}
@@ -0,0 +1,232 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Quiescent-state-based reclamation.
+//!
+//! Each participating thread promises to drop every reference it loaded
+//! from shared Atoms before it calls `quiescent`, typically once per frame
+//! or tick. A retired value is freed once every participant has called
+//! `quiescent` since it was retired. Reads cost nothing at all, the price
+//! is that a participant that stops calling `quiescent` holds up all
+//! reclamation.
+//!
+//! ```
+//! use std::sync::atomic::Ordering;
+//! use atom::qsbr::Qsbr;
+//! use atom::Atom;
+//!
+//! let qsbr = Qsbr::new();
+//! let atom = Atom::new(Box::new(0));
+//! let worker = qsbr.register();
+//!
+//! for frame in 1..4 {
+//!     if let Some(old) = atom.swap(Box::new(frame), Ordering::AcqRel) {
+//!         qsbr.retire(old);
+//!     }
+//!     worker.quiescent();
+//! }
+//! ```
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+
+use chain::Chain;
+use {Atom, AtomSetOnce, AtomStorable, FromRawPtr, GetNextMut};
+
+struct Participant {
+    // the last grace period this participant has acknowledged
+    seen: AtomicUsize,
+    claimed: AtomicBool,
+    next: AtomSetOnce<Box<Participant>>,
+}
+
+struct Retired {
+    next: Option<Box<Retired>>,
+    period: usize,
+    // raw pointers are not Send, the address is
+    addr: usize,
+    free: unsafe fn(*mut ()),
+}
+
+impl GetNextMut for Box<Retired> {
+    type NextPtr = Option<Box<Retired>>;
+    fn get_next(&mut self) -> &mut Option<Box<Retired>> {
+        &mut self.next
+    }
+}
+
+unsafe fn free<P: FromRawPtr>(ptr: *mut ()) {
+    drop(P::from_raw(ptr));
+}
+
+/// A group of participating threads and the values retired against them.
+pub struct Qsbr {
+    period: AtomicUsize,
+    participants: AtomSetOnce<Box<Participant>>,
+    retired: Atom<Box<Retired>>,
+}
+
+impl Qsbr {
+    /// Create a domain with no participants. This is a `const fn`, so it
+    /// can be placed in a `static`.
+    pub const fn new() -> Qsbr {
+        Qsbr {
+            period: AtomicUsize::new(0),
+            participants: AtomSetOnce::empty(),
+            retired: Atom::empty(),
+        }
+    }
+
+    /// Join the domain. Until the returned handle is dropped, values
+    /// retired in this domain wait for it to call `quiescent`.
+    pub fn register(&self) -> QsbrHandle<'_> {
+        for p in self.participants.iter(Ordering::Acquire, |p| &p.next) {
+            if !p.claimed.load(Ordering::Relaxed)
+                && p.claimed
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return QsbrHandle::new(self, p);
+            }
+        }
+
+        let mut new = Box::new(Participant {
+            seen: AtomicUsize::new(0),
+            claimed: AtomicBool::new(true),
+            next: AtomSetOnce::empty(),
+        });
+        let mut tail = &self.participants;
+        loop {
+            match tail.try_insert(new) {
+                Ok(p) => return QsbrHandle::new(self, p),
+                Err((last, rejected)) => {
+                    tail = &last.next;
+                    new = rejected;
+                }
+            }
+        }
+    }
+
+    /// Drop `value` once every participant has passed through a quiescent
+    /// state. `value` must already be unreachable for new readers.
+    pub fn retire<P>(&self, value: P)
+    where
+        P: AtomStorable + Send + 'static,
+    {
+        let addr = value.into_raw() as usize;
+        let period = self.period.fetch_add(1, Ordering::SeqCst) + 1;
+        let node = Box::new(Retired {
+            next: None,
+            period,
+            addr,
+            free: free::<P>,
+        });
+        self.retired
+            .replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+    }
+
+    /// Free every retired value whose grace period has elapsed, returning
+    /// how many were freed.
+    pub fn reclaim(&self) -> usize {
+        let retired = self.retired.take_chain(Ordering::Acquire);
+        if retired.is_empty() {
+            return 0;
+        }
+        let mut done = self.period.load(Ordering::SeqCst);
+        for p in self.participants.iter(Ordering::Acquire, |p| &p.next) {
+            if p.claimed.load(Ordering::Acquire) {
+                done = done.min(p.seen.load(Ordering::Acquire));
+            }
+        }
+
+        let mut keep = Chain::default();
+        let mut freed = 0;
+        for node in retired {
+            if node.period <= done {
+                unsafe { (node.free)(node.addr as *mut ()) };
+                freed += 1;
+            } else {
+                keep.push(node);
+            }
+        }
+        self.retired
+            .replace_and_set_next_chain(keep, Ordering::Relaxed, Ordering::AcqRel);
+        freed
+    }
+}
+
+impl Default for Qsbr {
+    fn default() -> Qsbr {
+        Qsbr::new()
+    }
+}
+
+impl Drop for Qsbr {
+    fn drop(&mut self) {
+        // handles borrow the domain, so nobody can be reading
+        for node in self.retired.drain(Ordering::Acquire) {
+            unsafe { (node.free)(node.addr as *mut ()) };
+        }
+        self.participants.drop_chain(|p| &mut p.next);
+    }
+}
+
+impl Debug for Qsbr {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Qsbr(period={})", self.period.load(Ordering::Relaxed))
+    }
+}
+
+/// A thread's membership in a `Qsbr` domain.
+pub struct QsbrHandle<'a> {
+    qsbr: &'a Qsbr,
+    participant: &'a Participant,
+}
+
+impl<'a> QsbrHandle<'a> {
+    fn new(qsbr: &'a Qsbr, participant: &'a Participant) -> QsbrHandle<'a> {
+        let handle = QsbrHandle { qsbr, participant };
+        handle.acknowledge();
+        handle
+    }
+
+    fn acknowledge(&self) {
+        fence(Ordering::SeqCst);
+        let period = self.qsbr.period.load(Ordering::SeqCst);
+        self.participant.seen.store(period, Ordering::Release);
+    }
+
+    /// Declare that this thread holds no references loaded from the
+    /// domain's Atoms, then free whatever that makes reclaimable.
+    pub fn quiescent(&self) -> usize {
+        self.acknowledge();
+        self.qsbr.reclaim()
+    }
+}
+
+impl<'a> Drop for QsbrHandle<'a> {
+    fn drop(&mut self) {
+        self.participant.claimed.store(false, Ordering::Release);
+    }
+}
+
+impl<'a> Debug for QsbrHandle<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "QsbrHandle(seen={})",
+            self.participant.seen.load(Ordering::Relaxed)
+        )
+    }
+}
@@ -0,0 +1,101 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A tripwire for `Box` pointers that are reconstituted twice.
+//!
+//! Every `FromRawPtr::from_raw` on a `Box` puts the raw pointer into a small
+//! quarantine, and `IntoRawPtr::into_raw` takes it out again. Seeing the same
+//! pointer released twice in a row means two owners now think they hold the
+//! same allocation, which is usually the result of calling `from_raw` on the
+//! pointer returned from a failed `compare_exchange`.
+//!
+//! The Atom itself briefly reconstitutes values it keeps owning, to read
+//! through them or copy them into a next pointer. Those conversions run
+//! inside a `Quiet` scope so that racing readers are not mistaken for a
+//! double release.
+//!
+//! The quarantine only remembers the most recent releases, it is a debugging
+//! aid and not a guarantee. Zero-sized types share a single dangling address
+//! and are not tracked.
+//!
+//! Only `Box` payloads are tracked. An `Arc` is meant to be reconstituted
+//! once per reference, so a pointer showing up twice says nothing there,
+//! and `Arc`s, `Rc`s, references and every other payload go unchecked.
+//! Every tracked conversion also takes the same global `Mutex`, which
+//! serializes all Atoms in the process and makes this feature unsuitable
+//! for measuring performance.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Mutex, MutexGuard};
+
+const CAPACITY: usize = 64;
+
+static QUARANTINE: Mutex<VecDeque<usize>> = Mutex::new(VecDeque::new());
+
+thread_local! {
+    static QUIET: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Suspends tracking on this thread while it is alive
+pub struct Quiet(());
+
+impl Quiet {
+    pub fn new() -> Quiet {
+        QUIET.with(|quiet| quiet.set(quiet.get() + 1));
+        Quiet(())
+    }
+}
+
+impl Drop for Quiet {
+    fn drop(&mut self) {
+        QUIET.with(|quiet| quiet.set(quiet.get() - 1));
+    }
+}
+
+fn tracked<T>() -> bool {
+    mem::size_of::<T>() != 0 && QUIET.with(|quiet| quiet.get() == 0)
+}
+
+fn lock() -> MutexGuard<'static, VecDeque<usize>> {
+    QUARANTINE
+        .lock()
+        .unwrap_or_else(|poison| poison.into_inner())
+}
+
+/// `ptr` is about to be turned back into an owning `Box<T>`
+pub fn release<T>(ptr: *mut ()) {
+    if !tracked::<T>() {
+        return;
+    }
+    let mut quarantine = lock();
+    let twice = quarantine.contains(&(ptr as usize));
+    if !twice {
+        if quarantine.len() == CAPACITY {
+            quarantine.pop_front();
+        }
+        quarantine.push_back(ptr as usize);
+    }
+    drop(quarantine);
+    assert!(!twice, "atom: pointer {:p} was released twice", ptr);
+}
+
+/// `ptr` was just produced by giving up ownership of a `Box<T>`
+pub fn acquire<T>(ptr: *mut ()) {
+    if !tracked::<T>() {
+        return;
+    }
+    lock().retain(|&p| p != ptr as usize);
+}
@@ -0,0 +1,728 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! FIFO queues.
+
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::mem::{ManuallyDrop, MaybeUninit};
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "async")]
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+
+use epoch;
+#[cfg(feature = "async")]
+use task::AtomicWaker;
+use {Atom, AtomStorable, FromRawPtr, GetNext};
+
+/// An intrusive multi-producer single-consumer FIFO, after Dmitry Vyukov.
+///
+/// Nodes link themselves through an `Atom<P>` next field, so the queue
+/// never allocates. Pushing is a single swap plus a store and is
+/// wait-free. Popping is done by one consumer at a time.
+///
+/// A push is only visible once it has linked its node in, a `pop` racing
+/// with the push of the next node may return `None` even though the push
+/// already started.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::queue::MpscQueue;
+/// use atom::{Atom, GetNext, GetNextMut};
+///
+/// struct Message {
+///     next: Atom<Box<Message>>,
+///     text: &'static str,
+/// }
+///
+/// impl GetNextMut for Box<Message> {
+///     type NextPtr = Atom<Box<Message>>;
+///     fn get_next(&mut self) -> &mut Atom<Box<Message>> {
+///         &mut self.next
+///     }
+/// }
+///
+/// impl GetNext for Box<Message> {
+///     fn get_next_ref(&self) -> &Atom<Box<Message>> {
+///         &self.next
+///     }
+/// }
+///
+/// let mut queue = MpscQueue::new();
+/// queue.push(Box::new(Message { next: Atom::empty(), text: "hello" }));
+/// queue.push(Box::new(Message { next: Atom::empty(), text: "world" }));
+/// assert_eq!(queue.pop_mut().map(|m| m.text), Some("hello"));
+/// assert_eq!(queue.pop_mut().map(|m| m.text), Some("world"));
+/// ```
+pub struct MpscQueue<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    // the most recently pushed node, producers swap themselves in here
+    head: Atom<P>,
+    // the oldest node, owned by the consumer once the queue is non-empty
+    tail: AtomicPtr<()>,
+}
+
+impl<P> MpscQueue<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    /// Create an empty queue
+    pub const fn new() -> MpscQueue<P> {
+        MpscQueue {
+            head: Atom::empty(),
+            tail: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Borrow the next field of a node that is owned by the queue. A
+    /// producer may be linking in behind the node at the same time, so it
+    /// is only reached through the shared `get_next_ref`.
+    unsafe fn next_of<'a>(raw: *mut ()) -> &'a Atom<P> {
+        let node: ManuallyDrop<P> = ManuallyDrop::new(Atom::inner_peek(raw).expect("null node"));
+        &*(node.get_next_ref() as *const Atom<P>)
+    }
+
+    /// Add `node` to the back of the queue. Anything still linked from the
+    /// node's next field is dropped first.
+    pub fn push(&self, mut node: P) {
+        drop(node.get_next().take(Ordering::Relaxed));
+        let raw = Atom::raw(node);
+        let prev = self.head.inner.swap(raw, Ordering::AcqRel);
+        if prev.is_null() {
+            self.tail.store(raw, Ordering::Release);
+        } else {
+            // `prev` is not popped until this link is visible
+            unsafe { Self::next_of(prev).inner.store(raw, Ordering::Release) };
+        }
+    }
+
+    /// Remove the node at the front of the queue.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may pop at a time. Two concurrent pops could both
+    /// return the same node.
+    pub unsafe fn pop(&self) -> Option<P> {
+        let tail = self.tail.load(Ordering::Acquire);
+        if tail.is_null() {
+            return None;
+        }
+        let next = Self::next_of(tail);
+        let after = next.inner.load(Ordering::Acquire);
+        if !after.is_null() {
+            self.tail.store(after, Ordering::Relaxed);
+            next.inner.store(ptr::null_mut(), Ordering::Relaxed);
+            return Some(FromRawPtr::from_raw(tail));
+        }
+
+        // `tail` looks like the last node. Producers only write `tail` when
+        // they find the queue empty, so clear it before emptying the queue.
+        self.tail.store(ptr::null_mut(), Ordering::Relaxed);
+        match self.head.inner.compare_exchange(
+            tail,
+            ptr::null_mut(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => Some(FromRawPtr::from_raw(tail)),
+            Err(_) => {
+                // a producer is about to link a node after `tail`
+                self.tail.store(tail, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Remove the node at the front of the queue, using exclusive access to
+    /// rule out a second consumer.
+    pub fn pop_mut(&mut self) -> Option<P> {
+        unsafe { self.pop() }
+    }
+
+    /// Check to see if the queue is empty
+    ///
+    /// This only means that the queue was empty when it was measured
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none(Ordering::Acquire)
+    }
+}
+
+impl<P> Default for MpscQueue<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    fn default() -> MpscQueue<P> {
+        MpscQueue::new()
+    }
+}
+
+impl<P> Debug for MpscQueue<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "MpscQueue({:?})", self.head)
+    }
+}
+
+impl<P> Drop for MpscQueue<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    fn drop(&mut self) {
+        // every push has completed, so the chain from `tail` is whole
+        while self.pop_mut().is_some() {}
+    }
+}
+
+/// Create an `MpscQueue` whose consumer can wait for nodes asynchronously.
+///
+/// Sending pushes the node and wakes the receiving task, neither of which
+/// allocates. The receiver yields nodes until every `Sender` is dropped and
+/// the queue is drained, so it can be consumed with
+/// `while let Some(msg) = rx.recv().await`.
+///
+/// ```
+/// use std::future::Future;
+/// use std::pin::pin;
+/// use std::sync::Arc;
+/// use std::task::{Context, Poll, Wake, Waker};
+/// use atom::queue;
+/// use atom::{Atom, GetNext, GetNextMut};
+///
+/// struct Message {
+///     next: Atom<Box<Message>>,
+///     id: u32,
+/// }
+///
+/// impl GetNextMut for Box<Message> {
+///     type NextPtr = Atom<Box<Message>>;
+///     fn get_next(&mut self) -> &mut Atom<Box<Message>> {
+///         &mut self.next
+///     }
+/// }
+///
+/// impl GetNext for Box<Message> {
+///     fn get_next_ref(&self) -> &Atom<Box<Message>> {
+///         &self.next
+///     }
+/// }
+///
+/// struct Noop;
+/// impl Wake for Noop {
+///     fn wake(self: Arc<Self>) {}
+/// }
+///
+/// let (tx, mut rx) = queue::channel();
+/// tx.send(Box::new(Message { next: Atom::empty(), id: 7 }));
+/// drop(tx);
+///
+/// let waker = Waker::from(Arc::new(Noop));
+/// let mut cx = Context::from_waker(&waker);
+/// match rx.poll_next(&mut cx) {
+///     Poll::Ready(Some(msg)) => assert_eq!(msg.id, 7),
+///     _ => unreachable!(),
+/// }
+/// assert!(pin!(rx.recv()).poll(&mut cx).is_ready());
+/// ```
+#[cfg(feature = "async")]
+pub fn channel<P>() -> (Sender<P>, Receiver<P>)
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    let shared = Arc::new(Shared {
+        queue: MpscQueue::new(),
+        task: AtomicWaker::new(),
+        senders: AtomicUsize::new(1),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(feature = "async")]
+struct Shared<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    queue: MpscQueue<P>,
+    task: AtomicWaker,
+    senders: AtomicUsize,
+}
+
+/// The sending half of `channel`, it can be cloned to add producers.
+#[cfg(feature = "async")]
+pub struct Sender<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    shared: Arc<Shared<P>>,
+}
+
+#[cfg(feature = "async")]
+impl<P> Sender<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    /// Push `node` onto the queue and wake the receiver. Anything still
+    /// linked from the node's next field is dropped first.
+    pub fn send(&self, node: P) {
+        self.shared.queue.push(node);
+        self.shared.task.wake();
+    }
+}
+
+#[cfg(feature = "async")]
+impl<P> Clone for Sender<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    fn clone(&self) -> Sender<P> {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<P> Drop for Sender<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.task.wake();
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<P> Debug for Sender<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Sender({:?})", self.shared.queue)
+    }
+}
+
+/// The receiving half of `channel`.
+#[cfg(feature = "async")]
+pub struct Receiver<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    shared: Arc<Shared<P>>,
+}
+
+#[cfg(feature = "async")]
+impl<P> Receiver<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    /// Take the node at the front of the queue without waiting
+    pub fn try_recv(&mut self) -> Option<P> {
+        // `&mut self` makes this the only consumer
+        unsafe { self.shared.queue.pop() }
+    }
+
+    /// Take the node at the front of the queue, registering the task to be
+    /// woken if there is none yet. Resolves to `None` once every `Sender`
+    /// is gone and the queue is drained.
+    pub fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<P>> {
+        if let Some(node) = self.try_recv() {
+            return Poll::Ready(Some(node));
+        }
+        self.shared.task.register(cx.waker());
+        // a send that finished before the register did not wake us
+        if let Some(node) = self.try_recv() {
+            return Poll::Ready(Some(node));
+        }
+        if self.shared.senders.load(Ordering::Acquire) == 0 {
+            // every push has completed, so this sees all of them
+            return Poll::Ready(self.try_recv());
+        }
+        Poll::Pending
+    }
+
+    /// Wait for the next node, resolving to `None` once every `Sender` is
+    /// gone and the queue is drained
+    pub fn recv(&mut self) -> Recv<'_, P> {
+        Recv { receiver: self }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<P> Debug for Receiver<P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>>,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Receiver({:?})", self.shared.queue)
+    }
+}
+
+/// The future returned by `Receiver::recv`.
+///
+/// Dropping it before it completes is fine, no node is taken until it
+/// resolves.
+#[cfg(feature = "async")]
+pub struct Recv<'a, P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>> + 'a,
+{
+    receiver: &'a mut Receiver<P>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, P> Future for Recv<'a, P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>> + 'a,
+{
+    type Output = Option<P>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<P>> {
+        self.get_mut().receiver.poll_next(cx)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, P> Debug for Recv<'a, P>
+where
+    P: AtomStorable + GetNext<NextPtr = Atom<P>> + 'a,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Recv({:?})", self.receiver)
+    }
+}
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    // uninitialized in the sentinel at the front of the queue
+    value: MaybeUninit<T>,
+}
+
+impl<T> Node<T> {
+    fn new(value: MaybeUninit<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            value,
+        }))
+    }
+}
+
+unsafe fn free_node<T>(addr: usize) {
+    drop(Box::from_raw(addr as *mut Node<T>));
+}
+
+/// An unbounded multi-producer multi-consumer FIFO, after Michael and Scott.
+///
+/// The front of the queue is always a sentinel node. Pushing links a node
+/// after the last one, popping moves the sentinel forward by one and takes
+/// the value out of the node that becomes the new sentinel. Retired
+/// sentinels are freed through `epoch`, since other threads may still be
+/// reading their next pointer.
+///
+/// ```
+/// use atom::queue::Queue;
+///
+/// let queue = Queue::new();
+/// queue.push(1);
+/// queue.push(2);
+/// assert_eq!(queue.pop(), Some(1));
+/// assert_eq!(queue.pop(), Some(2));
+/// assert_eq!(queue.pop(), None);
+/// ```
+pub struct Queue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    /// Create an empty queue
+    pub fn new() -> Queue<T> {
+        let sentinel = Node::new(MaybeUninit::uninit());
+        Queue {
+            head: AtomicPtr::new(sentinel),
+            tail: AtomicPtr::new(sentinel),
+        }
+    }
+
+    /// Add `value` to the back of the queue
+    pub fn push(&self, value: T) {
+        let node = Node::new(MaybeUninit::new(value));
+        let _guard = epoch::pin();
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            // Pinned, so `tail` is not freed even if it was popped meanwhile.
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+            if !next.is_null() {
+                // help a push that linked its node but has not moved `tail`
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            let linked = unsafe {
+                (*tail).next.compare_exchange(
+                    ptr::null_mut(),
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+            };
+            if linked.is_ok() {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    /// Remove the value at the front of the queue
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+            if next.is_null() {
+                return None;
+            }
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                // `tail` lags behind a completed link, move it along first
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                // `next` is the new sentinel, only the thread that installed
+                // it may move its value out.
+                let value = unsafe { ptr::read((*next).value.as_ptr()) };
+                let addr = head as usize;
+                let free: unsafe fn(usize) = free_node::<T>;
+                guard.defer(move || unsafe { free(addr) });
+                return Some(value);
+            }
+        }
+    }
+
+    /// Check to see if the queue is empty
+    ///
+    /// This only means that the queue was empty when it was measured
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Queue<T> {
+        Queue::new()
+    }
+}
+
+impl<T> Debug for Queue<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Queue({:p})", self.head.load(Ordering::Relaxed))
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        let sentinel = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut next = sentinel.next.load(Ordering::Relaxed);
+        while !next.is_null() {
+            let node = unsafe { Box::from_raw(next) };
+            next = node.next.load(Ordering::Relaxed);
+            drop(unsafe { node.value.assume_init() });
+        }
+    }
+}
+
+struct Slot<P>
+where
+    P: AtomStorable,
+{
+    // `pos` when free for the push at `pos`, `pos + 1` once it holds a value
+    // for the pop at `pos`
+    seq: AtomicUsize,
+    value: Atom<P>,
+}
+
+/// A fixed-capacity multi-producer multi-consumer FIFO, after Dmitry Vyukov.
+///
+/// Values live in a ring of slots allocated once by `new`. Each slot carries
+/// a sequence number which tells pushes and pops whose turn it is, so
+/// neither side allocates or waits on the other: a push into a full queue
+/// and a pop from an empty one both fail immediately.
+///
+/// ```
+/// use atom::queue::BoundedQueue;
+///
+/// let queue = BoundedQueue::new(2);
+/// assert!(queue.try_push(Box::new(1)).is_ok());
+/// assert!(queue.try_push(Box::new(2)).is_ok());
+/// assert_eq!(queue.try_push(Box::new(3)), Err(Box::new(3)));
+/// assert_eq!(queue.try_pop(), Some(Box::new(1)));
+/// ```
+pub struct BoundedQueue<P>
+where
+    P: AtomStorable,
+{
+    slots: Box<[Slot<P>]>,
+    push_pos: AtomicUsize,
+    pop_pos: AtomicUsize,
+}
+
+impl<P> BoundedQueue<P>
+where
+    P: AtomStorable,
+{
+    /// Create an empty queue that holds at most `capacity` values.
+    ///
+    /// The sequence scheme needs to tell a full slot from a free one a lap
+    /// later, so `capacity` must be at least 2.
+    pub fn new(capacity: usize) -> BoundedQueue<P> {
+        assert!(capacity >= 2, "a bounded queue needs at least two slots");
+        BoundedQueue {
+            slots: (0..capacity)
+                .map(|seq| Slot {
+                    seq: AtomicUsize::new(seq),
+                    value: Atom::empty(),
+                })
+                .collect(),
+            push_pos: AtomicUsize::new(0),
+            pop_pos: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of values the queue can hold
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Add `v` to the back of the queue, or hand it back if the queue is full
+    pub fn try_push(&self, v: P) -> Result<(), P> {
+        let mut pos = self.push_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let lag = seq.wrapping_sub(pos) as isize;
+            if lag == 0 {
+                match self.push_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let prev = slot.value.set_if_none(v, Ordering::Relaxed);
+                        debug_assert!(prev.is_none());
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if lag < 0 {
+                // the slot still holds the value from the previous lap
+                return Err(v);
+            } else {
+                pos = self.push_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Remove the value at the front of the queue, if there is one
+    pub fn try_pop(&self) -> Option<P> {
+        let mut pos = self.pop_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let lag = seq.wrapping_sub(pos.wrapping_add(1)) as isize;
+            if lag == 0 {
+                match self.pop_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let v = slot.value.take(Ordering::Relaxed);
+                        slot.seq
+                            .store(pos.wrapping_add(self.slots.len()), Ordering::Release);
+                        return v;
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if lag < 0 {
+                // nothing has been pushed into this slot yet
+                return None;
+            } else {
+                pos = self.pop_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The number of values in the queue
+    ///
+    /// This is only a snapshot, it may be stale as soon as it is returned
+    pub fn len(&self) -> usize {
+        loop {
+            let pop = self.pop_pos.load(Ordering::SeqCst);
+            let push = self.push_pos.load(Ordering::SeqCst);
+            if self.pop_pos.load(Ordering::SeqCst) == pop {
+                return push.wrapping_sub(pop).min(self.slots.len());
+            }
+        }
+    }
+
+    /// Check to see if the queue is empty
+    ///
+    /// This only means that the queue was empty when it was measured
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<P> Debug for BoundedQueue<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "BoundedQueue({}/{})", self.len(), self.slots.len())
+    }
+}
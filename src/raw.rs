@@ -0,0 +1,273 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Atoms for pointers whose ownership is managed by hand.
+
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "unsafe_borrowed")]
+use std::marker::PhantomData;
+#[cfg(feature = "unsafe_borrowed")]
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use {strongest_failure_ordering, FromRawPtr, Token};
+#[cfg(feature = "unsafe_borrowed")]
+use {AtomStorable, IntoRawPtr, RawDeref};
+
+/// An `Atom` for a `NonNull<T>` it does not own.
+///
+/// Use it for FFI handles, pointers into an arena or anything else whose
+/// lifetime is managed outside of the atom. Storing and loading pointers is
+/// safe, what the pointer may be used for is up to whoever created it. A
+/// RawAtom never frees anything: dropping one that still holds a pointer
+/// simply forgets it, and so does every method that returns an old pointer
+/// the caller ignores.
+///
+/// ```
+/// use std::ptr::NonNull;
+/// use std::sync::atomic::Ordering;
+/// use atom::raw::RawAtom;
+///
+/// let mut arena = [1u32, 2, 3];
+/// let cursor = RawAtom::new(NonNull::from(&mut arena[0]));
+/// let next = NonNull::from(&mut arena[1]);
+/// let old = cursor.swap(next, Ordering::AcqRel).unwrap();
+/// assert_eq!(unsafe { *old.as_ptr() }, 1);
+/// assert_eq!(cursor.load(Ordering::Acquire), Some(next));
+/// ```
+pub struct RawAtom<T> {
+    inner: AtomicPtr<T>,
+}
+
+impl<T> RawAtom<T> {
+    fn unpack(ptr: *mut T) -> Option<NonNull<T>> {
+        NonNull::new(ptr)
+    }
+
+    fn pack(ptr: Option<NonNull<T>>) -> *mut T {
+        ptr.map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    /// Create an empty RawAtom
+    pub const fn empty() -> RawAtom<T> {
+        RawAtom {
+            inner: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Create a RawAtom holding `ptr`
+    pub const fn new(ptr: NonNull<T>) -> RawAtom<T> {
+        RawAtom {
+            inner: AtomicPtr::new(ptr.as_ptr()),
+        }
+    }
+
+    /// Swap a new pointer into the RawAtom, returning the old one
+    pub fn swap(&self, ptr: NonNull<T>, order: Ordering) -> Option<NonNull<T>> {
+        Self::unpack(self.inner.swap(ptr.as_ptr(), order))
+    }
+
+    /// Take the pointer out of the RawAtom, leaving it empty
+    pub fn take(&self, order: Ordering) -> Option<NonNull<T>> {
+        Self::unpack(self.inner.swap(ptr::null_mut(), order))
+    }
+
+    /// Take the pointer out and turn it back into the value it came from.
+    ///
+    /// # Safety
+    ///
+    /// The RawAtom must have been empty or held a pointer produced by
+    /// `IntoRawPtr::into_raw` on `P`, whose ownership was handed to the
+    /// RawAtom and to nobody else, just like `FromRawPtr::from_raw`.
+    pub unsafe fn take_as<P: FromRawPtr>(&self, order: Ordering) -> Option<P> {
+        self.take(order)
+            .map(|ptr| FromRawPtr::from_raw(ptr.as_ptr() as *mut ()))
+    }
+
+    /// Store `ptr` if the RawAtom is empty. If it was not, `ptr` is returned.
+    pub fn set_if_none(&self, ptr: NonNull<T>, order: Ordering) -> Option<NonNull<T>> {
+        self.inner
+            .compare_exchange(
+                ptr::null_mut(),
+                ptr.as_ptr(),
+                order,
+                strongest_failure_ordering(order),
+            )
+            .err()
+            .map(|_| ptr)
+    }
+
+    /// Store `new` if the RawAtom holds `current`. Returns the pointer that
+    /// was there before either way.
+    pub fn compare_exchange(
+        &self,
+        current: Option<NonNull<T>>,
+        new: Option<NonNull<T>>,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<Option<NonNull<T>>, Option<NonNull<T>>> {
+        self.inner
+            .compare_exchange(Self::pack(current), Self::pack(new), success, failure)
+            .map(Self::unpack)
+            .map_err(Self::unpack)
+    }
+
+    /// Read the pointer
+    pub fn load(&self, order: Ordering) -> Option<NonNull<T>> {
+        Self::unpack(self.inner.load(order))
+    }
+
+    /// Get a `Token` identifying the pointer currently stored
+    pub fn token(&self, order: Ordering) -> Token {
+        Token(self.inner.load(order) as usize)
+    }
+
+    /// Check to see if the RawAtom is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.inner.load(order).is_null()
+    }
+
+    /// Get the pointer through exclusive access
+    pub fn get_mut(&mut self) -> Option<NonNull<T>> {
+        Self::unpack(*self.inner.get_mut())
+    }
+
+    /// Consume the RawAtom, returning its pointer
+    pub fn into_inner(self) -> Option<NonNull<T>> {
+        Self::unpack(self.inner.into_inner())
+    }
+}
+
+impl<T> Default for RawAtom<T> {
+    fn default() -> RawAtom<T> {
+        RawAtom::empty()
+    }
+}
+
+impl<T> From<Option<NonNull<T>>> for RawAtom<T> {
+    fn from(ptr: Option<NonNull<T>>) -> RawAtom<T> {
+        RawAtom {
+            inner: AtomicPtr::new(Self::pack(ptr)),
+        }
+    }
+}
+
+impl<T> Debug for RawAtom<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "RawAtom({:?})", self.inner.load(Ordering::Relaxed))
+    }
+}
+
+/// A `&'a T` that can be stored in an `Atom`.
+///
+/// Only `&'static T` is a payload by itself. `scoped::ScopedAtom` is the
+/// safe way to share shorter borrows; this is for code that cannot be
+/// arranged around a scope and checks the lifetime by hand instead.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::Atom;
+/// use atom::raw::Borrowed;
+///
+/// let value = 5;
+/// let slot = Atom::new(unsafe { Borrowed::new(&value) });
+/// assert_eq!(slot.take(Ordering::Acquire).as_deref(), Some(&5));
+/// ```
+#[cfg(feature = "unsafe_borrowed")]
+pub struct Borrowed<'a, T> {
+    ptr: NonNull<T>,
+    data: PhantomData<&'a T>,
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+impl<'a, T> Borrowed<'a, T> {
+    /// Wrap `value` so it can be stored.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must not be turned back into a reference after `'a` has
+    /// ended. The Atom it is stored in is bound by `'a`, but raw pointers
+    /// taken from it, and values rebuilt from them with `FromRawPtr` or
+    /// `RawAtom::take_as`, are not.
+    pub unsafe fn new(value: &'a T) -> Borrowed<'a, T> {
+        Borrowed {
+            ptr: NonNull::from(value),
+            data: PhantomData,
+        }
+    }
+
+    /// Get the reference back
+    pub fn get(self) -> &'a T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+impl<'a, T> Clone for Borrowed<'a, T> {
+    fn clone(&self) -> Borrowed<'a, T> {
+        *self
+    }
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+impl<'a, T> Copy for Borrowed<'a, T> {}
+
+#[cfg(feature = "unsafe_borrowed")]
+unsafe impl<'a, T: Sync> Send for Borrowed<'a, T> {}
+#[cfg(feature = "unsafe_borrowed")]
+unsafe impl<'a, T: Sync> Sync for Borrowed<'a, T> {}
+
+#[cfg(feature = "unsafe_borrowed")]
+impl<'a, T> Deref for Borrowed<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+impl<'a, T: Debug> Debug for Borrowed<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Borrowed({:?})", &**self)
+    }
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+impl<'a, T> IntoRawPtr for Borrowed<'a, T> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        self.ptr.as_ptr() as *mut ()
+    }
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+impl<'a, T> FromRawPtr for Borrowed<'a, T> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> Borrowed<'a, T> {
+        Borrowed {
+            ptr: NonNull::new_unchecked(ptr as *mut T),
+            data: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+unsafe impl<'a, T> AtomStorable for Borrowed<'a, T> {}
+
+#[cfg(feature = "unsafe_borrowed")]
+unsafe impl<'a, T> RawDeref for Borrowed<'a, T> {}
@@ -0,0 +1,173 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Read-copy-update over a boxed value.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::Ordering;
+
+use epoch;
+use {Atom, FromRawPtr, IntoRawPtr};
+
+/// A value that readers can borrow while writers replace it.
+///
+/// Readers pin the current thread and borrow the value in place, without
+/// touching any shared counters. Writers build a new value and swap it in,
+/// the old one is dropped through `epoch` once every reader that might
+/// still see it has finished.
+///
+/// ```
+/// use atom::rcu::RcuCell;
+///
+/// let cell = RcuCell::new(vec![1, 2]);
+/// {
+///     let before = cell.read();
+///     cell.update(|v| {
+///         let mut v = v.clone();
+///         v.push(3);
+///         v
+///     });
+///     assert_eq!(*before, [1, 2]);
+/// }
+/// assert_eq!(*cell.read(), [1, 2, 3]);
+/// ```
+pub struct RcuCell<T> {
+    value: Atom<Box<T>>,
+    // readers on every thread borrow the same value
+    _marker: PhantomData<T>,
+}
+
+/// A borrow of the value an `RcuCell` held when `read` was called.
+///
+/// The thread stays pinned while the guard is alive, so hold it only as
+/// long as needed: values retired meanwhile are not reclaimed.
+pub struct Guard<'a, T: 'a> {
+    value: &'a T,
+    _pin: epoch::Guard,
+}
+
+impl<T> RcuCell<T>
+where
+    T: Send + Sync + 'static,
+{
+    /// Create a cell holding `value`
+    pub fn new(value: T) -> RcuCell<T> {
+        RcuCell {
+            value: Atom::new(Box::new(value)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Borrow the current value
+    pub fn read(&self) -> Guard<'_, T> {
+        let pin = epoch::pin();
+        let ptr = self.value.inner.load(Ordering::Acquire) as *const T;
+        // The cell is never empty, and a replaced value is only freed once
+        // `pin` is gone.
+        Guard {
+            value: unsafe { &*ptr },
+            _pin: pin,
+        }
+    }
+
+    /// Replace the value, retiring the old one
+    pub fn store(&self, value: T) {
+        let new = IntoRawPtr::into_raw(Box::new(value));
+        let old = self.value.inner.swap(new, Ordering::AcqRel);
+        retire::<T>(&epoch::pin(), old);
+    }
+
+    /// Replace the value with `f` applied to the current one.
+    ///
+    /// If another writer gets in first `f` is called again on its value,
+    /// so `f` should not have side effects.
+    pub fn update<F>(&self, mut f: F)
+    where
+        F: FnMut(&T) -> T,
+    {
+        let pin = epoch::pin();
+        let mut current = self.value.inner.load(Ordering::Acquire);
+        loop {
+            let next = f(unsafe { &*(current as *const T) });
+            let new = IntoRawPtr::into_raw(Box::new(next));
+            match self.value.inner.compare_exchange(
+                current,
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(old) => return retire::<T>(&pin, old),
+                Err(actual) => {
+                    drop(unsafe { <Box<T> as FromRawPtr>::from_raw(new) });
+                    current = actual;
+                }
+            }
+        }
+    }
+
+    /// Consume the cell, returning the value
+    pub fn into_inner(self) -> T {
+        *self
+            .value
+            .take(Ordering::Relaxed)
+            .expect("RcuCell is never empty")
+    }
+}
+
+/// Drop the Box at `ptr` once no reader can still see it.
+fn retire<T>(pin: &epoch::Guard, ptr: *mut ())
+where
+    T: Send + 'static,
+{
+    // raw pointers are not Send, the address is
+    let addr = ptr as usize;
+    pin.defer(move || drop(unsafe { <Box<T> as FromRawPtr>::from_raw(addr as *mut ()) }));
+}
+
+impl<T> Default for RcuCell<T>
+where
+    T: Default + Send + Sync + 'static,
+{
+    fn default() -> RcuCell<T> {
+        RcuCell::new(T::default())
+    }
+}
+
+impl<T> Debug for RcuCell<T>
+where
+    T: Debug + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "RcuCell({:?})", &*self.read())
+    }
+}
+
+impl<'a, T> Deref for Guard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> Debug for Guard<'a, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Guard({:?})", self.value)
+    }
+}
@@ -0,0 +1,128 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A registry holding one value per type.
+
+use std::any::{Any, TypeId};
+use std::fmt::{self, Debug, Formatter};
+
+use map::AtomMap;
+
+type Value = Box<dyn Any + Send + Sync>;
+
+fn downcast<T: Any>(value: &Value) -> &T {
+    value
+        .downcast_ref()
+        .expect("values are stored under their own TypeId")
+}
+
+/// A map from a type to the one value registered for it.
+///
+/// Registration is first come, first served and a registered value is never
+/// replaced, so references handed out by `get` stay valid for as long as
+/// the registry. It is an `AtomMap` keyed by `TypeId` underneath, so looking
+/// a type up never blocks.
+///
+/// ```
+/// use atom::registry::TypeRegistry;
+///
+/// struct Gravity(f32);
+///
+/// let registry = TypeRegistry::new();
+/// assert!(registry.register(Gravity(9.81)).is_ok());
+/// assert!(registry.register(Gravity(1.62)).is_err());
+/// assert_eq!(registry.get::<Gravity>().map(|g| g.0), Some(9.81));
+/// assert!(registry.get::<String>().is_none());
+/// ```
+pub struct TypeRegistry {
+    values: AtomMap<TypeId, Value>,
+}
+
+impl TypeRegistry {
+    /// Create an empty registry
+    pub fn new() -> TypeRegistry {
+        TypeRegistry {
+            values: AtomMap::new(),
+        }
+    }
+
+    /// Register `value` as the value for `T`. If `T` already has one, it is
+    /// returned together with `value`.
+    pub fn register<T>(&self, value: T) -> Result<&T, (&T, T)>
+    where
+        T: Any + Send + Sync,
+    {
+        match self.values.try_insert(TypeId::of::<T>(), Box::new(value)) {
+            Ok(value) => Ok(downcast(value)),
+            Err((existing, _, value)) => {
+                let value = *value.downcast().expect("boxed as a T just above");
+                Err((downcast(existing), value))
+            }
+        }
+    }
+
+    /// Get the value registered for `T`
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: Any + Send + Sync,
+    {
+        self.values.get(&TypeId::of::<T>()).map(downcast)
+    }
+
+    /// Get the value registered for `T`, registering the result of `f` if
+    /// there is none.
+    ///
+    /// Threads that race to register `T` may each call `f`, only one
+    /// result is kept.
+    pub fn get_or_register_with<T, F>(&self, f: F) -> &T
+    where
+        T: Any + Send + Sync,
+        F: FnOnce() -> T,
+    {
+        let value = self
+            .values
+            .get_or_insert_with(TypeId::of::<T>(), || Box::new(f()));
+        downcast(value)
+    }
+
+    /// Check to see if a value is registered for `T`
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Any + Send + Sync,
+    {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// The number of types with a registered value
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check to see if nothing has been registered
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl Default for TypeRegistry {
+    fn default() -> TypeRegistry {
+        TypeRegistry::new()
+    }
+}
+
+impl Debug for TypeRegistry {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "TypeRegistry({} types)", self.values.len())
+    }
+}
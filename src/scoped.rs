@@ -0,0 +1,170 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Atoms that hold borrowed data for the length of a scope.
+//!
+//! `scope` works like `std::thread::scope`: the closure gets a `Scope` whose
+//! lifetime it cannot name outside, and every `ScopedAtom` made from it is
+//! tied to that lifetime. An atom can therefore only ever hold references
+//! that outlive the scope, and neither the atom nor anything loaded from it
+//! can be smuggled out of the closure.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use {strongest_failure_ordering, Token};
+
+/// Run `f` with a `Scope` to create `ScopedAtom`s from.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use std::thread;
+///
+/// let names = vec![String::from("a"), String::from("b")];
+/// let longest = atom::scoped::scope(|s| {
+///     let longest = s.atom();
+///     thread::scope(|t| {
+///         for name in &names {
+///             let longest = &longest;
+///             t.spawn(move || {
+///                 longest.set_if_none(name, Ordering::AcqRel);
+///             });
+///         }
+///     });
+///     longest.load(Ordering::Acquire).cloned()
+/// });
+/// assert!(longest.is_some());
+/// ```
+pub fn scope<'env, F, R>(f: F) -> R
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+{
+    f(&Scope {
+        scope: PhantomData,
+        env: PhantomData,
+    })
+}
+
+/// The scope a `ScopedAtom` belongs to, see `scope`
+pub struct Scope<'scope, 'env: 'scope> {
+    // both lifetimes are invariant, like in `std::thread::Scope`
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Create an empty atom that lives as long as the scope
+    pub fn atom<T>(&'scope self) -> ScopedAtom<'scope, T> {
+        ScopedAtom {
+            inner: AtomicPtr::new(ptr::null_mut()),
+            scope: PhantomData,
+        }
+    }
+}
+
+impl<'scope, 'env> Debug for Scope<'scope, 'env> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Scope")
+    }
+}
+
+/// An atom holding a `&'scope T`.
+///
+/// References are `Copy`, so unlike an `Atom` the current value can be
+/// loaded without taking it out.
+pub struct ScopedAtom<'scope, T> {
+    inner: AtomicPtr<T>,
+    scope: PhantomData<(&'scope mut &'scope (), &'scope T)>,
+}
+
+unsafe impl<'scope, T: Sync> Send for ScopedAtom<'scope, T> {}
+unsafe impl<'scope, T: Sync> Sync for ScopedAtom<'scope, T> {}
+
+impl<'scope, T> ScopedAtom<'scope, T> {
+    fn from_raw(ptr: *mut T) -> Option<&'scope T> {
+        // only references that outlive the scope are ever stored
+        unsafe { ptr.as_ref() }
+    }
+
+    fn into_raw(value: Option<&'scope T>) -> *mut T {
+        value.map_or(ptr::null_mut(), |value| value as *const T as *mut T)
+    }
+
+    /// Swap a new value into the atom, returning the old value
+    pub fn swap(&self, v: &'scope T, order: Ordering) -> Option<&'scope T> {
+        Self::from_raw(self.inner.swap(Self::into_raw(Some(v)), order))
+    }
+
+    /// Take the value out of the atom, leaving it empty
+    pub fn take(&self, order: Ordering) -> Option<&'scope T> {
+        Self::from_raw(self.inner.swap(ptr::null_mut(), order))
+    }
+
+    /// Store `v` if the atom is empty. If it was not, `v` is returned.
+    pub fn set_if_none(&self, v: &'scope T, order: Ordering) -> Option<&'scope T> {
+        self.inner
+            .compare_exchange(
+                ptr::null_mut(),
+                Self::into_raw(Some(v)),
+                order,
+                strongest_failure_ordering(order),
+            )
+            .err()
+            .map(|_| v)
+    }
+
+    /// Store `new` if the atom still holds `current`, compared by address.
+    /// Returns the value that was there before either way.
+    pub fn compare_exchange(
+        &self,
+        current: Option<&'scope T>,
+        new: Option<&'scope T>,
+        order: Ordering,
+    ) -> Result<Option<&'scope T>, Option<&'scope T>> {
+        self.inner
+            .compare_exchange(
+                Self::into_raw(current),
+                Self::into_raw(new),
+                order,
+                strongest_failure_ordering(order),
+            )
+            .map(Self::from_raw)
+            .map_err(Self::from_raw)
+    }
+
+    /// Read the value without taking it out
+    pub fn load(&self, order: Ordering) -> Option<&'scope T> {
+        Self::from_raw(self.inner.load(order))
+    }
+
+    /// Get a `Token` identifying the value currently stored
+    pub fn token(&self, order: Ordering) -> Token {
+        Token(self.inner.load(order) as usize)
+    }
+
+    /// Check to see if the atom is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.inner.load(order).is_null()
+    }
+}
+
+impl<'scope, T: Debug> Debug for ScopedAtom<'scope, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "ScopedAtom({:?})", self.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,99 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Payloads that wipe their memory before it is freed.
+//!
+//! An `Atom<SecretBox<T>>` behaves like an `Atom<Box<T>>`, except that the
+//! heap contents are zeroed whenever the value is dropped. This covers every
+//! way a value can leave the Atom: the value returned by `take`, `swap` or a
+//! rejected `set_if_none`, and the contents still held when the Atom itself
+//! is dropped.
+
+use std::fmt::{self, Debug, Formatter};
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+
+pub use zeroize::Zeroize;
+
+use {AtomStorable, FromRawPtr, IntoRawPtr, RawDeref};
+
+/// A `Box<T>` that zeroes its contents before freeing them.
+pub struct SecretBox<T: Zeroize> {
+    inner: Box<T>,
+}
+
+impl<T: Zeroize> SecretBox<T> {
+    /// Move `value` onto the heap
+    pub fn new(value: T) -> SecretBox<T> {
+        SecretBox {
+            inner: Box::new(value),
+        }
+    }
+}
+
+impl<T: Zeroize> From<Box<T>> for SecretBox<T> {
+    fn from(inner: Box<T>) -> SecretBox<T> {
+        SecretBox { inner }
+    }
+}
+
+impl<T: Zeroize> Deref for SecretBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Zeroize> DerefMut for SecretBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: Zeroize> Drop for SecretBox<T> {
+    fn drop(&mut self) {
+        self.inner.zeroize();
+    }
+}
+
+// The contents are deliberately not printed.
+impl<T: Zeroize> Debug for SecretBox<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "SecretBox(..)")
+    }
+}
+
+impl<T: Zeroize> IntoRawPtr for SecretBox<T> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        let this = ManuallyDrop::new(self);
+        let inner = unsafe { ptr::read(&this.inner) };
+        Box::into_raw(inner) as *mut ()
+    }
+}
+
+impl<T: Zeroize> FromRawPtr for SecretBox<T> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> SecretBox<T> {
+        SecretBox {
+            inner: Box::from_raw(ptr as *mut T),
+        }
+    }
+}
+
+unsafe impl<T: Zeroize> AtomStorable for SecretBox<T> {}
+
+unsafe impl<T: Zeroize> RawDeref for SecretBox<T> {}
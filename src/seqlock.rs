@@ -0,0 +1,185 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A sequence lock for values that do not fit in a word.
+//!
+//! Writers bump a sequence number to an odd value, write, and bump it back
+//! to even. Readers copy the value out and keep the copy only if the
+//! sequence number was the same even value before and after. A copy that
+//! raced with a writer may be torn, so it is made into a `MaybeUninit<T>`
+//! and thrown away unread, the way crossbeam's `AtomicCell` handles values
+//! it has no native atomic for.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::hint;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::thread;
+
+/// A `Copy` value that is read without locking and written under a lock.
+///
+/// Reads never block a writer and never allocate, they retry while a write
+/// is in progress. Writers lock out each other, so any number of threads may
+/// write, but a steady stream of writes can keep readers retrying.
+///
+/// ```
+/// use atom::seqlock::Snapshot;
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// struct Transform {
+///     position: [f64; 3],
+///     scale: f64,
+/// }
+///
+/// let transform = Snapshot::new(Transform { position: [0.0; 3], scale: 1.0 });
+/// transform.store(Transform { position: [1.0, 2.0, 3.0], scale: 2.0 });
+/// assert_eq!(transform.load().scale, 2.0);
+/// ```
+pub struct Snapshot<T: Copy> {
+    seq: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Send for Snapshot<T> {}
+unsafe impl<T: Copy + Send> Sync for Snapshot<T> {}
+
+impl<T: Copy> Snapshot<T> {
+    /// Create a new cell holding `value`
+    pub const fn new(value: T) -> Snapshot<T> {
+        Snapshot {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Read the value, retrying while a write is in progress
+    pub fn load(&self) -> T {
+        let mut spins = 0;
+        loop {
+            if let Some(value) = self.try_load() {
+                return value;
+            }
+            backoff(&mut spins);
+        }
+    }
+
+    /// Read the value, or return `None` if a write was in progress
+    pub fn try_load(&self) -> Option<T> {
+        let before = self.seq.load(Ordering::Acquire);
+        if before & 1 != 0 {
+            return None;
+        }
+        let copy = unsafe { ptr::read_volatile(self.value.get() as *const MaybeUninit<T>) };
+        // keep the copy from being reordered after the second load
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != before {
+            return None;
+        }
+        // no write overlapped the copy, so it is a whole `T`
+        Some(unsafe { copy.assume_init() })
+    }
+
+    /// Replace the value
+    pub fn store(&self, value: T) {
+        self.update(|_| value);
+    }
+
+    /// Replace the value with `f` applied to it, returning the new value.
+    /// Other writers wait until `f` returns. If `f` panics the value is left
+    /// as it was and the cell is unlocked again.
+    pub fn update<F>(&self, f: F) -> T
+    where
+        F: FnOnce(T) -> T,
+    {
+        let mut writing = self.lock();
+        let value = f(unsafe { ptr::read(self.value.get()) });
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+        writing.after = writing.after.wrapping_add(2);
+        value
+    }
+
+    fn lock(&self) -> Writing<'_> {
+        let mut spins = 0;
+        loop {
+            let seq = self.seq.load(Ordering::Relaxed);
+            if seq & 1 == 0
+                && self
+                    .seq
+                    .compare_exchange_weak(seq, seq | 1, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                // readers that see the new value must see the odd number
+                fence(Ordering::Release);
+                return Writing {
+                    seq: &self.seq,
+                    after: seq,
+                };
+            }
+            backoff(&mut spins);
+        }
+    }
+
+    /// Get the value through exclusive access
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Consume the cell, returning the value
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Holds the sequence odd while a write runs, and stores `after` once it is
+/// over. That is the sequence from before the write until the new value is
+/// in place, so a panicking writer leaves the old value readable.
+struct Writing<'a> {
+    seq: &'a AtomicUsize,
+    after: usize,
+}
+
+impl<'a> Drop for Writing<'a> {
+    fn drop(&mut self) {
+        self.seq.store(self.after, Ordering::Release);
+    }
+}
+
+fn backoff(spins: &mut u32) {
+    if *spins < 64 {
+        *spins += 1;
+        hint::spin_loop();
+    } else {
+        thread::yield_now();
+    }
+}
+
+impl<T: Copy + Default> Default for Snapshot<T> {
+    fn default() -> Snapshot<T> {
+        Snapshot::new(T::default())
+    }
+}
+
+impl<T: Copy> From<T> for Snapshot<T> {
+    fn from(value: T) -> Snapshot<T> {
+        Snapshot::new(value)
+    }
+}
+
+impl<T: Copy + Debug> Debug for Snapshot<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Snapshot({:?})", self.load())
+    }
+}
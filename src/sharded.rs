@@ -0,0 +1,137 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An Atom split into per-thread shards.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use {thread_shard, Atom, AtomStorable, CachePadded};
+
+/// A set of `Atom`s, each on its own cache line, that writers on different
+/// threads spread over.
+///
+/// A single Atom that many threads write to bounces its cache line between
+/// their cores. Here each thread writes to the shard it is assigned to, and
+/// readers gather the shards with `collect` or `reduce`. Threads are
+/// assigned shards in turn, so with at least as many shards as threads
+/// writers never share one.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::sharded::ShardedAtom;
+///
+/// let latest = ShardedAtom::with_shards(4);
+/// latest.swap(Box::new(1), Ordering::AcqRel);
+/// latest.swap(Box::new(2), Ordering::AcqRel);
+/// let values: Vec<_> = latest.collect(Ordering::Acquire);
+/// assert_eq!(values, [Box::new(2)]);
+/// ```
+pub struct ShardedAtom<P>
+where
+    P: AtomStorable,
+{
+    shards: Box<[CachePadded<Atom<P>>]>,
+}
+
+impl<P> ShardedAtom<P>
+where
+    P: AtomStorable,
+{
+    /// Create an empty ShardedAtom with a shard for each available CPU
+    pub fn new() -> ShardedAtom<P> {
+        ShardedAtom::with_shards(thread::available_parallelism().map_or(4, |n| n.get()))
+    }
+
+    /// Create an empty ShardedAtom with `shards` shards
+    pub fn with_shards(shards: usize) -> ShardedAtom<P> {
+        assert!(shards > 0, "a ShardedAtom needs at least one shard");
+        ShardedAtom {
+            shards: (0..shards).map(|_| CachePadded(Atom::empty())).collect(),
+        }
+    }
+
+    /// Get the shard the current thread writes to
+    pub fn local(&self) -> &Atom<P> {
+        &self.shards[thread_shard() % self.shards.len()]
+    }
+
+    /// Swap a new value into the current thread's shard, returning the
+    /// value it held
+    pub fn swap(&self, v: P, order: Ordering) -> Option<P> {
+        self.local().swap(v, order)
+    }
+
+    /// Take the values out of every shard
+    pub fn collect(&self, order: Ordering) -> Vec<P> {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.take(order))
+            .collect()
+    }
+
+    /// Iterate over every shard
+    pub fn iter(&self) -> impl Iterator<Item = &Atom<P>> {
+        self.shards.iter().map(|shard| &**shard)
+    }
+
+    /// The number of shards
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Check to see if every shard is empty
+    ///
+    /// This only means that each shard was empty when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.shards.iter().all(|shard| shard.is_none(order))
+    }
+}
+
+impl<P> ShardedAtom<P>
+where
+    P: AtomStorable + Clone,
+{
+    /// Fold over a copy of the value in each shard, leaving the shards
+    /// as they are. Each copy is taken with `Atom::load_cloned`, so a
+    /// concurrent writer may see its shard empty for a moment.
+    pub fn reduce<R, F>(&self, init: R, f: F) -> R
+    where
+        F: FnMut(R, P) -> R,
+    {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.load_cloned())
+            .fold(init, f)
+    }
+}
+
+impl<P> Default for ShardedAtom<P>
+where
+    P: AtomStorable,
+{
+    fn default() -> ShardedAtom<P> {
+        ShardedAtom::new()
+    }
+}
+
+impl<P> Debug for ShardedAtom<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
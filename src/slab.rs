@@ -0,0 +1,295 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A fixed-capacity slab addressed by generational handles.
+//!
+//! Free slots are kept on a lock-free stack of indices. Its head packs the
+//! top index together with a counter that every pop and push bumps, so a
+//! slot that is popped, reused and pushed back in the meantime cannot fool
+//! a CAS into linking a stale index.
+//!
+//! Removing a value first bumps its slot's generation, which is what makes
+//! a `Handle` go stale, and then takes the value out. Readers may still be
+//! looking at it, so lookups run pinned and removed values are dropped
+//! through `epoch`.
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use epoch;
+use {Atom, AtomStorable, IntoRawPtr, RawDeref};
+
+/// Marks the end of the free list
+const NONE: u32 = u32::MAX;
+
+/// A reference to a value stored in a `Slab`.
+///
+/// Handles are plain data. A handle whose value has been removed stays
+/// stale even if its slot is reused, until the slot's 32-bit generation
+/// counter wraps around.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl Handle {
+    /// The index of the slot
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// The generation of the slot the value was inserted in
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Pack the handle into a `u64`
+    pub fn to_bits(self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    /// Unpack a handle packed by `to_bits`
+    pub fn from_bits(bits: u64) -> Handle {
+        Handle {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+struct Slot<P>
+where
+    P: AtomStorable,
+{
+    generation: AtomicU32,
+    // the next free slot while this one is free
+    next_free: AtomicU32,
+    value: Atom<P>,
+}
+
+/// A fixed number of slots that values can be inserted into and removed
+/// from concurrently.
+///
+/// ```
+/// use atom::slab::Slab;
+///
+/// let slab = Slab::with_capacity(16);
+/// let handle = slab.insert(Box::new("sprite")).unwrap();
+/// assert_eq!(slab.get(handle).as_deref(), Some(&"sprite"));
+/// assert!(slab.remove(handle));
+/// assert!(slab.get(handle).is_none());
+/// ```
+pub struct Slab<P>
+where
+    P: AtomStorable,
+{
+    slots: Box<[Slot<P>]>,
+    // tag << 32 | index of the first free slot
+    free: AtomicU64,
+    len: AtomicUsize,
+}
+
+impl<T, P> Slab<P>
+where
+    P: AtomStorable + RawDeref<Target = T> + Send + 'static,
+{
+    /// Create a slab with `capacity` slots
+    pub fn with_capacity(capacity: usize) -> Slab<P> {
+        assert!(
+            capacity < NONE as usize,
+            "a slab holds fewer than u32::MAX slots"
+        );
+        Slab {
+            slots: (0..capacity)
+                .map(|index| Slot {
+                    generation: AtomicU32::new(0),
+                    next_free: AtomicU32::new(if index + 1 < capacity {
+                        index as u32 + 1
+                    } else {
+                        NONE
+                    }),
+                    value: Atom::empty(),
+                })
+                .collect(),
+            free: AtomicU64::new(if capacity > 0 { 0 } else { NONE as u64 }),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn pop_free(&self) -> Option<u32> {
+        let mut head = self.free.load(Ordering::Acquire);
+        loop {
+            let index = head as u32;
+            if index == NONE {
+                return None;
+            }
+            // A stale read of `next_free` is harmless, the tag makes the CAS
+            // fail if the slot was taken meanwhile.
+            let next = self.slots[index as usize].next_free.load(Ordering::Relaxed);
+            let new = ((head >> 32) + 1) << 32 | next as u64;
+            match self
+                .free
+                .compare_exchange_weak(head, new, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => return Some(index),
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    fn push_free(&self, index: u32) {
+        let mut head = self.free.load(Ordering::Relaxed);
+        loop {
+            self.slots[index as usize]
+                .next_free
+                .store(head as u32, Ordering::Relaxed);
+            let new = ((head >> 32) + 1) << 32 | index as u64;
+            match self
+                .free
+                .compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Insert `value` into a free slot, or hand it back if the slab is full
+    pub fn insert(&self, value: P) -> Result<Handle, P> {
+        let index = match self.pop_free() {
+            Some(index) => index,
+            None => return Err(value),
+        };
+        let slot = &self.slots[index as usize];
+        let generation = slot.generation.load(Ordering::Relaxed);
+        let prev = slot.value.set_if_none(value, Ordering::Release);
+        debug_assert!(prev.is_none());
+        self.len.fetch_add(1, Ordering::Relaxed);
+        Ok(Handle { index, generation })
+    }
+
+    fn slot(&self, handle: Handle) -> Option<&Slot<P>> {
+        self.slots.get(handle.index as usize)
+    }
+
+    /// Borrow the value for `handle`, if it has not been removed
+    pub fn get(&self, handle: Handle) -> Option<Guard<'_, T>> {
+        let slot = self.slot(handle)?;
+        let pin = epoch::pin();
+        let ptr = slot.value.inner.load(Ordering::Acquire) as *const T;
+        // A value stored after the generation moved on would have been
+        // seen together with the new generation.
+        if slot.generation.load(Ordering::Acquire) != handle.generation {
+            return None;
+        }
+        let value = unsafe { ptr.as_ref()? };
+        Some(Guard { value, _pin: pin })
+    }
+
+    /// Check to see if the value for `handle` is still in the slab
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// Remove the value for `handle`, returning whether it was still there.
+    ///
+    /// The value is dropped once no `Guard` can still be borrowing it.
+    pub fn remove(&self, handle: Handle) -> bool {
+        let slot = match self.slot(handle) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        if slot
+            .generation
+            .compare_exchange(
+                handle.generation,
+                handle.generation.wrapping_add(1),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        // The generation only moves on through the CAS above, so this
+        // thread is the only one that can take this value.
+        let value = slot
+            .value
+            .take(Ordering::Acquire)
+            .expect("a live handle's slot is full");
+        // raw pointers are not Send, the address is
+        let addr = IntoRawPtr::into_raw(value) as usize;
+        epoch::pin().defer(move || drop(unsafe { P::from_raw(addr as *mut ()) }));
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        self.push_free(handle.index);
+        true
+    }
+
+    /// The number of values in the slab
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Check to see if the slab is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of slots in the slab
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+impl<P> Debug for Slab<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Slab({}/{})",
+            self.len.load(Ordering::Relaxed),
+            self.slots.len()
+        )
+    }
+}
+
+/// A borrow of a value in a `Slab`, see `Slab::get`.
+///
+/// The thread stays pinned while the guard is alive, so values removed
+/// meanwhile are not dropped until it is gone.
+pub struct Guard<'a, T: 'a> {
+    value: &'a T,
+    _pin: epoch::Guard,
+}
+
+impl<'a, T> Deref for Guard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> Debug for Guard<'a, T>
+where
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Guard({:?})", self.value)
+    }
+}
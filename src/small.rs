@@ -0,0 +1,181 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Atoms that keep small values inline instead of behind a pointer.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use strongest_failure_ordering;
+
+/// A value that can be stored directly in a `SmallAtom`.
+///
+/// # Safety
+///
+/// The type must be smaller than a `usize` and must not contain padding
+/// bytes, every byte of it has to be initialized so that it can be copied
+/// into an integer.
+pub unsafe trait SmallValue: Copy {}
+
+unsafe impl SmallValue for () {}
+unsafe impl SmallValue for bool {}
+unsafe impl SmallValue for u8 {}
+unsafe impl SmallValue for i8 {}
+unsafe impl SmallValue for u16 {}
+unsafe impl SmallValue for i16 {}
+#[cfg(target_pointer_width = "64")]
+unsafe impl SmallValue for u32 {}
+#[cfg(target_pointer_width = "64")]
+unsafe impl SmallValue for i32 {}
+#[cfg(target_pointer_width = "64")]
+unsafe impl SmallValue for f32 {}
+#[cfg(target_pointer_width = "64")]
+unsafe impl SmallValue for char {}
+
+/// An atomic `Option<T>` for values small enough to share a word with a
+/// flag marking it as set, so storing one never allocates.
+///
+/// It has the same `swap`, `take` and `set_if_none` surface as `Atom`, and
+/// since the value is `Copy` it can also simply be `load`ed.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::small::SmallAtom;
+///
+/// let atom = SmallAtom::empty();
+/// assert_eq!(atom.set_if_none(7u16, Ordering::AcqRel), None);
+/// assert_eq!(atom.set_if_none(8, Ordering::AcqRel), Some(8));
+/// assert_eq!(atom.swap(9, Ordering::AcqRel), Some(7));
+/// assert_eq!(atom.load(Ordering::Acquire), Some(9));
+/// assert_eq!(atom.take(Ordering::AcqRel), Some(9));
+/// assert!(atom.is_none(Ordering::Acquire));
+/// ```
+pub struct SmallAtom<T: SmallValue> {
+    inner: AtomicUsize,
+    data: PhantomData<T>,
+}
+
+impl<T: SmallValue> SmallAtom<T> {
+    /// Set in the word whenever it holds a value
+    const SET: usize = {
+        assert!(
+            mem::size_of::<T>() < mem::size_of::<usize>(),
+            "SmallValue must be smaller than a usize"
+        );
+        1 << (8 * mem::size_of::<T>())
+    };
+
+    /// Create an empty SmallAtom
+    pub const fn empty() -> SmallAtom<T> {
+        SmallAtom {
+            inner: AtomicUsize::new(0),
+            data: PhantomData,
+        }
+    }
+
+    /// Create a SmallAtom holding `value`
+    pub fn new(value: T) -> SmallAtom<T> {
+        SmallAtom {
+            inner: AtomicUsize::new(Self::pack(value)),
+            data: PhantomData,
+        }
+    }
+
+    fn pack(value: T) -> usize {
+        let mut bytes = [0u8; mem::size_of::<usize>()];
+        // `SmallValue` promises every byte of `value` is initialized
+        unsafe {
+            ptr::copy_nonoverlapping(
+                &value as *const T as *const u8,
+                bytes.as_mut_ptr(),
+                mem::size_of::<T>(),
+            );
+        }
+        usize::from_le_bytes(bytes) | Self::SET
+    }
+
+    fn unpack(word: usize) -> Option<T> {
+        if word & Self::SET == 0 {
+            return None;
+        }
+        let bytes = word.to_le_bytes();
+        // the bytes were copied out of a valid `T` by `pack`
+        Some(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    /// Swap a new value into the SmallAtom, returning the old value
+    pub fn swap(&self, v: T, order: Ordering) -> Option<T> {
+        Self::unpack(self.inner.swap(Self::pack(v), order))
+    }
+
+    /// Take the value out of the SmallAtom, leaving it empty
+    pub fn take(&self, order: Ordering) -> Option<T> {
+        Self::unpack(self.inner.swap(0, order))
+    }
+
+    /// Set the value only if the SmallAtom is empty. Returns `None` if the
+    /// value was written, otherwise `v` is handed back.
+    pub fn set_if_none(&self, v: T, order: Ordering) -> Option<T> {
+        match self.inner.compare_exchange(
+            0,
+            Self::pack(v),
+            order,
+            strongest_failure_ordering(order),
+        ) {
+            Ok(_) => None,
+            Err(_) => Some(v),
+        }
+    }
+
+    /// Get a copy of the current value
+    pub fn load(&self, order: Ordering) -> Option<T> {
+        Self::unpack(self.inner.load(order))
+    }
+
+    /// Check to see if the SmallAtom is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.inner.load(order) & Self::SET == 0
+    }
+
+    /// Consume the SmallAtom, returning its value
+    pub fn into_inner(self) -> Option<T> {
+        Self::unpack(self.inner.into_inner())
+    }
+}
+
+impl<T: SmallValue> Default for SmallAtom<T> {
+    fn default() -> SmallAtom<T> {
+        SmallAtom::empty()
+    }
+}
+
+impl<T: SmallValue> From<Option<T>> for SmallAtom<T> {
+    fn from(value: Option<T>) -> SmallAtom<T> {
+        match value {
+            Some(value) => SmallAtom::new(value),
+            None => SmallAtom::empty(),
+        }
+    }
+}
+
+impl<T: SmallValue + Debug> Debug for SmallAtom<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "SmallAtom({:?})", self.load(Ordering::Relaxed))
+    }
+}
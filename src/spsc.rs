@@ -0,0 +1,195 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A bounded single-producer single-consumer ring.
+//!
+//! `Ring::new(capacity).split()` hands out one `Producer` and one `Consumer`.
+//! Each side owns one index and only reads the other's, so neither ever
+//! needs a read-modify-write. The indices sit on separate cache lines and
+//! each side keeps a cached copy of the other's index, only going back to
+//! the shared one when the cached copy says the ring is full or empty.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use {Atom, AtomStorable, CachePadded};
+
+/// The shared storage of a ring, see `split`
+pub struct Ring<P>
+where
+    P: AtomStorable,
+{
+    slots: Box<[Atom<P>]>,
+    // both indices only ever count up, the slot is the index modulo capacity
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+impl<P> Ring<P>
+where
+    P: AtomStorable,
+{
+    /// Create an empty ring that holds at most `capacity` values
+    pub fn new(capacity: usize) -> Ring<P> {
+        assert!(capacity > 0, "a ring needs at least one slot");
+        Ring {
+            slots: (0..capacity).map(|_| Atom::empty()).collect(),
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The number of values the ring can hold
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Split the ring into its two ends
+    pub fn split(self) -> (Producer<P>, Consumer<P>) {
+        let ring = Arc::new(self);
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Relaxed);
+        (
+            Producer {
+                ring: ring.clone(),
+                tail,
+                head,
+            },
+            Consumer { ring, head, tail },
+        )
+    }
+
+    fn slot(&self, index: usize) -> &Atom<P> {
+        &self.slots[index % self.slots.len()]
+    }
+}
+
+impl<P> Debug for Ring<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        write!(f, "Ring({}/{})", tail.wrapping_sub(head), self.slots.len())
+    }
+}
+
+/// The pushing end of a `Ring`
+pub struct Producer<P>
+where
+    P: AtomStorable,
+{
+    ring: Arc<Ring<P>>,
+    tail: usize,
+    // the consumer's index as last seen, it only ever lags behind
+    head: usize,
+}
+
+impl<P> Producer<P>
+where
+    P: AtomStorable,
+{
+    /// Add `v` to the ring, or hand it back if the ring is full
+    pub fn push(&mut self, v: P) -> Result<(), P> {
+        if self.tail.wrapping_sub(self.head) == self.ring.capacity() {
+            self.head = self.ring.head.load(Ordering::Acquire);
+            if self.tail.wrapping_sub(self.head) == self.ring.capacity() {
+                return Err(v);
+            }
+        }
+        let prev = self.ring.slot(self.tail).set_if_none(v, Ordering::Relaxed);
+        debug_assert!(prev.is_none());
+        self.tail = self.tail.wrapping_add(1);
+        self.ring.tail.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+
+    /// The number of free slots, at least as many as are really free
+    pub fn free(&mut self) -> usize {
+        self.head = self.ring.head.load(Ordering::Acquire);
+        self.ring.capacity() - self.tail.wrapping_sub(self.head)
+    }
+
+    /// The number of values the ring can hold
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+}
+
+impl<P> Debug for Producer<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Producer({:?})", self.ring)
+    }
+}
+
+/// The popping end of a `Ring`
+pub struct Consumer<P>
+where
+    P: AtomStorable,
+{
+    ring: Arc<Ring<P>>,
+    head: usize,
+    // the producer's index as last seen, it only ever lags behind
+    tail: usize,
+}
+
+impl<P> Consumer<P>
+where
+    P: AtomStorable,
+{
+    /// Remove the oldest value from the ring, if there is one
+    pub fn pop(&mut self) -> Option<P> {
+        if self.head == self.tail {
+            self.tail = self.ring.tail.load(Ordering::Acquire);
+            if self.head == self.tail {
+                return None;
+            }
+        }
+        let v = self.ring.slot(self.head).take(Ordering::Relaxed);
+        debug_assert!(v.is_some());
+        self.head = self.head.wrapping_add(1);
+        self.ring.head.store(self.head, Ordering::Release);
+        v
+    }
+
+    /// The number of values waiting, at least as many as are really there
+    pub fn len(&mut self) -> usize {
+        self.tail = self.ring.tail.load(Ordering::Acquire);
+        self.tail.wrapping_sub(self.head)
+    }
+
+    /// Check to see if the ring is empty
+    pub fn is_empty(&mut self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of values the ring can hold
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+}
+
+impl<P> Debug for Consumer<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Consumer({:?})", self.ring)
+    }
+}
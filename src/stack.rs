@@ -0,0 +1,281 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A Treiber stack.
+//!
+//! Pushing is `replace_and_set_next`. Popping has to read the `next` field
+//! of a node that another thread may pop and free at the same time, so pops
+//! run pinned and popped nodes are freed through `epoch`. Since no node can
+//! be freed and reused while a pop is in flight, the head CAS cannot be
+//! fooled by a recycled address either.
+//!
+//! Under heavy contention every operation fights over the head. A stack
+//! made `with_elimination` gives operations that lose a CAS a second place
+//! to go: a pusher parks its node in a random slot of an elimination array
+//! for a moment, and a popper that finds a node there takes it, so the pair
+//! completes without touching the head at all.
+
+use std::cell::Cell;
+use std::fmt::{self, Debug, Formatter};
+use std::hint;
+use std::mem::{self, ManuallyDrop};
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+use epoch;
+use {Atom, FromRawPtr, GetNextMut, IntoRawPtr};
+
+struct Node<T> {
+    // Neither field is dropped with the node: `next` belongs to the stack
+    // and `value` is moved out by `pop` or dropped by `Stack::drop`.
+    next: ManuallyDrop<Option<Box<Node<T>>>>,
+    value: ManuallyDrop<T>,
+}
+
+impl<T> GetNextMut for Box<Node<T>> {
+    type NextPtr = Option<Box<Node<T>>>;
+    fn get_next(&mut self) -> &mut Option<Box<Node<T>>> {
+        &mut self.next
+    }
+}
+
+unsafe fn free<T>(addr: usize) {
+    drop(<Box<Node<T>> as FromRawPtr>::from_raw(addr as *mut ()));
+}
+
+/// How long a pusher leaves its node in an elimination slot.
+const ELIMINATION_SPINS: usize = 128;
+
+thread_local! {
+    static SEED: Cell<u32> = const { Cell::new(0x9e37_79b9) };
+}
+
+/// Pick a pseudo-random elimination slot, different threads spread out.
+fn random_slot(len: usize) -> usize {
+    SEED.with(|seed| {
+        let mut x = seed.get() ^ (seed as *const _ as usize as u32);
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        seed.set(x);
+        x as usize % len
+    })
+}
+
+enum Pop<T> {
+    Value(T),
+    Empty,
+    Contended,
+}
+
+/// A lock-free LIFO stack.
+///
+/// ```
+/// use atom::stack::Stack;
+///
+/// let stack = Stack::new();
+/// stack.push(1);
+/// stack.push(2);
+/// assert_eq!(stack.pop(), Some(2));
+/// assert_eq!(stack.pop(), Some(1));
+/// assert_eq!(stack.pop(), None);
+/// ```
+pub struct Stack<T> {
+    head: Atom<Box<Node<T>>>,
+    elimination: Vec<Atom<Box<Node<T>>>>,
+}
+
+impl<T> Stack<T> {
+    /// Create an empty stack
+    pub const fn new() -> Stack<T> {
+        Stack {
+            head: Atom::empty(),
+            elimination: Vec::new(),
+        }
+    }
+
+    /// Create an empty stack with an elimination array of `slots` slots.
+    /// Around half the number of contending threads is a good size.
+    pub fn with_elimination(slots: usize) -> Stack<T> {
+        Stack {
+            head: Atom::empty(),
+            elimination: (0..slots).map(|_| Atom::empty()).collect(),
+        }
+    }
+
+    /// Push `value` onto the top of the stack
+    pub fn push(&self, value: T) {
+        let mut node = Box::new(Node {
+            next: ManuallyDrop::new(None),
+            value: ManuallyDrop::new(value),
+        });
+        if self.elimination.is_empty() {
+            self.head
+                .replace_and_set_next(node, Ordering::Relaxed, Ordering::Release);
+            return;
+        }
+        loop {
+            node = match self.try_push(node) {
+                Ok(()) => return,
+                Err(node) => node,
+            };
+            node = match self.offer(node, &epoch::pin()) {
+                None => return,
+                Some(node) => node,
+            };
+        }
+    }
+
+    /// Remove the value on the top of the stack
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            match self.try_pop(&guard) {
+                Pop::Value(value) => return Some(value),
+                Pop::Empty => return None,
+                Pop::Contended => {}
+            }
+            if let Some(value) = self.accept(&guard) {
+                return Some(value);
+            }
+        }
+    }
+
+    /// Make one attempt at linking `node` in as the new head.
+    fn try_push(&self, mut node: Box<Node<T>>) -> Result<(), Box<Node<T>>> {
+        let head = self.head.inner.load(Ordering::Relaxed);
+        *node.next = unsafe { Atom::inner_peek(head) };
+        let new = Atom::raw(node);
+        match self
+            .head
+            .inner
+            .compare_exchange(head, new, Ordering::Release, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                let mut node: Box<Node<T>> = unsafe { FromRawPtr::from_raw(new) };
+                mem::forget(node.next.take());
+                Err(node)
+            }
+        }
+    }
+
+    /// Make one attempt at unlinking the head, retrying only if the CAS
+    /// failed spuriously.
+    fn try_pop(&self, guard: &epoch::Guard) -> Pop<T> {
+        let head = self.head.inner.load(Ordering::Acquire) as *mut Node<T>;
+        // Pinned, so `head` cannot have been freed even if another thread
+        // popped it since it was loaded.
+        let node = match unsafe { head.as_ref() } {
+            Some(node) => node,
+            None => return Pop::Empty,
+        };
+        let next = match *node.next {
+            Some(ref next) => &**next as *const Node<T> as *mut (),
+            None => ptr::null_mut(),
+        };
+        loop {
+            match self.head.inner.compare_exchange_weak(
+                head as *mut (),
+                next,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(actual) if actual == head as *mut () => continue,
+                Err(_) => return Pop::Contended,
+            }
+        }
+        let value = unsafe { ptr::read(&*node.value) };
+        let addr = head as usize;
+        let free: unsafe fn(usize) = free::<T>;
+        guard.defer(move || unsafe { free(addr) });
+        Pop::Value(value)
+    }
+
+    /// Park `node` in an elimination slot for a while. Returns the node if
+    /// no popper took it.
+    ///
+    /// Poppers free the nodes they take through `epoch`, so while `_guard`
+    /// pins this thread the address of our node cannot show up in the slot
+    /// again on behalf of another pusher.
+    fn offer(&self, node: Box<Node<T>>, _guard: &epoch::Guard) -> Option<Box<Node<T>>> {
+        let slot = &self.elimination[random_slot(self.elimination.len())];
+        let parked = match slot.set_if_none(node, Ordering::Release) {
+            Some(node) => return Some(node),
+            None => slot.token(Ordering::Relaxed),
+        };
+        for _ in 0..ELIMINATION_SPINS {
+            if slot.token(Ordering::Relaxed) != parked {
+                return None;
+            }
+            hint::spin_loop();
+        }
+        let ours = parked.0 as *mut ();
+        match slot.inner.compare_exchange(
+            ours,
+            ptr::null_mut(),
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Some(unsafe { FromRawPtr::from_raw(ours) }),
+            Err(_) => None,
+        }
+    }
+
+    /// Take a node a pusher parked in an elimination slot, if there is one.
+    fn accept(&self, guard: &epoch::Guard) -> Option<T> {
+        if self.elimination.is_empty() {
+            return None;
+        }
+        let slot = &self.elimination[random_slot(self.elimination.len())];
+        let mut node = slot.take(Ordering::Acquire)?;
+        let value = unsafe { ManuallyDrop::take(&mut node.value) };
+        let addr = node.into_raw() as usize;
+        let free: unsafe fn(usize) = free::<T>;
+        guard.defer(move || unsafe { free(addr) });
+        Some(value)
+    }
+
+    /// Check to see if the stack is empty
+    ///
+    /// This only means that the stack was empty when it was measured
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none(Ordering::Acquire)
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Stack<T> {
+        Stack::new()
+    }
+}
+
+impl<T> Debug for Stack<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Stack({:?})", self.head)
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        let parked = self
+            .elimination
+            .iter()
+            .filter_map(|slot| slot.take(Ordering::Acquire));
+        for mut node in parked.chain(self.head.drain(Ordering::Acquire)) {
+            unsafe { ManuallyDrop::drop(&mut node.value) };
+        }
+    }
+}
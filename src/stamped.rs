@@ -0,0 +1,227 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An Atom paired with a version stamp.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+
+use dword::AtomicPair;
+use {Atom, AtomStorable, Token};
+
+/// What a `StampedAtom` held at one point, the pointer and its stamp.
+///
+/// Two stamps only compare equal if nothing was stored in between, even
+/// when the same allocation came back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Stamp {
+    token: Token,
+    version: usize,
+}
+
+impl Stamp {
+    /// Get a `Token` for the value that was stored
+    pub fn token(&self) -> Token {
+        self.token
+    }
+
+    /// Get the number of stores that had happened
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Check to see if the StampedAtom was empty
+    pub fn is_none(&self) -> bool {
+        self.token.is_none()
+    }
+
+    fn from_words(words: [usize; 2]) -> Stamp {
+        Stamp {
+            token: Token(words[0]),
+            version: words[1],
+        }
+    }
+
+    fn words(&self) -> [usize; 2] {
+        [self.token.0, self.version]
+    }
+}
+
+/// An `Atom` whose pointer is paired with a counter that every store bumps.
+///
+/// A compare and swap on a plain `Atom` succeeds whenever the pointer is
+/// the one expected, even if it was swapped out and back in between. When
+/// nodes are recycled, as they are by a free list or a pool, that is the
+/// ABA problem. `compare_exchange_stamped` compares the pointer and the
+/// stamp together, so it fails if anything was stored since the `Stamp`
+/// was taken. The counter wraps after `usize::MAX` stores.
+///
+/// The pair is updated with a double-width compare and swap where the
+/// processor has one, and under a striped spinlock elsewhere. Every
+/// operation is sequentially consistent.
+///
+/// ```
+/// use atom::stamped::StampedAtom;
+///
+/// let atom = StampedAtom::new(Box::new(1));
+/// let seen = atom.stamp();
+/// let old = atom.swap(Box::new(2)).unwrap();
+/// atom.swap(old);
+/// // the original box is back, but the stamp tells that it moved
+/// assert_eq!(atom.stamp().token(), seen.token());
+/// assert!(atom.compare_exchange_stamped(seen, None).is_err());
+/// ```
+pub struct StampedAtom<P>
+where
+    P: AtomStorable,
+{
+    pair: AtomicPair,
+    data: PhantomData<UnsafeCell<P>>,
+}
+
+unsafe impl<P> Send for StampedAtom<P> where P: AtomStorable + Send {}
+unsafe impl<P> Sync for StampedAtom<P> where P: AtomStorable + Send {}
+
+impl<P> StampedAtom<P>
+where
+    P: AtomStorable,
+{
+    /// Create an empty StampedAtom
+    pub const fn empty() -> StampedAtom<P> {
+        StampedAtom {
+            pair: AtomicPair::new([0, 0]),
+            data: PhantomData,
+        }
+    }
+
+    /// Create a StampedAtom holding `value`
+    pub fn new(value: P) -> StampedAtom<P> {
+        StampedAtom {
+            pair: AtomicPair::new([Atom::raw(value) as usize, 0]),
+            data: PhantomData,
+        }
+    }
+
+    /// Get the current `Stamp`
+    pub fn stamp(&self) -> Stamp {
+        Stamp::from_words(self.pair.load())
+    }
+
+    /// Store `new` if nothing was stored since `current` was taken,
+    /// returning the old value. Otherwise the actual stamp is returned
+    /// together with `new`.
+    pub fn compare_exchange_stamped(
+        &self,
+        current: Stamp,
+        new: Option<P>,
+    ) -> Result<Option<P>, (Stamp, Option<P>)> {
+        let raw = Atom::inner_into_raw(new) as usize;
+        let next = [raw, current.version.wrapping_add(1)];
+        match self.pair.compare_exchange(current.words(), next) {
+            Ok(old) => Ok(unsafe { Atom::inner_from_raw(old[0] as *mut ()) }),
+            Err(actual) => Err((Stamp::from_words(actual), unsafe {
+                Atom::inner_from_raw(raw as *mut ())
+            })),
+        }
+    }
+
+    fn replace(&self, new: Option<P>) -> Option<P> {
+        let raw = Atom::inner_into_raw(new) as usize;
+        let mut current = self.pair.load();
+        loop {
+            match self
+                .pair
+                .compare_exchange(current, [raw, current[1].wrapping_add(1)])
+            {
+                Ok(old) => return unsafe { Atom::inner_from_raw(old[0] as *mut ()) },
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Swap a new value in, returning the old value
+    pub fn swap(&self, v: P) -> Option<P> {
+        self.replace(Some(v))
+    }
+
+    /// Take the value out, leaving the StampedAtom empty. This counts as a
+    /// store and bumps the stamp.
+    pub fn take(&self) -> Option<P> {
+        self.replace(None)
+    }
+
+    /// Set the value only if the StampedAtom is empty, otherwise `v` is
+    /// handed back
+    pub fn set_if_none(&self, v: P) -> Option<P> {
+        let mut current = self.stamp();
+        let mut v = Some(v);
+        while current.is_none() {
+            match self.compare_exchange_stamped(current, v) {
+                Ok(_) => return None,
+                Err((actual, back)) => {
+                    current = actual;
+                    v = back;
+                }
+            }
+        }
+        v
+    }
+
+    /// Check to see if the StampedAtom is empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self) -> bool {
+        self.stamp().is_none()
+    }
+
+    /// Consume the StampedAtom, returning its value
+    pub fn into_inner(mut self) -> Option<P> {
+        let raw = std::mem::replace(&mut self.pair.get_mut()[0], 0);
+        unsafe { Atom::inner_from_raw(raw as *mut ()) }
+    }
+}
+
+impl<P> Drop for StampedAtom<P>
+where
+    P: AtomStorable,
+{
+    fn drop(&mut self) {
+        let raw = self.pair.get_mut()[0];
+        drop(unsafe { Atom::<P>::inner_from_raw(raw as *mut ()) });
+    }
+}
+
+impl<P> Default for StampedAtom<P>
+where
+    P: AtomStorable,
+{
+    fn default() -> StampedAtom<P> {
+        StampedAtom::empty()
+    }
+}
+
+impl<P> Debug for StampedAtom<P>
+where
+    P: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let stamp = self.stamp();
+        write!(
+            f,
+            "StampedAtom({:?}, {})",
+            stamp.token.0 as *const (), stamp.version
+        )
+    }
+}
@@ -0,0 +1,208 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Atoms that keep a few flag bits next to the pointer.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use {FromRawPtr, RawDeref, Token};
+
+/// An `Atom` that packs a `BITS` wide tag into the low bits of its pointer.
+///
+/// The payload's alignment guarantees those bits are zero in the pointer
+/// itself, so the pointer and the tag are read and written together by a
+/// single atomic operation. A typical use is a "marked" bit that flags a
+/// node as logically deleted, which stops anyone from linking a new node
+/// after it. Asking for more bits than the alignment of `P::Target` leaves
+/// free is a compile time error.
+///
+/// The tag survives the pointer: it stays as it is when the value is taken
+/// out, and is only changed by the `*_tag` methods.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::tagged::TaggedAtom;
+///
+/// const MARKED: usize = 1;
+///
+/// let next = TaggedAtom::<Box<u64>, 1>::new(Box::new(5), 0);
+/// assert_eq!(next.fetch_or_tag(MARKED, Ordering::AcqRel), 0);
+/// assert_eq!(next.load_tag(Ordering::Acquire), MARKED);
+/// let (old, tag) = next.swap_with_tag(None, 0, Ordering::AcqRel);
+/// assert_eq!((old, tag), (Some(Box::new(5)), MARKED));
+/// ```
+pub struct TaggedAtom<P, const BITS: u32>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    inner: AtomicUsize,
+    data: PhantomData<UnsafeCell<P>>,
+}
+
+unsafe impl<P, const BITS: u32> Send for TaggedAtom<P, BITS>
+where
+    P: RawDeref + FromRawPtr + Send,
+    P::Target: Sized,
+{
+}
+
+unsafe impl<P, const BITS: u32> Sync for TaggedAtom<P, BITS>
+where
+    P: RawDeref + FromRawPtr + Send,
+    P::Target: Sized,
+{
+}
+
+impl<P, const BITS: u32> TaggedAtom<P, BITS>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    /// The bits of the word that hold the tag
+    const MASK: usize = {
+        assert!(
+            1 << BITS <= mem::align_of::<P::Target>(),
+            "the payload is not aligned enough to hold that many tag bits"
+        );
+        (1 << BITS) - 1
+    };
+
+    /// Create an empty TaggedAtom with a zero tag
+    pub const fn empty() -> TaggedAtom<P, BITS> {
+        TaggedAtom {
+            inner: AtomicUsize::new(0),
+            data: PhantomData,
+        }
+    }
+
+    /// Create a TaggedAtom holding `value` and `tag`
+    pub fn new(value: P, tag: usize) -> TaggedAtom<P, BITS> {
+        TaggedAtom {
+            inner: AtomicUsize::new(Self::pack(Some(value), tag)),
+            data: PhantomData,
+        }
+    }
+
+    fn pack(value: Option<P>, tag: usize) -> usize {
+        assert!(tag & !Self::MASK == 0, "tag does not fit in {} bits", BITS);
+        let ptr = match value {
+            Some(value) => value.into_raw() as usize,
+            None => 0,
+        };
+        debug_assert!(ptr & Self::MASK == 0, "misaligned pointer");
+        ptr | tag
+    }
+
+    fn unpack(word: usize) -> (Option<P>, usize) {
+        let ptr = word & !Self::MASK;
+        let value = if ptr == 0 {
+            None
+        } else {
+            // the pointer was produced by `pack` and is owned by the caller
+            Some(unsafe { FromRawPtr::from_raw(ptr as *mut ()) })
+        };
+        (value, word & Self::MASK)
+    }
+
+    /// Store `value` and `tag` together, returning the old value and tag
+    pub fn swap_with_tag(
+        &self,
+        value: Option<P>,
+        tag: usize,
+        order: Ordering,
+    ) -> (Option<P>, usize) {
+        Self::unpack(self.inner.swap(Self::pack(value, tag), order))
+    }
+
+    /// Take the value out, leaving the tag as it is
+    pub fn take(&self, order: Ordering) -> Option<P> {
+        let word = self.inner.fetch_and(Self::MASK, order);
+        Self::unpack(word & !Self::MASK).0
+    }
+
+    /// Set bits in the tag, returning the previous tag
+    pub fn fetch_or_tag(&self, bits: usize, order: Ordering) -> usize {
+        assert!(bits & !Self::MASK == 0, "tag does not fit in {} bits", BITS);
+        self.inner.fetch_or(bits, order) & Self::MASK
+    }
+
+    /// Clear the tag bits that are not in `bits`, returning the previous tag
+    pub fn fetch_and_tag(&self, bits: usize, order: Ordering) -> usize {
+        self.inner.fetch_and(bits | !Self::MASK, order) & Self::MASK
+    }
+
+    /// Read the tag
+    pub fn load_tag(&self, order: Ordering) -> usize {
+        self.inner.load(order) & Self::MASK
+    }
+
+    /// Get a `Token` identifying the value currently stored, ignoring the tag
+    pub fn token(&self, order: Ordering) -> Token {
+        Token(self.inner.load(order) & !Self::MASK)
+    }
+
+    /// Check to see if the TaggedAtom holds no value, whatever its tag
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.inner.load(order) & !Self::MASK == 0
+    }
+
+    /// Consume the TaggedAtom, returning its value and tag
+    pub fn into_inner(self) -> (Option<P>, usize) {
+        let word = self.inner.swap(0, Ordering::Relaxed);
+        Self::unpack(word)
+    }
+}
+
+impl<P, const BITS: u32> Drop for TaggedAtom<P, BITS>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    fn drop(&mut self) {
+        drop(Self::unpack(*self.inner.get_mut()));
+    }
+}
+
+impl<P, const BITS: u32> Default for TaggedAtom<P, BITS>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    fn default() -> TaggedAtom<P, BITS> {
+        TaggedAtom::empty()
+    }
+}
+
+impl<P, const BITS: u32> Debug for TaggedAtom<P, BITS>
+where
+    P: RawDeref + FromRawPtr,
+    P::Target: Sized,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let word = self.inner.load(Ordering::Relaxed);
+        write!(
+            f,
+            "TaggedAtom({:?}, {:#b})",
+            (word & !Self::MASK) as *const (),
+            word & Self::MASK
+        )
+    }
+}
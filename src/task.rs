@@ -0,0 +1,147 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Building blocks for async integrations.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Waker;
+
+/// Nobody is touching the waker
+const WAITING: usize = 0;
+/// A `register` is replacing the waker
+const REGISTERING: usize = 0b01;
+/// A `wake` is taking the waker
+const WAKING: usize = 0b10;
+
+/// A slot for the `Waker` of the one task waiting on an event.
+///
+/// The task calls `register` every time it is about to return
+/// `Poll::Pending`, whoever completes the event calls `wake`. A wake that
+/// races with a register is never lost: if it arrives while the waker is
+/// being replaced, `register` wakes the new waker itself once it is done.
+///
+/// Only one task is meant to wait at a time. Should two `register` calls
+/// overlap anyway, the second one does not store its waker but wakes it
+/// straight away, so that task polls again rather than hanging.
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::task::{Wake, Waker};
+/// use atom::task::AtomicWaker;
+///
+/// struct Noop;
+/// impl Wake for Noop {
+///     fn wake(self: Arc<Self>) {}
+/// }
+///
+/// let slot = AtomicWaker::new();
+/// slot.register(&Waker::from(Arc::new(Noop)));
+/// assert!(slot.take().is_some());
+/// assert!(slot.take().is_none());
+/// ```
+pub struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+// `waker` is only accessed by whoever moved `state` away from WAITING.
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    /// Create an empty slot
+    pub const fn new() -> AtomicWaker {
+        AtomicWaker {
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Store `waker` to be woken by the next `wake`
+    pub fn register(&self, waker: &Waker) {
+        match self
+            .state
+            .compare_exchange(WAITING, REGISTERING, Ordering::Acquire, Ordering::Acquire)
+            .unwrap_or_else(|state| state)
+        {
+            WAITING => {
+                unsafe {
+                    let slot = &mut *self.waker.get();
+                    match *slot {
+                        Some(ref old) if old.will_wake(waker) => {}
+                        _ => *slot = Some(waker.clone()),
+                    }
+                }
+                let done = self.state.compare_exchange(
+                    REGISTERING,
+                    WAITING,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                );
+                if done.is_err() {
+                    // A wake came in meanwhile and left the waker to us.
+                    let waker = unsafe { (*self.waker.get()).take() };
+                    self.state.swap(WAITING, Ordering::AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            }
+            WAKING => {
+                // The waker is being taken, whatever it was, this task has
+                // to be polled again.
+                waker.wake_by_ref();
+            }
+            _ => {
+                // another register is in progress
+                waker.wake_by_ref();
+            }
+        }
+    }
+
+    /// Wake the registered waker, if there is one
+    pub fn wake(&self) {
+        if let Some(waker) = self.take() {
+            waker.wake();
+        }
+    }
+
+    /// Take the registered waker out without waking it
+    pub fn take(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.state.fetch_and(!WAKING, Ordering::Release);
+                waker
+            }
+            // A register will see WAKING and do the wake itself, or a
+            // wake is already underway.
+            _ => None,
+        }
+    }
+}
+
+impl Default for AtomicWaker {
+    fn default() -> AtomicWaker {
+        AtomicWaker::new()
+    }
+}
+
+impl Debug for AtomicWaker {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "AtomicWaker({:#b})", self.state.load(Ordering::Relaxed))
+    }
+}
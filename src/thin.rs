@@ -0,0 +1,150 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A box for trait objects that fits in a single word.
+//!
+//! A `Box<dyn Trait>` is a pointer and a vtable, too wide for an `Atom`. A
+//! `ThinBox<dyn Trait>` keeps the wide pointer in a header at the start of
+//! the allocation holding the value, so the box itself is one pointer to
+//! that header and can be stored like any other payload.
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+use {AtomStorable, FromRawPtr, IntoRawPtr};
+
+#[repr(C)]
+struct Header<D: ?Sized> {
+    /// The value, which lives right after this header
+    value: *mut D,
+    /// Frees the allocation this header starts
+    free: unsafe fn(*mut Header<D>),
+}
+
+#[repr(C)]
+struct Node<D: ?Sized, T> {
+    header: Header<D>,
+    value: T,
+}
+
+unsafe fn free<D: ?Sized, T>(header: *mut Header<D>) {
+    drop(Box::from_raw(header as *mut Node<D, T>));
+}
+
+/// An owning pointer to a `D`, usually a trait object, that is one word
+/// wide.
+///
+/// The value is turned into a `D` by the `coerce` function passed to `new`,
+/// which for a trait object is just `|v| v`.
+///
+/// ```
+/// use std::sync::atomic::Ordering;
+/// use atom::Atom;
+/// use atom::thin::ThinBox;
+///
+/// trait Handler {
+///     fn handle(&self, x: u32) -> u32;
+/// }
+///
+/// struct Add(u32);
+///
+/// impl Handler for Add {
+///     fn handle(&self, x: u32) -> u32 { x + self.0 }
+/// }
+///
+/// let slot: Atom<ThinBox<dyn Handler>> = Atom::new(ThinBox::new(Add(1), |v| v));
+/// let old = slot.swap(ThinBox::new(Add(2), |v| v), Ordering::AcqRel).unwrap();
+/// assert_eq!(old.handle(1), 2);
+/// ```
+pub struct ThinBox<D: ?Sized> {
+    ptr: NonNull<Header<D>>,
+    data: PhantomData<D>,
+}
+
+unsafe impl<D: ?Sized + Send> Send for ThinBox<D> {}
+unsafe impl<D: ?Sized + Sync> Sync for ThinBox<D> {}
+
+impl<D: ?Sized> ThinBox<D> {
+    /// Move `value` onto the heap, using `coerce` to get at it as a `D`
+    pub fn new<T>(value: T, coerce: fn(&mut T) -> &mut D) -> ThinBox<D> {
+        let node = Box::into_raw(Box::<Node<D, T>>::new_uninit()) as *mut Node<D, T>;
+        unsafe {
+            let slot = ptr::addr_of_mut!((*node).value);
+            slot.write(value);
+            ptr::addr_of_mut!((*node).header).write(Header {
+                value: coerce(&mut *slot),
+                free: free::<D, T>,
+            });
+            ThinBox {
+                ptr: NonNull::new_unchecked(node as *mut Header<D>),
+                data: PhantomData,
+            }
+        }
+    }
+
+    fn header(&self) -> &Header<D> {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<D: ?Sized> Deref for ThinBox<D> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        unsafe { &*self.header().value }
+    }
+}
+
+impl<D: ?Sized> DerefMut for ThinBox<D> {
+    fn deref_mut(&mut self) -> &mut D {
+        unsafe { &mut *self.header().value }
+    }
+}
+
+impl<D: ?Sized> Drop for ThinBox<D> {
+    fn drop(&mut self) {
+        unsafe { (self.header().free)(self.ptr.as_ptr()) }
+    }
+}
+
+impl<D: ?Sized + Debug> Debug for ThinBox<D> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "ThinBox({:?})", &**self)
+    }
+}
+
+// The raw pointer is the header's, so there is no `RawDeref` impl.
+impl<D: ?Sized> IntoRawPtr for ThinBox<D> {
+    #[inline]
+    fn into_raw(self) -> *mut () {
+        let ptr = self.ptr.as_ptr() as *mut ();
+        mem::forget(self);
+        ptr
+    }
+}
+
+impl<D: ?Sized> FromRawPtr for ThinBox<D> {
+    #[inline]
+    unsafe fn from_raw(ptr: *mut ()) -> ThinBox<D> {
+        ThinBox {
+            ptr: NonNull::new_unchecked(ptr as *mut Header<D>),
+            data: PhantomData,
+        }
+    }
+}
+
+unsafe impl<D: ?Sized> AtomStorable for ThinBox<D> {}
@@ -0,0 +1,528 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Parking threads or tasks until some condition becomes true.
+//!
+//! A waiter pushes a node naming its thread (or its task's `Waker`) onto an
+//! intrusive LIFO and then parks, a notifier takes the whole chain and wakes
+//! every waiter on it.
+//! Both sides put a `SeqCst` fence between publishing their own write and
+//! checking the other side's, so either the waiter sees the condition or
+//! the notifier sees the waiter.
+//!
+//! With the `parking_lot` feature blocked threads are not put on the list,
+//! they park in `parking_lot_core`'s global table instead, keyed by the
+//! address of the list. Tasks are queued on the list either way.
+
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::atomic::{fence, AtomicIsize, AtomicUsize, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+use std::task::{Wake, Waker};
+#[cfg(not(feature = "parking_lot"))]
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+#[cfg(feature = "parking_lot")]
+use parking_lot_core::{self, ParkResult, DEFAULT_PARK_TOKEN, DEFAULT_UNPARK_TOKEN};
+
+use {Atom, CachePadded, GetNextMut};
+#[cfg(feature = "async")]
+use {AtomSetOnce, AtomStorable, RawDeref};
+
+enum Waiter {
+    #[cfg(not(feature = "parking_lot"))]
+    Thread(Thread),
+    Task(Waker),
+}
+
+impl Waiter {
+    fn wake(&self) {
+        match *self {
+            #[cfg(not(feature = "parking_lot"))]
+            Waiter::Thread(ref thread) => thread.unpark(),
+            Waiter::Task(ref waker) => waker.wake_by_ref(),
+        }
+    }
+}
+
+/// A waiter, shared between the node that queues it and the thread or
+/// future that is waiting
+struct Entry {
+    waiter: Waiter,
+    state: AtomicUsize,
+}
+
+/// The waiter has no node on the list
+const IDLE: usize = 0;
+/// The waiter's node is on the list and will be woken
+const QUEUED: usize = 1;
+/// The waiter gave up while its node was still on the list
+const DEAD: usize = 2;
+
+/// How many nodes of waiters that gave up a list holds before the next
+/// push clears it with a `notify_all`
+const STALE_LIMIT: isize = 32;
+
+struct Node {
+    next: Option<Box<Node>>,
+    entry: Arc<Entry>,
+}
+
+impl GetNextMut for Box<Node> {
+    type NextPtr = Option<Box<Node>>;
+    fn get_next(&mut self) -> &mut Option<Box<Node>> {
+        &mut self.next
+    }
+}
+
+/// A list of threads and tasks waiting for some condition.
+///
+/// Waiters push a node onto an intrusive LIFO with `replace_and_set_next`
+/// and park, `notify_all` takes the whole chain with one swap and wakes
+/// everyone on it. The list knows nothing about the condition itself: the
+/// notifier makes it true first and notifies second, the waiter registers
+/// first and checks second, and both sides fence in between so no wakeup
+/// is lost.
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+/// use std::thread;
+/// use atom::WaitList;
+///
+/// let ready = Arc::new((AtomicBool::new(false), WaitList::new()));
+/// let waiter = {
+///     let ready = ready.clone();
+///     thread::spawn(move || ready.1.wait_until(|| ready.0.load(Ordering::Acquire), None))
+/// };
+/// ready.0.store(true, Ordering::Release);
+/// ready.1.notify_all();
+/// assert!(waiter.join().unwrap());
+/// ```
+pub struct WaitList {
+    head: Atom<Box<Node>>,
+    /// Nodes on the list
+    len: AtomicUsize,
+    /// Nodes on the list whose waiter gave up, briefly negative when a
+    /// notify drops one before its waiter has counted it
+    stale: AtomicIsize,
+}
+
+impl WaitList {
+    /// Create an empty list
+    pub const fn new() -> WaitList {
+        WaitList {
+            head: Atom::empty(),
+            len: AtomicUsize::new(0),
+            stale: AtomicIsize::new(0),
+        }
+    }
+
+    /// Park the current thread until `done` returns true, or until
+    /// `deadline` passes. Returns the last result of `done`.
+    ///
+    /// `done` does not have to stay true once it has been: a thread that is
+    /// woken by a notify and finds it false again queues itself again
+    /// before it parks. A spurious wakeup reuses the node that is still
+    /// queued. A waiter that times out leaves its node behind, marked dead;
+    /// once enough of those pile up the next waiter to queue clears the
+    /// list with a `notify_all`, which unparks everyone still on it
+    /// spuriously.
+    #[cfg(not(feature = "parking_lot"))]
+    pub fn wait_until<F>(&self, done: F, deadline: Option<Instant>) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let entry = Arc::new(Entry {
+            waiter: Waiter::Thread(thread::current()),
+            state: AtomicUsize::new(IDLE),
+        });
+        let result = loop {
+            if done() {
+                break true;
+            }
+            // a notify sets the state back to idle when it takes the node
+            if entry.state.load(Ordering::Acquire) == IDLE {
+                entry.state.store(QUEUED, Ordering::Relaxed);
+                self.push(entry.clone());
+                if done() {
+                    break true;
+                }
+            }
+            if !park(deadline) {
+                break done();
+            }
+        };
+        self.retire(&entry);
+        result
+    }
+
+    /// Park the current thread until `done` returns true, or until
+    /// `deadline` passes. Returns the last result of `done`.
+    ///
+    /// Threads park in `parking_lot_core`'s global table keyed by the
+    /// address of this list rather than on the list itself, `done` is
+    /// checked under that table's lock so no wakeup is lost.
+    #[cfg(feature = "parking_lot")]
+    pub fn wait_until<F>(&self, done: F, deadline: Option<Instant>) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let key = self as *const WaitList as usize;
+        loop {
+            if done() {
+                return true;
+            }
+            let parked = unsafe {
+                parking_lot_core::park(
+                    key,
+                    || !done(),
+                    || {},
+                    |_, _| {},
+                    DEFAULT_PARK_TOKEN,
+                    deadline,
+                )
+            };
+            if let ParkResult::TimedOut = parked {
+                return done();
+            }
+        }
+    }
+
+    /// Register `waker` to be woken by the next `notify_all`. Callers must
+    /// check their condition again after this returns, a notify that raced
+    /// with the registration may have missed it.
+    ///
+    /// The waker stays queued until that notify or until the returned
+    /// `Registration` is dropped. A future keeps the registration while it
+    /// is pending and only registers again once it is no longer queued, or
+    /// when it is polled with a waker that would not wake the same task.
+    pub fn register(&self, waker: Waker) -> Registration<'_> {
+        let entry = Arc::new(Entry {
+            waiter: Waiter::Task(waker),
+            state: AtomicUsize::new(QUEUED),
+        });
+        self.push(entry.clone());
+        Registration { list: self, entry }
+    }
+
+    fn push(&self, entry: Arc<Entry>) {
+        if self.stale.load(Ordering::Relaxed) >= STALE_LIMIT {
+            self.notify_all();
+        }
+        let node = Box::new(Node { next: None, entry });
+        self.len.fetch_add(1, Ordering::Relaxed);
+        self.head
+            .replace_and_set_next(node, Ordering::Relaxed, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+    }
+
+    /// Mark `entry` as given up. If its node is still queued it is counted
+    /// as stale, so that enough of them get the list cleared.
+    fn retire(&self, entry: &Entry) {
+        if entry.state.swap(DEAD, Ordering::AcqRel) == QUEUED {
+            self.stale.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count the nodes on the list, including the ones left behind by
+    /// waiters that have given up. This is only a snapshot.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Check to see if nothing is queued on the list
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Wake every thread or task that is currently waiting. This must be called
+    /// after the condition the waiters check has been made true.
+    ///
+    /// Costs a fence and a load when nobody is waiting, plus a lookup in the
+    /// parking lot with the `parking_lot` feature.
+    pub fn notify_all(&self) {
+        fence(Ordering::SeqCst);
+        #[cfg(feature = "parking_lot")]
+        unsafe {
+            parking_lot_core::unpark_all(self as *const WaitList as usize, DEFAULT_UNPARK_TOKEN);
+        }
+        if self.head.is_none(Ordering::SeqCst) {
+            return;
+        }
+        for node in self.head.drain(Ordering::SeqCst) {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            if node.entry.state.swap(IDLE, Ordering::AcqRel) == DEAD {
+                self.stale.fetch_sub(1, Ordering::Relaxed);
+            } else {
+                node.entry.waiter.wake();
+            }
+        }
+    }
+}
+
+impl Default for WaitList {
+    fn default() -> WaitList {
+        WaitList::new()
+    }
+}
+
+impl Drop for WaitList {
+    fn drop(&mut self) {
+        // unlink the stale nodes one at a time instead of recursively
+        drop(self.head.drain(Ordering::Relaxed));
+    }
+}
+
+impl Debug for WaitList {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "WaitList({:?})", self.head)
+    }
+}
+
+/// A waker queued on a `WaitList` with `register`.
+///
+/// Dropping it withdraws the waker. Its node stays on the list until the
+/// next notify, but it no longer wakes anybody, and once enough withdrawn
+/// nodes pile up the list is cleared.
+#[must_use]
+pub struct Registration<'a> {
+    list: &'a WaitList,
+    entry: Arc<Entry>,
+}
+
+impl<'a> Registration<'a> {
+    /// Check to see if the waker is still queued. A notify takes it off the
+    /// list, after which the waiter has to register again to hear about the
+    /// next one.
+    pub fn is_queued(&self) -> bool {
+        self.entry.state.load(Ordering::Acquire) == QUEUED
+    }
+
+    /// Check to see if the queued waker would wake the same task as `waker`
+    pub fn will_wake(&self, waker: &Waker) -> bool {
+        match self.entry.waiter {
+            #[cfg(not(feature = "parking_lot"))]
+            Waiter::Thread(_) => false,
+            Waiter::Task(ref queued) => queued.will_wake(waker),
+        }
+    }
+}
+
+impl<'a> Drop for Registration<'a> {
+    fn drop(&mut self) {
+        self.list.retire(&self.entry);
+    }
+}
+
+impl<'a> Debug for Registration<'a> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Registration {{ queued: {} }}", self.is_queued())
+    }
+}
+
+/// Park the current thread until it is unparked or `deadline` passes,
+/// returning false if the deadline had already passed
+#[cfg(not(feature = "parking_lot"))]
+fn park(deadline: Option<Instant>) -> bool {
+    match deadline {
+        None => thread::park(),
+        Some(deadline) => {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::park_timeout(deadline - now);
+        }
+    }
+    true
+}
+
+/// Park the current thread until `done` returns true or `deadline` passes,
+/// for waits that span several lists. `register` is handed a waker that
+/// unparks this thread and has to queue it wherever the condition can be
+/// made true, whatever it returns is held until the next registration.
+/// Returns the last result of `done`.
+///
+/// A wakeup may have come from a notify that took the waker off a list, so
+/// the thread registers again every time it is woken and drops what the
+/// previous registration returned.
+pub fn park_with<R, G, F>(register: R, done: F, deadline: Option<Instant>) -> bool
+where
+    R: Fn(&Waker) -> G,
+    F: Fn() -> bool,
+{
+    if done() {
+        return true;
+    }
+    let unpark = Arc::new(Unpark::new());
+    let waker = Waker::from(unpark.clone());
+    let mut _registered = register(&waker);
+    loop {
+        if done() {
+            return true;
+        }
+        if !unpark.park(&done, deadline) {
+            return done();
+        }
+        _registered = register(&waker);
+    }
+}
+
+/// The waker handed out by `park_with`
+#[cfg(not(feature = "parking_lot"))]
+struct Unpark(Thread);
+
+#[cfg(not(feature = "parking_lot"))]
+impl Unpark {
+    fn new() -> Unpark {
+        Unpark(thread::current())
+    }
+
+    fn park<F: Fn() -> bool>(&self, _: &F, deadline: Option<Instant>) -> bool {
+        park(deadline)
+    }
+}
+
+#[cfg(not(feature = "parking_lot"))]
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// The waker handed out by `park_with`, its address is the parking key
+#[cfg(feature = "parking_lot")]
+struct Unpark;
+
+#[cfg(feature = "parking_lot")]
+impl Unpark {
+    fn new() -> Unpark {
+        Unpark
+    }
+
+    fn key(&self) -> usize {
+        self as *const Unpark as usize
+    }
+
+    fn park<F: Fn() -> bool>(&self, done: &F, deadline: Option<Instant>) -> bool {
+        let parked = unsafe {
+            parking_lot_core::park(
+                self.key(),
+                || !done(),
+                || {},
+                |_, _| {},
+                DEFAULT_PARK_TOKEN,
+                deadline,
+            )
+        };
+        !matches!(parked, ParkResult::TimedOut)
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        unsafe {
+            parking_lot_core::unpark_all(self.key(), DEFAULT_UNPARK_TOKEN);
+        }
+    }
+}
+
+/// Number of lists shared by everything parked through `parking`
+const PARKING_LOT: usize = 64;
+
+static PARKING: [CachePadded<WaitList>; PARKING_LOT] =
+    [const { CachePadded(WaitList::new()) }; PARKING_LOT];
+
+/// Find the list that waiters on `addr` park in. Unrelated addresses can
+/// share a list, so waiters must tolerate being woken for someone else.
+pub fn parking<T>(addr: *const T) -> &'static WaitList {
+    // drop the low bits, they are the same for every aligned address
+    let addr = addr as usize >> 3;
+    &PARKING[(addr ^ addr >> 6) % PARKING_LOT]
+}
+
+/// The future returned by `AtomSetOnce::wait_async`.
+///
+/// Dropping it before it completes is fine, the waker it registered is
+/// withdrawn and its node is cleared out like that of a thread that timed
+/// out.
+#[cfg(feature = "async")]
+pub struct WaitAsync<'a, P>
+where
+    P: AtomStorable + 'a,
+{
+    once: &'a AtomSetOnce<P>,
+    registered: Option<Registration<'static>>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, P> WaitAsync<'a, P>
+where
+    P: AtomStorable + 'a,
+{
+    pub fn new(once: &'a AtomSetOnce<P>) -> WaitAsync<'a, P> {
+        WaitAsync {
+            once,
+            registered: None,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, T, P> Future for WaitAsync<'a, P>
+where
+    P: AtomStorable + RawDeref<Target = T> + 'a,
+    T: 'a,
+{
+    type Output = &'a T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<&'a T> {
+        let once = self.once;
+        let done = || !once.inner.is_none(Ordering::SeqCst);
+        if !done() {
+            // keep a queued node that wakes this task, a notify that took it
+            // off the list means it has to be queued again
+            let current = match self.registered {
+                Some(ref registered) => registered.is_queued() && registered.will_wake(cx.waker()),
+                None => false,
+            };
+            if !current {
+                self.registered = Some(once.waiters().register(cx.waker().clone()));
+            }
+            if !done() {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(
+            once.get(Ordering::Acquire)
+                .expect("AtomSetOnce was emptied while shared"),
+        )
+    }
+}
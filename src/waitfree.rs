@@ -0,0 +1,112 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! The wait-free subset of the `Atom` API.
+//!
+//! Every operation on a `WaitFree` view is a single atomic instruction: it
+//! never retries, never allocates, never takes a lock and never runs a
+//! destructor. Displaced values are always handed back to the caller, so
+//! code running in a signal handler or interrupt context can stash them
+//! and let a normal thread free them later.
+//!
+//! Writing the handler against `WaitFree` rather than `Atom` lets the
+//! compiler check that only this subset is used.
+//!
+//! The `paranoid` feature adds locked bookkeeping to every `Box` conversion,
+//! it must be disabled in builds that rely on these guarantees.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::Ordering;
+
+use {Atom, AtomStorable, Token};
+
+/// A borrowed view of an `Atom` restricted to wait-free operations.
+pub struct WaitFree<'a, P>
+where
+    P: AtomStorable + 'a,
+{
+    atom: &'a Atom<P>,
+}
+
+impl<'a, P> WaitFree<'a, P>
+where
+    P: AtomStorable + 'a,
+{
+    /// Restrict `atom` to its wait-free operations
+    pub fn new(atom: &'a Atom<P>) -> WaitFree<'a, P> {
+        WaitFree { atom }
+    }
+
+    /// Swap a new value into the Atom with a single atomic exchange.
+    ///
+    /// The old value is returned and must not be dropped in a context that
+    /// cannot run its destructor.
+    #[must_use]
+    pub fn swap(&self, v: P, order: Ordering) -> Option<P> {
+        self.atom.swap(v, order)
+    }
+
+    /// Take the value of the Atom with a single atomic exchange.
+    #[must_use]
+    pub fn take(&self, order: Ordering) -> Option<P> {
+        self.atom.take(order)
+    }
+
+    /// Store `v` only if the Atom is empty, using a single CAS that is never
+    /// retried. If the Atom was set `v` is handed back.
+    #[must_use]
+    pub fn set_if_none(&self, v: P, order: Ordering) -> Option<P> {
+        self.atom.set_if_none(v, order)
+    }
+
+    /// Check to see if the Atom is None with a single load
+    pub fn is_none(&self, order: Ordering) -> bool {
+        self.atom.is_none(order)
+    }
+
+    /// Get a `Token` for the current contents with a single load
+    pub fn token(&self, order: Ordering) -> Token {
+        self.atom.token(order)
+    }
+}
+
+impl<'a, P> Clone for WaitFree<'a, P>
+where
+    P: AtomStorable + 'a,
+{
+    fn clone(&self) -> WaitFree<'a, P> {
+        *self
+    }
+}
+
+impl<'a, P> Copy for WaitFree<'a, P> where P: AtomStorable + 'a {}
+
+impl<'a, P> Debug for WaitFree<'a, P>
+where
+    P: AtomStorable + 'a,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "waitfree({:?})", self.atom)
+    }
+}
+
+impl<P> Atom<P>
+where
+    P: AtomStorable,
+{
+    /// Borrow the wait-free subset of this Atom's API
+    pub fn wait_free(&self) -> WaitFree<'_, P> {
+        WaitFree::new(self)
+    }
+}
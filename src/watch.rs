@@ -0,0 +1,252 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A channel that publishes the latest value to any number of receivers.
+//!
+//! The current value lives in an `ArcCell`, so `borrow` is a cheap snapshot
+//! that never blocks the sender. Each `send` bumps a version counter and
+//! wakes everyone waiting for a change, and each `Receiver` remembers the
+//! last version it marked as seen.
+
+use std::fmt::{self, Debug, Formatter};
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::RecvError;
+use std::sync::Arc;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+
+use arc_cell::ArcCell;
+#[cfg(feature = "async")]
+use wait::Registration;
+use wait::WaitList;
+
+/// Set in `version` once the sender is gone, versions count in steps of 2
+const CLOSED: usize = 1;
+
+struct Shared<T> {
+    value: ArcCell<T>,
+    version: AtomicUsize,
+    waiters: WaitList,
+}
+
+impl<T> Shared<T> {
+    fn has_changed(&self, seen: usize) -> Result<bool, RecvError> {
+        let version = self.version.load(Ordering::Acquire);
+        if version & !CLOSED != seen {
+            Ok(true)
+        } else if version & CLOSED != 0 {
+            Err(RecvError)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn changed_or_closed(&self, seen: usize) -> bool {
+        self.version.load(Ordering::Acquire) != seen
+    }
+
+    fn mark_changed(&self, seen: &mut usize) -> Result<(), RecvError> {
+        let changed = self.has_changed(*seen)?;
+        debug_assert!(changed);
+        *seen = self.version.load(Ordering::Acquire) & !CLOSED;
+        Ok(())
+    }
+}
+
+/// Create a channel whose current value starts out as `init`
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use atom::watch;
+///
+/// let (tx, mut rx) = watch::channel(Arc::new("v1"));
+/// let reloader = thread::spawn(move || tx.send(Arc::new("v2")));
+/// rx.wait_changed().unwrap();
+/// assert_eq!(*rx.borrow_and_update(), "v2");
+/// reloader.join().unwrap();
+/// ```
+pub fn channel<T>(init: Arc<T>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        value: ArcCell::new(init),
+        version: AtomicUsize::new(0),
+        waiters: WaitList::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, seen: 0 },
+    )
+}
+
+/// The publishing half of a watch channel
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Publish `value`, returning the previous one
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        let old = self.shared.value.swap(value);
+        self.shared.version.fetch_add(2, Ordering::AcqRel);
+        self.shared.waiters.notify_all();
+        old
+    }
+
+    /// Publish `value`
+    pub fn send(&self, value: Arc<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Get a snapshot of the current value
+    pub fn borrow(&self) -> Arc<T> {
+        self.shared.value.load()
+    }
+
+    /// Create a new receiver that has seen the current value
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.shared.version.load(Ordering::Acquire) & !CLOSED,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.version.fetch_or(CLOSED, Ordering::AcqRel);
+        self.shared.waiters.notify_all();
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "Sender({})",
+            self.shared.version.load(Ordering::Relaxed) / 2
+        )
+    }
+}
+
+/// A subscriber to a watch channel
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // the last version marked as seen, never has CLOSED set
+    seen: usize,
+}
+
+impl<T> Receiver<T> {
+    /// Get a snapshot of the current value without marking it as seen
+    pub fn borrow(&self) -> Arc<T> {
+        self.shared.value.load()
+    }
+
+    /// Get a snapshot of the current value and mark it as seen
+    pub fn borrow_and_update(&mut self) -> Arc<T> {
+        // Read the version first, so a send in between is noticed again.
+        self.seen = self.shared.version.load(Ordering::Acquire) & !CLOSED;
+        self.shared.value.load()
+    }
+
+    /// Check to see if a value was sent since the last one marked as seen.
+    /// Fails once the `Sender` is gone and there is nothing new.
+    pub fn has_changed(&self) -> Result<bool, RecvError> {
+        self.shared.has_changed(self.seen)
+    }
+
+    /// Block until a value is sent that has not been marked as seen, and
+    /// mark it. Fails once the `Sender` is gone and there is nothing new.
+    pub fn wait_changed(&mut self) -> Result<(), RecvError> {
+        let seen = self.seen;
+        self.shared
+            .waiters
+            .wait_until(|| self.shared.changed_or_closed(seen), None);
+        self.shared.mark_changed(&mut self.seen)
+    }
+
+    /// Wait for a value that has not been marked as seen, and mark it.
+    /// Fails once the `Sender` is gone and there is nothing new.
+    #[cfg(feature = "async")]
+    pub fn changed(&mut self) -> Changed<'_, T> {
+        Changed {
+            shared: &self.shared,
+            seen: &mut self.seen,
+            registered: None,
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Receiver<T> {
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Receiver({})", self.seen / 2)
+    }
+}
+
+/// The future returned by `Receiver::changed`.
+///
+/// Dropping it before it completes is fine, its waker is withdrawn and the
+/// node it was queued in is cleared out without waiting for a `send`.
+#[cfg(feature = "async")]
+pub struct Changed<'a, T: 'a> {
+    shared: &'a Shared<T>,
+    seen: &'a mut usize,
+    registered: Option<Registration<'a>>,
+}
+
+#[cfg(feature = "async")]
+impl<'a, T> Future for Changed<'a, T> {
+    type Output = Result<(), RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), RecvError>> {
+        let this = self.get_mut();
+        let shared = this.shared;
+        if !shared.changed_or_closed(*this.seen) {
+            // keep a queued node that wakes this task, a notify that took it
+            // off the list means it has to be queued again
+            let current = match this.registered {
+                Some(ref registered) => registered.is_queued() && registered.will_wake(cx.waker()),
+                None => false,
+            };
+            if !current {
+                this.registered = Some(shared.waiters.register(cx.waker().clone()));
+            }
+            if !shared.changed_or_closed(*this.seen) {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(shared.mark_changed(this.seen))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a, T> Debug for Changed<'a, T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Changed(Receiver({}))", *self.seen / 2)
+    }
+}
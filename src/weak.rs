@@ -0,0 +1,166 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A weak reference that can be replaced and upgraded concurrently.
+
+use std::fmt::{self, Debug, Formatter};
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Arc, Weak};
+
+use epoch;
+
+unsafe fn free_weak<T>(addr: usize) {
+    drop(Weak::from_raw(addr as *const T));
+}
+
+/// An atomic `Option<Weak<T>>`.
+///
+/// It lets an observer registry hold its subscribers without keeping them
+/// alive: `get_strong` upgrades whatever is stored and returns `None` once
+/// the subscriber has been dropped.
+///
+/// Upgrading reads the reference counts behind the stored pointer, so a
+/// `Weak` that is replaced is not dropped straight away but retired through
+/// `epoch`, for readers that may still be upgrading it. For the same reason
+/// the old value is never handed back by a store.
+///
+/// ```
+/// use std::sync::Arc;
+/// use atom::weak::AtomWeak;
+///
+/// let subscriber = Arc::new("listener");
+/// let slot = AtomWeak::new(&subscriber);
+/// assert_eq!(slot.get_strong().as_deref(), Some(&"listener"));
+/// drop(subscriber);
+/// assert!(slot.get_strong().is_none());
+/// ```
+pub struct AtomWeak<T> {
+    inner: AtomicPtr<T>,
+}
+
+unsafe impl<T: Send + Sync> Send for AtomWeak<T> {}
+unsafe impl<T: Send + Sync> Sync for AtomWeak<T> {}
+
+impl<T> AtomWeak<T> {
+    /// Create an empty AtomWeak
+    pub const fn empty() -> AtomWeak<T> {
+        AtomWeak {
+            inner: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Create an AtomWeak pointing at `value`
+    pub fn new(value: &Arc<T>) -> AtomWeak<T> {
+        AtomWeak::from(Arc::downgrade(value))
+    }
+
+    /// Replace the stored reference with a weak reference to `value`
+    pub fn set(&self, value: &Arc<T>) {
+        self.store(Some(Arc::downgrade(value)));
+    }
+
+    /// Replace the stored reference, the old one is dropped once no
+    /// reader can still be upgrading it
+    pub fn store(&self, weak: Option<Weak<T>>) {
+        let new = weak.map_or(ptr::null_mut(), |weak| Weak::into_raw(weak) as *mut T);
+        let pin = epoch::pin();
+        let old = self.inner.swap(new, Ordering::AcqRel);
+        if !old.is_null() {
+            let addr = old as usize;
+            let free: unsafe fn(usize) = free_weak::<T>;
+            pin.defer(move || unsafe { free(addr) });
+        }
+    }
+
+    /// Remove the stored reference
+    pub fn clear(&self) {
+        self.store(None);
+    }
+
+    /// Upgrade the stored reference, returning `None` if there is none or
+    /// if its target has been dropped
+    pub fn get_strong(&self) -> Option<Arc<T>> {
+        self.with(|weak| weak.upgrade())
+    }
+
+    /// Get a copy of the stored reference
+    pub fn get_weak(&self) -> Option<Weak<T>> {
+        self.with(|weak| Some(Weak::clone(weak)))
+    }
+
+    fn with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&Weak<T>) -> Option<R>,
+    {
+        let _pin = epoch::pin();
+        let ptr = self.inner.load(Ordering::Acquire);
+        if ptr.is_null() {
+            return None;
+        }
+        // Whoever replaces `ptr` defers dropping it until after we unpin,
+        // so its weak count keeps the allocation alive until then.
+        let weak = ManuallyDrop::new(unsafe { Weak::from_raw(ptr as *const T) });
+        f(&weak)
+    }
+
+    /// Check to see if no reference is stored. A stored reference whose
+    /// target was dropped still counts.
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self) -> bool {
+        self.inner.load(Ordering::Acquire).is_null()
+    }
+
+    /// Consume the AtomWeak, returning the stored reference
+    pub fn into_inner(mut self) -> Option<Weak<T>> {
+        let ptr = std::mem::replace(self.inner.get_mut(), ptr::null_mut());
+        if ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { Weak::from_raw(ptr as *const T) })
+        }
+    }
+}
+
+impl<T> From<Weak<T>> for AtomWeak<T> {
+    fn from(weak: Weak<T>) -> AtomWeak<T> {
+        AtomWeak {
+            inner: AtomicPtr::new(Weak::into_raw(weak) as *mut T),
+        }
+    }
+}
+
+impl<T> Default for AtomWeak<T> {
+    fn default() -> AtomWeak<T> {
+        AtomWeak::empty()
+    }
+}
+
+impl<T> Drop for AtomWeak<T> {
+    fn drop(&mut self) {
+        let ptr = *self.inner.get_mut();
+        if !ptr.is_null() {
+            // nobody can be reading through `&mut self`
+            drop(unsafe { Weak::from_raw(ptr as *const T) });
+        }
+    }
+}
+
+impl<T> Debug for AtomWeak<T> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "AtomWeak({:?})", self.inner.load(Ordering::Relaxed))
+    }
+}
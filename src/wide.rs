@@ -0,0 +1,226 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Two pointers that are swapped as one.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::mem;
+
+use dword::AtomicPair;
+use {Atom, AtomStorable, Token};
+
+/// The two halves of a `WideAtom`
+pub type Pair<P, Q> = (Option<P>, Option<Q>);
+
+/// A pair of Atoms that are read and replaced together.
+///
+/// Readers never see the first pointer of one store next to the second
+/// pointer of another, so a `(data, metadata)` pair can be published
+/// without boxing the pair itself. The pair is updated with a double-width
+/// compare and swap where the processor has one, and under a striped
+/// spinlock elsewhere. Every operation is sequentially consistent.
+///
+/// ```
+/// use atom::wide::WideAtom;
+///
+/// static V1: &str = "v1";
+/// static V2: &str = "v2";
+///
+/// let published = WideAtom::new(Some(Box::new(1)), Some(&V1));
+/// let (old, _) = published.swap(Some(Box::new(2)), Some(&V2));
+/// assert_eq!(old, Some(Box::new(1)));
+/// assert_eq!(published.load_second(), Some(&V2));
+/// ```
+pub struct WideAtom<P, Q>
+where
+    P: AtomStorable,
+    Q: AtomStorable,
+{
+    pair: AtomicPair,
+    data: PhantomData<UnsafeCell<(P, Q)>>,
+}
+
+unsafe impl<P, Q> Send for WideAtom<P, Q>
+where
+    P: AtomStorable + Send,
+    Q: AtomStorable + Send,
+{
+}
+
+unsafe impl<P, Q> Sync for WideAtom<P, Q>
+where
+    P: AtomStorable + Send,
+    Q: AtomStorable + Send,
+{
+}
+
+impl<P, Q> WideAtom<P, Q>
+where
+    P: AtomStorable,
+    Q: AtomStorable,
+{
+    /// Create an empty WideAtom
+    pub const fn empty() -> WideAtom<P, Q> {
+        WideAtom {
+            pair: AtomicPair::new([0, 0]),
+            data: PhantomData,
+        }
+    }
+
+    /// Create a WideAtom holding `first` and `second`
+    pub fn new(first: Option<P>, second: Option<Q>) -> WideAtom<P, Q> {
+        WideAtom {
+            pair: AtomicPair::new(Self::raw(first, second)),
+            data: PhantomData,
+        }
+    }
+
+    fn raw(first: Option<P>, second: Option<Q>) -> [usize; 2] {
+        [
+            Atom::inner_into_raw(first) as usize,
+            Atom::inner_into_raw(second) as usize,
+        ]
+    }
+
+    /// # Safety
+    ///
+    /// `words` must have come from `raw` and be owned by the caller
+    unsafe fn from_raw(words: [usize; 2]) -> Pair<P, Q> {
+        (
+            Atom::inner_from_raw(words[0] as *mut ()),
+            Atom::inner_from_raw(words[1] as *mut ()),
+        )
+    }
+
+    /// Replace both values, returning the old ones
+    pub fn swap(&self, first: Option<P>, second: Option<Q>) -> Pair<P, Q> {
+        let old = self.pair.swap(Self::raw(first, second));
+        unsafe { Self::from_raw(old) }
+    }
+
+    /// Take both values out, leaving the WideAtom empty
+    pub fn take(&self) -> Pair<P, Q> {
+        self.swap(None, None)
+    }
+
+    /// Replace both values if the WideAtom still holds what `current`
+    /// identifies, returning the old values. Otherwise the new values are
+    /// handed back, `tokens` tells what the WideAtom holds instead.
+    pub fn compare_exchange(
+        &self,
+        current: (Token, Token),
+        new: Pair<P, Q>,
+    ) -> Result<Pair<P, Q>, Pair<P, Q>> {
+        let new = Self::raw(new.0, new.1);
+        match self
+            .pair
+            .compare_exchange([(current.0).0, (current.1).0], new)
+        {
+            Ok(old) => Ok(unsafe { Self::from_raw(old) }),
+            Err(_) => Err(unsafe { Self::from_raw(new) }),
+        }
+    }
+
+    /// Get `Token`s identifying the two values currently stored
+    pub fn tokens(&self) -> (Token, Token) {
+        let words = self.pair.load();
+        (Token(words[0]), Token(words[1]))
+    }
+
+    /// Check to see if both halves are empty
+    ///
+    /// This only means that the contents was None when it was measured
+    pub fn is_none(&self) -> bool {
+        self.pair.load() == [0, 0]
+    }
+
+    /// Consume the WideAtom, returning both values
+    pub fn into_inner(mut self) -> Pair<P, Q> {
+        let words = mem::take(self.pair.get_mut());
+        unsafe { Self::from_raw(words) }
+    }
+}
+
+impl<P, Q> WideAtom<P, Q>
+where
+    P: AtomStorable + Copy,
+    Q: AtomStorable + Copy,
+{
+    /// Read both values. Only pointers that do not own what they point at,
+    /// such as `&T`, can be copied out while the WideAtom keeps them.
+    pub fn load(&self) -> Pair<P, Q> {
+        unsafe { Self::from_raw(self.pair.load()) }
+    }
+}
+
+impl<P, Q> WideAtom<P, Q>
+where
+    P: AtomStorable + Copy,
+    Q: AtomStorable,
+{
+    /// Read the first value, see `load`
+    pub fn load_first(&self) -> Option<P> {
+        let words = self.pair.load();
+        unsafe { Atom::inner_from_raw(words[0] as *mut ()) }
+    }
+}
+
+impl<P, Q> WideAtom<P, Q>
+where
+    P: AtomStorable,
+    Q: AtomStorable + Copy,
+{
+    /// Read the second value, see `load`
+    pub fn load_second(&self) -> Option<Q> {
+        let words = self.pair.load();
+        unsafe { Atom::inner_from_raw(words[1] as *mut ()) }
+    }
+}
+
+impl<P, Q> Drop for WideAtom<P, Q>
+where
+    P: AtomStorable,
+    Q: AtomStorable,
+{
+    fn drop(&mut self) {
+        drop(unsafe { Self::from_raw(*self.pair.get_mut()) });
+    }
+}
+
+impl<P, Q> Default for WideAtom<P, Q>
+where
+    P: AtomStorable,
+    Q: AtomStorable,
+{
+    fn default() -> WideAtom<P, Q> {
+        WideAtom::empty()
+    }
+}
+
+impl<P, Q> Debug for WideAtom<P, Q>
+where
+    P: AtomStorable,
+    Q: AtomStorable,
+{
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        let words = self.pair.load();
+        write!(
+            f,
+            "WideAtom({:?}, {:?})",
+            words[0] as *const (), words[1] as *const ()
+        )
+    }
+}
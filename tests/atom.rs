@@ -12,7 +12,11 @@
 //   See the License for the specific language governing permissions and
 //   limitations under the License.
 
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
 extern crate atom;
+#[cfg(feature = "crossbeam")]
+extern crate crossbeam_epoch;
 
 use atom::*;
 use std::collections::HashSet;
@@ -20,6 +24,7 @@
 use std::sync::atomic::Ordering;
 use std::sync::*;
 use std::thread;
+use std::time::{Duration, Instant};
 
 #[test]
 fn swap() {
@@ -88,8 +93,11 @@ fn compare_exchange_weak_threads() {
     });
 }
 
-type TestCASFn = fn(&Atom<Arc<String>>, Option<&Arc<String>>, Option<Arc<String>>)
-    -> Result<Option<Arc<String>>, (Option<Arc<String>>, *mut Arc<String>)>;
+type TestCASFn = fn(
+    &Atom<Arc<String>>,
+    Option<&Arc<String>>,
+    Option<Arc<String>>,
+) -> Result<Option<Arc<String>>, (Option<Arc<String>>, *mut Arc<String>)>;
 
 fn cas_test_basics_helper(cas: TestCASFn) {
     let cur_val = Arc::new("123".to_owned());
@@ -101,7 +109,7 @@ fn cas_test_basics_helper(cas: TestCASFn) {
     let pcur = IntoRawPtr::into_raw(cur_val.clone());
     let pnext = IntoRawPtr::into_raw(next_val.clone());
 
-    for attempt in vec![None, Some(&other_val), Some(&Arc::new("wow".to_owned()))] {
+    for attempt in [None, Some(&other_val), Some(&Arc::new("wow".to_owned()))] {
         let res = cas(&a, attempt, Some(next_val.clone())).unwrap_err();
         next_val = res.0.unwrap();
         assert_eq!(res.1, pcur as *mut _);
@@ -110,7 +118,7 @@ fn cas_test_basics_helper(cas: TestCASFn) {
     let res = cas(&a, Some(&cur_val), Some(next_val.clone()));
     assert_eq!(res, Ok(Some(cur_val)));
 
-    for attempt in vec![None, Some(&other_val), Some(&Arc::new("wow".to_owned()))] {
+    for attempt in [None, Some(&other_val), Some(&Arc::new("wow".to_owned()))] {
         let res = cas(&a, attempt, None).unwrap_err();
         assert_eq!(res, (None, pnext as *mut _));
     }
@@ -252,12 +260,18 @@ fn get_next(&mut self) -> &mut Option<Box<Link>> {
     }
 }
 
+impl GetNext for Box<Link> {
+    fn get_next_ref(&self) -> &Option<Box<Link>> {
+        &self.next
+    }
+}
+
 #[test]
 fn lifo() {
     let atom = Atom::empty();
     for i in 0..100 {
         let x = atom.replace_and_set_next(Link::new(99 - i), Ordering::Relaxed, Ordering::AcqRel);
-        assert_eq!(x, i == 0);
+        assert_eq!(x.is_none(), i == 0);
     }
 
     let expected: Vec<u32> = (0..100).collect();
@@ -312,3 +326,3826 @@ fn borrow() {
     assert_eq!(a.swap(&7, Ordering::Relaxed), Some(&5));
     assert_eq!(a.take(Ordering::Relaxed), Some(&7));
 }
+
+#[test]
+fn reader_writer() {
+    let atom = Arc::new(Atom::empty());
+    let reader = AtomReader::new(&atom);
+    let writer = AtomWriter::new(&atom);
+
+    let empty = reader.token(Ordering::Acquire);
+    assert!(empty.is_none());
+    assert!(reader.is_none(Ordering::Acquire));
+
+    assert_eq!(writer.set_if_none(Box::new(1u8), Ordering::Release), None);
+    let first = reader.token(Ordering::Acquire);
+    assert!(!first.is_none());
+    assert_ne!(first, empty);
+    assert_eq!(first, atom.token(Ordering::Acquire));
+
+    assert_eq!(
+        writer.set_if_none(Box::new(2u8), Ordering::Release),
+        Some(Box::new(2u8))
+    );
+    assert_eq!(first, reader.token(Ordering::Acquire));
+    assert_eq!(
+        writer.swap(Box::new(3u8), Ordering::AcqRel),
+        Some(Box::new(1u8))
+    );
+    assert!(!reader.is_none(Ordering::Acquire));
+}
+
+#[cfg(feature = "zeroize")]
+mod secret {
+    use atom::secret::{SecretBox, Zeroize};
+    use atom::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Key {
+        bytes: [u8; 4],
+        wiped: Arc<AtomicUsize>,
+    }
+
+    impl Zeroize for Key {
+        fn zeroize(&mut self) {
+            self.bytes.zeroize();
+            self.wiped.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn key(wiped: &Arc<AtomicUsize>) -> SecretBox<Key> {
+        SecretBox::new(Key {
+            bytes: [1, 2, 3, 4],
+            wiped: wiped.clone(),
+        })
+    }
+
+    #[test]
+    fn wiped_on_every_release() {
+        let wiped = Arc::new(AtomicUsize::new(0));
+        let a = Atom::new(key(&wiped));
+
+        let old = a.swap(key(&wiped), Ordering::AcqRel).unwrap();
+        assert_eq!(old.bytes, [1, 2, 3, 4]);
+        drop(old);
+        assert_eq!(wiped.load(Ordering::SeqCst), 1);
+
+        drop(a.set_if_none(key(&wiped), Ordering::AcqRel));
+        assert_eq!(wiped.load(Ordering::SeqCst), 2);
+
+        drop(a.take(Ordering::Acquire));
+        assert_eq!(wiped.load(Ordering::SeqCst), 3);
+
+        a.swap(key(&wiped), Ordering::AcqRel);
+        drop(a);
+        assert_eq!(wiped.load(Ordering::SeqCst), 4);
+    }
+}
+
+#[test]
+fn zero_sized_box() {
+    let a = Atom::empty();
+    assert!(a.is_none(Ordering::Acquire));
+    assert_eq!(a.set_if_none(Box::new(()), Ordering::Release), None);
+    assert!(!a.is_none(Ordering::Acquire));
+    assert_eq!(
+        a.set_if_none(Box::new(()), Ordering::Release),
+        Some(Box::new(()))
+    );
+    assert_eq!(a.swap(Box::new(()), Ordering::AcqRel), Some(Box::new(())));
+    assert_eq!(a.take(Ordering::Acquire), Some(Box::new(())));
+    assert_eq!(a.take(Ordering::Acquire), None);
+}
+
+#[test]
+fn zero_sized_arc() {
+    let v = Arc::new(());
+    let a = Atom::new(v.clone());
+    assert_eq!(Arc::strong_count(&v), 2);
+    assert!(!a.is_none(Ordering::Acquire));
+    let old = a.take(Ordering::Acquire).unwrap();
+    assert!(Arc::ptr_eq(&old, &v));
+    assert!(a.is_none(Ordering::Acquire));
+    assert_eq!(a.set_if_none(old, Ordering::Release), None);
+    drop(a);
+    assert_eq!(Arc::strong_count(&v), 1);
+}
+
+#[test]
+fn zero_sized_drop() {
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Zst;
+    impl Drop for Zst {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let a = Atom::new(Box::new(Zst));
+    drop(a.swap(Box::new(Zst), Ordering::AcqRel));
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    drop(a);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn drop_policies() {
+    use atom::policy::{Deferred, DropNow, Leak};
+
+    let v = Arc::new(AtomicUsize::new(0));
+    let a = Atom::new(Box::new(Canary(v.clone())));
+
+    a.store_with(Box::new(Canary(v.clone())), Ordering::AcqRel, &DropNow);
+    assert_eq!(v.load(Ordering::SeqCst), 1);
+
+    let deferred = Deferred::new();
+    a.store_with(Box::new(Canary(v.clone())), Ordering::AcqRel, &deferred);
+    a.clear_with(Ordering::AcqRel, &deferred);
+    assert!(a.is_none(Ordering::Acquire));
+    assert_eq!(v.load(Ordering::SeqCst), 1);
+    assert!(!deferred.is_empty());
+    assert_eq!(deferred.collect(), 2);
+    assert_eq!(v.load(Ordering::SeqCst), 3);
+    assert!(deferred.is_empty());
+
+    let leaked = Arc::new(AtomicUsize::new(0));
+    let b = Atom::new(Arc::new(Canary(leaked.clone())));
+    b.clear_with(Ordering::AcqRel, &Leak);
+    drop(b);
+    assert_eq!(leaked.load(Ordering::SeqCst), 0);
+}
+
+#[cfg(feature = "paranoid")]
+mod paranoid {
+    use atom::*;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn balanced_use_is_quiet() {
+        let a = AtomSetOnce::new(Box::new(5u32));
+        assert_eq!(a.get(Ordering::Acquire), Some(&5));
+        assert_eq!(a.get(Ordering::Acquire), Some(&5));
+        assert_eq!(a.dup(Ordering::Acquire), Some(Box::new(5)));
+        let a = a.into_atom();
+        assert_eq!(a.take(Ordering::Acquire), Some(Box::new(5)));
+    }
+
+    #[test]
+    fn racing_readers_are_quiet() {
+        let a = Arc::new(AtomSetOnce::new(Box::new(5u32)));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let a = a.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        assert_eq!(a.get(Ordering::Acquire), Some(&5));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "released twice")]
+    fn from_raw_on_cas_failure() {
+        let a = Atom::new(Box::new(1u32));
+        let other = Box::new(2u32);
+        let (_, pprev) = a
+            .compare_exchange(Some(&other), None, Ordering::SeqCst, Ordering::SeqCst)
+            .unwrap_err();
+        // The Atom still owns this pointer, reconstituting it is a bug
+        let stolen: Box<u32> = unsafe { FromRawPtr::from_raw(pprev as *mut ()) };
+        let _ = a.take(Ordering::Acquire);
+        drop(stolen);
+    }
+}
+
+#[test]
+fn wait_free_subset() {
+    use atom::waitfree::WaitFree;
+
+    fn handler(slot: WaitFree<Box<u8>>, stash: WaitFree<Box<u8>>) {
+        if let Some(v) = slot.take(Ordering::Acquire) {
+            if let Some(v) = stash.set_if_none(v, Ordering::Release) {
+                let _ = slot.swap(v, Ordering::AcqRel);
+            }
+        }
+    }
+
+    let slot = Atom::new(Box::new(3u8));
+    let stash = Atom::empty();
+    handler(slot.wait_free(), stash.wait_free());
+    assert!(slot.wait_free().is_none(Ordering::Acquire));
+    assert_eq!(stash.take(Ordering::Acquire), Some(Box::new(3u8)));
+}
+
+#[test]
+fn pop_next() {
+    let atom = Atom::empty();
+    for i in 0..10 {
+        atom.replace_and_set_next(Link::new(i), Ordering::Relaxed, Ordering::AcqRel);
+    }
+    for i in (0..10).rev() {
+        let link = unsafe { atom.pop_next(Ordering::Acquire, Ordering::AcqRel) }.unwrap();
+        assert_eq!(link.value, i);
+        assert!(link.next.is_none());
+    }
+    assert!(unsafe { atom.pop_next(Ordering::Acquire, Ordering::AcqRel) }.is_none());
+    assert!(atom.is_none(Ordering::Acquire));
+}
+
+#[test]
+fn pop_next_many_producers() {
+    let atom = Arc::new(Atom::empty());
+    let producers: Vec<_> = (0..4)
+        .map(|t| {
+            let atom = atom.clone();
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    atom.replace_and_set_next(
+                        Link::new(t * 1000 + i),
+                        Ordering::Relaxed,
+                        Ordering::AcqRel,
+                    );
+                }
+            })
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    while seen.len() < 4000 {
+        if let Some(link) = unsafe { atom.pop_next(Ordering::Acquire, Ordering::AcqRel) } {
+            assert!(seen.insert(link.value));
+        }
+    }
+    for p in producers {
+        p.join().unwrap();
+    }
+    assert!(atom.is_none(Ordering::Acquire));
+}
+
+#[test]
+fn drain() {
+    let atom = Atom::empty();
+    for i in 0..100 {
+        atom.replace_and_set_next(Link::new(i), Ordering::Relaxed, Ordering::AcqRel);
+    }
+    let found: Vec<u32> = atom
+        .drain(Ordering::Acquire)
+        .map(|link| {
+            assert!(link.next.is_none());
+            link.value
+        })
+        .collect();
+    let expected: Vec<u32> = (0..100).rev().collect();
+    assert_eq!(expected, found);
+    assert!(atom.is_none(Ordering::Acquire));
+}
+
+#[test]
+fn drain_drops_rest() {
+    let v = Arc::new(AtomicUsize::new(0));
+    let atom = Atom::empty();
+    for _ in 0..100_000 {
+        atom.replace_and_set_next(
+            LinkCanary::new(Canary(v.clone())),
+            Ordering::Relaxed,
+            Ordering::AcqRel,
+        );
+    }
+    let mut drain = atom.drain(Ordering::Acquire);
+    drop(drain.next());
+    assert_eq!(v.load(Ordering::SeqCst), 1);
+    drop(drain);
+    assert_eq!(v.load(Ordering::SeqCst), 100_000);
+}
+
+#[test]
+fn chain_utilities() {
+    let atom = Atom::empty();
+    for i in 0..10 {
+        atom.replace_and_set_next(Link::new(i), Ordering::Relaxed, Ordering::AcqRel);
+    }
+    let mut chain = atom.take_chain(Ordering::Acquire);
+    assert_eq!(chain.len(), 10);
+    let lifo: Vec<u32> = chain.iter().map(|link| link.value).collect();
+    assert_eq!(lifo, (0..10).rev().collect::<Vec<_>>());
+
+    chain.reverse();
+    let fifo: Vec<u32> = chain.into_iter().map(|link| link.value).collect();
+    assert_eq!(fifo, (0..10).collect::<Vec<_>>());
+
+    let mut empty = atom.take_chain(Ordering::Acquire);
+    assert!(empty.is_empty());
+    assert_eq!(empty.len(), 0);
+    empty.reverse();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn set_once_chain() {
+    struct Node {
+        value: u32,
+        next: AtomSetOnce<Box<Node>>,
+    }
+
+    let head = AtomSetOnce::empty();
+    let mut tail = &head;
+    for i in 0..5 {
+        let node = Box::new(Node {
+            value: i,
+            next: AtomSetOnce::empty(),
+        });
+        assert!(tail.set_if_none(node, Ordering::Release).is_none());
+        tail = &tail.get(Ordering::Acquire).unwrap().next;
+    }
+
+    assert_eq!(head.chain_len(Ordering::Acquire, |n| &n.next), 5);
+    let values: Vec<u32> = head
+        .iter(Ordering::Acquire, |n| &n.next)
+        .map(|n| n.value)
+        .collect();
+    assert_eq!(values, vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn push_chain() {
+    let local = Atom::empty();
+    for i in 0..5 {
+        local.replace_and_set_next(Link::new(i), Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    let shared = Atom::empty();
+    let previous = shared.replace_and_set_next_chain(
+        local.take_chain(Ordering::Relaxed),
+        Ordering::Relaxed,
+        Ordering::AcqRel,
+    );
+    assert!(previous.unwrap().is_none());
+    shared.replace_and_set_next(Link::new(100), Ordering::Relaxed, Ordering::AcqRel);
+    for i in 0..5 {
+        local.replace_and_set_next(Link::new(i), Ordering::Relaxed, Ordering::Relaxed);
+    }
+    let head = shared.token(Ordering::Acquire);
+    let previous = shared.replace_and_set_next_chain(
+        local.take_chain(Ordering::Relaxed),
+        Ordering::Relaxed,
+        Ordering::AcqRel,
+    );
+    assert_eq!(previous, Some(head));
+    assert_eq!(
+        shared.replace_and_set_next_chain(
+            chain::Chain::new(None),
+            Ordering::Relaxed,
+            Ordering::AcqRel
+        ),
+        None
+    );
+
+    let found: Vec<u32> = shared.drain(Ordering::Acquire).map(|l| l.value).collect();
+    assert_eq!(found, vec![4, 3, 2, 1, 0, 100, 4, 3, 2, 1, 0]);
+}
+
+#[test]
+fn push_chain_threads() {
+    let shared = Arc::new(Atom::empty());
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for burst in 0..100 {
+                    let local = Atom::empty();
+                    for i in 0..10 {
+                        local.replace_and_set_next(
+                            Link::new(t * 1000 + burst * 10 + i),
+                            Ordering::Relaxed,
+                            Ordering::Relaxed,
+                        );
+                    }
+                    shared.replace_and_set_next_chain(
+                        local.take_chain(Ordering::Relaxed),
+                        Ordering::Relaxed,
+                        Ordering::AcqRel,
+                    );
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let found: HashSet<u32> = shared.drain(Ordering::Acquire).map(|l| l.value).collect();
+    assert_eq!(found.len(), 8000);
+}
+
+#[test]
+fn collect_chain() {
+    let mut atom: Atom<Box<Link>> = (0..5).map(Link::new).collect();
+    atom.extend((5..10).map(Link::new));
+    let found: Vec<u32> = atom.drain(Ordering::Acquire).map(|l| l.value).collect();
+    assert_eq!(found, (0..10).rev().collect::<Vec<_>>());
+
+    let empty: Atom<Box<Link>> = None.into_iter().collect();
+    assert!(empty.is_none(Ordering::Acquire));
+
+    let chain: chain::Chain<Box<Link>> = (0..3).map(Link::new).collect();
+    assert_eq!(chain.len(), 3);
+    assert_eq!(chain.iter().next().map(|l| l.value), Some(2));
+}
+
+#[test]
+fn drop_deep_chain() {
+    let chain: chain::Chain<Box<Link>> = (0..1_000_000).map(Link::new).collect();
+    chain::drop_chain(chain.into_inner());
+
+    let atom: Atom<Box<Link>> = (0..1_000_000).map(Link::new).collect();
+    drop(atom.take_chain(Ordering::Acquire));
+}
+
+#[test]
+fn drop_deep_set_once_chain() {
+    struct Node {
+        next: AtomSetOnce<Box<Node>>,
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.next.drop_chain(|n| &mut n.next);
+        }
+    }
+
+    let mut head = Node {
+        next: AtomSetOnce::empty(),
+    };
+    {
+        let mut tail = &head.next;
+        for _ in 0..1_000_000 {
+            let node = Box::new(Node {
+                next: AtomSetOnce::empty(),
+            });
+            tail.set_if_none(node, Ordering::Release);
+            tail = &tail.get(Ordering::Acquire).unwrap().next;
+        }
+    }
+    assert_eq!(
+        head.next.chain_len(Ordering::Acquire, |n| &n.next),
+        1_000_000
+    );
+    head.next.drop_chain(|n| &mut n.next);
+    assert!(head.next.is_none(Ordering::Acquire));
+}
+
+struct LinkedNode {
+    link: AtomLink<Box<LinkedNode>>,
+    value: u32,
+}
+
+impl GetNextMut for Box<LinkedNode> {
+    type NextPtr = AtomLink<Box<LinkedNode>>;
+    fn get_next(&mut self) -> &mut AtomLink<Box<LinkedNode>> {
+        &mut self.link
+    }
+}
+
+impl GetNext for Box<LinkedNode> {
+    fn get_next_ref(&self) -> &AtomLink<Box<LinkedNode>> {
+        &self.link
+    }
+}
+
+#[test]
+fn atom_link() {
+    let atom = Atom::empty();
+    for i in 0..10 {
+        let node = Box::new(LinkedNode {
+            link: AtomLink::new(),
+            value: i,
+        });
+        atom.replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+    }
+    let top = unsafe { atom.pop_next(Ordering::Acquire, Ordering::AcqRel) }.unwrap();
+    assert_eq!(top.value, 9);
+    assert!(!top.link.is_linked());
+
+    let mut chain = atom.take_chain(Ordering::Acquire);
+    assert_eq!(chain.len(), 9);
+    chain.reverse();
+    let found: Vec<u32> = chain.iter().map(|n| n.value).collect();
+    assert_eq!(found, (0..9).collect::<Vec<_>>());
+}
+
+#[test]
+fn lifo_previous_head() {
+    let atom = Atom::empty();
+    assert!(atom
+        .replace_and_set_next(Link::new(0), Ordering::Relaxed, Ordering::AcqRel)
+        .is_none());
+    let head = atom.token(Ordering::Acquire);
+    let previous = atom.replace_and_set_next(Link::new(1), Ordering::Relaxed, Ordering::AcqRel);
+    assert_eq!(previous, head);
+    assert_ne!(atom.token(Ordering::Acquire), head);
+
+    drop(atom.take(Ordering::Acquire));
+    assert!(atom
+        .replace_and_set_next(Link::new(2), Ordering::Relaxed, Ordering::AcqRel)
+        .is_none());
+}
+
+#[test]
+fn combining_lifo() {
+    use atom::combining::CombiningLifo;
+
+    let lifo = Arc::new(CombiningLifo::new(4));
+    let threads: Vec<_> = (0..8)
+        .map(|t| {
+            let lifo = lifo.clone();
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    lifo.push(Link::new(t * 1000 + i));
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let found: HashSet<u32> = lifo
+        .head()
+        .drain(Ordering::Acquire)
+        .map(|l| l.value)
+        .collect();
+    assert_eq!(found.len(), 8000);
+
+    lifo.push(Link::new(1));
+    assert_eq!(
+        lifo.head().take(Ordering::Acquire).map(|l| l.value),
+        Some(1)
+    );
+}
+
+#[test]
+fn get_or_init() {
+    let atom = AtomSetOnce::empty();
+    assert_eq!(*atom.get_or_init(|| Box::new(1u32)), 1);
+    assert_eq!(*atom.get_or_init(|| Box::new(2u32)), 1);
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let inits = Arc::new(AtomicUsize::new(0));
+    let atom = Arc::new(AtomSetOnce::empty());
+    let barrier = Arc::new(Barrier::new(8));
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let (atom, drops, inits, barrier) =
+                (atom.clone(), drops.clone(), inits.clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                let value = atom.get_or_init(|| {
+                    inits.fetch_add(1, Ordering::SeqCst);
+                    Arc::new(Canary(drops.clone()))
+                });
+                value as *const Canary as usize
+            })
+        })
+        .collect();
+    let winners: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert!(winners.iter().all(|&w| w == winners[0]));
+    let inits = inits.load(Ordering::SeqCst);
+    assert_eq!(drops.load(Ordering::SeqCst), inits - 1);
+    drop(atom);
+    assert_eq!(drops.load(Ordering::SeqCst), inits);
+}
+
+#[test]
+fn get_or_try_init() {
+    let atom = AtomSetOnce::empty();
+    assert_eq!(
+        atom.get_or_try_init(|| Err::<Box<u32>, _>("offline")),
+        Err("offline")
+    );
+    assert!(atom.is_none(Ordering::Acquire));
+    assert_eq!(
+        atom.get_or_try_init(|| Ok::<_, &str>(Box::new(7u32))),
+        Ok(&7)
+    );
+    assert_eq!(
+        atom.get_or_try_init(|| Err::<Box<u32>, _>("unused")),
+        Ok(&7)
+    );
+}
+
+#[test]
+fn wait() {
+    let atom = Arc::new(AtomSetOnce::empty());
+    let waiters: Vec<_> = (0..4)
+        .map(|_| {
+            let atom = atom.clone();
+            thread::spawn(move || *atom.wait())
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(10));
+    atom.set_if_none(Box::new(11u32), Ordering::Release);
+    for w in waiters {
+        assert_eq!(w.join().unwrap(), 11);
+    }
+    assert_eq!(atom.wait(), &11);
+}
+
+#[test]
+fn wait_timeout() {
+    let atom = Arc::new(AtomSetOnce::empty());
+    let start = Instant::now();
+    assert_eq!(atom.wait_timeout(Duration::from_millis(20)), None);
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    let setter = {
+        let atom = atom.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            atom.set_if_none(Box::new(3u8), Ordering::Release);
+        })
+    };
+    assert_eq!(atom.wait_timeout(Duration::from_secs(10)), Some(&3));
+    setter.join().unwrap();
+
+    // waiting costs nothing in the cell itself
+    assert_eq!(
+        std::mem::size_of::<AtomSetOnce<Box<u8>>>(),
+        std::mem::size_of::<usize>()
+    );
+}
+
+#[cfg(feature = "async")]
+mod wait_async {
+    use atom::*;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+    use std::time::Duration;
+
+    struct Unparker(Thread, AtomicUsize);
+
+    impl Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(mut f: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unparker(thread::current(), AtomicUsize::new(0))));
+        let mut cx = Context::from_waker(&waker);
+        let mut f = unsafe { Pin::new_unchecked(&mut f) };
+        loop {
+            if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+                return v;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn wakes_on_set() {
+        let atom = Arc::new(AtomSetOnce::empty());
+        let setter = {
+            let atom = atom.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                atom.set_if_none(Box::new(5u32), Ordering::Release);
+            })
+        };
+        assert_eq!(block_on(atom.wait_async()), &5);
+        setter.join().unwrap();
+        assert_eq!(block_on(atom.wait_async()), &5);
+    }
+
+    #[test]
+    fn cancelled_wait() {
+        let atom = AtomSetOnce::empty();
+        let unparker = Arc::new(Unparker(thread::current(), AtomicUsize::new(0)));
+        let waker = Waker::from(unparker.clone());
+        let mut cx = Context::from_waker(&waker);
+        {
+            let mut f = Box::pin(atom.wait_async());
+            assert!(f.as_mut().poll(&mut cx).is_pending());
+        }
+        // the dropped future withdrew its waker
+        let mut f = Box::pin(atom.wait_async());
+        assert!(f.as_mut().poll(&mut cx).is_pending());
+        // polling again with the same waker does not queue it twice
+        assert!(f.as_mut().poll(&mut cx).is_pending());
+        assert!(atom.set_if_none(Box::new(1u8), Ordering::Release).is_none());
+        assert_eq!(unparker.1.load(Ordering::SeqCst), 1);
+        assert_eq!(f.as_mut().poll(&mut cx), Poll::Ready(&1));
+        assert_eq!(block_on(atom.wait_async()), &1);
+    }
+
+    #[test]
+    fn atomic_waker() {
+        use atom::task::AtomicWaker;
+        use std::sync::atomic::AtomicBool;
+
+        struct Flag {
+            slot: AtomicWaker,
+            set: AtomicBool,
+        }
+
+        struct WaitFlag(Arc<Flag>);
+
+        impl Future for WaitFlag {
+            type Output = ();
+            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+                // register before checking, or a set in between is missed
+                self.0.slot.register(cx.waker());
+                if self.0.set.load(Ordering::Acquire) {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            }
+        }
+
+        for _ in 0..200 {
+            let flag = Arc::new(Flag {
+                slot: AtomicWaker::new(),
+                set: AtomicBool::new(false),
+            });
+            let setter = {
+                let flag = flag.clone();
+                thread::spawn(move || {
+                    flag.set.store(true, Ordering::Release);
+                    flag.slot.wake();
+                })
+            };
+            block_on(WaitFlag(flag));
+            setter.join().unwrap();
+        }
+
+        // a wake with nothing registered is a no-op, and take empties the slot
+        let slot = AtomicWaker::new();
+        slot.wake();
+        let unparker = Arc::new(Unparker(thread::current(), AtomicUsize::new(0)));
+        slot.register(&Waker::from(unparker.clone()));
+        slot.wake();
+        slot.wake();
+        assert_eq!(unparker.1.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn async_oneshot() {
+        use atom::oneshot;
+        use std::sync::mpsc::RecvError;
+
+        let (tx, rx) = oneshot::channel();
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            tx.send(7u32).unwrap();
+        });
+        assert_eq!(block_on(rx), Ok(7));
+        sender.join().unwrap();
+
+        let (tx, rx) = oneshot::channel::<u32>();
+        let dropper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            drop(tx);
+        });
+        assert_eq!(block_on(rx), Err(RecvError));
+        dropper.join().unwrap();
+
+        // a receiver dropped while pending leaves the sender to find out
+        let (tx, rx) = oneshot::channel();
+        let unparker = Arc::new(Unparker(thread::current(), AtomicUsize::new(0)));
+        let waker = Waker::from(unparker);
+        {
+            let mut rx = rx;
+            assert!(Pin::new(&mut rx)
+                .poll(&mut Context::from_waker(&waker))
+                .is_pending());
+        }
+        assert!(tx.is_closed());
+        assert!(tx.send(1).is_err());
+    }
+
+    #[test]
+    fn watch_changed() {
+        use atom::watch;
+
+        let (tx, mut rx) = watch::channel(Arc::new(0));
+        let sender = thread::spawn(move || {
+            for i in 1..=3 {
+                thread::sleep(Duration::from_millis(5));
+                tx.send(Arc::new(i));
+            }
+        });
+        let mut last = 0;
+        while block_on(rx.changed()).is_ok() {
+            let v = *rx.borrow_and_update();
+            assert!(v > last);
+            last = v;
+        }
+        assert_eq!(last, 3);
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn cancellation_token() {
+        use atom::cancel::CancellationToken;
+
+        let root = CancellationToken::new();
+        let child = root.child().detach();
+        let grandchild = child.child();
+        let canceller = {
+            let root = root.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                root.cancel();
+            })
+        };
+        block_on(grandchild.cancelled());
+        assert!(child.is_cancelled());
+        block_on(root.cancelled());
+        canceller.join().unwrap();
+    }
+
+    #[test]
+    fn mpsc_channel() {
+        use atom::queue;
+
+        let (tx, mut rx) = queue::channel();
+        let producers: Vec<_> = (0..4)
+            .map(|producer| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for seq in 0..1_000 {
+                        tx.send(Box::new(::Message {
+                            next: Atom::empty(),
+                            producer,
+                            seq,
+                        }));
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        // each producer's messages arrive in the order it sent them
+        let mut next = [0; 4];
+        while let Some(msg) = block_on(rx.recv()) {
+            assert_eq!(msg.seq, next[msg.producer]);
+            next[msg.producer] += 1;
+        }
+        assert_eq!(next, [1_000; 4]);
+        for t in producers {
+            t.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn try_insert() {
+    let atom = AtomSetOnce::empty();
+    assert_eq!(atom.try_insert(Box::new(1u32)), Ok(&1));
+    match atom.try_insert(Box::new(2)) {
+        Err((current, rejected)) => {
+            assert_eq!(current, &1);
+            assert_eq!(*rejected, 2);
+        }
+        Ok(_) => panic!("second insert should be rejected"),
+    }
+
+    let atom = Arc::new(AtomSetOnce::empty());
+    let threads: Vec<_> = (0..8u32)
+        .map(|i| {
+            let atom = atom.clone();
+            thread::spawn(move || match atom.try_insert(Box::new(i)) {
+                Ok(v) => (*v, true),
+                Err((v, rejected)) => {
+                    assert_eq!(*rejected, i);
+                    (*v, false)
+                }
+            })
+        })
+        .collect();
+    let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    let winners: Vec<_> = results.iter().filter(|r| r.1).collect();
+    assert_eq!(winners.len(), 1);
+    assert!(results.iter().all(|r| r.0 == winners[0].0));
+}
+
+#[test]
+fn clone_set_once() {
+    #[derive(Clone)]
+    struct Config {
+        name: AtomSetOnce<Arc<String>>,
+    }
+
+    let config = Config {
+        name: AtomSetOnce::empty(),
+    };
+    let early = config.clone();
+    config
+        .name
+        .set_if_none(Arc::new("atom".to_string()), Ordering::Release);
+    let late = config.clone();
+
+    assert!(early.name.is_none(Ordering::Acquire));
+    let name = late.name.dup(Ordering::Acquire).unwrap();
+    assert_eq!(*name, "atom");
+    assert_eq!(Arc::strong_count(&name), 3);
+    drop(late);
+    assert_eq!(Arc::strong_count(&name), 2);
+}
+
+#[test]
+fn set_once_into_inner() {
+    let atom: AtomSetOnce<Box<u8>> = AtomSetOnce::empty();
+    assert_eq!(atom.into_inner(), None);
+
+    let canary = Arc::new(AtomicUsize::new(0));
+    let atom = AtomSetOnce::new(Box::new(Canary(canary.clone())));
+    let value = atom.into_inner().unwrap();
+    assert_eq!(canary.load(Ordering::SeqCst), 0);
+    drop(value);
+    assert_eq!(canary.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn set_once_arc_get_mut() {
+    let mut atom: AtomSetOnce<Arc<u32>> = AtomSetOnce::empty();
+    assert_eq!(atom.get_mut(), None);
+
+    atom.set_if_none(Arc::new(1), Ordering::Release);
+    *atom.get_mut().unwrap() += 1;
+    assert_eq!(atom.get(Ordering::Acquire), Some(&2));
+
+    let shared = atom.dup(Ordering::Acquire).unwrap();
+    assert_eq!(atom.get_mut(), None);
+    drop(shared);
+    assert_eq!(atom.get_mut(), Some(&mut 2));
+}
+
+#[test]
+fn set_once_take_reset() {
+    let canary = Arc::new(AtomicUsize::new(0));
+    let mut atom = AtomSetOnce::empty();
+    assert!(atom.take().is_none());
+
+    for round in 1..4 {
+        assert!(atom
+            .set_if_none(Box::new(Canary(canary.clone())), Ordering::Release)
+            .is_none());
+        assert!(atom
+            .set_if_none(Box::new(Canary(canary.clone())), Ordering::Release)
+            .is_some());
+        assert_eq!(canary.load(Ordering::SeqCst), 2 * round - 1);
+        atom.reset();
+        assert!(atom.is_none(Ordering::Acquire));
+        assert_eq!(canary.load(Ordering::SeqCst), 2 * round);
+    }
+
+    atom.set_if_none(Box::new(Canary(canary.clone())), Ordering::Release);
+    let taken = atom.take().unwrap();
+    assert!(atom.is_none(Ordering::Acquire));
+    drop(taken);
+    assert_eq!(canary.load(Ordering::SeqCst), 7);
+}
+
+#[test]
+fn once_lock_parity() {
+    let cell: AtomSetOnce<Box<String>> = AtomSetOnce::empty();
+    assert_eq!(cell.set("a".to_string()), Ok(()));
+    assert_eq!(cell.set("b".to_string()), Err("b".to_string()));
+    assert_eq!(cell.get_or_init(|| Box::new("c".to_string())), "a");
+    assert_eq!(unsafe { cell.get_unchecked() }, "a");
+
+    let lock: OnceLock<String> = cell.into();
+    assert_eq!(lock.get().map(|s| &s[..]), Some("a"));
+    let cell = AtomSetOnce::from(lock);
+    assert_eq!(cell.get(Ordering::Acquire).map(|s| &s[..]), Some("a"));
+
+    let empty: OnceLock<u8> = AtomSetOnce::<Box<u8>>::empty().into();
+    assert!(empty.get().is_none());
+    assert!(AtomSetOnce::from(empty).is_none(Ordering::Acquire));
+}
+
+#[test]
+fn atom_lazy() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let lazy = {
+        let calls = calls.clone();
+        Arc::new(AtomLazy::new(move || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            "value".to_string()
+        }))
+    };
+    assert_eq!(format!("{:?}", lazy), "AtomLazy(<uninit>)");
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let lazy = lazy.clone();
+            thread::spawn(move || lazy.len())
+        })
+        .collect();
+    for r in readers {
+        assert_eq!(r.join().unwrap(), 5);
+    }
+    let ran = calls.load(Ordering::SeqCst);
+    assert!((1..=4).contains(&ran));
+    assert_eq!(&**lazy, "value");
+    assert_eq!(calls.load(Ordering::SeqCst), ran);
+    assert_eq!(format!("{:?}", lazy), "AtomLazy(\"value\")");
+
+    let unused: AtomLazy<u8> = AtomLazy::default();
+    assert!(AtomLazy::into_value(unused).is_err());
+    let used: AtomLazy<u8> = AtomLazy::new(|| 7);
+    assert_eq!(*used, 7);
+    assert_eq!(AtomLazy::into_value(used).ok(), Some(7));
+}
+
+atom_static! {
+    static STATIC_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static STATIC_TABLE: Vec<u32> = {
+        STATIC_CALLS.fetch_add(1, Ordering::SeqCst);
+        (0..10).collect()
+    };
+}
+
+#[test]
+fn atom_static() {
+    let readers: Vec<_> = (0..4)
+        .map(|_| thread::spawn(|| STATIC_TABLE.iter().sum::<u32>()))
+        .collect();
+    for r in readers {
+        assert_eq!(r.join().unwrap(), 45);
+    }
+    assert_eq!(STATIC_TABLE.len(), 10);
+    assert!(STATIC_CALLS.load(Ordering::SeqCst) >= 1);
+}
+
+#[test]
+fn set_once_get_without_rebuilding() {
+    static VALUE: u32 = 9;
+    let borrowed: AtomSetOnce<&u32> = AtomSetOnce::new(&VALUE);
+    assert!(std::ptr::eq(
+        borrowed.get(Ordering::Acquire).unwrap(),
+        &VALUE
+    ));
+
+    let arc = Arc::new(3u64);
+    let shared = AtomSetOnce::new(arc.clone());
+    assert!(std::ptr::eq(shared.get(Ordering::Acquire).unwrap(), &*arc));
+    assert_eq!(Arc::strong_count(&arc), 2);
+}
+
+#[test]
+fn set_once_get_with() {
+    use atom::thin::ThinBox;
+
+    // ThinBox is Deref but its raw pointer is the header, not the slice
+    let once: AtomSetOnce<ThinBox<[u32]>> = AtomSetOnce::empty();
+    assert_eq!(once.get_with(Ordering::Acquire, |s| s.len()), None);
+    once.set_if_none(ThinBox::new([1, 2, 3], |v| v), Ordering::Release);
+    let sum = once.get_with(Ordering::Acquire, |s| s.iter().sum::<u32>());
+    assert_eq!(sum, Some(6));
+
+    let arc = Arc::new(5u64);
+    let shared = AtomSetOnce::new(arc.clone());
+    assert_eq!(shared.get_with(Ordering::Acquire, |v| *v), Some(5));
+    assert_eq!(Arc::strong_count(&arc), 2);
+}
+
+#[test]
+fn arc_cell() {
+    use atom::arc_cell::ArcCell;
+
+    let cell = ArcCell::new(Arc::new(0usize));
+    let old = cell.swap(Arc::new(1));
+    assert_eq!(*old, 0);
+    assert_eq!(Arc::strong_count(&old), 1);
+    assert_eq!(format!("{:?}", cell), "ArcCell(1)");
+
+    let cell = Arc::new(cell);
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..10_000 {
+                    let v = *cell.load();
+                    assert!(v >= last);
+                    last = v;
+                }
+            })
+        })
+        .collect();
+    for i in 2..1_000 {
+        cell.store(Arc::new(i));
+    }
+    for r in readers {
+        r.join().unwrap();
+    }
+
+    let value = Arc::try_unwrap(cell).unwrap().into_inner();
+    assert_eq!(*value, 999);
+    assert_eq!(Arc::strong_count(&value), 1);
+}
+
+#[test]
+fn arc_cell_back_to_back_writers() {
+    use atom::arc_cell::ArcCell;
+
+    // a value that notices being read after it was dropped
+    struct Checked(AtomicUsize);
+
+    impl Drop for Checked {
+        fn drop(&mut self) {
+            self.0.store(usize::MAX, Ordering::SeqCst);
+        }
+    }
+
+    let cell = Arc::new(ArcCell::new(Arc::new(Checked(AtomicUsize::new(0)))));
+    let done = Arc::new(AtomicUsize::new(0));
+    let reader = {
+        let (cell, done) = (cell.clone(), done.clone());
+        thread::spawn(move || {
+            while done.load(Ordering::Acquire) == 0 {
+                let value = cell.load();
+                // stall while holding the value so writers overtake us
+                thread::yield_now();
+                assert_ne!(value.0.load(Ordering::SeqCst), usize::MAX);
+            }
+        })
+    };
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                for i in 0..20_000 {
+                    cell.store(Arc::new(Checked(AtomicUsize::new(i))));
+                }
+            })
+        })
+        .collect();
+    for w in writers {
+        w.join().unwrap();
+    }
+    done.store(1, Ordering::Release);
+    reader.join().unwrap();
+}
+
+#[test]
+fn rcu_cell() {
+    use atom::rcu::RcuCell;
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let cell = Arc::new(RcuCell::new((0usize, Canary(drops.clone()))));
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..2_000 {
+                    let v = cell.read();
+                    assert!(v.0 >= last);
+                    last = v.0;
+                }
+            })
+        })
+        .collect();
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let (cell, drops) = (cell.clone(), drops.clone());
+            thread::spawn(move || {
+                for _ in 0..500 {
+                    cell.update(|v| (v.0 + 1, Canary(drops.clone())));
+                }
+            })
+        })
+        .collect();
+    for t in readers.into_iter().chain(writers) {
+        t.join().unwrap();
+    }
+    assert_eq!(cell.read().0, 1_000);
+
+    // the replaced values are reclaimed once the epoch moves past them
+    for _ in 0..10 {
+        atom::epoch::collect();
+    }
+    let value = Arc::try_unwrap(cell).ok().unwrap().into_inner();
+    assert_eq!(value.0, 1_000);
+    assert!(drops.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn hazard_pointers() {
+    use atom::hazard::Domain;
+
+    let domain = Domain::new();
+    let drops = Arc::new(AtomicUsize::new(0));
+    let atom = Atom::new(Box::new((0u32, Canary(drops.clone()))));
+
+    let mut guard = domain.guard();
+    let protected = unsafe { atom.load_protected(&mut guard) }.unwrap();
+    let old = atom
+        .swap(Box::new((1, Canary(drops.clone()))), Ordering::AcqRel)
+        .unwrap();
+    domain.retire(old);
+    assert_eq!(domain.reclaim(), 0);
+    assert_eq!(protected.0, 0);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    drop(guard);
+    assert_eq!(domain.reclaim(), 1);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    let domain = Arc::new(domain);
+    let atom = Arc::new(atom);
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let (domain, atom) = (domain.clone(), atom.clone());
+            thread::spawn(move || {
+                let mut guard = domain.guard();
+                let mut last = 0;
+                for _ in 0..2_000 {
+                    let v = unsafe { atom.load_protected(&mut guard) }.unwrap().0;
+                    assert!(v >= last);
+                    last = v;
+                }
+            })
+        })
+        .collect();
+    for i in 2..1_000 {
+        let old = atom.swap(Box::new((i, Canary(drops.clone()))), Ordering::AcqRel);
+        domain.retire(old.unwrap());
+    }
+    for r in readers {
+        r.join().unwrap();
+    }
+    drop(atom);
+    drop(domain);
+    assert_eq!(drops.load(Ordering::SeqCst), 1_000);
+}
+
+#[test]
+fn qsbr_grace_period() {
+    use atom::qsbr::Qsbr;
+
+    let qsbr = Qsbr::new();
+    let drops = Arc::new(AtomicUsize::new(0));
+    let atom = Atom::new(Box::new(Canary(drops.clone())));
+    let a = qsbr.register();
+    let b = qsbr.register();
+
+    let old = atom.swap(Box::new(Canary(drops.clone())), Ordering::AcqRel);
+    qsbr.retire(old.unwrap());
+    assert_eq!(a.quiescent(), 0);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    assert_eq!(b.quiescent(), 1);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    // a participant that leaves no longer holds up reclamation
+    let old = atom.swap(Box::new(Canary(drops.clone())), Ordering::AcqRel);
+    qsbr.retire(old.unwrap());
+    drop(b);
+    assert_eq!(a.quiescent(), 1);
+
+    let old = atom.take(Ordering::AcqRel);
+    qsbr.retire(old.unwrap());
+    drop(a);
+    drop(qsbr);
+    assert_eq!(drops.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn swap_deferred() {
+    use atom::policy::Collector;
+
+    struct Where(Arc<Mutex<Vec<thread::ThreadId>>>);
+
+    impl Drop for Where {
+        fn drop(&mut self) {
+            self.0.lock().unwrap().push(thread::current().id());
+        }
+    }
+
+    let dropped_on = Arc::new(Mutex::new(Vec::new()));
+    let collector = Collector::new();
+    let atom = Atom::new(Box::new(Where(dropped_on.clone())));
+    for _ in 0..100 {
+        atom.swap_deferred(
+            Box::new(Where(dropped_on.clone())),
+            Ordering::AcqRel,
+            &collector,
+        );
+    }
+    atom.clear_with(Ordering::AcqRel, &collector);
+    drop(collector);
+
+    let dropped_on = dropped_on.lock().unwrap();
+    assert_eq!(dropped_on.len(), 101);
+    assert!(dropped_on.iter().all(|id| *id != thread::current().id()));
+}
+
+#[cfg(feature = "crossbeam")]
+mod crossbeam_interop {
+    use super::Canary;
+    use atom::*;
+    use crossbeam_epoch::{self as epoch, Atomic, Owned};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn load_under_guard() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let atom = Atom::new(Box::new((1u32, Canary(drops.clone()))));
+        {
+            let guard = epoch::pin();
+            let current = atom.load(Ordering::Acquire, &guard);
+            atom.store_with(
+                Box::new((2, Canary(drops.clone()))),
+                Ordering::AcqRel,
+                &guard,
+            );
+            assert_eq!(unsafe { current.deref() }.0, 1);
+            assert_eq!(drops.load(Ordering::SeqCst), 0);
+        }
+        let guard = epoch::pin();
+        assert_eq!(unsafe { atom.load(Ordering::Acquire, &guard).deref() }.0, 2);
+        assert!(Atom::<Box<u8>>::empty()
+            .load(Ordering::Acquire, &guard)
+            .is_null());
+    }
+
+    #[test]
+    fn conversions() {
+        let atomic: Atomic<u32> = Atom::new(Box::new(7)).into();
+        let atom = unsafe { Atom::from_atomic(atomic) };
+        assert_eq!(atom.take(Ordering::Acquire), Some(Box::new(7)));
+
+        let empty: Atomic<u32> = Atom::empty().into();
+        assert!(unsafe { Atom::from_atomic(empty) }.is_none(Ordering::Acquire));
+
+        let atom = Atom::from(Owned::new(3u8));
+        assert_eq!(atom.take(Ordering::Acquire), Some(Box::new(3)));
+    }
+}
+
+#[test]
+fn load_cloned() {
+    let atom = Arc::new(Atom::new(Arc::new(1u32)));
+    let copy = atom.load_cloned().unwrap();
+    assert_eq!(*copy, 1);
+    assert_eq!(Arc::strong_count(&copy), 2);
+    assert!(Atom::<Arc<u8>>::empty().load_cloned().is_none());
+
+    let writer = {
+        let atom = atom.clone();
+        thread::spawn(move || {
+            for i in 2..1_000 {
+                atom.swap(Arc::new(i), Ordering::AcqRel);
+            }
+        })
+    };
+    let mut last = 0;
+    while last < 999 {
+        if let Some(v) = atom.load_cloned() {
+            assert!(*v >= last);
+            last = *v;
+        }
+    }
+    writer.join().unwrap();
+    assert_eq!(atom.load_cloned(), Some(Arc::new(999)));
+}
+
+#[test]
+fn cache() {
+    use atom::arc_cell::ArcCell;
+    use atom::cache::Cache;
+
+    let cell = Arc::new(ArcCell::new(Arc::new(1u32)));
+    let mut cache = Cache::new(cell.clone());
+    let first = cache.load().unwrap().clone();
+    assert!(Arc::ptr_eq(&first, cache.load().unwrap()));
+    cell.store(Arc::new(2));
+    assert_eq!(**cache.load().unwrap(), 2);
+    assert_eq!(*first, 1);
+}
+
+#[test]
+fn treiber_stack() {
+    use atom::stack::Stack;
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let stack = Arc::new(Stack::new());
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let (stack, drops) = (stack.clone(), drops.clone());
+            thread::spawn(move || {
+                let mut popped = 0;
+                for _ in 0..1_000 {
+                    stack.push(Canary(drops.clone()));
+                    if stack.pop().is_some() {
+                        popped += 1;
+                    }
+                }
+                popped
+            })
+        })
+        .collect();
+    let popped: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(drops.load(Ordering::SeqCst), popped);
+
+    let remaining = 4_000 - popped;
+    assert_eq!(stack.is_empty(), remaining == 0);
+    drop(stack);
+    assert_eq!(drops.load(Ordering::SeqCst), 4_000);
+}
+
+#[test]
+fn elimination_stack() {
+    use atom::stack::Stack;
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let stack = Arc::new(Stack::with_elimination(4));
+    let threads: Vec<_> = (0..8)
+        .map(|i| {
+            let (stack, drops) = (stack.clone(), drops.clone());
+            thread::spawn(move || {
+                let mut popped = 0;
+                for _ in 0..2_000 {
+                    if i % 2 == 0 {
+                        stack.push(Canary(drops.clone()));
+                    } else if stack.pop().is_some() {
+                        popped += 1;
+                    }
+                }
+                popped
+            })
+        })
+        .collect();
+    let popped: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(drops.load(Ordering::SeqCst), popped);
+    while stack.pop().is_some() {}
+    assert_eq!(drops.load(Ordering::SeqCst), 8_000);
+    assert!(stack.is_empty());
+}
+
+struct Message {
+    next: Atom<Box<Message>>,
+    producer: usize,
+    seq: usize,
+}
+
+impl GetNextMut for Box<Message> {
+    type NextPtr = Atom<Box<Message>>;
+    fn get_next(&mut self) -> &mut Atom<Box<Message>> {
+        &mut self.next
+    }
+}
+
+impl GetNext for Box<Message> {
+    fn get_next_ref(&self) -> &Atom<Box<Message>> {
+        &self.next
+    }
+}
+
+#[test]
+fn mpsc_queue() {
+    use atom::queue::MpscQueue;
+
+    let queue = Arc::new(MpscQueue::new());
+    let producers: Vec<_> = (0..4)
+        .map(|producer| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for seq in 0..1_000 {
+                    queue.push(Box::new(Message {
+                        next: Atom::empty(),
+                        producer,
+                        seq,
+                    }));
+                }
+            })
+        })
+        .collect();
+
+    // this thread is the only consumer
+    let mut expected = [0; 4];
+    let mut received = 0;
+    while received < 4_000 {
+        if let Some(msg) = unsafe { queue.pop() } {
+            assert!(msg.next.is_none(Ordering::Relaxed));
+            assert_eq!(expected[msg.producer], msg.seq);
+            expected[msg.producer] += 1;
+            received += 1;
+        }
+    }
+    for p in producers {
+        p.join().unwrap();
+    }
+    assert!(queue.is_empty());
+
+    let mut queue = Arc::try_unwrap(queue).unwrap();
+    for seq in 0..3 {
+        queue.push(Box::new(Message {
+            next: Atom::empty(),
+            producer: 0,
+            seq,
+        }));
+    }
+    assert_eq!(queue.pop_mut().map(|m| m.seq), Some(0));
+}
+
+#[test]
+fn ms_queue() {
+    use atom::queue::Queue;
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let queue = Arc::new(Queue::new());
+    let producers: Vec<_> = (0..4)
+        .map(|producer| {
+            let (queue, drops) = (queue.clone(), drops.clone());
+            thread::spawn(move || {
+                for seq in 0..1_000 {
+                    queue.push((producer, seq, Canary(drops.clone())));
+                }
+            })
+        })
+        .collect();
+    let consumers: Vec<_> = (0..4)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                let mut last = [None; 4];
+                let mut popped = 0;
+                for _ in 0..2_000 {
+                    if let Some((producer, seq, _)) = queue.pop() {
+                        // each producer's values come out in order
+                        assert!(last[producer] < Some(seq));
+                        last[producer] = Some(seq);
+                        popped += 1;
+                    }
+                }
+                popped
+            })
+        })
+        .collect();
+    for p in producers {
+        p.join().unwrap();
+    }
+    let popped: usize = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+    assert_eq!(drops.load(Ordering::SeqCst), popped);
+    assert_eq!(queue.is_empty(), popped == 4_000);
+    drop(queue);
+    assert_eq!(drops.load(Ordering::SeqCst), 4_000);
+}
+
+#[test]
+fn bounded_queue() {
+    use atom::queue::BoundedQueue;
+
+    let queue = BoundedQueue::new(3);
+    assert_eq!(queue.capacity(), 3);
+    for lap in 0..4 {
+        for i in 0..3 {
+            assert!(queue.try_push(Box::new(lap * 3 + i)).is_ok());
+        }
+        assert_eq!(queue.try_push(Box::new(99)), Err(Box::new(99)));
+        assert_eq!(queue.len(), 3);
+        for i in 0..3 {
+            assert_eq!(queue.try_pop(), Some(Box::new(lap * 3 + i)));
+        }
+        assert_eq!(queue.try_pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let queue = Arc::new(BoundedQueue::new(16));
+    let producers: Vec<_> = (0..4)
+        .map(|_| {
+            let (queue, drops) = (queue.clone(), drops.clone());
+            thread::spawn(move || {
+                for _ in 0..1_000 {
+                    let mut v = Box::new(Canary(drops.clone()));
+                    while let Err(back) = queue.try_push(v) {
+                        v = back;
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+    let consumers: Vec<_> = (0..4)
+        .map(|_| {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                let mut popped = 0;
+                while popped < 1_000 {
+                    match queue.try_pop() {
+                        Some(_) => popped += 1,
+                        None => thread::yield_now(),
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in producers.into_iter().chain(consumers) {
+        t.join().unwrap();
+    }
+    assert!(queue.is_empty());
+    assert_eq!(drops.load(Ordering::SeqCst), 4_000);
+
+    // values still queued are dropped with the queue
+    for _ in 0..5 {
+        assert!(queue.try_push(Box::new(Canary(drops.clone()))).is_ok());
+    }
+    drop(queue);
+    assert_eq!(drops.load(Ordering::SeqCst), 4_005);
+}
+
+#[test]
+fn spsc_ring() {
+    use atom::spsc::Ring;
+
+    let (mut tx, mut rx) = Ring::new(2).split();
+    assert_eq!(tx.push(Box::new(1)), Ok(()));
+    assert_eq!(tx.push(Box::new(2)), Ok(()));
+    assert_eq!(tx.push(Box::new(3)), Err(Box::new(3)));
+    assert_eq!(tx.free(), 0);
+    assert_eq!(rx.pop(), Some(Box::new(1)));
+    assert_eq!(tx.push(Box::new(3)), Ok(()));
+    assert_eq!(rx.len(), 2);
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (mut tx, mut rx) = Ring::new(8).split();
+    let producer = {
+        let drops = drops.clone();
+        thread::spawn(move || {
+            for i in 0..10_000 {
+                let mut v = Box::new((i, Canary(drops.clone())));
+                while let Err(back) = tx.push(v) {
+                    v = back;
+                    thread::yield_now();
+                }
+            }
+            // left in the ring for the drop check below
+            for i in 0..3 {
+                while tx.free() == 0 {
+                    thread::yield_now();
+                }
+                assert!(tx.push(Box::new((i, Canary(drops.clone())))).is_ok());
+            }
+        })
+    };
+    let mut expected = 0;
+    while expected < 10_000 {
+        match rx.pop() {
+            Some(v) => {
+                assert_eq!(v.0, expected);
+                expected += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    producer.join().unwrap();
+    assert_eq!(drops.load(Ordering::SeqCst), 10_000);
+    assert_eq!(rx.len(), 3);
+    drop(rx);
+    assert_eq!(drops.load(Ordering::SeqCst), 10_003);
+}
+
+#[test]
+fn append_list() {
+    use atom::list::AppendList;
+
+    let list = Arc::new(AppendList::new());
+    assert!(list.is_empty());
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            let list = list.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    assert_eq!(*list.append((t, i)), (t, i));
+                }
+            })
+        })
+        .collect();
+    // readers never wait for the writers, they just see a shorter list
+    let seen = list.iter().count();
+    assert!(seen <= 4_000);
+    for w in writers {
+        w.join().unwrap();
+    }
+
+    let mut next = [0; 4];
+    for &(t, i) in list.iter() {
+        assert_eq!(next[t], i);
+        next[t] += 1;
+    }
+    assert_eq!(next, [1_000; 4]);
+
+    // long lists are dropped without recursing
+    let drops = Arc::new(AtomicUsize::new(0));
+    let list: AppendList<_> = (0..100_000).map(|_| Canary(drops.clone())).collect();
+    assert_eq!((&list).into_iter().count(), 100_000);
+    drop(list);
+    assert_eq!(drops.load(Ordering::SeqCst), 100_000);
+}
+
+#[test]
+fn persistent_stack() {
+    use atom::persistent::PersistentStack;
+
+    let stack = Arc::new(PersistentStack::new());
+    assert!(stack.is_empty());
+    assert!(stack.iter_snapshot().is_empty());
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            let stack = stack.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    stack.push((t, i));
+                    if i % 3 == 0 {
+                        assert!(stack.pop().is_some());
+                    }
+                }
+            })
+        })
+        .collect();
+    // every snapshot is internally consistent: per writer, newest first
+    for _ in 0..100 {
+        let snapshot = stack.iter_snapshot();
+        let mut last = [None; 4];
+        for &(t, i) in &snapshot {
+            assert!(last[t].is_none_or(|last| i < last));
+            last[t] = Some(i);
+        }
+    }
+    for w in writers {
+        w.join().unwrap();
+    }
+    assert_eq!(stack.iter_snapshot().iter().count(), 4 * 666);
+
+    // popped values stay visible through older snapshots
+    let drops = Arc::new(AtomicUsize::new(0));
+    let stack = PersistentStack::new();
+    for _ in 0..100_000 {
+        stack.push(Arc::new(Canary(drops.clone())));
+    }
+    let snapshot = stack.iter_snapshot();
+    while stack.pop().is_some() {}
+    assert!(stack.is_empty());
+    atom::epoch::collect();
+    assert_eq!(snapshot.iter().count(), 100_000);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    drop(snapshot);
+    drop(stack);
+    // the stack's own references go once the epoch moves past them
+    let start = Instant::now();
+    while drops.load(Ordering::SeqCst) < 100_000 && start.elapsed() < Duration::from_secs(10) {
+        atom::epoch::collect();
+        thread::yield_now();
+    }
+    assert_eq!(drops.load(Ordering::SeqCst), 100_000);
+}
+
+#[test]
+fn ordered_list() {
+    use atom::list::OrderedList;
+
+    let list = Arc::new(OrderedList::new());
+    assert!(list.is_empty());
+    let drops = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..4)
+        .map(|t| {
+            let (list, drops) = (list.clone(), drops.clone());
+            thread::spawn(move || {
+                // threads fight over the same keys, each key goes in and out
+                for round in 0..200 {
+                    let key = (round * 7 + t) % 50;
+                    // a value for a key already present is handed back
+                    drop(list.insert(key, Canary(drops.clone())));
+                    list.remove(&((round * 3 + t) % 50));
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+
+    // every key is present at most once
+    for k in 0..50 {
+        if list.contains(&k) {
+            assert!(list.remove(&k));
+        }
+        assert!(!list.remove(&k));
+    }
+    assert!(list.is_empty());
+    drop(list);
+    let start = Instant::now();
+    while drops.load(Ordering::SeqCst) < 800 && start.elapsed() < Duration::from_secs(10) {
+        atom::epoch::collect();
+        thread::yield_now();
+    }
+    assert_eq!(drops.load(Ordering::SeqCst), 800);
+
+    let list = OrderedList::new();
+    for k in [5, 1, 4, 2, 3].iter() {
+        assert!(list.insert(*k, *k * 10).is_ok());
+    }
+    assert_eq!(list.insert(3, 0), Err((3, 0)));
+    assert_eq!(
+        (1..6).map(|k| list.get(&k)).collect::<Vec<_>>(),
+        (1..6).map(|k| Some(k * 10)).collect::<Vec<_>>()
+    );
+    assert!(list.remove(&3));
+    assert_eq!(list.get(&3), None);
+    assert!(list.insert(3, 33).is_ok());
+    assert_eq!(list.get(&3), Some(33));
+}
+
+#[test]
+fn object_pool() {
+    use atom::pool::{Pool, Pooled};
+
+    let created = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let created = created.clone();
+        Arc::new(Pool::with_capacity(8, move || {
+            created.fetch_add(1, Ordering::SeqCst);
+            Vec::<usize>::with_capacity(16)
+        }))
+    };
+    let workers: Vec<_> = (0..4)
+        .map(|t| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    let mut v = pool.get();
+                    assert!(v.is_empty());
+                    v.push(t * 1_000 + i);
+                    v.clear();
+                    pool.put(v);
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    // objects are reused rather than created for every get
+    assert!(created.load(Ordering::SeqCst) < 4_000);
+    assert!(pool.len() <= 8);
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let pool = Pool::with_capacity(2, || unreachable!());
+    for _ in 0..3 {
+        pool.put(Pooled::new(Canary(drops.clone())));
+    }
+    // the third object does not fit
+    assert_eq!(pool.len(), 2);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    let a = pool.get();
+    assert_eq!(pool.len(), 1);
+    drop(Pooled::into_inner(a));
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+    drop(pool);
+    assert_eq!(drops.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn bag() {
+    use atom::bag::Bag;
+
+    let bag = Arc::new(Bag::with_shards(3));
+    let workers: Vec<_> = (0..8)
+        .map(|t| {
+            let bag = bag.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    bag.insert(t * 1_000 + i);
+                }
+            })
+        })
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    let mut bag = Arc::try_unwrap(bag).unwrap();
+    let mut all: Vec<_> = bag.drain().collect();
+    all.sort();
+    assert_eq!(all, (0..8_000).collect::<Vec<_>>());
+    assert_eq!(bag.drain().count(), 0);
+
+    // partly drained and undrained values are all dropped
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut bag = Bag::new();
+    for _ in 0..100_000 {
+        bag.insert(Canary(drops.clone()));
+    }
+    assert_eq!(bag.drain().take(10).count(), 10);
+    assert_eq!(drops.load(Ordering::SeqCst), 100_000);
+    for _ in 0..10 {
+        bag.insert(Canary(drops.clone()));
+    }
+    drop(bag);
+    assert_eq!(drops.load(Ordering::SeqCst), 100_010);
+}
+
+#[test]
+fn work_stealing_deque() {
+    use atom::deque::{Steal, Worker};
+
+    // owner side behaves as a LIFO, and grows past its initial capacity
+    let mut worker = Worker::with_capacity(2);
+    for i in 0..100 {
+        worker.push(i);
+    }
+    assert_eq!(worker.len(), 100);
+    assert_eq!(worker.stealer().steal(), Steal::Success(0));
+    for i in (1..100).rev() {
+        assert_eq!(worker.pop(), Some(i));
+    }
+    assert_eq!(worker.pop(), None);
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut worker = Worker::with_capacity(4);
+    let stolen = Arc::new(AtomicUsize::new(0));
+    let thieves: Vec<_> = (0..3)
+        .map(|_| {
+            let (stealer, stolen) = (worker.stealer(), stolen.clone());
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                while stolen.load(Ordering::SeqCst) < 10_000 {
+                    if let Steal::Success((i, _)) = stealer.steal() {
+                        seen.push(i);
+                        stolen.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+                seen
+            })
+        })
+        .collect();
+    let mut popped = Vec::new();
+    for i in 0..10_000 {
+        worker.push((i, Canary(drops.clone())));
+        if i % 4 == 0 {
+            if let Some((i, _)) = worker.pop() {
+                popped.push(i);
+                stolen.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+    while let Some((i, _)) = worker.pop() {
+        popped.push(i);
+        stolen.fetch_add(1, Ordering::SeqCst);
+    }
+    // every element is taken exactly once
+    let mut all = popped;
+    for t in thieves {
+        all.extend(t.join().unwrap());
+    }
+    all.sort();
+    assert_eq!(all, (0..10_000).collect::<Vec<_>>());
+    assert_eq!(drops.load(Ordering::SeqCst), 10_000);
+
+    // elements left in the deque are dropped with it
+    for _ in 0..5 {
+        worker.push((0, Canary(drops.clone())));
+    }
+    drop(worker);
+    assert_eq!(drops.load(Ordering::SeqCst), 10_005);
+}
+
+#[test]
+fn atom_map() {
+    use atom::map::AtomMap;
+
+    let map = Arc::new(AtomMap::with_buckets(4));
+    let calls = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..8)
+        .map(|_| {
+            let (map, calls) = (map.clone(), calls.clone());
+            thread::spawn(move || {
+                (0..200)
+                    .map(|k| {
+                        let v = map.get_or_insert_with(k, || {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            k * 2
+                        });
+                        v as *const i32 as usize
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let addrs: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+    // every thread got the very same value for each key
+    assert!(addrs.windows(2).all(|w| w[0] == w[1]));
+    assert_eq!(map.len(), 200);
+    assert!(calls.load(Ordering::SeqCst) >= 200);
+    for k in 0..200 {
+        assert_eq!(map.get(&k), Some(&(k * 2)));
+    }
+    assert_eq!(map.get(&200), None);
+    let mut keys: Vec<_> = map.iter().map(|(k, _)| *k).collect();
+    keys.sort();
+    assert_eq!(keys, (0..200).collect::<Vec<_>>());
+
+    let map = AtomMap::new();
+    assert_eq!(map.try_insert(String::from("a"), 1), Ok(&1));
+    match map.try_insert(String::from("a"), 2) {
+        Err((existing, key, value)) => assert_eq!((*existing, key.as_str(), value), (1, "a", 2)),
+        Ok(_) => panic!("key inserted twice"),
+    }
+    // looked up by a borrowed form of the key
+    assert!(map.contains_key("a"));
+    assert_eq!(map.get_key_value("a"), Some((&String::from("a"), &1)));
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let map = AtomMap::with_buckets(4096);
+    for k in 0..100_000 {
+        assert!(map.try_insert(k, Canary(drops.clone())).is_ok());
+    }
+    assert_eq!(map.len(), 100_000);
+    drop(map);
+    assert_eq!(drops.load(Ordering::SeqCst), 100_000);
+}
+
+#[test]
+fn atom_map_drop_deep_bucket() {
+    use atom::map::AtomMap;
+
+    // every entry lands in the same bucket, which must not be dropped
+    // recursively
+    let drops = Arc::new(AtomicUsize::new(0));
+    let map = AtomMap::with_buckets(1);
+    for k in 0..10_000 {
+        assert!(map.try_insert(k, Canary(drops.clone())).is_ok());
+    }
+    drop(map);
+    assert_eq!(drops.load(Ordering::SeqCst), 10_000);
+}
+
+#[test]
+fn interner() {
+    use atom::intern::Interner;
+
+    atom_static! {
+        static NAMES: Interner = Interner::new();
+    }
+
+    let workers: Vec<_> = (0..8)
+        .map(|t| {
+            thread::spawn(move || {
+                (0..100)
+                    .map(|i| NAMES.intern(&format!("name-{}", (i + t * 7) % 100)))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut symbols: Vec<atom::intern::Symbol<'static>> = Vec::new();
+    for w in workers {
+        symbols.extend(w.join().unwrap());
+    }
+    assert_eq!(NAMES.len(), 100);
+    for symbol in &symbols {
+        // equal strings give identical symbols
+        assert_eq!(NAMES.intern(symbol), *symbol);
+        assert_eq!(NAMES.get(symbol.as_str()), Some(*symbol));
+    }
+    assert_eq!(NAMES.get("missing"), None);
+
+    // each string is listed once
+    let mut listed: Vec<_> = NAMES.iter().map(|s| s.as_str()).collect();
+    assert_eq!(listed.len(), 100);
+    listed.sort();
+    listed.dedup();
+    assert_eq!(listed.len(), 100);
+
+    let local = Interner::with_buckets(4);
+    let a = local.intern("a");
+    local.intern("b");
+    local.intern("a");
+    assert_eq!(
+        local.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        ["a", "b"]
+    );
+    assert_ne!(a, NAMES.intern("a"));
+}
+
+#[test]
+fn type_registry() {
+    use atom::registry::TypeRegistry;
+
+    #[derive(Debug, PartialEq)]
+    struct Plugin(usize);
+
+    let registry = Arc::new(TypeRegistry::new());
+    let workers: Vec<_> = (0..8)
+        .map(|t| {
+            let registry = registry.clone();
+            thread::spawn(move || {
+                let plugin = match registry.register(Plugin(t)) {
+                    Ok(plugin) => plugin,
+                    Err((existing, rejected)) => {
+                        assert_eq!(rejected, Plugin(t));
+                        existing
+                    }
+                };
+                plugin as *const Plugin as usize
+            })
+        })
+        .collect();
+    let addrs: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+    // exactly one registration won and everyone sees it
+    assert!(addrs.windows(2).all(|w| w[0] == w[1]));
+    let winner = registry.get::<Plugin>().unwrap();
+    assert_eq!(winner as *const Plugin as usize, addrs[0]);
+
+    assert!(!registry.contains::<String>());
+    assert_eq!(registry.get_or_register_with(|| String::from("a")), "a");
+    assert_eq!(registry.get_or_register_with(|| String::from("b")), "a");
+    assert_eq!(registry.len(), 2);
+}
+
+#[test]
+fn generational_slab() {
+    use atom::slab::{Handle, Slab};
+
+    let slab = Slab::with_capacity(2);
+    let a = slab.insert(Box::new(1)).unwrap();
+    let b = slab.insert(Box::new(2)).unwrap();
+    assert_eq!(slab.insert(Box::new(3)), Err(Box::new(3)));
+    assert_eq!(*slab.get(a).unwrap(), 1);
+    assert!(slab.remove(a));
+    assert!(!slab.remove(a));
+    // the slot is reused, the old handle stays stale
+    let c = slab.insert(Box::new(3)).unwrap();
+    assert_eq!(c.index(), a.index());
+    assert_ne!(c, a);
+    assert!(slab.get(a).is_none());
+    assert_eq!(*slab.get(c).unwrap(), 3);
+    assert_eq!(Handle::from_bits(b.to_bits()), b);
+    assert_eq!(slab.len(), 2);
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let slab = Arc::new(Slab::with_capacity(64));
+    let workers: Vec<_> = (0..4)
+        .map(|t| {
+            let (slab, drops) = (slab.clone(), drops.clone());
+            thread::spawn(move || {
+                let mut mine = Vec::new();
+                for i in 0..2_000 {
+                    if let Ok(h) = slab.insert(Arc::new((t, i, Canary(drops.clone())))) {
+                        mine.push((h, i));
+                    }
+                    if mine.len() > 8 {
+                        let (h, i) = mine.remove(0);
+                        assert_eq!(slab.get(h).map(|v| (v.0, v.1)), Some((t, i)));
+                        assert!(slab.remove(h));
+                        assert!(slab.get(h).is_none());
+                    }
+                }
+                mine.len()
+            })
+        })
+        .collect();
+    let left: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+    assert_eq!(slab.len(), left);
+    drop(slab);
+    let start = Instant::now();
+    while drops.load(Ordering::SeqCst) < 8_000 && start.elapsed() < Duration::from_secs(10) {
+        atom::epoch::collect();
+        thread::yield_now();
+    }
+    assert_eq!(drops.load(Ordering::SeqCst), 8_000);
+}
+
+#[test]
+fn oneshot_channel() {
+    use atom::oneshot;
+    use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+
+    let (tx, rx) = oneshot::channel();
+    let sender = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        tx.send(String::from("hello")).unwrap();
+    });
+    assert_eq!(rx.recv().as_deref(), Ok("hello"));
+    sender.join().unwrap();
+
+    // dropping the sender wakes a blocked receiver
+    let (tx, rx) = oneshot::channel::<u32>();
+    let dropper = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        drop(tx);
+    });
+    assert_eq!(rx.recv(), Err(RecvError));
+    dropper.join().unwrap();
+
+    let (tx, mut rx) = oneshot::channel();
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(
+        rx.recv_timeout(Duration::from_millis(5)),
+        Err(RecvTimeoutError::Timeout)
+    );
+    assert!(!tx.is_closed());
+    tx.send(1).unwrap();
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+    // sending to a dropped receiver hands the value back
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = oneshot::channel();
+    drop(rx);
+    assert!(tx.is_closed());
+    match tx.send(Canary(drops.clone())) {
+        Err(SendError(value)) => drop(value),
+        Ok(()) => panic!("sent to a dropped receiver"),
+    }
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    // a value nobody received is dropped with the channel
+    let (tx, rx) = oneshot::channel();
+    tx.send(Canary(drops.clone())).unwrap();
+    drop(rx);
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn watch_channel() {
+    use atom::watch;
+    use std::sync::mpsc::RecvError;
+
+    let (tx, mut rx) = watch::channel(Arc::new(String::from("v1")));
+    let mut late = rx.clone();
+    assert_eq!(rx.has_changed(), Ok(false));
+    tx.send(Arc::new(String::from("v2")));
+    assert_eq!(rx.has_changed(), Ok(true));
+    // borrow is only a peek
+    assert_eq!(*rx.borrow(), "v2");
+    assert_eq!(rx.has_changed(), Ok(true));
+    assert_eq!(*rx.borrow_and_update(), "v2");
+    assert_eq!(rx.has_changed(), Ok(false));
+    let fresh = tx.subscribe();
+    assert_eq!(fresh.has_changed(), Ok(false));
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let mut rx = rx.clone();
+            thread::spawn(move || {
+                let mut seen = Vec::new();
+                while rx.wait_changed().is_ok() {
+                    seen.push(rx.borrow_and_update().clone());
+                }
+                seen
+            })
+        })
+        .collect();
+    for i in 3..=50 {
+        tx.send(Arc::new(format!("v{}", i)));
+    }
+    assert_eq!(*tx.swap(Arc::new(String::from("last"))), "v50");
+    drop(tx);
+    for r in readers {
+        // readers may skip values, but always end on the last one
+        assert_eq!(r.join().unwrap().last().map(|s| s.as_str()), Some("last"));
+    }
+
+    // a closed channel still reports a value nobody has seen yet
+    assert_eq!(late.wait_changed(), Ok(()));
+    assert_eq!(*late.borrow_and_update(), "last");
+    assert_eq!(late.wait_changed(), Err(RecvError));
+    assert_eq!(fresh.has_changed(), Ok(true));
+    assert_eq!(rx.has_changed(), Ok(true));
+}
+
+#[test]
+fn event() {
+    use atom::event::Event;
+
+    static SHUTDOWN: Event = Event::new();
+
+    let woken = Arc::new(AtomicUsize::new(0));
+    let waiters: Vec<_> = (0..8)
+        .map(|_| {
+            let woken = woken.clone();
+            thread::spawn(move || {
+                SHUTDOWN.wait();
+                woken.fetch_add(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    assert!(!SHUTDOWN.is_set());
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(woken.load(Ordering::SeqCst), 0);
+    assert!(SHUTDOWN.set());
+    for w in waiters {
+        w.join().unwrap();
+    }
+    assert_eq!(woken.load(Ordering::SeqCst), 8);
+    assert!(SHUTDOWN.is_set());
+    assert!(!SHUTDOWN.set());
+    SHUTDOWN.wait();
+
+    let event = Event::new();
+    let start = Instant::now();
+    assert!(!event.wait_timeout(Duration::from_millis(10)));
+    assert!(start.elapsed() >= Duration::from_millis(10));
+    event.set();
+    assert!(event.wait_timeout(Duration::from_secs(10)));
+}
+
+#[test]
+fn wait_list() {
+    use std::sync::atomic::AtomicBool;
+    use std::task::{Wake, Waker};
+
+    struct Count(AtomicUsize);
+
+    impl Wake for Count {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let state = Arc::new((AtomicBool::new(false), WaitList::new()));
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let state = state.clone();
+            thread::spawn(move || state.1.wait_until(|| state.0.load(Ordering::Acquire), None))
+        })
+        .collect();
+    let count = Arc::new(Count(AtomicUsize::new(0)));
+    let registration = state.1.register(Waker::from(count.clone()));
+    assert!(registration.is_queued());
+
+    // a notify before the condition holds wakes waiters, who go back to sleep
+    state.1.notify_all();
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    assert!(!registration.is_queued());
+    thread::sleep(Duration::from_millis(10));
+    assert!(threads.iter().all(|t| !t.is_finished()));
+
+    state.0.store(true, Ordering::Release);
+    state.1.notify_all();
+    for t in threads {
+        assert!(t.join().unwrap());
+    }
+    // the task was woken once and then dropped off the list
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+
+    let deadline = Instant::now() + Duration::from_millis(10);
+    assert!(!WaitList::new().wait_until(|| false, Some(deadline)));
+    assert!(Instant::now() >= deadline);
+}
+
+#[test]
+fn wait_list_timeouts_stay_bounded() {
+    let list = WaitList::new();
+    for _ in 0..10_000 {
+        assert!(!list.wait_until(|| false, Some(Instant::now())));
+    }
+    // waiters that gave up are cleared out by the ones that come later
+    assert!(list.len() <= 64, "{} nodes left behind", list.len());
+}
+
+#[cfg(feature = "parking_lot")]
+#[test]
+fn parking_lot_waits() {
+    use atom::cancel::CancellationToken;
+    use atom::event::Event;
+    use std::sync::atomic::AtomicBool;
+
+    // blocked threads park in parking_lot_core's table, not on the list
+    let state = Arc::new((AtomicBool::new(false), WaitList::new()));
+    let waiter = {
+        let state = state.clone();
+        thread::spawn(move || state.1.wait_until(|| state.0.load(Ordering::Acquire), None))
+    };
+    thread::sleep(Duration::from_millis(10));
+    assert!(state.1.is_empty());
+    state.0.store(true, Ordering::Release);
+    state.1.notify_all();
+    assert!(waiter.join().unwrap());
+
+    // the waits built on top of it are woken through the table too
+    let atom = Arc::new(Atom::empty());
+    let event = Arc::new(Event::new());
+    let token = CancellationToken::new();
+    let waiters = vec![
+        {
+            let atom = atom.clone();
+            thread::spawn(move || assert_eq!(atom.take_blocking(), Box::new(7u32)))
+        },
+        {
+            let event = event.clone();
+            thread::spawn(move || event.wait())
+        },
+        {
+            let token = token.clone();
+            thread::spawn(move || token.wait())
+        },
+    ];
+    thread::sleep(Duration::from_millis(10));
+    assert!(atom
+        .set_and_notify(Box::new(7), Ordering::Release)
+        .is_none());
+    assert!(event.set());
+    assert!(token.cancel());
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+}
+
+#[test]
+fn cancellation_token() {
+    use atom::cancel::CancellationToken;
+
+    let root = CancellationToken::new();
+    let child = root.child();
+    let grandchild = child.child().detach();
+    let waiters: Vec<_> = (0..4)
+        .map(|_| {
+            let token = grandchild.clone();
+            thread::spawn(move || token.wait())
+        })
+        .collect();
+
+    // cancelling a branch leaves the parent alone
+    let sibling = root.child();
+    let scoped = sibling.clone();
+    drop(sibling);
+    assert!(scoped.is_cancelled());
+    assert!(scoped.wait_timeout(Duration::from_secs(10)));
+    assert!(!root.is_cancelled());
+    assert!(!grandchild.wait_timeout(Duration::from_millis(10)));
+
+    assert!(root.cancel());
+    assert!(!root.cancel());
+    for t in waiters {
+        t.join().unwrap();
+    }
+    assert!(child.is_cancelled());
+    // cancelled through its parent, but not on its own yet
+    assert!(child.cancel());
+}
+
+#[test]
+fn sync_mailbox() {
+    use atom::mailbox::SyncMailbox;
+
+    let mailbox = Arc::new(SyncMailbox::new());
+    assert_eq!(mailbox.take(), None);
+    assert_eq!(mailbox.take_timeout(Duration::from_millis(10)), None);
+    assert_eq!(mailbox.put(1), None);
+    assert_eq!(mailbox.put(2), Some(1));
+    assert_eq!(mailbox.take_blocking(), 2);
+    assert!(mailbox.is_empty());
+
+    // every put either reaches the consumer or is handed back to the producer
+    let mailbox = Arc::new(SyncMailbox::new());
+    let consumer = {
+        let mailbox = mailbox.clone();
+        thread::spawn(move || {
+            let mut seen = Vec::new();
+            loop {
+                match mailbox.take_blocking() {
+                    None => return seen,
+                    Some(v) => seen.push(v),
+                }
+            }
+        })
+    };
+    let mut displaced = Vec::new();
+    for i in 0..10_000 {
+        displaced.extend(mailbox.put(Some(i)).and_then(|v| v));
+    }
+    displaced.extend(mailbox.put(None).and_then(|v| v));
+    let seen = consumer.join().unwrap();
+    assert!(seen.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(seen.len() + displaced.len(), 10_000);
+}
+
+#[test]
+fn take_blocking() {
+    let atom = Arc::new(Atom::empty());
+    assert_eq!(atom.take_blocking_timeout(Duration::from_millis(10)), None);
+    atom.swap(Box::new(0u32), Ordering::Release);
+    assert_eq!(
+        atom.take_blocking_timeout(Duration::from_millis(10)),
+        Some(Box::new(0))
+    );
+
+    // two consumers share every value between them exactly once
+    let consumers: Vec<_> = (0..2)
+        .map(|_| {
+            let atom = atom.clone();
+            thread::spawn(move || {
+                let mut sum = 0;
+                loop {
+                    match *atom.take_blocking() {
+                        0 => return sum,
+                        v => sum += v,
+                    }
+                }
+            })
+        })
+        .collect();
+    for v in (1..=1000u32).chain([0, 0]) {
+        while !atom.is_none(Ordering::Acquire) {
+            thread::yield_now();
+        }
+        assert!(atom
+            .set_and_notify(Box::new(v), Ordering::Release)
+            .is_none());
+    }
+    let total: u32 = consumers.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(total, 500 * 1001);
+}
+
+#[test]
+fn wait_for_change() {
+    let atom = Arc::new(Atom::new(Arc::new(0u32)));
+    let start = atom.token(Ordering::Acquire);
+    assert_eq!(
+        atom.wait_for_change(start, Duration::from_millis(10)),
+        start
+    );
+
+    let writer = {
+        let atom = atom.clone();
+        thread::spawn(move || {
+            // keep the old values alive so no allocation is reused
+            let mut old = Vec::new();
+            for i in 1..=3 {
+                thread::sleep(Duration::from_millis(5));
+                old.push(atom.set_and_notify(Arc::new(i), Ordering::Release));
+            }
+            old
+        })
+    };
+    let mut seen = start;
+    let mut last = 0;
+    while last < 3 {
+        let now = atom.wait_for_change(seen, Duration::from_secs(10));
+        assert_ne!(now, seen);
+        seen = now;
+        last = *atom.load_cloned().unwrap();
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn split_slot() {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let (mut setter, mut taker) = Arc::new(Atom::empty()).split_slot();
+    assert!(taker.take(Ordering::Acquire).is_none());
+    setter.set(Box::new(Canary(drops.clone())));
+    setter.set(Box::new(Canary(drops.clone())));
+    // the value the taker missed is dropped by the setter
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert!(!setter.is_none(Ordering::Acquire));
+    assert!(taker.take(Ordering::Acquire).is_some());
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+
+    let (mut setter, mut taker) = Arc::new(Atom::<Box<u32>>::empty()).split_slot();
+    let consumer = thread::spawn(move || {
+        let mut last = 0;
+        while last < 100 {
+            let v = *taker.take_blocking();
+            assert!(v > last);
+            last = v;
+        }
+        taker.take_blocking_timeout(Duration::from_millis(10))
+    });
+    for i in 1..=100 {
+        setter.swap(Box::new(i), Ordering::Release);
+        thread::yield_now();
+    }
+    assert_eq!(consumer.join().unwrap(), None);
+}
+
+#[cfg(feature = "observe")]
+#[test]
+fn observed_atom() {
+    use atom::observe::ObservedAtom;
+    use std::sync::Mutex;
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let atom = Arc::new(ObservedAtom::new(Box::new(0u32)));
+    // stores before the callback is installed go unobserved
+    atom.swap(Box::new(1), Ordering::AcqRel);
+    let log = seen.clone();
+    assert!(atom.watch(move |old: Option<&Box<u32>>| {
+        log.lock().unwrap().push(old.map(|v| **v));
+    }));
+    assert!(!atom.watch(|_| panic!("a second callback must not be installed")));
+
+    atom.swap(Box::new(2), Ordering::AcqRel);
+    assert_eq!(atom.take(Ordering::Acquire), Some(Box::new(2)));
+    assert!(atom.set_if_none(Box::new(3), Ordering::AcqRel).is_none());
+    assert!(atom.set_if_none(Box::new(4), Ordering::AcqRel).is_some());
+    assert_eq!(*seen.lock().unwrap(), [Some(1), None]);
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let atom = atom.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    atom.swap(Box::new(i), Ordering::AcqRel);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(seen.lock().unwrap().len(), 402);
+}
+
+#[test]
+fn small_atom() {
+    use atom::small::{SmallAtom, SmallValue};
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[repr(u8)]
+    enum Mode {
+        Idle,
+        Busy,
+    }
+    unsafe impl SmallValue for Mode {}
+
+    let mode = SmallAtom::new(Mode::Idle);
+    assert_eq!(mode.swap(Mode::Busy, Ordering::AcqRel), Some(Mode::Idle));
+    assert_eq!(mode.take(Ordering::AcqRel), Some(Mode::Busy));
+    assert_eq!(mode.take(Ordering::AcqRel), None);
+    // an all-zero value is still told apart from empty
+    assert_eq!(mode.set_if_none(Mode::Idle, Ordering::AcqRel), None);
+    assert!(!mode.is_none(Ordering::Acquire));
+    assert_eq!(format!("{:?}", mode), "SmallAtom(Some(Idle))");
+
+    let unit = SmallAtom::from(Some(()));
+    assert_eq!(unit.into_inner(), Some(()));
+
+    let slot = Arc::new(SmallAtom::<u16>::empty());
+    let threads: Vec<_> = (0..4u16)
+        .map(|i| {
+            let slot = slot.clone();
+            thread::spawn(move || slot.set_if_none(i, Ordering::AcqRel).is_none())
+        })
+        .collect();
+    let winners = threads.into_iter().map(|t| t.join().unwrap());
+    assert_eq!(winners.filter(|&won| won).count(), 1);
+    assert!(slot.load(Ordering::Acquire).unwrap() < 4);
+}
+
+#[test]
+fn tagged_atom() {
+    use atom::tagged::TaggedAtom;
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let atom = TaggedAtom::<Box<Canary>, 2>::empty();
+    assert_eq!(atom.load_tag(Ordering::Acquire), 0);
+    let (old, tag) = atom.swap_with_tag(Some(Box::new(Canary(drops.clone()))), 2, Ordering::AcqRel);
+    assert!(old.is_none());
+    assert_eq!(tag, 0);
+    assert_eq!(atom.fetch_or_tag(1, Ordering::AcqRel), 2);
+    assert_eq!(atom.fetch_and_tag(1, Ordering::AcqRel), 3);
+    assert_eq!(atom.load_tag(Ordering::Acquire), 1);
+    assert!(!atom.is_none(Ordering::Acquire));
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+    // taking the value keeps the tag
+    drop(atom.take(Ordering::AcqRel));
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert!(atom.is_none(Ordering::Acquire));
+    assert_eq!(atom.load_tag(Ordering::Acquire), 1);
+
+    atom.swap_with_tag(Some(Box::new(Canary(drops.clone()))), 3, Ordering::AcqRel);
+    let token = atom.token(Ordering::Acquire);
+    atom.fetch_and_tag(0, Ordering::AcqRel);
+    assert_eq!(atom.token(Ordering::Acquire), token);
+    drop(atom);
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+
+    // concurrent markers each set their own bit exactly once
+    let atom = Arc::new(TaggedAtom::<Box<u32>, 2>::new(Box::new(1), 0));
+    let threads: Vec<_> = (0..2)
+        .map(|i| {
+            let atom = atom.clone();
+            thread::spawn(move || atom.fetch_or_tag(1 << i, Ordering::AcqRel) & (1 << i) == 0)
+        })
+        .collect();
+    assert!(threads.into_iter().all(|t| t.join().unwrap()));
+    let atom = Arc::try_unwrap(atom).unwrap();
+    assert_eq!(atom.into_inner(), (Some(Box::new(1)), 3));
+}
+
+#[test]
+fn stamped_atom() {
+    use atom::stamped::StampedAtom;
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let atom = StampedAtom::empty();
+    let empty = atom.stamp();
+    assert!(empty.is_none());
+    assert!(atom.set_if_none(Box::new(Canary(drops.clone()))).is_none());
+    assert!(atom.set_if_none(Box::new(Canary(drops.clone()))).is_some());
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert_eq!(atom.stamp().version(), 1);
+
+    // an ABA swap is caught by the stamp
+    let seen = atom.stamp();
+    let old = atom.take().unwrap();
+    assert!(atom.is_none());
+    assert!(atom.swap(old).is_none());
+    assert_eq!(atom.stamp().token(), seen.token());
+    let fresh = Box::new(Canary(drops.clone()));
+    let (actual, fresh) = match atom.compare_exchange_stamped(seen, Some(fresh)) {
+        Err(failed) => failed,
+        Ok(_) => panic!("a stale stamp must not match"),
+    };
+    assert_eq!(actual.version(), 3);
+    assert!(atom
+        .compare_exchange_stamped(actual, fresh)
+        .is_ok_and(|old| old.is_some()));
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+    drop(atom);
+    assert_eq!(drops.load(Ordering::SeqCst), 3);
+
+    // a counter where every increment goes through a stamped exchange
+    let atom = Arc::new(StampedAtom::new(Box::new(0usize)));
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let atom = atom.clone();
+            thread::spawn(move || {
+                for _ in 0..1_000 {
+                    let mut current = atom.stamp();
+                    let value = loop {
+                        if current.is_none() {
+                            // another thread holds the value, wait for it
+                            thread::yield_now();
+                            current = atom.stamp();
+                            continue;
+                        }
+                        match atom.compare_exchange_stamped(current, None) {
+                            Ok(value) => break value.unwrap(),
+                            Err((actual, _)) => current = actual,
+                        }
+                    };
+                    // only this thread can have taken the value out
+                    assert!(atom.swap(Box::new(*value + 1)).is_none());
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(atom.stamp().version(), 8_000);
+    assert_eq!(
+        Arc::try_unwrap(atom).unwrap().into_inner(),
+        Some(Box::new(4_000))
+    );
+}
+
+#[test]
+fn wide_atom() {
+    use atom::wide::WideAtom;
+
+    static NAMES: [&str; 2] = ["even", "odd"];
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let atom = WideAtom::new(Some(Box::new(Canary(drops.clone()))), Some(&NAMES[0]));
+    let tokens = atom.tokens();
+    let (old, name) = atom.swap(None, Some(&NAMES[1]));
+    assert!(old.is_some());
+    assert_eq!(name, Some(&"even"));
+    drop(old);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+    // stale tokens hand the new values back
+    let rejected = atom.compare_exchange(tokens, (Some(Box::new(Canary(drops.clone()))), None));
+    assert!(rejected.is_err_and(|(first, second)| first.is_some() && second.is_none()));
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+    let current = atom.tokens();
+    assert!(atom.compare_exchange(current, (None, None)).is_ok());
+    assert!(atom.is_none());
+
+    // readers never see one half of a store next to the other half of another
+    let atom = Arc::new(WideAtom::new(Some(&NAMES[0]), Some(&0usize)));
+    let writer = {
+        let atom = atom.clone();
+        thread::spawn(move || {
+            static NUMBERS: [usize; 2] = [0, 1];
+            for i in 0..10_000 {
+                atom.swap(Some(&NAMES[i % 2]), Some(&NUMBERS[i % 2]));
+            }
+        })
+    };
+    for _ in 0..10_000 {
+        let (name, number) = atom.load();
+        assert_eq!(*name.unwrap(), NAMES[*number.unwrap()]);
+    }
+    writer.join().unwrap();
+    assert_eq!(atom.load_first(), Some(&"odd"));
+    assert_eq!(atom.load_second(), Some(&1));
+}
+
+#[test]
+fn atom_weak() {
+    use atom::weak::AtomWeak;
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let first = Arc::new(Canary(drops.clone()));
+    let slot = Arc::new(AtomWeak::new(&first));
+    assert!(slot
+        .get_strong()
+        .is_some_and(|strong| Arc::ptr_eq(&strong, &first)));
+
+    // the slot does not keep its target alive
+    drop(first);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    assert!(slot.get_strong().is_none());
+    assert!(!slot.is_none());
+    assert!(slot.get_weak().is_some());
+    slot.clear();
+    assert!(slot.is_none());
+    assert!(slot.get_strong().is_none());
+
+    let targets: Vec<_> = (0..4).map(|_| Arc::new(Canary(drops.clone()))).collect();
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let slot = slot.clone();
+            thread::spawn(move || {
+                let mut upgraded = 0;
+                for _ in 0..10_000 {
+                    if slot.get_strong().is_some() {
+                        upgraded += 1;
+                    }
+                }
+                upgraded
+            })
+        })
+        .collect();
+    for i in 0..10_000 {
+        slot.set(&targets[i % 4]);
+    }
+    for t in readers {
+        t.join().unwrap();
+    }
+    drop(targets);
+    assert_eq!(drops.load(Ordering::SeqCst), 5);
+    assert!(Arc::try_unwrap(slot)
+        .unwrap()
+        .into_inner()
+        .is_some_and(|weak| weak.upgrade().is_none()));
+}
+
+#[test]
+fn sharded_atom() {
+    use atom::sharded::ShardedAtom;
+
+    let atom = Arc::new(ShardedAtom::with_shards(8));
+    assert_eq!(atom.shards(), 8);
+    assert!(atom.is_none(Ordering::Acquire));
+    let threads: Vec<_> = (0..8usize)
+        .map(|i| {
+            let atom = atom.clone();
+            thread::spawn(move || {
+                (0..100)
+                    .filter_map(|n| atom.swap(Arc::new(i * 100 + n), Ordering::AcqRel))
+                    .map(|v| *v)
+                    .sum::<usize>()
+            })
+        })
+        .collect();
+    let displaced: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+
+    // every value was either displaced by a later swap or is still stored
+    let stored = atom.reduce(0, |acc, v| acc + *v);
+    assert!(!atom.is_none(Ordering::Acquire));
+    assert_eq!(displaced + stored, (0..800).sum());
+    let collected: usize = atom
+        .collect(Ordering::Acquire)
+        .into_iter()
+        .map(|v| *v)
+        .sum();
+    assert_eq!(collected, stored);
+    assert!(atom.is_none(Ordering::Acquire));
+}
+
+#[test]
+fn padded_atom() {
+    use std::mem;
+
+    let slots: Vec<PaddedAtom<Box<usize>>> =
+        (0..4).map(|_| PaddedAtom::new(Atom::empty())).collect();
+    assert!(mem::align_of::<PaddedAtom<Box<usize>>>() >= 64);
+    let first = &*slots[0] as *const Atom<Box<usize>> as usize;
+    let second = &*slots[1] as *const Atom<Box<usize>> as usize;
+    assert!(second - first >= 64);
+
+    let slots = Arc::new(slots);
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let slots = slots.clone();
+            thread::spawn(move || {
+                for n in 0..1_000 {
+                    slots[i].swap(Box::new(n), Ordering::AcqRel);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let slots = Arc::try_unwrap(slots).unwrap();
+    for slot in slots {
+        assert_eq!(
+            slot.into_inner().take(Ordering::Acquire),
+            Some(Box::new(999))
+        );
+    }
+
+    let mut counter = CachePadded::from(0u32);
+    *counter += 1;
+    assert_eq!(format!("{:?}", counter), "CachePadded(1)");
+}
+
+#[test]
+fn seqlock_snapshot() {
+    use atom::seqlock::Snapshot;
+    use std::panic::{self, AssertUnwindSafe};
+
+    let cell = Snapshot::new([0u64; 8]);
+    assert_eq!(
+        cell.update(|mut v| {
+            v[0] = 1;
+            v
+        })[0],
+        1
+    );
+    assert_eq!(cell.try_load(), Some([1, 0, 0, 0, 0, 0, 0, 0]));
+
+    // a writer that panics leaves the old value behind and the cell unlocked
+    let failed = panic::catch_unwind(AssertUnwindSafe(|| {
+        cell.update(|_| panic!("update failed"));
+    }));
+    assert!(failed.is_err());
+    assert_eq!(cell.try_load(), Some([1, 0, 0, 0, 0, 0, 0, 0]));
+    cell.store([2; 8]);
+    assert_eq!(cell.load(), [2; 8]);
+
+    // readers only ever see arrays written in one piece
+    let cell = Arc::new(Snapshot::new([0u64; 8]));
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                for _ in 0..5_000 {
+                    cell.update(|v| [v[0] + 1; 8]);
+                }
+            })
+        })
+        .collect();
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..10_000 {
+                    let v = cell.load();
+                    assert!(v.iter().all(|&x| x == v[0]));
+                    assert!(v[0] >= last);
+                    last = v[0];
+                }
+            })
+        })
+        .collect();
+    for t in writers.into_iter().chain(readers) {
+        t.join().unwrap();
+    }
+    assert_eq!(Arc::try_unwrap(cell).unwrap().into_inner(), [10_000; 8]);
+}
+
+#[test]
+fn mcas_transaction() {
+    use atom::mcas::{transaction, McasAtom};
+
+    let a = McasAtom::new(Box::new(1u64));
+    let b = McasAtom::<Box<u64>>::empty();
+    let stale = a.token();
+    assert_eq!(a.swap(Box::new(2)), Some(Box::new(1)));
+    let failed = transaction(vec![(&b, b.token(), Some(Box::new(5))), (&a, stale, None)]);
+    assert_eq!(failed, Err(vec![Some(Box::new(5)), None]));
+    assert!(b.is_none());
+
+    // transfers between accounts never lose or create money
+    static AMOUNTS: [u64; 65] = {
+        let mut amounts = [0; 65];
+        let mut i = 0;
+        while i < 65 {
+            amounts[i] = i as u64;
+            i += 1;
+        }
+        amounts
+    };
+    let accounts: Arc<Vec<McasAtom<&'static u64>>> =
+        Arc::new((0..4).map(|_| McasAtom::new(&AMOUNTS[16])).collect());
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let accounts = accounts.clone();
+            thread::spawn(move || {
+                for i in 0..2_000 {
+                    let from = &accounts[(t + i) % 4];
+                    let to = &accounts[(t + i + 1 + i % 2) % 4];
+                    loop {
+                        let (tf, tt) = (from.token(), to.token());
+                        let (&vf, &vt) = (from.load().unwrap(), to.load().unwrap());
+                        if (tf, tt) != (from.token(), to.token()) {
+                            continue;
+                        }
+                        let amount = vf.min(3) as usize;
+                        let moved = transaction(vec![
+                            (from, tf, Some(&AMOUNTS[vf as usize - amount])),
+                            (to, tt, Some(&AMOUNTS[vt as usize + amount])),
+                        ]);
+                        if moved.is_ok() {
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let total: u64 = accounts.iter().map(|a| *a.load().unwrap()).sum();
+    assert_eq!(total, 64);
+}
+
+#[test]
+fn atom_group() {
+    use atom::arc_cell::ArcCell;
+
+    let group = Arc::new(AtomGroup::new());
+    let cells = Arc::new((
+        ArcCell::new(Arc::new(0usize)),
+        ArcCell::new(Arc::new(0usize)),
+    ));
+    let writer = {
+        let (group, cells) = (group.clone(), cells.clone());
+        thread::spawn(move || {
+            for n in 1..=2_000 {
+                group.write(|| {
+                    cells.0.store(Arc::new(n));
+                    cells.1.store(Arc::new(n));
+                });
+            }
+        })
+    };
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let (group, cells) = (group.clone(), cells.clone());
+            thread::spawn(move || {
+                for _ in 0..2_000 {
+                    let (a, b) = group.read(|| (cells.0.load(), cells.1.load()));
+                    assert_eq!(a, b);
+                }
+            })
+        })
+        .collect();
+    writer.join().unwrap();
+    for t in readers {
+        t.join().unwrap();
+    }
+    assert_eq!(group.generation(), 2_000);
+    assert_eq!(group.try_read(|| *cells.0.load()), Some(2_000));
+}
+
+#[test]
+fn scoped_atom() {
+    use atom::scoped;
+
+    let values = [1, 2, 3, 4];
+    let total = scoped::scope(|s| {
+        let slot = s.atom::<i32>();
+        assert!(slot.is_none(Ordering::Acquire));
+        assert_eq!(slot.set_if_none(&values[0], Ordering::AcqRel), None);
+        assert_eq!(slot.set_if_none(&values[1], Ordering::AcqRel), Some(&2));
+        assert_eq!(
+            slot.compare_exchange(Some(&values[1]), None, Ordering::AcqRel),
+            Err(Some(&1))
+        );
+
+        let sum = AtomicUsize::new(0);
+        thread::scope(|t| {
+            for value in &values[1..] {
+                let (slot, sum) = (&slot, &sum);
+                t.spawn(move || {
+                    let old = slot.swap(value, Ordering::AcqRel).unwrap();
+                    sum.fetch_add(*old as usize, Ordering::Relaxed);
+                });
+            }
+        });
+        sum.load(Ordering::Relaxed) + *slot.take(Ordering::Acquire).unwrap() as usize
+    });
+    assert_eq!(total, 10);
+}
+
+#[test]
+fn atom_cow() {
+    use atom::cow::{AtomCow, Payload};
+
+    static DEFAULT: u64 = 7;
+    let count = Arc::new(AtomicUsize::new(0));
+    let cow = AtomCow::from_static(&DEFAULT);
+    assert!(!cow.is_owned(Ordering::Acquire));
+    assert_eq!(
+        cow.set_if_none(Box::new(1).into(), Ordering::AcqRel),
+        Some(Payload::Owned(Box::new(1)))
+    );
+    let old = cow
+        .swap(Payload::Owned(Box::new(9)), Ordering::AcqRel)
+        .unwrap();
+    assert!(std::ptr::eq(&*old, &DEFAULT));
+    assert!(cow.is_owned(Ordering::Acquire));
+    assert_eq!(*cow.take(Ordering::Acquire).unwrap().into_owned(), 9);
+    assert!(cow.is_none(Ordering::Acquire));
+
+    // owned canaries are dropped, static ones never are
+    let cow = AtomCow::new(Payload::Owned(Box::new(Canary(count.clone()))));
+    let forever: &'static Canary = Box::leak(Box::new(Canary(Arc::new(AtomicUsize::new(0)))));
+    drop(cow.swap(forever.into(), Ordering::AcqRel));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    drop(cow);
+    assert_eq!(forever.0.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn atom_either() {
+    use atom::either::{AtomEither, Either};
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let slot = AtomEither::<u32, Canary>::empty();
+    assert!(slot.is_none(Ordering::Acquire));
+    assert!(slot
+        .set_if_none(Either::Left(Box::new(1)), Ordering::AcqRel)
+        .is_none());
+    assert!(slot.is_left(Ordering::Acquire));
+    assert!(slot.take_right(Ordering::Acquire).is_none());
+
+    let old = slot.swap_right(Box::new(Canary(count.clone())), Ordering::AcqRel);
+    assert_eq!(old.and_then(Either::left), Some(Box::new(1)));
+    assert!(slot.is_right(Ordering::Acquire));
+    assert!(slot.take_left(Ordering::Acquire).is_none());
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    drop(slot.take_right(Ordering::Acquire));
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    slot.swap_right(Box::new(Canary(count.clone())), Ordering::AcqRel);
+    assert!(slot
+        .swap_left(Box::new(2), Ordering::AcqRel)
+        .unwrap()
+        .right()
+        .is_some());
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+    slot.swap_right(Box::new(Canary(count.clone())), Ordering::AcqRel);
+    drop(slot);
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn index_atom() {
+    use atom::index::IndexAtom;
+
+    let mut arena = vec![10, 20, 30];
+    let slot = IndexAtom::<usize>::new(0);
+    assert_eq!(slot.set_if_none(1, Ordering::AcqRel), Some(1));
+    assert_eq!(
+        slot.compare_exchange(Some(0), Some(2), Ordering::AcqRel),
+        Ok(Some(0))
+    );
+    assert_eq!(
+        slot.compare_exchange(Some(0), None, Ordering::AcqRel),
+        Err(Some(2))
+    );
+    *slot.resolve_mut(&mut arena, Ordering::Acquire).unwrap() += 1;
+    assert_eq!(slot.resolve(&arena, Ordering::Acquire), Some(&31));
+    assert_eq!(slot.swap(7, Ordering::AcqRel), Some(2));
+    assert_eq!(slot.resolve(&arena, Ordering::Acquire), None);
+    assert_eq!(slot.take(Ordering::Acquire), Some(7));
+    assert!(slot.is_none(Ordering::Acquire));
+
+    assert_eq!(std::mem::size_of::<IndexAtom>(), 4);
+    let slots: Arc<Vec<IndexAtom>> = Arc::new((0..4).map(|_| IndexAtom::empty()).collect());
+    let threads: Vec<_> = (0..4u32)
+        .map(|t| {
+            let slots = slots.clone();
+            thread::spawn(move || {
+                slots
+                    .iter()
+                    .filter(|s| s.set_if_none(t, Ordering::AcqRel).is_none())
+                    .count()
+            })
+        })
+        .collect();
+    let claimed: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(claimed, 4);
+}
+
+#[test]
+#[should_panic(expected = "marks an empty IndexAtom")]
+fn index_atom_none() {
+    atom::index::IndexAtom::<u32>::new(u32::MAX);
+}
+
+#[test]
+fn atom_callback() {
+    use atom::callback::AtomCallback;
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let canary = Canary(count.clone());
+    let handler = Arc::new(AtomCallback::new(move |x: usize| {
+        let _ = &canary;
+        x
+    }));
+    assert_eq!(handler.call(3), 3);
+
+    // a running call keeps its closure alive through a replace
+    let running = handler.get();
+    drop(handler.replace(|x| x * 2));
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    assert_eq!(running(3), 3);
+    drop(running);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let handler = handler.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    if t == 0 && i % 10 == 0 {
+                        handler.replace(|x| x + x);
+                    }
+                    assert_eq!(handler.call(i), i * 2);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // a closure can replace itself
+    let hook = Arc::new(AtomCallback::new(|_: ()| 1));
+    let weak = Arc::downgrade(&hook);
+    hook.replace(move |_| {
+        weak.upgrade().unwrap().replace(|_| 3);
+        2
+    });
+    assert_eq!((hook.call(()), hook.call(())), (2, 3));
+}
+
+#[test]
+fn atom_fn() {
+    use atom::callback::AtomFn;
+
+    fn double(x: usize) -> usize {
+        x * 2
+    }
+
+    let dispatch = Arc::new(AtomFn::new(double));
+    assert!(dispatch.set_if_none(|x| x + x, Ordering::AcqRel).is_some());
+    assert_eq!(dispatch.call(3), Some(6));
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let dispatch = dispatch.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    if t == 0 && i % 10 == 0 {
+                        dispatch.swap(|x| x + x, Ordering::AcqRel);
+                    }
+                    assert_eq!(dispatch.call(i), Some(i * 2));
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    assert!(dispatch.take(Ordering::Acquire).is_some());
+    assert!(dispatch.is_none(Ordering::Acquire));
+    assert_eq!(dispatch.call(1), None);
+}
+
+#[test]
+fn double_buffer() {
+    use atom::buffer::DoubleBuffer;
+
+    let mut frames = DoubleBuffer::new([0usize; 16], [0usize; 16]);
+    let readers: Vec<_> = (0..3)
+        .map(|_| {
+            let reader = frames.reader();
+            thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..2_000 {
+                    let frame = reader.read();
+                    // the writer never touches a frame a reader can see
+                    assert!(frame.iter().all(|&x| x == frame[0]));
+                    assert!(frame[0] >= last);
+                    last = frame[0];
+                }
+            })
+        })
+        .collect();
+    for n in 1..=2_000 {
+        for x in frames.back_mut().iter_mut() {
+            *x = n;
+        }
+        frames.flip();
+        assert_eq!(frames.front()[0], n);
+    }
+    for t in readers {
+        t.join().unwrap();
+    }
+    assert_eq!(frames.back()[0], 1_999);
+}
+
+#[test]
+fn triple_buffer() {
+    use atom::buffer::TripleBuffer;
+
+    let (mut input, mut output) = TripleBuffer::new([0usize; 16]);
+    assert!(!output.has_update());
+    assert_eq!(output.read()[0], 0);
+
+    let producer = thread::spawn(move || {
+        for n in 1..=10_000 {
+            *input.back_mut() = [n; 16];
+            input.publish();
+        }
+    });
+    let mut last = 0;
+    while last < 10_000 {
+        let value = output.read();
+        assert!(value.iter().all(|&x| x == value[0]));
+        assert!(value[0] >= last);
+        last = value[0];
+    }
+    producer.join().unwrap();
+    assert!(!output.has_update());
+}
+
+#[test]
+fn left_right() {
+    use atom::left_right::LeftRight;
+
+    let cell = Arc::new(LeftRight::new(vec![0usize; 8]));
+    let readers: Vec<_> = (0..3)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                let mut last = 0;
+                for _ in 0..5_000 {
+                    let value = cell.read();
+                    assert!(value.iter().all(|&x| x == value[0]));
+                    assert!(value[0] >= last);
+                    last = value[0];
+                }
+            })
+        })
+        .collect();
+    let writers: Vec<_> = (0..2)
+        .map(|_| {
+            let cell = cell.clone();
+            thread::spawn(move || {
+                for _ in 0..1_000 {
+                    cell.write(|v| v.iter_mut().for_each(|x| *x += 1));
+                }
+            })
+        })
+        .collect();
+    for t in readers.into_iter().chain(writers) {
+        t.join().unwrap();
+    }
+    assert_eq!(Arc::try_unwrap(cell).unwrap().into_inner(), vec![2_000; 8]);
+}
+
+#[test]
+fn atom_accumulator() {
+    use atom::accumulator::AtomAccumulator;
+
+    let sums = Arc::new(AtomAccumulator::new());
+    let threads: Vec<_> = (0..4u64)
+        .map(|t| {
+            let sums = sums.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    sums.contribute(t * 1_000 + i);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(sums.fold(0, |acc, x| acc + x), (0..4_000).sum());
+    assert!(sums.is_empty());
+
+    // leftover partial results are dropped without recursing
+    let count = Arc::new(AtomicUsize::new(0));
+    let canaries = AtomAccumulator::new();
+    for _ in 0..100_000 {
+        canaries.contribute(Canary(count.clone()));
+    }
+    drop(canaries);
+    assert_eq!(count.load(Ordering::SeqCst), 100_000);
+}
+
+#[test]
+fn local_atom() {
+    use atom::local::LocalAtom;
+    use std::rc::Rc;
+
+    let first = Rc::new(1);
+    let slot = LocalAtom::new(first.clone());
+    assert_eq!(Rc::strong_count(&first), 2);
+    assert_eq!(slot.set_if_none(Rc::new(2)), Some(Rc::new(2)));
+    assert_eq!(slot.load_cloned(), Some(first.clone()));
+    assert!(slot.compare_exchange(Some(&Rc::new(1)), None).is_err());
+    assert_eq!(
+        slot.compare_exchange(Some(&first), Some(Rc::new(3))),
+        Ok(Some(first.clone()))
+    );
+    assert_eq!(Rc::strong_count(&first), 1);
+    assert_eq!(slot.take(), Some(Rc::new(3)));
+    assert!(slot.is_none());
+
+    // a LocalAtom holding Send values can move to another thread
+    let boxed = LocalAtom::new(Box::new(5));
+    let back = thread::spawn(move || boxed.into_inner()).join().unwrap();
+    assert_eq!(back, Some(Box::new(5)));
+}
+
+#[test]
+fn vec_and_string_payloads() {
+    let mut packet = Vec::with_capacity(64);
+    packet.extend_from_slice(b"hello");
+    let buffer = packet.as_ptr();
+    let slot = Atom::new(packet);
+    let packet = slot.swap(Vec::new(), Ordering::AcqRel).unwrap();
+    assert_eq!((packet.as_ptr(), packet.capacity()), (buffer, 64));
+    assert_eq!(slot.take(Ordering::Acquire), Some(Vec::new()));
+
+    let name = Atom::empty();
+    assert_eq!(name.set_if_none(String::from("a"), Ordering::AcqRel), None);
+    assert_eq!(name.load_cloned().as_deref(), Some("a"));
+    assert_eq!(
+        name.swap(String::from("b"), Ordering::AcqRel),
+        Some(String::from("a"))
+    );
+    drop(name);
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let canaries = Atom::new(vec![Canary(count.clone()), Canary(count.clone())]);
+    drop(canaries);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn custom_payload() {
+    // a handle that owns its allocation like a Box does
+    #[derive(Debug, PartialEq)]
+    struct Handle(Box<u32>);
+
+    impl IntoRawPtr for Handle {
+        fn into_raw(self) -> *mut () {
+            IntoRawPtr::into_raw(self.0)
+        }
+    }
+
+    impl FromRawPtr for Handle {
+        unsafe fn from_raw(ptr: *mut ()) -> Handle {
+            Handle(FromRawPtr::from_raw(ptr))
+        }
+    }
+
+    unsafe impl AtomStorable for Handle {}
+
+    let slot = Atom::new(Handle(Box::new(1)));
+    assert_eq!(
+        slot.swap(Handle(Box::new(2)), Ordering::AcqRel),
+        Some(Handle(Box::new(1)))
+    );
+    assert_eq!(slot.take(Ordering::Acquire), Some(Handle(Box::new(2))));
+}
+
+#[test]
+fn weak_payloads() {
+    let subscriber = Arc::new(5);
+    let slot = Atom::new(Arc::downgrade(&subscriber));
+    assert_eq!(Arc::weak_count(&subscriber), 1);
+    let old = slot.swap(Weak::new(), Ordering::AcqRel).unwrap();
+    assert_eq!(old.upgrade(), Some(subscriber.clone()));
+    assert!(slot.take(Ordering::Acquire).unwrap().upgrade().is_none());
+    drop(old);
+    assert_eq!(Arc::weak_count(&subscriber), 0);
+
+    // the stored weak reference keeps the allocation but not the value
+    let count = Arc::new(AtomicUsize::new(0));
+    let canary = Arc::new(Canary(count.clone()));
+    let slot = Atom::new(Arc::downgrade(&canary));
+    drop(canary);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert!(slot.take(Ordering::Acquire).unwrap().upgrade().is_none());
+}
+
+#[test]
+fn thin_box_payloads() {
+    use atom::thin::ThinBox;
+    use std::fmt::Debug;
+    use std::mem;
+
+    type Handler = dyn Fn(u32) -> u32 + Send + Sync;
+
+    assert_eq!(mem::size_of::<ThinBox<Handler>>(), mem::size_of::<usize>());
+    let offset = 3;
+    let slot: Arc<Atom<ThinBox<Handler>>> =
+        Arc::new(Atom::new(ThinBox::new(move |x| x + offset, |v| v)));
+    let worker = slot.clone();
+    let old = thread::spawn(move || worker.swap(ThinBox::new(|x| x * 2, |v| v), Ordering::AcqRel))
+        .join()
+        .unwrap()
+        .unwrap();
+    assert_eq!(old(1), 4);
+    assert_eq!(slot.take(Ordering::Acquire).unwrap()(5), 10);
+
+    // the value is dropped through the box it was stored in
+    let count = Arc::new(AtomicUsize::new(0));
+    let canary: ThinBox<dyn Send> = ThinBox::new(Canary(count.clone()), |v| v);
+    let slot = Atom::new(canary);
+    assert!(slot
+        .set_if_none(ThinBox::new(Canary(count.clone()), |v| v), Ordering::AcqRel)
+        .is_some());
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    drop(slot);
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    let debug: ThinBox<dyn Debug> = ThinBox::new(vec![1, 2], |v| v);
+    assert_eq!(format!("{:?}", debug), "ThinBox([1, 2])");
+}
+
+#[test]
+fn dst_payloads() {
+    use atom::dst::DstAtom;
+    use std::fmt::Display;
+
+    let slot: Arc<DstAtom<Arc<dyn Display + Send + Sync>>> = Arc::new(DstAtom::empty());
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let slot = slot.clone();
+            thread::spawn(move || {
+                for i in 0..1_000 {
+                    let text: Arc<dyn Display + Send + Sync> = if i % 2 == 0 {
+                        Arc::new(t)
+                    } else {
+                        Arc::new("text")
+                    };
+                    if let Some(old) = slot.swap(text) {
+                        let old = old.to_string();
+                        assert!(old == "text" || old.parse::<u32>().unwrap() < 4);
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert!(!slot.is_none());
+
+    let name: DstAtom<Box<str>> = DstAtom::new("first".into());
+    assert_eq!(name.set_if_none("second".into()).as_deref(), Some("second"));
+    assert_eq!(name.into_inner().as_deref(), Some("first"));
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let canaries: Box<[Canary]> = vec![Canary(count.clone()), Canary(count.clone())].into();
+    let slot = DstAtom::new(canaries);
+    assert_eq!(slot.take().map(|c| c.len()), Some(2));
+    assert!(slot.is_none());
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn uninit_payloads() {
+    let slot = Arc::new(Atom::empty());
+    let buffer = slot.set_uninit(Ordering::AcqRel).unwrap();
+    assert!(slot.set_uninit(Ordering::AcqRel).is_none());
+
+    // the slot is already published while the "device" fills it in
+    let done = Arc::new(AtomicUsize::new(0));
+    let filled = done.clone();
+    let buffer = buffer as usize;
+    thread::spawn(move || {
+        unsafe { (buffer as *mut [u8; 4]).write(*b"data") };
+        filled.store(1, Ordering::Release);
+    })
+    .join()
+    .unwrap();
+    assert_eq!(done.load(Ordering::Acquire), 1);
+    let data = unsafe { slot.assume_init_take(Ordering::Acquire) };
+    assert_eq!(data, Some(Box::new(*b"data")));
+    assert!(slot.is_none(Ordering::Acquire));
+}
+
+#[cfg(feature = "unsafe_borrowed")]
+#[test]
+fn borrowed_payloads() {
+    use atom::raw::Borrowed;
+
+    let values = [1, 2];
+    let slot = Atom::new(unsafe { Borrowed::new(&values[0]) });
+    thread::scope(|s| {
+        s.spawn(|| slot.swap(unsafe { Borrowed::new(&values[1]) }, Ordering::AcqRel));
+    });
+    assert_eq!(slot.take(Ordering::Acquire).map(Borrowed::get), Some(&2));
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn allocator_payloads() {
+    use std::alloc::{AllocError, Allocator, Global, Layout};
+    use std::ptr::NonNull;
+
+    // counts the allocations it has live, like a pool would
+    #[derive(Clone)]
+    struct Counting(Arc<AtomicUsize>);
+
+    unsafe impl Allocator for Counting {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    let live = Arc::new(AtomicUsize::new(0));
+    let pool = Counting(live.clone());
+    let slot = Atom::new(Box::new_in(1u32, pool.clone()));
+    let old = slot.swap(Box::new_in(2, pool.clone()), Ordering::AcqRel);
+    assert_eq!(old.as_deref(), Some(&1));
+    assert_eq!(live.load(Ordering::SeqCst), 2);
+    // the allocator came back out with the box and frees it
+    drop(old);
+    drop(slot);
+    assert_eq!(live.load(Ordering::SeqCst), 0);
+
+    let mut items = Vec::new_in(pool);
+    items.push(3u32);
+    let slot = Atom::new(items);
+    assert_eq!(slot.take(Ordering::Acquire).unwrap()[..], [3]);
+    assert_eq!(live.load(Ordering::SeqCst), 0);
+
+    // a zero-sized allocator is not given a header
+    let boxed = Box::new_in(4u32, Global);
+    let raw = &*boxed as *const u32;
+    let slot = Atom::new(boxed);
+    assert_eq!(
+        slot.token(Ordering::Acquire),
+        Atom::new(unsafe { &*raw }).token(Ordering::Acquire)
+    );
+}
+
+#[test]
+fn cstring_payloads() {
+    use std::ffi::CString;
+
+    let greeting = CString::new("hello").unwrap();
+    let chars = greeting.as_ptr();
+    let slot = Atom::new(greeting);
+    // the buffer comes back out without being copied
+    let old = slot
+        .swap(CString::new("bye").unwrap(), Ordering::AcqRel)
+        .unwrap();
+    assert_eq!((old.as_ptr(), old.to_str()), (chars, Ok("hello")));
+    assert_eq!(slot.load_cloned(), Some(CString::new("bye").unwrap()));
+}
+
+#[test]
+fn pinned_payloads() {
+    use std::future::Future;
+    use std::marker::PhantomPinned;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    // a future that must not move once it has been polled
+    struct Countdown {
+        left: u32,
+        _pinned: PhantomPinned,
+    }
+
+    impl Future for Countdown {
+        type Output = u32;
+        fn poll(self: Pin<&mut Self>, _: &mut Context) -> Poll<u32> {
+            let this = unsafe { self.get_unchecked_mut() };
+            if this.left == 0 {
+                Poll::Ready(7)
+            } else {
+                this.left -= 1;
+                Poll::Pending
+            }
+        }
+    }
+
+    let future = Box::pin(Countdown {
+        left: 1,
+        _pinned: PhantomPinned,
+    });
+    let addr = &*future as *const Countdown;
+    let slot = Atom::new(future);
+    let future = slot.take(Ordering::Acquire).unwrap();
+    assert!(std::ptr::eq(&*future, addr));
+
+    let mut cell = AtomSetOnce::empty();
+    assert!(cell.get_pin_mut(Ordering::Acquire).is_none());
+    cell.set_if_none(future, Ordering::Release);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut future = cell.get_pin_mut(Ordering::Acquire).unwrap();
+    assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(future.poll(&mut cx), Poll::Ready(7));
+}
+
+#[test]
+fn raw_atom() {
+    use atom::raw::RawAtom;
+    use std::ptr::NonNull;
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let raw = IntoRawPtr::into_raw(Box::new(Canary(count.clone())));
+    let owned = NonNull::new(raw as *mut Canary).unwrap();
+    {
+        let slot = RawAtom::empty();
+        assert_eq!(slot.set_if_none(owned, Ordering::AcqRel), None);
+        assert_eq!(slot.set_if_none(owned, Ordering::AcqRel), Some(owned));
+        assert_eq!(
+            slot.compare_exchange(None, None, Ordering::AcqRel, Ordering::Acquire),
+            Err(Some(owned))
+        );
+        assert_eq!(slot.load(Ordering::Acquire), Some(owned));
+    }
+    // dropping the atom forgot the pointer
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+
+    // and taking it back as its owner frees it
+    let slot = RawAtom::new(owned);
+    let canary: Option<Box<Canary>> = unsafe { slot.take_as(Ordering::Acquire) };
+    drop(canary);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    assert!(slot.is_none(Ordering::Acquire));
+}
+
+#[test]
+fn static_mut_payloads() {
+    let pool: &'static mut [u32; 2] = Box::leak(Box::new([0, 0]));
+    let (first, second) = pool.split_at_mut(1);
+    let free = Atom::new(&mut first[0]);
+    // a full slot hands the borrow straight back
+    let rejected = free.set_if_none(&mut second[0], Ordering::AcqRel).unwrap();
+    assert_eq!(*rejected, 0);
+
+    // the exclusive borrow moves to another thread and comes back
+    let free = Arc::new(free);
+    let worker = free.clone();
+    thread::spawn(move || {
+        let item = worker.take(Ordering::Acquire).unwrap();
+        *item += 5;
+        worker.swap(item, Ordering::AcqRel);
+    })
+    .join()
+    .unwrap();
+
+    let item = free.take(Ordering::Acquire).unwrap();
+    assert_eq!(*item, 5);
+    assert!(free.is_none(Ordering::Acquire));
+}
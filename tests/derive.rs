@@ -0,0 +1,136 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+#![cfg(feature = "atom-derive")]
+
+extern crate atom;
+
+use atom::*;
+use std::sync::atomic::Ordering;
+
+#[derive(GetNextMut)]
+struct Named {
+    #[next]
+    link: Option<Box<Named>>,
+    value: u32,
+}
+
+#[derive(GetNextMut)]
+struct Implicit<T> {
+    value: T,
+    next: Option<Box<Implicit<T>>>,
+}
+
+#[derive(GetNextMut)]
+struct Tuple(u32, #[next] Option<Box<Tuple>>);
+
+#[test]
+fn derived_named() {
+    let atom = Atom::empty();
+    for i in 0..3 {
+        let node = Box::new(Named {
+            link: None,
+            value: i,
+        });
+        atom.replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+    }
+    let found: Vec<u32> = atom.drain(Ordering::Acquire).map(|n| n.value).collect();
+    assert_eq!(found, vec![2, 1, 0]);
+}
+
+#[test]
+fn derived_generic() {
+    let atom = Atom::empty();
+    for s in ["a", "b"] {
+        let node = Box::new(Implicit {
+            value: s.to_owned(),
+            next: None,
+        });
+        atom.replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+    }
+    let found: Vec<String> = atom.drain(Ordering::Acquire).map(|n| n.value).collect();
+    assert_eq!(found, vec!["b", "a"]);
+}
+
+#[test]
+fn derived_tuple() {
+    let atom = Atom::empty();
+    atom.replace_and_set_next(
+        Box::new(Tuple(1, None)),
+        Ordering::Relaxed,
+        Ordering::AcqRel,
+    );
+    atom.replace_and_set_next(
+        Box::new(Tuple(2, None)),
+        Ordering::Relaxed,
+        Ordering::AcqRel,
+    );
+    let found: Vec<u32> = atom.drain(Ordering::Acquire).map(|n| n.0).collect();
+    assert_eq!(found, vec![2, 1]);
+}
+
+#[derive(GetNextMut)]
+struct WithLink {
+    #[next]
+    link: AtomLink<Box<WithLink>>,
+    value: u32,
+}
+
+#[test]
+fn derived_atom_link() {
+    let atom: Atom<Box<WithLink>> = (0..3)
+        .map(|value| {
+            Box::new(WithLink {
+                link: AtomLink::new(),
+                value,
+            })
+        })
+        .collect();
+    let found: Vec<u32> = atom.drain(Ordering::Acquire).map(|n| n.value).collect();
+    assert_eq!(found, vec![2, 1, 0]);
+}
+
+#[test]
+fn derived_iter() {
+    let chain: chain::Chain<Box<Named>> = (0..3)
+        .map(|value| Box::new(Named { link: None, value }))
+        .collect();
+    let found: Vec<u32> = chain.iter().map(|n| n.value).collect();
+    assert_eq!(found, vec![2, 1, 0]);
+}
+
+/// A module with its own `Box`, the derive must not pick it up
+mod shadowed {
+    use atom::GetNextMut;
+
+    #[allow(dead_code)]
+    pub struct Box;
+
+    #[derive(GetNextMut)]
+    pub struct Node {
+        pub next: Option<::std::boxed::Box<Node>>,
+        pub value: u32,
+    }
+}
+
+#[test]
+fn derived_with_box_shadowed() {
+    let atom = Atom::empty();
+    for value in 0..2 {
+        let node = Box::new(shadowed::Node { next: None, value });
+        atom.replace_and_set_next(node, Ordering::Relaxed, Ordering::AcqRel);
+    }
+    let found: Vec<u32> = atom.drain(Ordering::Acquire).map(|n| n.value).collect();
+    assert_eq!(found, vec![1, 0]);
+}
@@ -0,0 +1,146 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Checks that waiters who give up do not leave memory behind.
+//!
+//! Every allocation in this binary is counted, so the tests take turns
+//! instead of running in parallel.
+
+extern crate atom;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+#[cfg(feature = "async")]
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use atom::cancel::CancellationToken;
+use atom::*;
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::task::{Context, Wake, Waker};
+use std::time::Duration;
+
+struct Counting;
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size() as isize, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Run `round` over and over, and check that the memory it leaves behind
+/// stops growing after a warm-up.
+fn bounded<F: FnMut()>(mut round: F) {
+    let _serial = SERIAL.lock().unwrap_or_else(|poison| poison.into_inner());
+    for _ in 0..100 {
+        round();
+    }
+    let before = LIVE.load(Ordering::SeqCst);
+    for _ in 0..10_000 {
+        round();
+    }
+    let grown = LIVE.load(Ordering::SeqCst) - before;
+    assert!(grown < 16 * 1024, "{} bytes left behind", grown);
+}
+
+#[test]
+fn wait_for_change_timeouts() {
+    let atom = Atom::new(Box::new(0u32));
+    let token = atom.token(Ordering::Acquire);
+    bounded(|| {
+        assert_eq!(atom.wait_for_change(token, Duration::ZERO), token);
+    });
+}
+
+#[test]
+fn take_blocking_timeouts() {
+    let atom: Atom<Box<u32>> = Atom::empty();
+    bounded(|| {
+        assert_eq!(atom.take_blocking_timeout(Duration::ZERO), None);
+    });
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn dropped_wait_async() {
+    let once: AtomSetOnce<Box<u32>> = AtomSetOnce::empty();
+    let mut cx = Context::from_waker(Waker::noop());
+    bounded(|| {
+        let mut future = Box::pin(once.wait_async());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+    });
+}
+
+/// A waker that does not wake the same task as `Waker::noop`
+#[cfg(feature = "async")]
+struct Other;
+
+#[cfg(feature = "async")]
+impl Wake for Other {
+    fn wake(self: Arc<Self>) {}
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn changed_with_new_wakers() {
+    let (_tx, mut rx) = watch::channel(Arc::new(0u32));
+    let other = Waker::from(Arc::new(Other));
+    bounded(|| {
+        let mut future = Box::pin(rx.changed());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        let mut cx = Context::from_waker(&other);
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+    });
+}
+
+#[test]
+fn cancel_wait_timeouts() {
+    let root = CancellationToken::new();
+    let middle = root.child();
+    let child = middle.child();
+    bounded(|| {
+        assert!(!child.wait_timeout(Duration::ZERO));
+    });
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn dropped_cancelled() {
+    let root = CancellationToken::new();
+    let child = root.child();
+    let other = Waker::from(Arc::new(Other));
+    bounded(|| {
+        let mut future = Box::pin(child.cancelled());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        let mut cx = Context::from_waker(&other);
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+    });
+}
@@ -0,0 +1,106 @@
+//   Copyright 2015 Colin Sherratt
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Model checks for the work-stealing deque, run with
+//! `RUSTFLAGS="--cfg loom" cargo test --release --test loom`.
+//!
+//! Only the `top` and `bottom` indices are loom atomics, so every model
+//! stays within the initial capacity and never grows the buffer.
+
+#![cfg(loom)]
+
+extern crate atom;
+extern crate loom;
+
+use atom::deque::{Steal, Worker};
+use loom::thread;
+
+fn steal(stealer: &atom::deque::Stealer<usize>) -> Option<usize> {
+    loop {
+        match stealer.steal() {
+            Steal::Success(v) => return Some(v),
+            Steal::Empty => return None,
+            Steal::Retry => thread::yield_now(),
+        }
+    }
+}
+
+#[test]
+fn pop_races_steal_for_last_element() {
+    loom::model(|| {
+        let mut worker = Worker::with_capacity(4);
+        let stealer = worker.stealer();
+        worker.push(1);
+        let thief = thread::spawn(move || steal(&stealer));
+        let popped = worker.pop();
+        let stolen = thief.join().unwrap();
+        // exactly one side gets the element
+        assert_eq!(popped.is_some(), stolen.is_none());
+        assert_eq!(popped.or(stolen), Some(1));
+    });
+}
+
+#[test]
+fn push_while_stealing() {
+    loom::model(|| {
+        let mut worker = Worker::with_capacity(4);
+        let stealer = worker.stealer();
+        let thief = thread::spawn(move || steal(&stealer));
+        worker.push(1);
+        worker.push(2);
+        let stolen = thief.join().unwrap();
+        let mut rest = Vec::new();
+        while let Some(v) = worker.pop() {
+            rest.push(v);
+        }
+        // a thief only ever sees fully written elements, oldest first
+        match stolen {
+            Some(v) => {
+                assert_eq!(v, 1);
+                assert_eq!(rest, [2]);
+            }
+            None => assert_eq!(rest, [2, 1]),
+        }
+    });
+}
+
+#[test]
+fn two_thieves() {
+    loom::model(|| {
+        let mut worker = Worker::with_capacity(4);
+        worker.push(1);
+        worker.push(2);
+        let thieves: Vec<_> = (0..2)
+            .map(|_| {
+                let stealer = worker.stealer();
+                thread::spawn(move || steal(&stealer))
+            })
+            .collect();
+        let popped = worker.pop();
+        let mut all: Vec<_> = thieves
+            .into_iter()
+            .filter_map(|t| t.join().unwrap())
+            .chain(popped)
+            .collect();
+        all.sort();
+        assert!(all.len() <= 2);
+        all.dedup();
+        assert!(all.iter().all(|v| *v == 1 || *v == 2));
+        while let Some(v) = worker.pop() {
+            assert!(!all.contains(&v));
+            all.push(v);
+        }
+        assert_eq!(all.len(), 2);
+    });
+}